@@ -1,8 +1,12 @@
+use std::fmt;
+
 use crate::errors::*;
+use crate::keywords::*;
+use crate::random::RANDOM_FUNCTIONS;
 use crate::tokenize::*;
 
 // The Abstract Syntax Tree represent the order of operations. Operators farther down will be evaluated first.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ASTNode {
     pub token: Token,
     pub children: Vec<ASTNode>,
@@ -13,8 +17,43 @@ pub fn get_ast(tokens: &[Token]) -> Result<ASTNode, EvalError> {
     construct_ast(tokens)
 }
 
+/// Binary operator precedence levels, from the loosest to the tightest binding. Levels found
+/// first are placed higher in the AST, which means they'll be evaluated last. The bitwise levels
+/// follow the same order as most programming languages.
+const BINARY_LEVELS: [fn(&Operator) -> bool; 8] = [
+    |op| matches!(op, Operator::Range),
+    |op| matches!(op, Operator::BitOr),
+    |op| matches!(op, Operator::BitXor),
+    |op| matches!(op, Operator::BitAnd),
+    |op| matches!(op, Operator::Shift(_)),
+    |op| matches!(op, Operator::Additive(_)),
+    |op| {
+        matches!(
+            op,
+            Operator::Multiplicative(_)
+                | Operator::ElementWise(ElementWise::Multiply | ElementWise::Divide)
+        )
+    },
+    // `2 * 9.81 ± 0.02` is twice the measurement.
+    |op| matches!(op, Operator::PlusMinus),
+];
+
+/// Returns whether the operator raises to a power, which is the tightest binding operator.
+fn is_power(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Operator(Operator::Exponential | Operator::ElementWise(ElementWise::Power))
+    )
+}
+
 /// Recursively prases the tokens into the AST.
 fn construct_ast(tokens: &[Token]) -> Result<ASTNode, EvalError> {
+    if tokens.is_empty() {
+        return Err(EvalError::InvalidExpression(
+            "Missing operand".to_string(),
+        ));
+    }
+
     if tokens.len() == 1 {
         match tokens[0] {
             Token::Number(n) => {
@@ -23,18 +62,46 @@ fn construct_ast(tokens: &[Token]) -> Result<ASTNode, EvalError> {
                     children: vec![],
                 })
             }
+            Token::Integer(n) => {
+                return Ok(ASTNode {
+                    token: Token::Integer(n),
+                    children: vec![],
+                })
+            }
+            Token::Decimal(d) => {
+                return Ok(ASTNode {
+                    token: Token::Decimal(d),
+                    children: vec![],
+                })
+            }
+            Token::Timestamp(t) => {
+                return Ok(ASTNode {
+                    token: Token::Timestamp(t),
+                    children: vec![],
+                })
+            }
+            Token::Duration(d) => {
+                return Ok(ASTNode {
+                    token: Token::Duration(d),
+                    children: vec![],
+                })
+            }
             Token::Constant(n) => {
                 return Ok(ASTNode {
                     token: Token::Constant(n),
                     children: vec![],
                 })
             }
+            Token::Variable(ref name) => return Ok(ASTNode::variable(name)),
             Token::InnerExpression(ref inner) => return construct_ast(inner),
+            Token::InnerMatrix(ref inner) => return construct_matrix(inner),
+            Token::InnerList(ref inner) => return construct_list(inner),
             Token::Function(ref func) => {
                 let (function, args) = func.clone();
-                if args.len() != function.argument_count {
+                let max_arguments = function.argument_count + function.optional_arguments;
+                if args.len() < function.argument_count || args.len() > max_arguments {
                     return Err(EvalError::InvalidArgumentCount {
-                        expected: function.argument_count,
+                        expected: args.len().clamp(function.argument_count, max_arguments),
                         got: args.len(),
                     });
                 }
@@ -46,11 +113,18 @@ fn construct_ast(tokens: &[Token]) -> Result<ASTNode, EvalError> {
                     children.push(ast);
                 }
 
+                // The arguments are kept as children, so the tokens aren't needed anymore.
                 return Ok(ASTNode {
-                    token: Token::Function(func.clone()),
+                    token: Token::Function((function, vec![])),
                     children,
                 });
             }
+            Token::Call((ref name, ref args)) => {
+                return Ok(ASTNode {
+                    token: Token::Call((name.clone(), vec![])),
+                    children: args.iter().map(|arg| construct_ast(arg)).collect::<Result<_, _>>()?,
+                });
+            }
             _ => {
                 dbg!(&tokens[0]);
                 return Err(EvalError::InvalidExpression(
@@ -60,52 +134,473 @@ fn construct_ast(tokens: &[Token]) -> Result<ASTNode, EvalError> {
         }
     }
 
-    // Additive operators are placed into the AST first, which means they'll be evaluated last.
-    if let Some((i, Token::Operator(Operator::Additive(op)))) = tokens
-        .iter()
-        .enumerate()
-        .find(|(_, t)| matches!(t, Token::Operator(Operator::Additive(_))))
+    // The last operator of a level is used so that operators of the same level are evaluated from
+    // left to right.
+    for level in BINARY_LEVELS {
+        if let Some(i) = find_binary_operator(tokens, level) {
+            let left = construct_ast(&tokens[..i])?;
+            let right = construct_ast(&tokens[i + 1..])?;
+            return Ok(ASTNode {
+                token: tokens[i].clone(),
+                children: vec![left, right],
+            });
+        }
+    }
+
+    // A leading minus or complement applies to everything after it, except looser binary operators
+    // which have already been split off above.
+    if let Token::Operator(op @ (Operator::Additive(Additive::Subtract) | Operator::Unary(_))) =
+        tokens[0]
     {
-        let left = construct_ast(&tokens[..i])?;
-        let right = construct_ast(&tokens[i + 1..])?;
+        let unary = match op {
+            Operator::Unary(unary) => unary,
+            _ => Unary::Negate,
+        };
+
+        let operand = construct_ast(&tokens[1..])?;
         return Ok(ASTNode {
-            token: Token::Operator(Operator::Additive(*op)),
-            children: vec![left, right],
+            token: Token::Operator(Operator::Unary(unary)),
+            children: vec![operand],
         });
     }
 
-    // Multiplicative operators are placed into the AST last which means they'll be evaluated first
-    if let Some((i, Token::Operator(Operator::Multiplicative(op)))) = tokens
-        .iter()
-        .enumerate()
-        .find(|(_, t)| matches!(t, Token::Operator(Operator::Multiplicative(_))))
-    {
+    // Implicit multiplication binds tighter than the other operators, except for exponents, so that
+    // `2x^2` is `2 * x^2`. It happens wherever an operand is followed by another operand.
+    if let Some(i) = (1..tokens.len()).rev().find(|&i| {
+        !matches!(tokens[i - 1], Token::Operator(_))
+            && !matches!(tokens[i], Token::Operator(ref op) if !is_unary(op))
+    }) {
         let left = construct_ast(&tokens[..i])?;
-        let right = construct_ast(&tokens[i + 1..])?;
+        let right = construct_ast(&tokens[i..])?;
         return Ok(ASTNode {
-            token: Token::Operator(Operator::Multiplicative(*op)),
+            token: Token::Operator(Operator::Multiplicative(Multiplicative::Multiply)),
             children: vec![left, right],
         });
     }
 
-    if let Some((i, Token::Operator(Operator::Exponential))) = tokens
-        .iter()
-        .enumerate()
-        .find(|(_, t)| matches!(t, Token::Operator(Operator::Exponential)))
-    {
+    if let Some(i) = tokens.iter().position(is_power) {
         let left = construct_ast(&tokens[..i])?;
         let right = construct_ast(&tokens[i + 1..])?;
         return Ok(ASTNode {
-            token: Token::Operator(Operator::Exponential),
+            token: tokens[i].clone(),
             children: vec![left, right],
         });
     }
 
-    // At this point, only implicit multiplication is left.
-    let left = construct_ast(&tokens[..1])?;
-    let right = construct_ast(&tokens[1..])?;
-    return Ok(ASTNode {
-        token: Token::Operator(Operator::Multiplicative(Multiplicative::Multiply)),
-        children: vec![left, right],
-    });
+    Err(EvalError::InvalidExpression(
+        "Invalid expression".to_string(),
+    ))
+}
+
+/// Parses the tokens inside curly brackets into a list. The elements can be separated by commas
+/// or semicolons.
+fn construct_list(tokens: &[Token]) -> Result<ASTNode, EvalError> {
+    let children = match tokens.is_empty() {
+        true => vec![],
+        false => tokens
+            .split(|t| matches!(t, Token::Separator | Token::RowSeparator))
+            .map(construct_ast)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    Ok(ASTNode {
+        token: Token::List,
+        children,
+    })
+}
+
+/// Parses the tokens inside square brackets into a matrix, with rows separated by semicolons and
+/// elements by commas. A matrix with a single element is just that element.
+fn construct_matrix(tokens: &[Token]) -> Result<ASTNode, EvalError> {
+    let rows = tokens
+        .split(|t| matches!(t, Token::RowSeparator))
+        .map(|row| {
+            row.split(|t| matches!(t, Token::Separator))
+                .map(construct_ast)
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let columns = rows[0].len();
+    if rows.iter().any(|row| row.len() != columns) {
+        return Err(EvalError::InvalidExpression(
+            "Every row of a matrix needs the same number of elements".to_string(),
+        ));
+    }
+
+    let mut children = rows.concat();
+    if children.len() == 1 {
+        return Ok(children.remove(0));
+    }
+
+    Ok(ASTNode {
+        token: Token::Matrix {
+            rows: children.len() / columns,
+            columns,
+        },
+        children,
+    })
+}
+
+/// Finds the last binary operator of the given precedence level. Operators at the start of the
+/// tokens or right after another operator are unary, so they're skipped.
+fn find_binary_operator(tokens: &[Token], level: fn(&Operator) -> bool) -> Option<usize> {
+    (1..tokens.len()).rev().find(|&i| match (&tokens[i - 1], &tokens[i]) {
+        (Token::Operator(_), _) => false,
+        (_, Token::Operator(op)) => level(op),
+        _ => false,
+    })
+}
+
+impl ASTNode {
+    pub fn number(n: f64) -> ASTNode {
+        ASTNode {
+            token: Token::Number(n),
+            children: vec![],
+        }
+    }
+
+    pub fn variable(name: &str) -> ASTNode {
+        ASTNode {
+            token: Token::Variable(name.to_string()),
+            children: vec![],
+        }
+    }
+
+    pub fn operator(op: Operator, children: Vec<ASTNode>) -> ASTNode {
+        ASTNode {
+            token: Token::Operator(op),
+            children,
+        }
+    }
+
+    /// Creates a call to one of the functions in [`FUNCTIONS`].
+    pub fn function(name: &str, children: Vec<ASTNode>) -> ASTNode {
+        ASTNode {
+            token: Token::Function((FUNCTIONS[name], vec![])),
+            children,
+        }
+    }
+
+    /// Returns the name of the function if the node is a function call.
+    pub fn function_name(&self) -> Option<&'static str> {
+        match self.token {
+            Token::Function((func, _)) => Some(func.name),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the function is called anywhere in the tree.
+    pub fn contains_function(&self, name: &str) -> bool {
+        self.function_name() == Some(name) || self.children.iter().any(|c| c.contains_function(name))
+    }
+
+    /// Returns whether evaluating the tree always gives the same value, which isn't the case when
+    /// it draws random numbers. Impure trees can't be folded into constants or have like terms
+    /// combined, since `rand() - rand()` isn't zero.
+    pub fn is_pure(&self) -> bool {
+        !RANDOM_FUNCTIONS.iter().any(|name| self.contains_function(name))
+    }
+
+    /// Returns the name of the variable the node consists of, which the function needs to know
+    /// what to work with respect to.
+    pub fn variable_name(&self, function: &str) -> Result<String, EvalError> {
+        match self.token {
+            Token::Variable(ref name) => Ok(name.clone()),
+            _ => Err(EvalError::ExpectedVariable(function.to_string())),
+        }
+    }
+
+    /// Returns where a function like `integrate` takes its bound variable and the expression it's
+    /// bound in, if the function is called with enough arguments to bind one. `solve(A, b)` solves
+    /// a linear system instead, so it doesn't.
+    pub fn bound_arguments(&self) -> Option<Binding> {
+        let binding = BINDING_FUNCTIONS.get(self.function_name()?)?;
+        (self.children.len() >= binding.arguments).then_some(*binding)
+    }
+
+    /// Returns the variable bound by a function like `integrate`, and where the function takes
+    /// its arguments.
+    pub fn binding(&self) -> Option<(&str, Binding)> {
+        let binding = self.bound_arguments()?;
+
+        match self.children.get(binding.variable)?.token {
+            Token::Variable(ref name) => Some((name, binding)),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the value of the tree depends on the variable. Variables bound by a
+    /// function like `integrate` don't count inside the expression they're bound in.
+    pub fn contains_variable(&self, name: &str) -> bool {
+        match (&self.token, self.binding()) {
+            (Token::Variable(v), _) => v == name,
+            (_, Some((bound, _))) if bound == name => self
+                .children
+                .iter()
+                .enumerate()
+                .any(|(i, c)| !self.is_bound_child(i) && c.contains_variable(name)),
+            _ => self.children.iter().any(|c| c.contains_variable(name)),
+        }
+    }
+
+    /// Returns the names of all free variables in the tree, without duplicates.
+    pub fn free_variables(&self) -> Vec<String> {
+        let mut variables = vec![];
+        self.collect_variables(&mut variables);
+        variables.retain(|v| self.contains_variable(v));
+        variables
+    }
+
+    fn collect_variables(&self, variables: &mut Vec<String>) {
+        match self.token {
+            Token::Variable(ref v) if !variables.contains(v) => variables.push(v.clone()),
+            _ => self.children.iter().for_each(|c| c.collect_variables(variables)),
+        }
+    }
+
+    /// Returns whether the child is the bound variable or the expression it's bound in.
+    fn is_bound_child(&self, i: usize) -> bool {
+        self.binding()
+            .is_some_and(|(_, binding)| i == binding.expression || i == binding.variable)
+    }
+
+    /// Returns a copy of the tree with every free occurrence of the variable replaced by the
+    /// value.
+    pub fn substitute(&self, name: &str, value: &ASTNode) -> ASTNode {
+        let bound = self.binding().is_some_and(|(bound, _)| bound == name);
+
+        match self.token {
+            Token::Variable(ref v) if v == name => value.clone(),
+            _ => ASTNode {
+                token: self.token.clone(),
+                children: self
+                    .children
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| match bound && self.is_bound_child(i) {
+                        true => c.clone(),
+                        false => c.substitute(name, value),
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// How tightly the node binds when printed, used to decide where parentheses are needed.
+    fn precedence(&self) -> u8 {
+        match self.token {
+            Token::Operator(Operator::Range) => 0,
+            Token::Operator(Operator::BitOr) => 1,
+            Token::Operator(Operator::BitXor) => 2,
+            Token::Operator(Operator::BitAnd) => 3,
+            Token::Operator(Operator::Shift(_)) => 4,
+            Token::Operator(Operator::Additive(_)) => 5,
+            Token::Operator(Operator::Multiplicative(_)) => 6,
+            Token::Operator(Operator::ElementWise(ElementWise::Power)) => 9,
+            Token::Operator(Operator::ElementWise(_)) => 6,
+            Token::Operator(Operator::PlusMinus) => 7,
+            Token::Operator(Operator::Unary(_)) => 8,
+            Token::Operator(Operator::Exponential) => 9,
+            // Negative numbers are printed with a minus, so they bind like one.
+            Token::Number(n) if n < 0.0 => 8,
+            _ => 10,
+        }
+    }
+
+    /// Returns whether a multiplication can be printed without the `*`, like `2x` or `3sin(x)`.
+    fn is_implicit_product(&self) -> bool {
+        let starts_with_letter = |node: &ASTNode| {
+            matches!(
+                node.token,
+                Token::Variable(_) | Token::Constant(_) | Token::Function(_)
+            )
+        };
+
+        let [left, right] = &self.children[..] else {
+            return false;
+        };
+
+        matches!(left.token, Token::Number(n) if n >= 0.0)
+            && (starts_with_letter(right)
+                || matches!(right.token, Token::Operator(Operator::Exponential)
+                    if starts_with_letter(&right.children[0])))
+    }
+}
+
+impl ASTNode {
+    /// Writes the children as the arguments of a call, along with the closing parenthesis.
+    fn write_arguments(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, arg) in self.children.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for ASTNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Wraps the child in parentheses if it binds looser than the limit.
+        let child = |f: &mut fmt::Formatter<'_>, node: &ASTNode, limit: u8| {
+            if node.precedence() < limit {
+                write!(f, "({node})")
+            } else {
+                write!(f, "{node}")
+            }
+        };
+
+        match &self.token {
+            Token::Number(n) => write!(f, "{n}"),
+            Token::Integer(n) => write!(f, "{n}"),
+            Token::Decimal(d) => write!(f, "{d}"),
+            Token::Timestamp(t) => write!(f, "{t}"),
+            Token::Duration(d) => write!(f, "{d}"),
+            Token::Variable(name) => write!(f, "{name}"),
+            // Constants only store their value, so the name is looked up again.
+            Token::Constant(n) => match CONSTANTS.iter().find(|(_, c)| *c == n) {
+                Some((name, _)) => write!(f, "{name}"),
+                None => write!(f, "{n}"),
+            },
+            Token::Function((func, _)) => {
+                write!(f, "{}(", func.name)?;
+                self.write_arguments(f)
+            }
+            Token::Call((name, _)) => {
+                write!(f, "{name}(")?;
+                self.write_arguments(f)
+            }
+            Token::Matrix { columns, .. } => {
+                write!(f, "[")?;
+                for (i, element) in self.children.iter().enumerate() {
+                    match i {
+                        0 => {}
+                        i if i % columns == 0 => write!(f, "; ")?,
+                        _ => write!(f, ", ")?,
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, "]")
+            }
+            Token::List => {
+                write!(f, "{{")?;
+                for (i, element) in self.children.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, "}}")
+            }
+            Token::Operator(Operator::Unary(op)) => {
+                match op {
+                    Unary::Negate => write!(f, "-")?,
+                    Unary::Complement => write!(f, "~")?,
+                }
+                child(f, &self.children[0], 6)
+            }
+            Token::Operator(op) => {
+                let symbol = match op {
+                    Operator::Additive(Additive::Add) => " + ",
+                    Operator::Additive(Additive::Subtract) => " - ",
+                    Operator::Multiplicative(Multiplicative::Multiply) if self.is_implicit_product() => "",
+                    Operator::Multiplicative(Multiplicative::Multiply) => " * ",
+                    Operator::Multiplicative(Multiplicative::Divide) => " / ",
+                    Operator::Multiplicative(Multiplicative::Modulo) => " mod ",
+                    Operator::Exponential => "^",
+                    Operator::ElementWise(ElementWise::Multiply) => " .* ",
+                    Operator::ElementWise(ElementWise::Divide) => " ./ ",
+                    Operator::ElementWise(ElementWise::Power) => ".^",
+                    Operator::Range => "..",
+                    Operator::PlusMinus => " ± ",
+                    Operator::Shift(Shift::Left) => " << ",
+                    Operator::Shift(Shift::Right) => " >> ",
+                    Operator::BitAnd => " & ",
+                    Operator::BitOr => " | ",
+                    Operator::BitXor => " xor ",
+                    Operator::Unary(_) => unreachable!("Unary operators are handled above"),
+                };
+
+                // Exponents are right associative, everything else is left associative. Negative
+                // exponents are wrapped too, since `e^-x^2` is hard to read.
+                let precedence = self.precedence();
+                let (left_limit, right_limit) = match op {
+                    Operator::Exponential | Operator::ElementWise(ElementWise::Power) => {
+                        (precedence + 1, precedence)
+                    }
+                    _ => (precedence, precedence + 1),
+                };
+
+                child(f, &self.children[0], left_limit)?;
+                write!(f, "{symbol}")?;
+                child(f, &self.children[1], right_limit)
+            }
+            token => write!(f, "{token:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(expression: &str) -> ASTNode {
+        get_ast(&parse_expression(expression).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_implicit_multiplication_precedence() {
+        let ast = parse("2x^2");
+
+        assert_eq!(
+            ast,
+            ASTNode::operator(
+                Operator::Multiplicative(Multiplicative::Multiply),
+                vec![
+                    ASTNode::number(2.0),
+                    ASTNode::operator(
+                        Operator::Exponential,
+                        vec![ASTNode::variable("x"), ASTNode::number(2.0)]
+                    ),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let expressions = [
+            "2x^2 - 3sin(x) / (x + 1)",
+            "-(a - b) * c^(2 + d)",
+            "(2^3)^x - log(2, pi x)",
+            "[1, -x; 2^x, 4] .^ 2 ./ [a, b; c, d]",
+            "{1, x + 1, {}} + (1..n + 1)",
+            "2(-9.81 ± 0.02) / (l pm 0.1 - 1)",
+        ];
+
+        for expression in expressions {
+            let ast = parse(expression);
+            assert_eq!(parse(&ast.to_string()), ast, "{expression} printed as {ast}");
+        }
+    }
+
+    #[test]
+    fn test_bound_variables() {
+        let ast = parse("integrate(x y, x, 0, x)");
+
+        assert_eq!(ast.free_variables(), vec!["x", "y"]);
+        assert_eq!(
+            ast.substitute("x", &ASTNode::number(2.0)).to_string(),
+            "integrate(x * y, x, 0, 2)"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let ast = parse("((2x^2)) - ((3sin(x)) / (x + 1))");
+
+        assert_eq!(ast.to_string(), "2x^2 - 3sin(x) / (x + 1)");
+    }
 }
@@ -0,0 +1,491 @@
+use crate::{
+    ast::ASTNode,
+    distributions,
+    errors::EvalError,
+    eval::*,
+    integrate,
+    limit,
+    random::RANDOM_FUNCTIONS,
+    series::{self, Series, MAX_TERMS},
+    symbolic,
+    tokenize::*,
+};
+
+/// A value along with its partial derivatives with respect to each of the variables it was
+/// differentiated with respect to, in the same order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dual {
+    pub value: f64,
+    pub gradient: Vec<f64>,
+}
+
+impl Dual {
+    fn constant(value: f64, variables: usize) -> Dual {
+        Dual {
+            value,
+            gradient: vec![0.0; variables],
+        }
+    }
+}
+
+/// Evaluates the expression along with its gradient with respect to the variables, carrying the
+/// derivatives forward through every operation. The variables get their values from the scope.
+pub fn forward(ast: &ASTNode, scope: &Scope, variables: &[&str]) -> Result<Dual, EvalError> {
+    let n = variables.len();
+
+    match ast.token {
+        Token::Number(value) | Token::Constant(value) => return Ok(Dual::constant(value, n)),
+        Token::Integer(value) => return Ok(Dual::constant(value as f64, n)),
+        Token::Variable(ref name) => {
+            let mut dual = Dual::constant(evaluate_ast(ast, scope)?, n);
+            if let Some(i) = variables.iter().position(|v| v == name) {
+                dual.gradient[i] = 1.0;
+            }
+            return Ok(dual);
+        }
+        _ => {}
+    }
+
+    if ast.function_name().is_some_and(|name| symbolic::SYMBOLIC_FUNCTIONS.contains(&name)) {
+        return forward(&symbolic::expand(ast, scope)?, scope, variables);
+    }
+
+    if ast.bound_arguments().is_some() {
+        return forward_binding(ast, scope, variables);
+    }
+
+    let args = ast
+        .children
+        .iter()
+        .map(|child| forward(child, scope, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+    let (value, partials) = apply(ast, &values, scope)?;
+
+    let mut gradient = vec![0.0; n];
+    for (arg, partial) in args.iter().zip(partials) {
+        for (g, d) in gradient.iter_mut().zip(&arg.gradient) {
+            // Skipping arguments that don't change keeps an undefined partial, like the one for
+            // the exponent of a negative base, from turning the whole gradient into NaN.
+            if *d != 0.0 {
+                *g += partial * d;
+            }
+        }
+    }
+
+    Ok(Dual { value, gradient })
+}
+
+/// Evaluates the expression along with its gradient with respect to the variables by recording
+/// every operation on a tape and then going through it backwards. This takes one pass however
+/// many variables there are, where [`forward`] carries the whole gradient through every
+/// operation.
+pub fn reverse(ast: &ASTNode, scope: &Scope, variables: &[&str]) -> Result<Dual, EvalError> {
+    let mut tape = Tape::default();
+    for _ in variables {
+        tape.push(vec![]);
+    }
+
+    let (output, value) = tape.record(ast, scope, variables)?;
+
+    Ok(Dual {
+        value,
+        gradient: tape.gradient(output, variables.len()),
+    })
+}
+
+/// The operations an expression was evaluated with, each stored as the operations it took its
+/// arguments from and the partial derivative with respect to each of them. The first entries
+/// are the variables.
+#[derive(Debug, Default)]
+struct Tape {
+    nodes: Vec<Vec<(usize, f64)>>,
+}
+
+impl Tape {
+    fn push(&mut self, parents: Vec<(usize, f64)>) -> usize {
+        self.nodes.push(parents);
+        self.nodes.len() - 1
+    }
+
+    /// Evaluates the tree onto the tape, returning where its value ended up and the value.
+    fn record(
+        &mut self,
+        ast: &ASTNode,
+        scope: &Scope,
+        variables: &[&str],
+    ) -> Result<(usize, f64), EvalError> {
+        match ast.token {
+            Token::Number(value) | Token::Constant(value) => return Ok((self.push(vec![]), value)),
+            Token::Integer(value) => return Ok((self.push(vec![]), value as f64)),
+            Token::Variable(ref name) => {
+                let value = evaluate_ast(ast, scope)?;
+                return match variables.iter().position(|v| v == name) {
+                    Some(i) => Ok((i, value)),
+                    None => Ok((self.push(vec![]), value)),
+                };
+            }
+            _ => {}
+        }
+
+        if ast.function_name().is_some_and(|name| symbolic::SYMBOLIC_FUNCTIONS.contains(&name)) {
+            return self.record(&symbolic::expand(ast, scope)?, scope, variables);
+        }
+
+        // Functions that bind a variable are differentiated as a whole, and their gradient is
+        // recorded as coming straight from the variables.
+        if ast.bound_arguments().is_some() {
+            let dual = forward_binding(ast, scope, variables)?;
+            let parents = dual
+                .gradient
+                .iter()
+                .enumerate()
+                .filter(|(_, g)| **g != 0.0)
+                .map(|(i, g)| (i, *g))
+                .collect();
+            return Ok((self.push(parents), dual.value));
+        }
+
+        let mut indices = vec![];
+        let mut values = vec![];
+        for child in &ast.children {
+            let (index, value) = self.record(child, scope, variables)?;
+            indices.push(index);
+            values.push(value);
+        }
+
+        let (value, partials) = apply(ast, &values, scope)?;
+        Ok((self.push(indices.into_iter().zip(partials).collect()), value))
+    }
+
+    /// Returns the derivative of the output with respect to each of the variables.
+    fn gradient(&self, output: usize, variables: usize) -> Vec<f64> {
+        let mut adjoints = vec![0.0; self.nodes.len()];
+        adjoints[output] = 1.0;
+
+        for (i, parents) in self.nodes.iter().enumerate().rev() {
+            let adjoint = adjoints[i];
+            if adjoint == 0.0 {
+                continue;
+            }
+            for &(parent, partial) in parents {
+                adjoints[parent] += partial * adjoint;
+            }
+        }
+
+        adjoints.truncate(variables);
+        adjoints
+    }
+}
+
+/// Returns the value of the operator or function applied to the arguments, along with its partial
+/// derivative with respect to each argument. Functions that were given to a variable, like a
+/// spline, are looked up in the scope.
+pub fn apply(ast: &ASTNode, args: &[f64], scope: &Scope) -> Result<(f64, Vec<f64>), EvalError> {
+    if let Token::Matrix { .. } | Token::List | Token::Operator(Operator::Range) = ast.token {
+        return Err(EvalError::NotANumber(ast.to_string()));
+    }
+
+    if let Token::Call(_) = ast.token {
+        let (value, derivative) = called_function(ast, scope)?.evaluate(args[0])?;
+        return Ok((value, vec![derivative]));
+    }
+
+    if let Token::Function((ref func, _)) = ast.token {
+        // Random functions, some of which take no arguments, have no derivative.
+        let Some(&x) = args.first().filter(|_| !RANDOM_FUNCTIONS.contains(&func.name)) else {
+            return Err(EvalError::NotDifferentiable(func.name.to_string()));
+        };
+
+        return match func.name {
+            "sin" => Ok((x.sin(), vec![x.cos()])),
+            "cos" => Ok((x.cos(), vec![-x.sin()])),
+            "tan" => Ok((x.tan(), vec![1.0 / x.cos().powi(2)])),
+            "arcsin" => Ok((x.asin(), vec![1.0 / (1.0 - x * x).sqrt()])),
+            "arccos" => Ok((x.acos(), vec![-1.0 / (1.0 - x * x).sqrt()])),
+            "arctan" => Ok((x.atan(), vec![1.0 / (1.0 + x * x)])),
+            "ln" => Ok((x.ln(), vec![1.0 / x])),
+            "sqrt" => Ok((x.sqrt(), vec![0.5 / x.sqrt()])),
+            name if distributions::DISTRIBUTIONS.contains(&name) => Ok((
+                distributions::evaluate(name, x, &args[1..])?,
+                distributions::partials(name, x, &args[1..])?,
+            )),
+            "roots" | "polydiv" => Err(EvalError::NotANumber(func.name.to_string())),
+            "rotl" | "rotr" | "popcount" | "clz" | "ctz" => {
+                Err(EvalError::ProgrammerModeOnly(func.name.to_string()))
+            }
+            // log(b, y) = ln(y) / ln(b)
+            "log" => {
+                let (b, y) = (args[0], args[1]);
+                Ok((
+                    y.log(b),
+                    vec![-y.ln() / (b * b.ln().powi(2)), 1.0 / (y * b.ln())],
+                ))
+            }
+            name => Err(EvalError::NotDifferentiable(name.to_string())),
+        };
+    }
+
+    if let Token::Operator(Operator::Unary(op)) = ast.token {
+        return match op {
+            Unary::Negate => Ok((-args[0], vec![-1.0])),
+            Unary::Complement => Err(EvalError::ProgrammerModeOnly("~".to_string())),
+        };
+    }
+
+    if let Token::Operator(ref op) = ast.token {
+        let (a, b) = (args[0], args[1]);
+
+        return match op {
+            Operator::Additive(Additive::Add) => Ok((a + b, vec![1.0, 1.0])),
+            Operator::Additive(Additive::Subtract) => Ok((a - b, vec![1.0, -1.0])),
+            // The element-wise operators are the same as the usual ones on numbers.
+            Operator::Multiplicative(Multiplicative::Multiply)
+            | Operator::ElementWise(ElementWise::Multiply) => Ok((a * b, vec![b, a])),
+            Operator::Multiplicative(Multiplicative::Divide)
+            | Operator::ElementWise(ElementWise::Divide) => {
+                Ok((a / b, vec![1.0 / b, -a / (b * b)]))
+            }
+            // a mod b = a - b floor(a / b), where the floor is flat almost everywhere.
+            Operator::Multiplicative(Multiplicative::Modulo) => {
+                Ok(((a % b + b) % b, vec![1.0, -(a / b).floor()]))
+            }
+            Operator::Exponential | Operator::ElementWise(ElementWise::Power) => {
+                let value = a.powf(b);
+                Ok((value, vec![b * a.powf(b - 1.0), value * a.ln()]))
+            }
+            Operator::Shift(Shift::Left) => Err(EvalError::ProgrammerModeOnly("<<".to_string())),
+            Operator::Shift(Shift::Right) => Err(EvalError::ProgrammerModeOnly(">>".to_string())),
+            Operator::BitAnd => Err(EvalError::ProgrammerModeOnly("&".to_string())),
+            Operator::BitOr => Err(EvalError::ProgrammerModeOnly("|".to_string())),
+            Operator::BitXor => Err(EvalError::ProgrammerModeOnly("xor".to_string())),
+            // The uncertainty of a measurement doesn't change its value.
+            Operator::PlusMinus => Ok((a, vec![1.0, 0.0])),
+            Operator::Range | Operator::Unary(_) => unreachable!("Handled above"),
+        };
+    }
+
+    unreachable!("Invalid AST")
+}
+
+/// Differentiates a function that binds a variable, like `integrate`, by differentiating the
+/// expression it binds the variable in.
+fn forward_binding(ast: &ASTNode, scope: &Scope, variables: &[&str]) -> Result<Dual, EvalError> {
+    let name = ast.function_name().unwrap_or_default();
+    let binding = ast.bound_arguments().unwrap();
+    let var = ast.children[binding.variable].variable_name(name)?;
+    let expr = &ast.children[binding.expression];
+    let value = evaluate_ast(ast, scope)?;
+    let n = variables.len();
+
+    // The bound variable is a different variable inside the expression, even when it has the
+    // same name as one of the variables.
+    let inner_variables = variables
+        .iter()
+        .map(|&v| if v == var { "" } else { v })
+        .collect::<Vec<_>>();
+    let mut inner = scope.clone();
+    let mut at = |x: f64| {
+        inner.set(&var, x);
+        forward(expr, &inner, &inner_variables)
+    };
+
+    let mut gradient = vec![0.0; n];
+    let dependent = (0..n).filter(|&j| ast.contains_variable(variables[j])).collect::<Vec<_>>();
+
+    match name {
+        // Leibniz rule: the integral of the derivative, plus the change from moving the limits.
+        "integrate" => {
+            let a = forward(&ast.children[2], scope, variables)?;
+            let b = forward(&ast.children[3], scope, variables)?;
+            let tolerance = match ast.children.get(4) {
+                Some(tolerance) => evaluate_ast(tolerance, scope)?,
+                None => integrate::DEFAULT_TOLERANCE,
+            };
+
+            for &j in &dependent {
+                let partial = |x: f64| at(x).map(|dual| dual.gradient[j]);
+                gradient[j] = integrate::integrate(partial, a.value, b.value, tolerance)?.value;
+
+                if b.gradient[j] != 0.0 {
+                    gradient[j] += at(b.value)?.value * b.gradient[j];
+                }
+                if a.gradient[j] != 0.0 {
+                    gradient[j] -= at(a.value)?.value * a.gradient[j];
+                }
+            }
+        }
+        // Implicit function theorem: the root r of f(r) = 0 moves by -(df/dv) / (df/dr).
+        "solve" => {
+            let mut with_root = inner_variables.clone();
+            with_root.push(&var);
+            inner.set(&var, value);
+            let dual = forward(expr, &inner, &with_root)?;
+
+            for &j in &dependent {
+                gradient[j] = -dual.gradient[j] / dual.gradient[n];
+            }
+        }
+        // The limit of the derivative.
+        "limit" => {
+            let point = evaluate_ast(&ast.children[2], scope)?;
+            let direction = match ast.children.get(3) {
+                Some(direction) => evaluate_ast(direction, scope)?,
+                None => 0.0,
+            };
+
+            for &j in &dependent {
+                gradient[j] = limit::approach(|x| at(x).map(|dual| dual.gradient[j]), point, direction)?;
+            }
+        }
+        "sum" | "prod" => {
+            let series = Series::from_name(name).unwrap_or(Series::Sum);
+            let (a, b) = series::evaluate_limits(ast, scope)?;
+
+            match series {
+                // The sum of the derivatives. Differentiating doesn't raise the degree of a
+                // polynomial in the index, so long sums can still skip most terms.
+                Series::Sum => {
+                    let degree = series::polynomial_degree(expr, &var, scope);
+                    for &j in &dependent {
+                        let partial = |i: f64| at(i).map(|dual| dual.gradient[j]);
+                        gradient[j] = series::evaluate(Series::Sum, partial, a, b, || degree)?;
+                    }
+                }
+                // Product rule, applied one term at a time.
+                Series::Product if b.is_finite() => {
+                    if b - a + 1.0 > MAX_TERMS as f64 {
+                        return Err(EvalError::TooManyTerms { limit: MAX_TERMS });
+                    }
+
+                    let mut product = Dual::constant(1.0, n);
+                    let mut i = a;
+                    while i <= b {
+                        let term = at(i)?;
+                        for (g, d) in product.gradient.iter_mut().zip(&term.gradient) {
+                            *g = *g * term.value + product.value * d;
+                        }
+                        product.value *= term.value;
+                        i += 1.0;
+                    }
+
+                    gradient = product.gradient;
+                }
+                // The logarithmic derivative of an infinite product is the sum of the logarithmic
+                // derivatives of its terms.
+                Series::Product => {
+                    for &j in &dependent {
+                        let partial = |i: f64| at(i).map(|dual| dual.gradient[j] / dual.value);
+                        gradient[j] = value * series::evaluate(Series::Sum, partial, a, b, || None)?;
+                    }
+                }
+            }
+        }
+        name => return Err(EvalError::NotDifferentiable(name.to_string())),
+    }
+
+    Ok(Dual { value, gradient })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::get_ast, testing::assert_close, tokenize::parse_expression};
+
+    /// Differentiates the expression with both modes, checking that they agree.
+    fn gradient(expression: &str, values: &[(&str, f64)]) -> Dual {
+        let ast = get_ast(&parse_expression(expression).unwrap()).unwrap();
+        let mut scope = Scope::new();
+        for (name, value) in values {
+            scope.set(name, *value);
+        }
+        let variables = values.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+
+        let forward = forward(&ast, &scope, &variables).unwrap();
+        let reverse = reverse(&ast, &scope, &variables).unwrap();
+
+        assert_close(forward.value, reverse.value, 1e-8);
+        for (f, r) in forward.gradient.iter().zip(&reverse.gradient) {
+            assert_close(*f, *r, 1e-8);
+        }
+        forward
+    }
+
+    #[test]
+    fn test_polynomial() {
+        let dual = gradient("x^2 y + 3x - y", &[("x", 2.0), ("y", 5.0)]);
+
+        assert_eq!(dual.value, 21.0);
+        assert_eq!(dual.gradient, vec![23.0, 3.0]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let dual = gradient("sin(x) ln(y) + sqrt(x y) - log(2, y)", &[("x", 1.0), ("y", 4.0)]);
+
+        assert_close(dual.gradient[0], 1f64.cos() * 4f64.ln() + 1.0, 1e-8);
+        assert_close(dual.gradient[1], 1f64.sin() / 4.0 + 0.25 - 1.0 / (4.0 * 2f64.ln()), 1e-8);
+    }
+
+    #[test]
+    fn test_negative_base() {
+        let dual = gradient("x^3", &[("x", -2.0)]);
+
+        assert_eq!(dual.gradient, vec![12.0]);
+    }
+
+    #[test]
+    fn test_repeated_input() {
+        // The tape has to add up the contributions from every use of `x`.
+        let dual = gradient("x x x + x / x", &[("x", 3.0)]);
+
+        assert_close(dual.gradient[0], 27.0, 1e-8);
+    }
+
+    #[test]
+    fn test_integral() {
+        let dual = gradient("integrate(x a, x, 0, b)", &[("a", 3.0), ("b", 2.0), ("x", 7.0)]);
+
+        assert_close(dual.value, 6.0, 1e-8);
+        assert_eq!(dual.gradient.len(), 3);
+        assert_close(dual.gradient[0], 2.0, 1e-8);
+        assert_close(dual.gradient[1], 6.0, 1e-8);
+        assert_close(dual.gradient[2], 0.0, 1e-8);
+    }
+
+    #[test]
+    fn test_solve() {
+        let dual = gradient("solve(x^2 - a, x, 1)", &[("a", 4.0)]);
+
+        assert_close(dual.value, 2.0, 1e-8);
+        assert_close(dual.gradient[0], 0.25, 1e-8);
+    }
+
+    #[test]
+    fn test_series() {
+        let sum = gradient("sum(i, 1, 10, y i^2)", &[("y", 2.0)]);
+        let prod = gradient("prod(i, 1, 3, i + y)", &[("y", 1.0)]);
+        let geometric = gradient("sum(k, 0, inf, r^k)", &[("r", 0.5)]);
+
+        assert_close(sum.gradient[0], 385.0, 1e-8);
+        assert_close(prod.gradient[0], 26.0, 1e-8);
+        assert_close(geometric.gradient[0], 4.0, 1e-8);
+    }
+
+    #[test]
+    fn test_random_functions() {
+        let ast = get_ast(&parse_expression("x^2 + rand()").unwrap()).unwrap();
+        let mut scope = Scope::new();
+        scope.set("x", 1.0);
+
+        assert_eq!(reverse(&ast, &scope, &["x"]), Err(EvalError::NotDifferentiable("rand".to_string())));
+        assert_eq!(forward(&ast, &scope, &["x"]), Err(EvalError::NotDifferentiable("rand".to_string())));
+    }
+
+    #[test]
+    fn test_limit() {
+        let dual = gradient("limit(sin(a x) / x, x, 0)", &[("a", 2.0)]);
+
+        assert_close(dual.gradient[0], 1.0, 1e-8);
+    }
+}
@@ -0,0 +1,453 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    ast, decimal::*, fraction, identify, errors::EvalError, eval::{self, Scope}, integrate, modular::{self, Residue}, montecarlo, programmer::*, symbolic,
+    tokenize::{self, Token},
+    value::Value,
+};
+
+/// The kind of numbers expressions are evaluated with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Float,
+    Programmer(IntFormat),
+    Decimal(DecimalFormat),
+    /// Integers modulo the given number.
+    Modular(u64),
+}
+
+/// Holds the settings and variables that last between expressions entered in the REPL.
+#[derive(Debug, Clone)]
+pub struct Calculator {
+    pub mode: Mode,
+    /// Whether results that are very close to a simple fraction are shown along with it.
+    pub fractions: bool,
+    /// Whether results are followed by a closed form they're recognized as, like `pi/4`.
+    pub identify: bool,
+    /// The variables defined with `x = expr`, and the generator of random numbers.
+    scope: Scope,
+}
+
+impl Calculator {
+    /// Creates a calculator whose random numbers are seeded from the clock.
+    pub fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Calculator::with_seed(seed)
+    }
+
+    /// Creates a calculator whose random numbers are always the same.
+    pub fn with_seed(seed: u64) -> Self {
+        Calculator {
+            mode: Mode::Float,
+            fractions: false,
+            identify: false,
+            scope: Scope::with_seed(seed),
+        }
+    }
+
+    /// Evaluates the expression in the current mode. Expressions with symbolic functions like
+    /// `diff` that still depend on a variable are returned as expressions instead of numbers,
+    /// integrals are returned along with their error estimate, simulations with `montecarlo` are
+    /// summarized, functions like `roots` that give several values return a list, and matrices
+    /// stay matrices.
+    ///
+    /// `x = expr` defines a variable as the value of the expression and returns it. A variable
+    /// defined with random functions, like `x = randn(10, 0.1)`, keeps its value until it's used
+    /// in `montecarlo`, which draws it again for every sample. A variable defined as a function,
+    /// like `f = spline(xs, ys)`, can be called like `f(2.5)`.
+    ///
+    /// Several statements can be separated by semicolons, like `f = pchip(xs, ys); f(2.5)`. They're
+    /// evaluated in order and the value of the last one is returned.
+    pub fn evaluate(&mut self, input: &str) -> Result<Value, EvalError> {
+        let mut result = Err(EvalError::InvalidExpression("Missing operand".to_string()));
+        for statement in statements(input).into_iter().filter(|s| !s.trim().is_empty()) {
+            result = Ok(self.evaluate_statement(statement)?);
+        }
+        result
+    }
+
+    /// Shows a result the way the REPL prints it. When identifying is turned on, a number that
+    /// isn't an integer is followed by the closed form it's recognized as, if any, like
+    /// `0.7853981633974483 = pi/4`. Otherwise when fractions are turned on, a number that's very
+    /// close to a simple fraction is followed by it, like `0.142857142857 ≈ 1/7`, or `0.75 = 3/4`
+    /// when they're equal.
+    pub fn display(&self, value: &Value) -> String {
+        let Value::Number(x) = *value else {
+            return value.to_string();
+        };
+
+        if self.identify && x.fract() != 0.0 {
+            if let Ok(identification) = identify::identify(x) {
+                return identification.to_string();
+            }
+        }
+
+        match fraction::simple(x).filter(|_| self.fractions) {
+            Some(fraction) if fraction.to_f64() == x => format!("{x} = {fraction}"),
+            Some(fraction) => format!("{x} ≈ {fraction}"),
+            None => x.to_string(),
+        }
+    }
+
+    fn evaluate_statement(&mut self, expr: &str) -> Result<Value, EvalError> {
+        if let Some((name, expr)) = expr.split_once('=') {
+            return self.define(name, expr);
+        }
+
+        let mut ast = self.parse(expr)?;
+
+        if let Mode::Programmer(format) = self.mode {
+            return evaluate_int(&ast, &format).map(Value::Integer);
+        }
+
+        if let Mode::Decimal(format) = self.mode {
+            return evaluate_decimal(&ast, &format, &self.scope).map(Value::Decimal);
+        }
+
+        if let Mode::Modular(modulus) = self.mode {
+            let value = modular::evaluate_mod(&ast, modulus, &self.scope)?;
+            return Ok(Value::Residue(Residue { value, modulus }));
+        }
+
+        if symbolic::is_symbolic(&ast) {
+            ast = symbolic::expand(&ast, &self.scope)?;
+            if ast.free_variables().iter().any(|name| self.scope.get(name).is_none()) {
+                return Ok(Value::Expression(ast));
+            }
+        }
+
+        if ast.function_name() == Some("integrate") {
+            let integral = integrate::evaluate_integral(&ast, &self.scope)?;
+            return Ok(Value::Estimate {
+                value: integral.value,
+                error: integral.error,
+            });
+        }
+
+        if ast.function_name() == Some("montecarlo") {
+            let simulation = montecarlo::evaluate_montecarlo(&ast, &self.scope)?;
+            return Ok(Value::Simulation(simulation));
+        }
+
+        eval::evaluate(&ast, &self.scope)
+    }
+
+    fn define(&mut self, name: &str, expr: &str) -> Result<Value, EvalError> {
+        let variable = tokenize::parse_expression(name)?;
+        let [Token::Variable(name)] = variable.as_slice() else {
+            return Err(EvalError::InvalidOperation(format!(
+                "Only a variable can be given a value, not {:?}",
+                name.trim()
+            )));
+        };
+
+        let ast = self.parse(expr)?;
+        match self.mode {
+            Mode::Programmer(_) => Err(EvalError::NotInProgrammerMode("=".to_string())),
+            // Decimals are kept as they are, so they stay exact when they're used in decimal mode
+            // again.
+            Mode::Decimal(format) => {
+                let value = Value::Decimal(evaluate_decimal(&ast, &format, &self.scope)?);
+                self.scope.assign(name, value.clone());
+                Ok(value)
+            }
+            Mode::Modular(_) => Err(EvalError::NotInModularMode("=".to_string())),
+            Mode::Float => self.scope.define(name, &ast),
+        }
+    }
+
+    /// Parses the expression, where variables that hold a function can be called. Literals are
+    /// parsed into decimals in decimal mode, and in modular mode so long integers stay exact, and
+    /// into integers in programmer mode.
+    fn parse(&self, expr: &str) -> Result<ast::ASTNode, EvalError> {
+        let tokens = match self.mode {
+            Mode::Decimal(_) | Mode::Modular(_) => tokenize::parse_decimal_expression(expr)?,
+            Mode::Programmer(_) => tokenize::parse_integer_expression(expr)?,
+            _ => tokenize::parse_expression(expr)?,
+        };
+        let is_function = |name: &str| matches!(self.scope.get(name), Some(Value::Function(_)));
+        ast::get_ast(&tokenize::parse_calls(&tokens, &is_function))
+    }
+
+    /// Runs a REPL command, the part of the input after the `:`. Returns a message describing what
+    /// changed.
+    ///
+    /// - `float` switches back to floating point numbers.
+    /// - `prog <type> [wrap|saturate|trap]` switches to programmer mode, where every value is an
+    ///   integer of the given type, e.g. `u8` or `i64`. Overflow wraps by default.
+    /// - `decimal [rounding] [digits]` switches to decimal mode, where numbers are base 10 with 34
+    ///   significant digits, or fewer if given. Results are rounded half to even by default, or
+    ///   `half-up`, `half-down`, `up`, `down`, `ceiling` or `floor`.
+    /// - `mod <n>` switches to modular mode, where every value is an integer modulo `n`. `/`
+    ///   multiplies by the modular inverse, and variables defined in float mode can be used if
+    ///   they're integers.
+    /// - `fractions [on|off]` turns showing results as simple fractions on or off, or toggles it.
+    /// - `identify [on|off]` turns showing the closed forms results are recognized as on or off,
+    ///   or toggles it.
+    /// - `seed <n>` restarts the random numbers from the given seed, so they can be reproduced.
+    pub fn run_command(&mut self, command: &str) -> Result<String, EvalError> {
+        let unknown = || EvalError::UnknownCommand(command.to_string());
+        let mut words = command.split_whitespace();
+
+        match words.next() {
+            Some("float") => {
+                self.mode = Mode::Float;
+                Ok("Switched to float mode".to_string())
+            }
+            Some("prog") => {
+                let name = words.next().ok_or_else(unknown)?;
+                let int_type = IntType::parse(name).ok_or_else(unknown)?;
+                let overflow = match words.next() {
+                    Some(name) => Overflow::parse(name).ok_or_else(unknown)?,
+                    None => Overflow::Wrap,
+                };
+
+                self.mode = Mode::Programmer(IntFormat { int_type, overflow });
+                Ok(format!("Switched to programmer mode with {name} integers ({overflow:?} on overflow)"))
+            }
+            Some("decimal") => {
+                let mut format = DecimalFormat::default();
+                for word in words {
+                    match (Rounding::parse(word), word.parse::<u32>()) {
+                        (Some(rounding), _) => format.rounding = rounding,
+                        (None, Ok(precision @ 1..=MAX_PRECISION)) => format.precision = precision,
+                        _ => return Err(unknown()),
+                    }
+                }
+
+                self.mode = Mode::Decimal(format);
+                Ok(format!(
+                    "Switched to decimal mode with {} digits ({:?} rounding)",
+                    format.precision, format.rounding
+                ))
+            }
+            Some("mod") => {
+                let modulus = words.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0).ok_or_else(unknown)?;
+                self.mode = Mode::Modular(modulus);
+                Ok(format!("Switched to modular mode, where numbers are integers mod {modulus}"))
+            }
+            Some("fractions") => {
+                self.fractions = toggle(self.fractions, words.next()).ok_or_else(unknown)?;
+                Ok(format!("Fractions are {}", if self.fractions { "shown" } else { "hidden" }))
+            }
+            Some("identify") => {
+                self.identify = toggle(self.identify, words.next()).ok_or_else(unknown)?;
+                Ok(format!("Closed forms are {}", if self.identify { "shown" } else { "hidden" }))
+            }
+            Some("seed") => {
+                let seed = words.next().and_then(|seed| seed.parse().ok()).ok_or_else(unknown)?;
+                self.scope.reseed(seed);
+                Ok(format!("Seeded the random numbers with {seed}"))
+            }
+            _ => Err(unknown()),
+        }
+    }
+}
+
+/// The new value of a setting turned `on` or `off`, or toggled when neither is given.
+fn toggle(setting: bool, word: Option<&str>) -> Option<bool> {
+    match word {
+        Some("on") => Some(true),
+        Some("off") => Some(false),
+        Some(_) => None,
+        None => Some(!setting),
+    }
+}
+
+/// Splits the input into the statements separated by semicolons. Semicolons inside brackets
+/// separate the rows of a matrix or the arguments of a function instead.
+fn statements(input: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ';' if depth == 0 => {
+                statements.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    statements.push(&input[start..]);
+    statements
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_definitions() {
+        let mut calculator = Calculator::with_seed(1);
+
+        assert_eq!(calculator.evaluate("x = 3"), Ok(Value::Number(3.0)));
+        assert_eq!(calculator.evaluate("y = 2x + 1"), Ok(Value::Number(7.0)));
+        assert_eq!(calculator.evaluate("x = x + 1"), Ok(Value::Number(4.0)));
+        assert_eq!(calculator.evaluate("x y"), Ok(Value::Number(28.0)));
+        assert!(calculator.evaluate("2 = x").is_err());
+    }
+
+    #[test]
+    fn test_names_with_digits() {
+        let mut calculator = Calculator::with_seed(1);
+
+        let Ok(Value::Number(p)) = calculator.evaluate("chi2cdf(2, 2)") else { panic!() };
+        assert_close(p, 1.0 - (-1.0f64).exp(), 1e-12);
+        assert_eq!(calculator.evaluate("x1 = 3"), Ok(Value::Number(3.0)));
+        assert_eq!(calculator.evaluate("2x1"), Ok(Value::Number(6.0)));
+        assert_eq!(calculator.evaluate("e2"), Ok(Value::Number(2.0 * std::f64::consts::E)));
+        assert_eq!(calculator.evaluate("2pi2"), Ok(Value::Number(4.0 * std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn test_seed() {
+        let mut a = Calculator::with_seed(7);
+        let mut b = Calculator::with_seed(7);
+        let first = a.evaluate("rand()").unwrap();
+
+        assert_eq!(b.evaluate("rand()"), Ok(first.clone()));
+        assert_ne!(a.evaluate("rand()"), Ok(first.clone()));
+
+        a.run_command("seed 7").unwrap();
+        assert_eq!(a.evaluate("rand()"), Ok(first));
+    }
+
+    #[test]
+    fn test_montecarlo() {
+        let mut calculator = Calculator::with_seed(3);
+        calculator.evaluate("length = randn(100, 0.5)").unwrap();
+        calculator.evaluate("width = randn(20, 0.2)").unwrap();
+
+        let Ok(Value::Simulation(simulation)) = calculator.evaluate("montecarlo(length - width, 20000)") else {
+            panic!("Expected a simulation");
+        };
+
+        assert_close(simulation.mean, 80.0, 0.02);
+        assert_close(simulation.stdev, 0.29f64.sqrt(), 0.02);
+    }
+
+    #[test]
+    fn test_correlated_uncertainty() {
+        let mut calculator = Calculator::with_seed(1);
+        calculator.evaluate("x = 2 ± 0.1").unwrap();
+        calculator.evaluate("y = 3 ± 0.1").unwrap();
+
+        assert_eq!(calculator.evaluate("x - x").unwrap().to_string(), "0 ± 0");
+        assert_eq!(calculator.evaluate("x / x + y").unwrap().to_string(), "4.00 ± 0.10");
+        assert_eq!(calculator.evaluate("x + y").unwrap().to_string(), "5.00 ± 0.14");
+    }
+
+    #[test]
+    fn test_decimal_mode() {
+        let mut calculator = Calculator::with_seed(1);
+        calculator.evaluate("r = 0.1").unwrap();
+        calculator.evaluate("xs = {1, 2}").unwrap();
+        calculator.run_command("decimal").unwrap();
+        let decimal = |calculator: &mut Calculator, expression: &str| calculator.evaluate(expression).map(|v| v.to_string());
+
+        assert_eq!(decimal(&mut calculator, "x = 0.1"), Ok("0.1".to_string()));
+        assert_eq!(decimal(&mut calculator, "x + 0.2"), Ok("0.3".to_string()));
+        assert_eq!(decimal(&mut calculator, "r * 3"), Ok("0.3".to_string()));
+        assert_eq!(decimal(&mut calculator, "npv(0.5, {3, 4.5})"), Ok("4".to_string()));
+        assert_eq!(decimal(&mut calculator, "sum(i, 1, 10, 0.1)"), Err(EvalError::NotInDecimalMode("sum".to_string())));
+        assert_eq!(decimal(&mut calculator, "xs + 1"), Err(EvalError::NotInDecimalMode("xs".to_string())));
+
+        calculator.run_command("float").unwrap();
+        assert_eq!(calculator.evaluate("x"), Ok(Value::Number(0.1)));
+    }
+
+    #[test]
+    fn test_modular_mode() {
+        let mut calculator = Calculator::with_seed(1);
+        calculator.evaluate("k = 65537").unwrap();
+
+        assert_eq!(calculator.evaluate("(3^200) mod 97"), Ok(Value::Number(62.0)));
+        assert_eq!(calculator.evaluate("-7 mod 3"), Ok(Value::Number(2.0)));
+        assert_eq!(calculator.evaluate("7.5 mod 2"), Ok(Value::Number(1.5)));
+        assert_eq!(calculator.evaluate("(3^200) mod -5"), Ok(Value::Number(-4.0)));
+        assert_eq!(calculator.evaluate("(3^200) mod -3"), Ok(Value::Number(0.0)));
+        assert_eq!(calculator.evaluate("7.5 mod -2"), Ok(Value::Number(-0.5)));
+        assert_eq!(calculator.evaluate("(3^200) mod 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(calculator.evaluate("7.5 mod 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(calculator.evaluate("inv(k, 3120)"), Ok(Value::Number(2753.0)));
+        assert_eq!(calculator.evaluate("crt({2, 3, 2}, {3, 5, 7})"), Ok(Value::Number(23.0)));
+        assert_eq!(calculator.evaluate("discretelog(2, 3)"), Err(EvalError::InvalidArgumentCount { expected: 3, got: 2 }));
+
+        calculator.run_command("mod 97").unwrap();
+        assert_eq!(calculator.evaluate("3^200").unwrap().to_string(), "62 (mod 97)");
+        assert_eq!(calculator.evaluate("1 / 2 + 100").unwrap().to_string(), "52 (mod 97)");
+        assert_eq!(calculator.evaluate("inv(2) * 2 - 1").unwrap().to_string(), "0 (mod 97)");
+        assert_eq!(calculator.evaluate("discretelog(5, 5^43)").unwrap().to_string(), "43 (mod 97)");
+        assert_eq!(calculator.evaluate("3^k").unwrap().to_string(), format!("{} (mod 97)", modular::power(3, 65537, 97).unwrap()));
+        assert_eq!(calculator.evaluate("1 / 97"), Err(EvalError::NoInverse { value: 0, modulus: 97 }));
+        assert!(calculator.evaluate("x = 3").is_err());
+        assert!(calculator.run_command("mod 0").is_err());
+    }
+
+    #[test]
+    fn test_fractions() {
+        let mut calculator = Calculator::with_seed(1);
+
+        assert_eq!(calculator.evaluate("rat(pi, 1000)").unwrap().to_string(), "355/113");
+        assert_eq!(calculator.evaluate("113 rat(pi, 1000)"), Ok(Value::Number(355.0)));
+        assert_eq!(calculator.evaluate("cf(sqrt(7), 5)").unwrap().to_string(), "{2, 1, 1, 1, 4}");
+
+        let x = calculator.evaluate("0.142857142857").unwrap();
+        assert_eq!(calculator.display(&x), "0.142857142857");
+        calculator.run_command("fractions").unwrap();
+        assert_eq!(calculator.display(&x), "0.142857142857 ≈ 1/7");
+        assert_eq!(calculator.display(&Value::Number(-0.75)), "-0.75 = -3/4");
+        assert_eq!(calculator.display(&Value::Number(2.0)), "2");
+        calculator.run_command("fractions off").unwrap();
+        assert_eq!(calculator.display(&x), "0.142857142857");
+    }
+
+    #[test]
+    fn test_identify() {
+        let mut calculator = Calculator::with_seed(1);
+
+        let Ok(Value::Identification(identification)) = calculator.evaluate("identify(arctan(1))") else {
+            panic!("Expected an identification");
+        };
+        assert_eq!(identification.formula, "pi/4");
+        assert_eq!(calculator.evaluate("4 identify(arctan(1))"), Ok(Value::Number(std::f64::consts::PI)));
+        assert!(calculator.evaluate("identify(0.5772156649015329)").is_err());
+
+        let x = calculator.evaluate("(1 + sqrt(5)) / 2").unwrap();
+        calculator.run_command("identify on").unwrap();
+        calculator.run_command("fractions on").unwrap();
+        assert!(calculator.display(&x).starts_with("1.618033988749895 = (1 + sqrt(5))/2 (chance"));
+        assert_eq!(calculator.display(&Value::Number(0.142857142857)), "0.142857142857 ≈ 1/7 (chance of a coincidence below 1e-9)");
+        assert_eq!(calculator.display(&Value::Number(2.0)), "2");
+    }
+
+    #[test]
+    fn test_function_values() {
+        let mut calculator = Calculator::with_seed(1);
+
+        assert_eq!(calculator.evaluate("f = spline({0, 1, 2}, {0, 1, 0}); f(0.5)"), Ok(Value::Number(0.6875)));
+        assert_eq!(calculator.evaluate("2f(1) + f({0, 2})"), Ok(Value::List(vec![Value::Number(2.0), Value::Number(2.0)])));
+        assert_eq!(calculator.evaluate("x = 3; x(2)"), Ok(Value::Number(6.0)));
+        assert_eq!(calculator.evaluate("f(1, 2)"), Err(EvalError::InvalidArgumentCount { expected: 1, got: 2 }));
+        assert!(calculator.evaluate("f(3)").is_err());
+
+        let Ok(Value::Estimate { value, .. }) = calculator.evaluate("integrate(f(t), t, 0, 2)") else {
+            panic!("Expected an estimate");
+        };
+        assert_close(value, 1.25, 1e-9);
+    }
+}
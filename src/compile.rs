@@ -0,0 +1,156 @@
+use crate::{ast::ASTNode, eval::Scope, simplify::simplify, tokenize::*, value::Value};
+
+/// One step of a compiled expression, which works on a stack of numbers.
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    Constant(f64),
+    /// Pushes the value at the index of the variables the program was compiled with.
+    Variable(usize),
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+}
+
+/// An expression compiled to instructions for a stack machine. Evaluating it is much faster than
+/// walking the tree, which matters when the same expression is evaluated many times with different
+/// values, like the right-hand side of a differential equation.
+#[derive(Debug, Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    stack: Vec<f64>,
+}
+
+impl Program {
+    /// Compiles the expression of numbers in the variables. Other variables get their values from
+    /// the scope. Returns `None` when the expression uses something that can't be compiled, like
+    /// a matrix, a random number or a function that binds a variable, in which case the tree has
+    /// to be evaluated instead. The expression is simplified first, so constant parts are folded
+    /// once instead of being worked out on every evaluation.
+    pub fn compile(ast: &ASTNode, variables: &[&str], scope: &Scope) -> Option<Program> {
+        let mut instructions = vec![];
+        compile_node(&simplify(ast), variables, scope, &mut instructions)?;
+
+        Some(Program {
+            instructions,
+            stack: vec![],
+        })
+    }
+
+    /// Evaluates the program with the values of the variables, in the order it was compiled with.
+    pub fn evaluate(&mut self, values: &[f64]) -> f64 {
+        let stack = &mut self.stack;
+        stack.clear();
+
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::Constant(n) => stack.push(n),
+                Instruction::Variable(i) => stack.push(values[i]),
+                Instruction::Unary(f) => {
+                    let a = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(f(a));
+                }
+                Instruction::Binary(f) => {
+                    let b = stack.pop().unwrap_or(f64::NAN);
+                    let a = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(f(a, b));
+                }
+            }
+        }
+
+        stack.pop().unwrap_or(f64::NAN)
+    }
+}
+
+/// Appends the instructions that leave the value of the node on the stack.
+fn compile_node(
+    ast: &ASTNode,
+    variables: &[&str],
+    scope: &Scope,
+    instructions: &mut Vec<Instruction>,
+) -> Option<()> {
+    let instruction = match &ast.token {
+        Token::Number(n) | Token::Constant(n) => Instruction::Constant(*n),
+        Token::Integer(n) => Instruction::Constant(*n as f64),
+        Token::Variable(name) => match variables.iter().position(|v| v == name) {
+            Some(i) => Instruction::Variable(i),
+            None => match scope.get(name)? {
+                Value::Number(n) => Instruction::Constant(*n),
+                _ => return None,
+            },
+        },
+        Token::Function((func, _)) => match func.name {
+            "sin" => Instruction::Unary(f64::sin),
+            "cos" => Instruction::Unary(f64::cos),
+            "tan" => Instruction::Unary(f64::tan),
+            "arcsin" => Instruction::Unary(f64::asin),
+            "arccos" => Instruction::Unary(f64::acos),
+            "arctan" => Instruction::Unary(f64::atan),
+            "ln" => Instruction::Unary(f64::ln),
+            "sqrt" => Instruction::Unary(f64::sqrt),
+            "log" => Instruction::Binary(|b, y| y.log(b)),
+            _ => return None,
+        },
+        Token::Operator(op) => match op {
+            Operator::Unary(Unary::Negate) => Instruction::Unary(|a| -a),
+            Operator::Additive(Additive::Add) => Instruction::Binary(|a, b| a + b),
+            Operator::Additive(Additive::Subtract) => Instruction::Binary(|a, b| a - b),
+            Operator::Multiplicative(Multiplicative::Multiply)
+            | Operator::ElementWise(ElementWise::Multiply) => Instruction::Binary(|a, b| a * b),
+            Operator::Multiplicative(Multiplicative::Divide)
+            | Operator::ElementWise(ElementWise::Divide) => Instruction::Binary(|a, b| a / b),
+            Operator::Exponential | Operator::ElementWise(ElementWise::Power) => {
+                Instruction::Binary(f64::powf)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    for child in &ast.children {
+        compile_node(child, variables, scope, instructions)?;
+    }
+    instructions.push(instruction);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::get_ast, eval::evaluate_ast, tokenize::parse_expression};
+
+    #[test]
+    fn test_matches_tree() {
+        let ast = get_ast(&parse_expression("-x^2 / 3 + sin(k y) - log(2, y) sqrt(x)").unwrap()).unwrap();
+        let mut scope = Scope::new();
+        scope.set("k", 0.5);
+        let mut program = Program::compile(&ast, &["x", "y"], &scope).unwrap();
+
+        for (x, y) in [(1.0, 2.0), (4.0, 0.5), (0.25, 9.0)] {
+            scope.set("x", x);
+            scope.set("y", y);
+            assert_eq!(program.evaluate(&[x, y]), evaluate_ast(&ast, &scope).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_constants_folded() {
+        let ast = get_ast(&parse_expression("x (2 * 3 + sin(pi)) + 0 y").unwrap()).unwrap();
+        let mut program = Program::compile(&ast, &["x", "y"], &Scope::new()).unwrap();
+
+        // Only `6`, `x` and the multiplication are left.
+        assert_eq!(program.instructions.len(), 3);
+        assert_eq!(program.evaluate(&[2.0, 5.0]), 12.0);
+    }
+
+    #[test]
+    fn test_not_compiled() {
+        let compile = |expr: &str| {
+            let ast = get_ast(&parse_expression(expr).unwrap()).unwrap();
+            Program::compile(&ast, &["x"], &Scope::new()).is_some()
+        };
+
+        assert!(compile("x e^x"));
+        assert!(!compile("x + randn()"));
+        assert!(!compile("integrate(t x, t, 0, 1)"));
+        assert!(!compile("x + unknown"));
+    }
+}
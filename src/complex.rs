@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+/// A complex number `re + im i`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn real(re: f64) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+
+    /// Returns the complex number with the given magnitude and angle.
+    pub fn polar(r: f64, theta: f64) -> Complex {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    /// The principal square root, which has a non-negative real part.
+    pub fn sqrt(self) -> Complex {
+        let r = self.abs();
+        Complex::new(
+            ((r + self.re) / 2.0).sqrt(),
+            ((r - self.re) / 2.0).sqrt().copysign(self.im),
+        )
+    }
+
+    /// The principal cube root. Real numbers get their real cube root, so the cube root of -8 is
+    /// -2 rather than `1 + 1.732i`.
+    pub fn cbrt(self) -> Complex {
+        if self.im == 0.0 {
+            return Complex::real(self.re.cbrt());
+        }
+        Complex::polar(self.abs().cbrt(), self.arg() / 3.0)
+    }
+
+    pub fn powi(self, n: i32) -> Complex {
+        Complex::polar(self.abs().powi(n), self.arg() * n as f64)
+    }
+
+    /// Returns whether the imaginary part is negligible next to the magnitude.
+    pub fn is_real(self, tolerance: f64) -> bool {
+        self.im.abs() <= tolerance * self.abs().max(1.0)
+    }
+}
+
+/// Sorts real numbers before complex ones, and each by their real and then imaginary parts.
+pub fn sort(values: &mut [Complex]) {
+    values.sort_by(|a, b| {
+        (a.im != 0.0)
+            .cmp(&(b.im != 0.0))
+            .then(a.re.total_cmp(&b.re))
+            .then(a.im.total_cmp(&b.im))
+    });
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Complex {
+        Complex::real(re)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.re * other.re + other.im * other.im;
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: f64) -> Complex {
+        Complex::new(self.re * other, self.im * other)
+    }
+}
+
+impl Div<f64> for Complex {
+    type Output = Complex;
+
+    fn div(self, other: f64) -> Complex {
+        Complex::new(self.re / other, self.im / other)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.im.is_sign_negative() { '-' } else { '+' };
+
+        match (self.re, self.im.abs()) {
+            (_, 0.0) => write!(f, "{}", self.re),
+            (0.0, 1.0) => write!(f, "{}i", if sign == '-' { "-" } else { "" }),
+            (0.0, _) => write!(f, "{}i", self.im),
+            (_, 1.0) => write!(f, "{} {sign} i", self.re),
+            (_, im) => write!(f, "{} {sign} {im}i", self.re),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+
+        assert_eq!(a + b, Complex::new(4.0, 1.0));
+        assert_eq!(a * b, Complex::new(5.0, 5.0));
+        assert_eq!((a * b) / b, a);
+    }
+
+    #[test]
+    fn test_roots() {
+        assert_eq!(Complex::real(-4.0).sqrt().to_string(), "2i");
+        assert_eq!(Complex::real(-8.0).cbrt(), Complex::real(-2.0));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Complex::new(1.5, -2.0).to_string(), "1.5 - 2i");
+        assert_eq!(Complex::new(0.0, -1.0).to_string(), "-i");
+        assert_eq!(Complex::new(3.0, 1.0).to_string(), "3 + i");
+    }
+}
@@ -0,0 +1,421 @@
+use std::{
+    fmt,
+    iter::Peekable,
+    str::Chars,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{ast::ASTNode, errors::EvalError, eval::*, tokenize::*, value::Value};
+
+const DAY: f64 = 86_400.0;
+
+/// The units a duration like `3d 4h` or `90min` can be written in, with their length in seconds.
+pub const UNITS: [(&str, f64); 5] = [("w", 7.0 * DAY), ("d", DAY), ("h", 3600.0), ("min", 60.0), ("s", 1.0)];
+
+/// Time zone abbreviations and their offsets from UTC in minutes, for `tz(t, zone)`. There's no
+/// daylight saving, so summer and winter time have names of their own, like `cet` and `cest`.
+const ZONES: [(&str, i32); 36] = [
+    ("utc", 0),
+    ("gmt", 0),
+    ("wet", 0),
+    ("west", 60),
+    ("bst", 60),
+    ("cet", 60),
+    ("cest", 120),
+    ("eet", 120),
+    ("eest", 180),
+    ("msk", 180),
+    ("gst", 240),
+    ("pkt", 300),
+    ("ist", 330),
+    ("npt", 345),
+    ("ict", 420),
+    ("awst", 480),
+    ("hkt", 480),
+    ("jst", 540),
+    ("kst", 540),
+    ("acst", 570),
+    ("aest", 600),
+    ("aedt", 660),
+    ("nzst", 720),
+    ("nzdt", 780),
+    ("hst", -600),
+    ("akst", -540),
+    ("akdt", -480),
+    ("pst", -480),
+    ("pdt", -420),
+    ("mst", -420),
+    ("mdt", -360),
+    ("cst", -360),
+    ("cdt", -300),
+    ("est", -300),
+    ("edt", -240),
+    ("brt", -180),
+];
+
+/// A moment in time, or a whole day when it was written as a date like `2026-10-17`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamp {
+    /// Seconds since 1970-01-01 00:00 UTC.
+    pub seconds: f64,
+    /// How many seconds east of UTC the time is shown.
+    pub offset: i32,
+    /// Whether it's a day rather than a moment, like `today`, so it's shown without a time.
+    pub date_only: bool,
+}
+
+/// A length of time, like `3d 4h` or the difference between two dates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Duration {
+    pub seconds: f64,
+}
+
+impl Timestamp {
+    fn date(days: i64) -> Timestamp {
+        Timestamp {
+            seconds: days as f64 * DAY,
+            offset: 0,
+            date_only: true,
+        }
+    }
+
+    /// The day it falls on where it's shown, counted from 1970-01-01.
+    fn day(&self) -> i64 {
+        ((self.seconds + self.offset as f64) / DAY).floor() as i64
+    }
+
+    /// The day of the week, from 1 for Monday to 7 for Sunday like ISO 8601.
+    pub fn weekday(&self) -> u32 {
+        // 1970-01-01 was a Thursday.
+        ((self.day() + 3).rem_euclid(7) + 1) as u32
+    }
+
+    fn shifted(self, seconds: f64) -> Timestamp {
+        Timestamp {
+            seconds: self.seconds + seconds,
+            date_only: self.date_only && seconds % DAY == 0.0,
+            ..self
+        }
+    }
+}
+
+/// The days since 1970-01-01 of a date in the proleptic Gregorian calendar, with Howard Hinnant's
+/// algorithm, which counts from a year that starts in March so leap days come last.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day of a day counted from 1970-01-01, undoing [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (if month <= 2 { year_of_era + era * 400 + 1 } else { year_of_era + era * 400 }, month, day)
+}
+
+/// Returns whether the character continues a date or time literal that's being collected in
+/// `num`, like the dashes of `2026-10-17`, the `T` of `2026-10-17T14:30` or the colon of `14:30`.
+/// They're only taken when digits follow, so `2026 - 10` is still a subtraction.
+pub fn continues_literal(num: &str, c: char, rest: &Peekable<Chars>) -> bool {
+    let shape = |s: &str| s.chars().map(|c| if c.is_ascii_digit() { '9' } else { c }).collect::<String>();
+    let (num, rest) = (shape(num), shape(&rest.clone().take(3).collect::<String>()));
+
+    match c {
+        '-' => num == "9999" && rest == "99-" || num == "9999-99" && rest.starts_with("99"),
+        'T' => num == "9999-99-99" && rest.starts_with("99"),
+        // A colon after the hours or minutes always belongs to the time, so that an incomplete
+        // one like `12:` is an error instead of the colon being skipped.
+        ':' => {
+            let time = num.rsplit('T').next().unwrap_or_default();
+            matches!(time, "9" | "99" | "9:99" | "99:99")
+        }
+        _ => false,
+    }
+}
+
+/// Returns whether the collected digits are a date or time literal rather than a number.
+pub fn is_literal(num: &str) -> bool {
+    num.contains([':', '-'])
+}
+
+/// Parses a date like `2026-10-17`, a date and time like `2026-10-17T14:30`, or a time of day like
+/// `14:30:15`, which is the duration since midnight so it can be added to a date.
+pub fn parse_literal(literal: &str) -> Result<Token, EvalError> {
+    let invalid = || EvalError::InvalidExpression(format!("Invalid date or time: {literal}"));
+    let number = |s: &str| s.parse::<i64>().map_err(|_| invalid());
+
+    let (date, time) = match literal.split_once('T') {
+        Some((date, time)) => (Some(date), Some(time)),
+        None if literal.contains('-') => (Some(literal), None),
+        None => (None, Some(literal)),
+    };
+
+    let seconds = match time {
+        Some(time) => {
+            if time.split(':').skip(1).any(|part| part.len() != 2) {
+                return Err(invalid());
+            }
+            let parts = time.split(':').map(number).collect::<Result<Vec<_>, _>>()?;
+            let (hour, minute, second) = match parts[..] {
+                [hour, minute] => (hour, minute, 0),
+                [hour, minute, second] => (hour, minute, second),
+                _ => return Err(invalid()),
+            };
+            if hour >= 24 || minute >= 60 || second >= 60 {
+                return Err(invalid());
+            }
+            (hour * 3600 + minute * 60 + second) as f64
+        }
+        None => 0.0,
+    };
+
+    let Some(date) = date else {
+        return Ok(Token::Duration(Duration { seconds }));
+    };
+
+    let parts = date.split('-').map(number).collect::<Result<Vec<_>, _>>()?;
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let days = days_from_civil(year, month, day);
+    if !(1..=12).contains(&month) || civil_from_days(days) != (year, month, day) {
+        return Err(invalid());
+    }
+
+    Ok(Token::Timestamp(Timestamp::date(days).shifted(seconds)))
+}
+
+/// Returns the duration a number followed by a unit, like `90min`, stands for. The unit is taken
+/// from the characters after the number, which are skipped.
+pub fn parse_unit(num: &str, first: char, chars: &mut Peekable<Chars>) -> Option<Token> {
+    let word = std::iter::once(first)
+        .chain(chars.clone().take_while(|c| c.is_ascii_alphabetic()))
+        .collect::<String>();
+    let (_, length) = UNITS.iter().find(|(unit, _)| *unit == word)?;
+    let value = num.parse::<f64>().ok()?;
+
+    for _ in 1..word.len() {
+        chars.next();
+    }
+    Some(Token::Duration(Duration { seconds: value * length }))
+}
+
+/// Joins a date or duration literal with the one before it, so `3d 4h` is a single duration and
+/// `2026-10-17 14:30` a single moment. Returns the literal back when they can't be joined.
+pub fn join_literals(previous: Option<&mut Token>, token: Token) -> Option<Token> {
+    match (previous, &token) {
+        (Some(Token::Duration(a)), Token::Duration(b)) => a.seconds += b.seconds,
+        (Some(Token::Timestamp(t)), Token::Duration(d)) => *t = t.shifted(d.seconds),
+        _ => return Some(token),
+    }
+    None
+}
+
+/// The value of `now`, the current moment, and `today`, the current day, in UTC.
+pub fn clock(name: &str) -> Option<Value> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as f64;
+    let now = Timestamp {
+        seconds,
+        offset: 0,
+        date_only: false,
+    };
+
+    match name {
+        "now" => Some(Value::Time(now)),
+        "today" => Some(Value::Time(Timestamp::date(now.day()))),
+        _ => None,
+    }
+}
+
+/// The number of working days from `a` to `b`, Monday to Friday, counting both ends like
+/// spreadsheets do. It's negative when `b` is before `a`.
+pub fn workdays(a: &Timestamp, b: &Timestamp) -> f64 {
+    let (start, end) = (a.day(), b.day());
+    if end < start {
+        return -workdays(b, a);
+    }
+
+    let days = end - start + 1;
+    let mut count = days / 7 * 5;
+    for day in start + days / 7 * 7..=end {
+        if Timestamp::date(day).weekday() <= 5 {
+            count += 1;
+        }
+    }
+    count as f64
+}
+
+/// Evaluates a call to `tz(t, zone)`, which shows a moment in another time zone. The zone is an
+/// abbreviation like `cet` or `pst`, or an offset from UTC in hours like `5.5`, from the UTC-12 of
+/// Baker Island to the UTC+14 of Kiribati.
+pub fn evaluate_tz(ast: &ASTNode, scope: &Scope) -> Result<Value, EvalError> {
+    let Value::Time(time) = evaluate(&ast.children[0], scope)? else {
+        return Err(EvalError::InvalidArgument(format!("{} isn't a date or time", ast.children[0])));
+    };
+
+    let zone = ast.children[1].variable_name("tz").ok().and_then(|name| {
+        let name = name.to_lowercase();
+        ZONES.iter().find(|(zone, _)| *zone == name).map(|(_, minutes)| minutes * 60)
+    });
+    let offset = match zone {
+        Some(offset) => offset,
+        None => {
+            let hours = evaluate_ast(&ast.children[1], scope)?;
+            if !(-12.0..=14.0).contains(&hours) {
+                return Err(EvalError::InvalidArgument(format!(
+                    "A time zone is an abbreviation like cet or an offset between -12 and 14 hours, not {}",
+                    ast.children[1]
+                )));
+            }
+            (hours * 3600.0).round() as i32
+        }
+    };
+
+    Ok(Value::Time(Timestamp {
+        offset,
+        date_only: false,
+        ..time
+    }))
+}
+
+/// Applies an operator to dates, times and durations. Subtracting two moments gives the duration
+/// between them, durations can be added to and subtracted from moments and each other, and scaled
+/// by numbers. Returns `None` when neither side is a date, time or duration.
+pub fn operate(op: Operator, left: &Value, right: &Value) -> Option<Result<Value, EvalError>> {
+    use Operator::{Additive as A, Multiplicative as M};
+    use Value::{Duration as D, Number as N, Time as T};
+
+    let duration = |seconds: f64| Ok(D(Duration { seconds }));
+    let result = match (op, left, right) {
+        (A(Additive::Subtract), T(a), T(b)) => duration(a.seconds - b.seconds),
+        (A(Additive::Add), T(t), D(d)) | (A(Additive::Add), D(d), T(t)) => Ok(T(t.shifted(d.seconds))),
+        (A(Additive::Subtract), T(t), D(d)) => Ok(T(t.shifted(-d.seconds))),
+        (A(Additive::Add), D(a), D(b)) => duration(a.seconds + b.seconds),
+        (A(Additive::Subtract), D(a), D(b)) => duration(a.seconds - b.seconds),
+        (M(Multiplicative::Multiply), D(d), N(n)) | (M(Multiplicative::Multiply), N(n), D(d)) => {
+            duration(d.seconds * n)
+        }
+        (M(Multiplicative::Divide), D(d), N(n)) => duration(d.seconds / n),
+        (M(Multiplicative::Divide), D(a), D(b)) => Ok(N(a.seconds / b.seconds)),
+        (_, T(_) | D(_), _) | (_, _, T(_) | D(_)) => Err(EvalError::InvalidOperation(format!(
+            "{left} and {right} can't be combined like that. Durations like 3d can be added to dates and each other, and dates subtracted from each other"
+        ))),
+        _ => return None,
+    };
+    Some(result)
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.day());
+        write!(f, "{year:04}-{month:02}-{day:02}")?;
+        if self.date_only {
+            return Ok(());
+        }
+
+        let seconds = (self.seconds + self.offset as f64 - self.day() as f64 * DAY).floor() as i64;
+        write!(f, " {:02}:{:02}", seconds / 3600, seconds / 60 % 60)?;
+        if seconds % 60 != 0 {
+            write!(f, ":{:02}", seconds % 60)?;
+        }
+
+        let (hours, minutes) = (self.offset / 3600, self.offset.abs() / 60 % 60);
+        match (hours, minutes) {
+            (0, 0) => write!(f, " UTC"),
+            (_, 0) => write!(f, " UTC{hours:+}"),
+            (_, _) => write!(f, " UTC{}{}:{minutes:02}", if self.offset < 0 { '-' } else { '+' }, hours.abs()),
+        }
+    }
+}
+
+impl fmt::Display for Duration {
+    /// Writes the duration in days, hours, minutes and seconds, like `3d 4h 30min`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.seconds < 0.0 {
+            write!(f, "-")?;
+        }
+
+        let mut rest = self.seconds.abs();
+        let mut parts = vec![];
+        for (unit, length) in &UNITS[1..4] {
+            let count = (rest / length).floor();
+            rest -= count * length;
+            if count > 0.0 {
+                parts.push(format!("{count}{unit}"));
+            }
+        }
+
+        // Fractions of a second are rounded, so rounding errors from the arithmetic don't show.
+        let seconds = (rest * 1e6).round() / 1e6;
+        if seconds > 0.0 || parts.is_empty() {
+            parts.push(format!("{seconds}s"));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculator::Calculator;
+
+    fn evaluate(expression: &str) -> String {
+        Calculator::with_seed(1).evaluate(expression).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_calendar() {
+        for days in [-719_468, -1, 0, 59, 11_016, 20_743, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(20_743), (2026, 10, 17));
+        assert_eq!(Timestamp::date(20_743).weekday(), 6);
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(evaluate("2026-10-17"), "2026-10-17");
+        assert_eq!(evaluate("2026-10-17T14:30"), "2026-10-17 14:30 UTC");
+        assert_eq!(evaluate("2026-10-17 14:30:15"), "2026-10-17 14:30:15 UTC");
+        assert_eq!(evaluate("3d 4h"), "3d 4h");
+        assert_eq!(evaluate("2026 - 10 - 17"), "1999");
+        assert!(Calculator::with_seed(1).evaluate("2026-02-29").is_err());
+        assert!(Calculator::with_seed(1).evaluate("2026-10-1").is_err());
+        assert!(Calculator::with_seed(1).evaluate("12:").is_err());
+        assert!(Calculator::with_seed(1).evaluate("12:3").is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(evaluate("2026-12-25 - 2026-10-17"), "69d");
+        assert_eq!(evaluate("2026-10-17 + 90min"), "2026-10-17 01:30 UTC");
+        assert_eq!(evaluate("2026-10-17 + 2w"), "2026-10-31");
+        assert_eq!(evaluate("17:00 - 14:30"), "2h 30min");
+        assert_eq!(evaluate("(3d 4h) / 2"), "1d 14h");
+        assert_eq!(evaluate("1d / 1h"), "24");
+        assert!(Calculator::with_seed(1).evaluate("2026-10-17 + 2026-10-18").is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(evaluate("weekday(2026-10-19)"), "1");
+        assert_eq!(evaluate("workdays(2026-10-16, 2026-10-26)"), "7");
+        assert_eq!(evaluate("workdays(2026-10-26, 2026-10-16)"), "-7");
+        assert_eq!(evaluate("tz(2026-10-17T23:00, cest)"), "2026-10-18 01:00 UTC+2");
+        assert_eq!(evaluate("tz(2026-10-17T12:00, -9.5)"), "2026-10-17 02:30 UTC-9:30");
+        assert_eq!(evaluate("tz(2026-10-17T12:00, 14)"), "2026-10-18 02:00 UTC+14");
+        assert!(Calculator::with_seed(1).evaluate("tz(2026-10-17T12:00, 15)").is_err());
+        assert!(Calculator::with_seed(1).evaluate("tz(2026-10-17T12:00, -13)").is_err());
+    }
+}
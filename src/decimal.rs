@@ -0,0 +1,718 @@
+use std::fmt;
+
+use crate::{ast::*, errors::EvalError, eval::Scope, tokenize::*, value::Value};
+
+/// The most significant digits a decimal can have, which is as many as a decimal128 has.
+pub const MAX_PRECISION: u32 = 34;
+
+/// The largest power of ten a decimal can have before it overflows, and the smallest before it
+/// underflows to zero.
+const MAX_EXPONENT: i64 = 999_999;
+
+/// How many digits a coefficient can have, since `10^38` is the largest power of ten in a `u128`.
+const MAX_DIGITS: u32 = 38;
+
+/// How a result with more digits than the precision is rounded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Rounding {
+    /// To the nearest, with ties going to the even digit, like banks do.
+    HalfEven,
+    /// To the nearest, with ties going away from zero, like schools teach.
+    HalfUp,
+    /// To the nearest, with ties going towards zero.
+    HalfDown,
+    /// Away from zero.
+    Up,
+    /// Towards zero, which truncates.
+    Down,
+    /// Towards positive infinity.
+    Ceiling,
+    /// Towards negative infinity.
+    Floor,
+}
+
+/// The settings used when evaluating in decimal mode.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DecimalFormat {
+    /// How many significant digits results are rounded to, at most [`MAX_PRECISION`].
+    pub precision: u32,
+    pub rounding: Rounding,
+}
+
+/// A base 10 floating point number, `±coefficient × 10^exponent`. Trailing zeros are kept, so
+/// `2.50` and `2.5` are the same number written with a different exponent.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Decimal {
+    pub negative: bool,
+    pub coefficient: u128,
+    pub exponent: i64,
+}
+
+/// An unsigned 256-bit integer, wide enough for the product of two coefficients.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+struct Wide {
+    high: u128,
+    low: u128,
+}
+
+impl Rounding {
+    pub fn parse(name: &str) -> Option<Rounding> {
+        match name {
+            "half-even" => Some(Rounding::HalfEven),
+            "half-up" => Some(Rounding::HalfUp),
+            "half-down" => Some(Rounding::HalfDown),
+            "up" => Some(Rounding::Up),
+            "down" => Some(Rounding::Down),
+            "ceiling" => Some(Rounding::Ceiling),
+            "floor" => Some(Rounding::Floor),
+            _ => None,
+        }
+    }
+}
+
+impl Default for DecimalFormat {
+    fn default() -> Self {
+        DecimalFormat {
+            precision: MAX_PRECISION,
+            rounding: Rounding::HalfEven,
+        }
+    }
+}
+
+impl Wide {
+    const ZERO: Wide = Wide { high: 0, low: 0 };
+
+    fn new(low: u128) -> Wide {
+        Wide { high: 0, low }
+    }
+
+    /// The full product of two 128-bit integers, multiplied in 64-bit halves.
+    fn product(a: u128, b: u128) -> Wide {
+        let half = |x: u128| (x >> 64, x & u64::MAX as u128);
+        let ((a1, a0), (b1, b0)) = (half(a), half(b));
+        let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+
+        let middle = (p00 >> 64) + (p01 & u64::MAX as u128) + (p10 & u64::MAX as u128);
+        Wide {
+            high: p11 + (p01 >> 64) + (p10 >> 64) + (middle >> 64),
+            low: (p00 & u64::MAX as u128) | (middle << 64),
+        }
+    }
+
+    fn times_ten(self) -> Option<Wide> {
+        let low = Wide::product(self.low, 10);
+        Some(Wide {
+            high: self.high.checked_mul(10)?.checked_add(low.high)?,
+            low: low.low,
+        })
+    }
+
+    /// Divides by a divisor below `2^127` one bit at a time, returning the quotient and remainder.
+    fn div_rem(self, divisor: u128) -> (Wide, u128) {
+        let mut quotient = Wide::ZERO;
+        let mut remainder = 0u128;
+
+        for i in (0..256).rev() {
+            let bit = match i {
+                128.. => self.high >> (i - 128) & 1,
+                _ => self.low >> i & 1,
+            };
+            remainder = remainder << 1 | bit;
+            if remainder >= divisor {
+                remainder -= divisor;
+                match i {
+                    128.. => quotient.high |= 1 << (i - 128),
+                    _ => quotient.low |= 1 << i,
+                }
+            }
+        }
+
+        (quotient, remainder)
+    }
+
+    fn digits(self) -> u32 {
+        let mut power = Some(Wide::new(1));
+        let mut digits = 0;
+        while power.is_some_and(|power| power <= self) {
+            power = power.and_then(Wide::times_ten);
+            digits += 1;
+        }
+        digits
+    }
+
+    /// The largest integer whose square is at most this one, found with Newton's method from an
+    /// estimate above it.
+    fn sqrt(self) -> u128 {
+        let estimate = (self.high as f64 * 2f64.powi(128) + self.low as f64).sqrt();
+        let mut x = (estimate * (1.0 + 1e-10)) as u128 + 2;
+
+        loop {
+            let (quotient, _) = self.div_rem(x);
+            let next = (x + quotient.low) / 2;
+            if next >= x {
+                return x;
+            }
+            x = next;
+        }
+    }
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        negative: false,
+        coefficient: 0,
+        exponent: 0,
+    };
+
+    pub const ONE: Decimal = Decimal {
+        negative: false,
+        coefficient: 1,
+        exponent: 0,
+    };
+
+    pub fn new(negative: bool, coefficient: u128, exponent: i64) -> Decimal {
+        Decimal {
+            negative: negative && coefficient != 0,
+            coefficient,
+            exponent,
+        }
+    }
+
+    /// Parses the digits of a literal like `0.1` directly, without going through a float. Digits
+    /// past the most a coefficient can hold are only remembered by making the last kept one odd,
+    /// which is enough for the literal to be rounded correctly later.
+    pub fn parse(literal: &str) -> Option<Decimal> {
+        let (whole, fraction) = literal.split_once('.').unwrap_or((literal, ""));
+        let digits = whole.chars().chain(fraction.chars()).collect::<Vec<_>>();
+        if digits.is_empty() || !digits.iter().all(char::is_ascii_digit) {
+            return None;
+        }
+
+        let mut coefficient = 0u128;
+        let mut exponent = -(fraction.len() as i64);
+        let mut inexact = false;
+        let significant = digits.iter().skip_while(|&&d| d == '0').collect::<Vec<_>>();
+        for (i, d) in significant.iter().enumerate() {
+            let digit = d.to_digit(10)? as u128;
+            match i < MAX_DIGITS as usize - 1 {
+                true => coefficient = coefficient * 10 + digit,
+                false => {
+                    inexact |= digit != 0;
+                    exponent += 1;
+                }
+            }
+        }
+        if inexact {
+            coefficient |= 1;
+        }
+
+        Some(Decimal::new(false, coefficient, exponent))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficient == 0
+    }
+
+    /// The closest float, which is found by parsing the digits.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = format!("{}e{}", self.coefficient, self.exponent).parse::<f64>().unwrap_or(f64::NAN);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    /// The value as an integer, when it is one and isn't too large.
+    pub fn to_integer(&self) -> Option<i64> {
+        let magnitude = match self.exponent {
+            e if e >= 0 => self.coefficient.checked_mul(10u128.checked_pow(e.try_into().ok()?)?)?,
+            e if -e > MAX_DIGITS as i64 => (self.coefficient == 0).then_some(0)?,
+            e => {
+                let scale = 10u128.pow(-e as u32);
+                self.coefficient.is_multiple_of(scale).then_some(self.coefficient / scale)?
+            }
+        };
+        let magnitude = i64::try_from(magnitude).ok()?;
+        Some(if self.negative { -magnitude } else { magnitude })
+    }
+
+    fn digits(&self) -> i64 {
+        Wide::new(self.coefficient).digits() as i64
+    }
+
+    /// The exponent just above the most significant digit.
+    fn top(&self) -> i64 {
+        self.exponent + self.digits()
+    }
+
+    fn negate(self) -> Decimal {
+        Decimal::new(!self.negative, self.coefficient, self.exponent)
+    }
+
+    /// Drops the digits below `10^floor`. When any of them weren't zero, the last kept digit is
+    /// made odd, which lets the sum be rounded as if none were dropped as long as `floor` is a
+    /// few digits below where the sum is rounded.
+    fn truncated(self, floor: i64) -> Decimal {
+        if self.exponent >= floor {
+            return self;
+        }
+
+        let scale = (floor - self.exponent) as u32;
+        let (quotient, remainder) = match scale {
+            ..=MAX_DIGITS => (self.coefficient / 10u128.pow(scale), self.coefficient % 10u128.pow(scale)),
+            _ => (0, self.coefficient),
+        };
+        let coefficient = if remainder != 0 { quotient | 1 } else { quotient };
+        Decimal::new(self.negative, coefficient, floor)
+    }
+
+    /// Removes trailing zeros until the exponent reaches the ideal one, like `6.0 / 2` giving
+    /// `3.0` rather than `3.000000000000000000000000000000000`.
+    fn reduced(mut self, ideal: i64) -> Decimal {
+        while self.exponent < ideal && self.coefficient.is_multiple_of(10) && self.coefficient != 0 {
+            self.coefficient /= 10;
+            self.exponent += 1;
+        }
+        self
+    }
+}
+
+impl DecimalFormat {
+    /// Rounds `±coefficient × 10^exponent` to the precision and to a power of ten of at least
+    /// `quantum`. `sticky` means the exact value is slightly larger than the coefficient, because
+    /// digits were already dropped while calculating it.
+    fn round(
+        &self,
+        negative: bool,
+        coefficient: Wide,
+        exponent: i64,
+        sticky: bool,
+        quantum: i64,
+    ) -> Result<Decimal, EvalError> {
+        let digits = coefficient.digits() as i64;
+        let drop = (digits - self.precision as i64)
+            .max(quantum - exponent)
+            .max(-MAX_EXPONENT - exponent)
+            .max(0);
+
+        // Once every digit is dropped, the rest are zeros.
+        let (mut kept, mut last, mut sticky) = (coefficient, 0, sticky);
+        for _ in 0..drop.min(digits + 1) {
+            let (quotient, remainder) = kept.div_rem(10);
+            sticky |= last != 0;
+            (kept, last) = (quotient, remainder);
+        }
+        if drop > digits + 1 {
+            sticky |= last != 0;
+            last = 0;
+        }
+
+        let kept = kept.low;
+        let inexact = last != 0 || sticky;
+        let up = match self.rounding {
+            Rounding::HalfEven => last > 5 || last == 5 && (sticky || kept % 2 == 1),
+            Rounding::HalfUp => last >= 5,
+            Rounding::HalfDown => last > 5 || last == 5 && sticky,
+            Rounding::Up => inexact,
+            Rounding::Down => false,
+            Rounding::Ceiling => inexact && !negative,
+            Rounding::Floor => inexact && negative,
+        };
+
+        let (mut kept, mut exponent) = (kept + up as u128, exponent + drop);
+        if kept == 10u128.pow(self.precision) {
+            kept /= 10;
+            exponent += 1;
+        }
+
+        let result = Decimal::new(negative, kept, exponent);
+        match result.top() - 1 > MAX_EXPONENT {
+            true => Err(EvalError::Overflow),
+            false => Ok(result),
+        }
+    }
+
+    /// Rounds a decimal, like a literal with more digits than the precision, to the precision.
+    pub fn fit(&self, a: Decimal) -> Result<Decimal, EvalError> {
+        self.round(a.negative, Wide::new(a.coefficient), a.exponent, false, -MAX_EXPONENT)
+    }
+
+    pub fn add(&self, a: Decimal, b: Decimal) -> Result<Decimal, EvalError> {
+        match (a.is_zero(), b.is_zero()) {
+            (true, true) => return Ok(Decimal::new(false, 0, a.exponent.min(b.exponent))),
+            (true, false) => return self.fit(b),
+            (false, true) => return self.fit(a),
+            (false, false) => {}
+        }
+
+        // Digits far below the precision of the larger operand only matter for rounding, so
+        // they're dropped to keep the aligned coefficients within a `u128`.
+        let floor = a.top().max(b.top()) - self.precision as i64 - 4;
+        let (a, b) = (a.truncated(floor), b.truncated(floor));
+        let exponent = a.exponent.min(b.exponent);
+        let aligned = |x: Decimal| x.coefficient * 10u128.pow((x.exponent - exponent) as u32);
+        let (x, y) = (aligned(a), aligned(b));
+
+        let (negative, sum) = match (a.negative == b.negative, x >= y) {
+            (true, _) => (a.negative, x + y),
+            (false, true) => (a.negative, x - y),
+            (false, false) => (b.negative, y - x),
+        };
+        self.round(negative, Wide::new(sum), exponent, false, -MAX_EXPONENT)
+    }
+
+    pub fn subtract(&self, a: Decimal, b: Decimal) -> Result<Decimal, EvalError> {
+        self.add(a, b.negate())
+    }
+
+    pub fn multiply(&self, a: Decimal, b: Decimal) -> Result<Decimal, EvalError> {
+        let product = Wide::product(a.coefficient, b.coefficient);
+        self.round(a.negative != b.negative, product, a.exponent + b.exponent, false, -MAX_EXPONENT)
+    }
+
+    /// Divides with one digit more than the precision and a remainder, so the quotient can be
+    /// rounded correctly. Exact quotients drop the trailing zeros that the extra digits added.
+    pub fn divide(&self, a: Decimal, b: Decimal) -> Result<Decimal, EvalError> {
+        if b.is_zero() {
+            return Err(EvalError::DivisionByZero);
+        }
+        let ideal = a.exponent - b.exponent;
+        if a.is_zero() {
+            return Ok(Decimal::new(false, 0, ideal));
+        }
+
+        let shift = (self.precision as i64 + b.digits() - a.digits() + 1).max(0);
+        let mut numerator = Wide::new(a.coefficient);
+        for _ in 0..shift {
+            numerator = numerator.times_ten().ok_or(EvalError::Overflow)?;
+        }
+
+        let (quotient, remainder) = numerator.div_rem(b.coefficient);
+        let exponent = ideal - shift;
+        let result = self.round(a.negative != b.negative, quotient, exponent, remainder != 0, -MAX_EXPONENT)?;
+        match remainder {
+            0 => Ok(result.reduced(ideal)),
+            _ => Ok(result),
+        }
+    }
+
+    /// Raises a decimal to an integer power by repeated squaring, rounding at every step.
+    pub fn power(&self, base: Decimal, exponent: i64) -> Result<Decimal, EvalError> {
+        if exponent < 0 {
+            let power = self.power(base, exponent.checked_neg().ok_or(EvalError::Overflow)?)?;
+            return self.divide(Decimal::ONE, power);
+        }
+
+        let (mut result, mut base, mut exponent) = (Decimal::ONE, base, exponent);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = self.multiply(result, base)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = self.multiply(base, base)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// The correctly rounded square root, found from the integer square root of the coefficient
+    /// scaled up to twice the precision.
+    pub fn sqrt(&self, a: Decimal) -> Result<Decimal, EvalError> {
+        if a.negative {
+            return Err(EvalError::InvalidArgument(format!(
+                "Can't take the square root of the negative number {a}"
+            )));
+        }
+        let ideal = a.exponent.div_euclid(2);
+        if a.is_zero() {
+            return Ok(Decimal::new(false, 0, ideal));
+        }
+
+        let (mut radicand, mut exponent) = (Wide::new(a.coefficient), a.exponent);
+        let target = 2 * (self.precision as i64 + 1);
+        while exponent % 2 != 0 || (radicand.digits() as i64) < target {
+            radicand = radicand.times_ten().ok_or(EvalError::Overflow)?;
+            exponent -= 1;
+        }
+
+        let root = radicand.sqrt();
+        let exact = Wide::product(root, root) == radicand;
+        let result = self.round(false, Wide::new(root), exponent / 2, !exact, -MAX_EXPONENT)?;
+        match exact {
+            true => Ok(result.reduced(ideal)),
+            false => Ok(result),
+        }
+    }
+
+    /// Rounds to a multiple of `10^-places` with the rounding mode, like `round(2.675, 2)`. Numbers
+    /// with fewer decimal places are padded with zeros, so amounts of money line up.
+    pub fn quantize(&self, a: Decimal, places: i64) -> Result<Decimal, EvalError> {
+        let quantum = -places;
+        if a.exponent < quantum {
+            return self.round(a.negative, Wide::new(a.coefficient), a.exponent, false, quantum);
+        }
+
+        let mut coefficient = Wide::new(a.coefficient);
+        for _ in quantum..a.exponent {
+            coefficient = coefficient.times_ten().ok_or(EvalError::Overflow)?;
+        }
+        match coefficient.digits() <= self.precision {
+            true => Ok(Decimal::new(a.negative, coefficient.low, quantum)),
+            false => self.fit(a),
+        }
+    }
+
+    /// The net present value of cash flows at the end of each period, where the first one is
+    /// discounted by one period like in spreadsheets.
+    fn npv(&self, r: Decimal, cashflows: &[Decimal]) -> Result<Decimal, EvalError> {
+        let growth = self.add(Decimal::ONE, r)?;
+        let mut discount = Decimal::ONE;
+        let mut total = Decimal::ZERO;
+        for &cashflow in cashflows {
+            discount = self.multiply(discount, growth)?;
+            total = self.add(total, self.divide(cashflow, discount)?)?;
+        }
+        Ok(total)
+    }
+
+    /// Evaluates `pv`, `fv` or `pmt` in decimal, so amounts of money have no binary rounding. The
+    /// number of periods has to be an integer.
+    fn time_value(&self, name: &str, params: &[Decimal]) -> Result<Decimal, EvalError> {
+        let param = |i: usize| params.get(i).copied().unwrap_or(Decimal::ZERO);
+        let due = match param(4).to_integer() {
+            Some(due @ (0 | 1)) => due,
+            _ => {
+                return Err(EvalError::InvalidArgument(format!(
+                    "The payment type has to be 0 for the end of each period or 1 for the start, not {}",
+                    param(4)
+                )))
+            }
+        };
+
+        let (r, n) = (params[0], params[1]);
+        let periods = n.to_integer().ok_or(EvalError::NotAnInteger(n.to_f64()))?;
+        let growth = self.power(self.add(Decimal::ONE, r)?, periods)?;
+        let annuity = match r.is_zero() {
+            true => n,
+            false => {
+                let factor = self.add(Decimal::ONE, self.multiply(r, Decimal::new(false, due as u128, 0))?)?;
+                let grown = self.divide(self.subtract(growth, Decimal::ONE)?, r)?;
+                self.multiply(factor, grown)?
+            }
+        };
+
+        match name {
+            "pv" => {
+                let (pmt, fv) = (params[2], param(3));
+                let future = self.add(fv, self.multiply(pmt, annuity)?)?;
+                self.divide(future, growth).map(Decimal::negate)
+            }
+            "fv" => {
+                let (pmt, pv) = (params[2], param(3));
+                self.add(self.multiply(pv, growth)?, self.multiply(pmt, annuity)?).map(Decimal::negate)
+            }
+            _ => {
+                let (pv, fv) = (params[2], param(3));
+                let future = self.add(fv, self.multiply(pv, growth)?)?;
+                self.divide(future, annuity).map(Decimal::negate)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// Writes the digits out, or in scientific notation when the number is large or very small,
+    /// like Python's decimals.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.coefficient.to_string();
+        let adjusted = self.exponent + digits.len() as i64 - 1;
+        if self.negative {
+            write!(f, "-")?;
+        }
+
+        if self.exponent <= 0 && adjusted >= -6 {
+            let places = -self.exponent as usize;
+            return match places {
+                0 => write!(f, "{digits}"),
+                p if p >= digits.len() => write!(f, "0.{}{digits}", "0".repeat(p - digits.len())),
+                p => write!(f, "{}.{}", &digits[..digits.len() - p], &digits[digits.len() - p..]),
+            };
+        }
+
+        match digits.len() {
+            1 => write!(f, "{digits}e{adjusted}"),
+            _ => write!(f, "{}.{}e{adjusted}", &digits[..1], &digits[1..]),
+        }
+    }
+}
+
+/// Recursively collapses the ast using decimals instead of floats. Variables get their values
+/// from the scope, where numbers defined in float mode are read as the decimal they're shown as.
+pub fn evaluate_decimal(ast: &ASTNode, format: &DecimalFormat, scope: &Scope) -> Result<Decimal, EvalError> {
+    let evaluate = |ast: &ASTNode| evaluate_decimal(ast, format, scope);
+
+    match &ast.token {
+        Token::Decimal(d) => format.fit(*d),
+        Token::Integer(n) => format.fit(Decimal::new(false, *n as u128, 0)),
+        Token::Number(n) => from_f64(*n),
+        Token::Constant(_)
+        | Token::Matrix { .. }
+        | Token::List
+        | Token::Call(_)
+        | Token::Timestamp(_)
+        | Token::Duration(_) => {
+            Err(EvalError::NotInDecimalMode(ast.to_string()))
+        }
+        Token::Variable(name) => match scope.get(name) {
+            Some(Value::Decimal(d)) => format.fit(*d),
+            Some(Value::Number(n)) => format.fit(from_f64(*n)?),
+            Some(_) => Err(EvalError::NotInDecimalMode(name.clone())),
+            None => Err(EvalError::UnknownKeyword(name.clone())),
+        },
+        // Functions that bind a variable, like `sum` and `integrate`, work on floats.
+        Token::Function((func, _)) if ast.bound_arguments().is_some() => {
+            Err(EvalError::NotInDecimalMode(func.name.to_string()))
+        }
+        // npv(rate, cashflows) takes a list of cash flows.
+        Token::Function((func, _)) if func.name == "npv" => {
+            let rate = evaluate(&ast.children[0])?;
+            let cashflows = match ast.children[1].token {
+                Token::List => ast.children[1].children.iter().map(evaluate).collect::<Result<Vec<_>, _>>()?,
+                _ => vec![evaluate(&ast.children[1])?],
+            };
+            format.npv(rate, &cashflows)
+        }
+        Token::Function((func, _)) => {
+            let mut args = vec![];
+            for child in &ast.children {
+                args.push(evaluate(child)?);
+            }
+
+            match func.name {
+                "sqrt" => format.sqrt(args[0]),
+                "round" => {
+                    let places = args.get(1).copied().unwrap_or(Decimal::ZERO);
+                    let places = places.to_integer().ok_or(EvalError::NotAnInteger(places.to_f64()))?;
+                    format.quantize(args[0], places)
+                }
+                name @ ("pv" | "fv" | "pmt") => format.time_value(name, &args),
+                name => Err(EvalError::NotInDecimalMode(name.to_string())),
+            }
+        }
+        Token::Operator(Operator::Unary(op)) => {
+            let operand = evaluate(&ast.children[0])?;
+
+            match op {
+                Unary::Negate => Ok(operand.negate()),
+                Unary::Complement => Err(EvalError::ProgrammerModeOnly("~".to_string())),
+            }
+        }
+        Token::Operator(op) => {
+            let left = evaluate(&ast.children[0])?;
+            let right = evaluate(&ast.children[1])?;
+
+            match op {
+                Operator::Additive(Additive::Add) => format.add(left, right),
+                Operator::Additive(Additive::Subtract) => format.subtract(left, right),
+                Operator::Multiplicative(Multiplicative::Multiply)
+                | Operator::ElementWise(ElementWise::Multiply) => format.multiply(left, right),
+                Operator::Multiplicative(Multiplicative::Divide)
+                | Operator::ElementWise(ElementWise::Divide) => format.divide(left, right),
+                Operator::Multiplicative(Multiplicative::Modulo) => Err(EvalError::NotInDecimalMode("mod".to_string())),
+                Operator::Exponential | Operator::ElementWise(ElementWise::Power) => {
+                    let exponent = right.to_integer().ok_or(EvalError::NotAnInteger(right.to_f64()))?;
+                    format.power(left, exponent)
+                }
+                Operator::Shift(Shift::Left) => Err(EvalError::ProgrammerModeOnly("<<".to_string())),
+                Operator::Shift(Shift::Right) => Err(EvalError::ProgrammerModeOnly(">>".to_string())),
+                Operator::BitAnd => Err(EvalError::ProgrammerModeOnly("&".to_string())),
+                Operator::BitOr => Err(EvalError::ProgrammerModeOnly("|".to_string())),
+                Operator::BitXor => Err(EvalError::ProgrammerModeOnly("xor".to_string())),
+                Operator::Range => Err(EvalError::NotInDecimalMode("..".to_string())),
+                Operator::PlusMinus => Err(EvalError::NotInDecimalMode("±".to_string())),
+                Operator::Unary(_) => unreachable!("Unary operators are handled above"),
+            }
+        }
+        _ => unreachable!("Invalid AST"),
+    }
+}
+
+/// The decimal a float is shown as, like `0.1` for the float closest to it.
+fn from_f64(n: f64) -> Result<Decimal, EvalError> {
+    Decimal::parse(&n.abs().to_string())
+        .map(|d| if n < 0.0 { d.negate() } else { d })
+        .ok_or(EvalError::NotInDecimalMode(n.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str, precision: u32, rounding: Rounding) -> Result<String, EvalError> {
+        let format = DecimalFormat { precision, rounding };
+
+        let tokens = parse_decimal_expression(expression)?;
+        let ast = get_ast(&tokens)?;
+        evaluate_decimal(&ast, &format, &Scope::new()).map(|d| d.to_string())
+    }
+
+    fn exact(expression: &str) -> String {
+        evaluate(expression, MAX_PRECISION, Rounding::HalfEven).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(exact("0.1 + 0.2"), "0.3");
+        assert_eq!(exact("1.10 * 3"), "3.30");
+        assert_eq!(exact("6.0 / 2"), "3.0");
+        assert_eq!(exact("1 / 3"), "0.3333333333333333333333333333333333");
+        assert_eq!(exact("2 / 3"), "0.6666666666666666666666666666666667");
+        assert_eq!(exact("1 - 0.9999999999"), "1e-10");
+        assert_eq!(exact("2^100"), "1267650600228229401496703205376");
+        assert_eq!(exact("2^200"), "1.606938044258990275541962092341163e60");
+        assert_eq!(exact("1.5^-2"), "0.4444444444444444444444444444444444");
+    }
+
+    #[test]
+    fn test_far_apart_sums() {
+        assert_eq!(exact("1000000000000000000000000000000000 + 0.5"), "1000000000000000000000000000000000");
+        assert_eq!(exact("1000000000000000000000000000000001 + 0.5"), "1000000000000000000000000000000002");
+        assert_eq!(exact("1 - 0.00000000000000000000000000000000000000001"), "1.000000000000000000000000000000000");
+        let truncated = evaluate("1 - 0.00000000000000000000000000000000000000001", 34, Rounding::Down);
+        assert_eq!(truncated.unwrap(), "0.9999999999999999999999999999999999");
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        assert_eq!(evaluate("round(2.665, 2)", 28, Rounding::HalfEven).unwrap(), "2.66");
+        assert_eq!(evaluate("round(2.665, 2)", 28, Rounding::HalfUp).unwrap(), "2.67");
+        assert_eq!(evaluate("round(2.665, 2)", 28, Rounding::HalfDown).unwrap(), "2.66");
+        assert_eq!(evaluate("round(2.661, 2)", 28, Rounding::Up).unwrap(), "2.67");
+        assert_eq!(evaluate("round(2.669, 2)", 28, Rounding::Down).unwrap(), "2.66");
+        assert_eq!(evaluate("round(-0.125, 2)", 28, Rounding::Ceiling).unwrap(), "-0.12");
+        assert_eq!(evaluate("round(-0.125, 2)", 28, Rounding::Floor).unwrap(), "-0.13");
+        assert_eq!(evaluate("round(3, 2)", 28, Rounding::Down).unwrap(), "3.00");
+        assert_eq!(evaluate("2 / 3", 5, Rounding::Down).unwrap(), "0.66666");
+    }
+
+    #[test]
+    fn test_sqrt_and_money() {
+        assert_eq!(exact("sqrt(2)"), "1.414213562373095048801688724209698");
+        assert_eq!(exact("sqrt(0.25)"), "0.5");
+        assert_eq!(exact("round(pmt(0.005, 360, 200000), 2)"), "-1199.10");
+        assert_eq!(exact("fv(0, 12, -100)"), "1200");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(evaluate("1 / 0", 28, Rounding::HalfEven), Err(EvalError::DivisionByZero));
+        assert_eq!(evaluate("2^0.5", 28, Rounding::HalfEven), Err(EvalError::NotAnInteger(0.5)));
+        assert_eq!(evaluate("10^999999 * 10", 28, Rounding::HalfEven), Err(EvalError::Overflow));
+        assert_eq!(evaluate("pi", 28, Rounding::HalfEven), Err(EvalError::NotInDecimalMode("pi".to_string())));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Decimal::parse("0.10"), Some(Decimal::new(false, 10, -2)));
+        assert_eq!(Decimal::parse("."), None);
+        assert_eq!(Decimal::parse("1.2.3"), None);
+    }
+}
@@ -0,0 +1,403 @@
+use std::f64::consts::PI;
+
+use crate::{errors::EvalError, special};
+
+/// The names of the probability distribution functions. Each takes the point to evaluate at
+/// first, followed by the parameters of the distribution.
+pub const DISTRIBUTIONS: [&str; 11] = [
+    "normpdf",
+    "normcdf",
+    "norminv",
+    "binompdf",
+    "binomcdf",
+    "poissonpdf",
+    "tcdf",
+    "chi2cdf",
+    "fcdf",
+    "expcdf",
+    "unifcdf",
+];
+
+/// Evaluates one of the [`DISTRIBUTIONS`] at `x` with the given parameters, checking that the
+/// parameters describe a valid distribution.
+pub fn evaluate(name: &str, x: f64, params: &[f64]) -> Result<f64, EvalError> {
+    let param = |i: usize, default: f64| params.get(i).copied().unwrap_or(default);
+
+    match name {
+        "normpdf" => {
+            let (mu, sigma) = (param(0, 0.0), positive(param(1, 1.0), "The standard deviation")?);
+            Ok(normal_pdf((x - mu) / sigma) / sigma)
+        }
+        "normcdf" => {
+            let (mu, sigma) = (param(0, 0.0), positive(param(1, 1.0), "The standard deviation")?);
+            special::normal_cdf((x - mu) / sigma)
+        }
+        "norminv" => {
+            let (mu, sigma) = (param(0, 0.0), positive(param(1, 1.0), "The standard deviation")?);
+            Ok(mu + sigma * special::normal_quantile(probability(x)?)?)
+        }
+        "binompdf" => {
+            let (n, p) = (count(params[0], "The number of trials")?, probability(params[1])?);
+            Ok(binomial_pdf(x, n, p))
+        }
+        "binomcdf" => {
+            let (n, p) = (count(params[0], "The number of trials")?, probability(params[1])?);
+            binomial_cdf(x, n, p)
+        }
+        "poissonpdf" => Ok(poisson_pdf(x, positive(params[0], "The mean")?)),
+        "tcdf" => student_cdf(x, positive(params[0], "The degrees of freedom")?),
+        "chi2cdf" => {
+            let k = positive(params[0], "The degrees of freedom")?;
+            special::gamma_inc(k / 2.0, x / 2.0).map(|(p, _)| p)
+        }
+        "fcdf" => {
+            let d1 = positive(params[0], "The degrees of freedom")?;
+            let d2 = positive(params[1], "The degrees of freedom")?;
+            fisher_cdf(x, d1, d2)
+        }
+        "expcdf" => {
+            let rate = positive(params[0], "The rate")?;
+            Ok(if x <= 0.0 { 0.0 } else { -(-rate * x).exp_m1() })
+        }
+        "unifcdf" => {
+            let (a, b) = (params[0], params[1]);
+            if a >= b {
+                return Err(EvalError::InvalidArgument(format!(
+                    "The lower end of a uniform distribution has to be below the upper end, but {a} isn't below {b}"
+                )));
+            }
+            Ok(((x - a) / (b - a)).clamp(0.0, 1.0))
+        }
+        name => unreachable!("{name} is not a distribution"),
+    }
+}
+
+/// The partial derivatives of one of the [`DISTRIBUTIONS`] with respect to `x` and each of the
+/// given parameters, which have already been checked by [`evaluate`]. Counts, like the number of
+/// trials, only change in whole steps, so their derivatives are zero. The derivatives with respect
+/// to degrees of freedom are found with central differences.
+pub fn partials(name: &str, x: f64, params: &[f64]) -> Result<Vec<f64>, EvalError> {
+    let param = |i: usize, default: f64| params.get(i).copied().unwrap_or(default);
+    let distribution = |x: f64, params: &[f64]| evaluate(name, x, params);
+
+    let partials = match name {
+        "normpdf" => {
+            let (mu, sigma) = (param(0, 0.0), param(1, 1.0));
+            let z = (x - mu) / sigma;
+            let f = distribution(x, params)?;
+            vec![-z * f / sigma, z * f / sigma, f * (z * z - 1.0) / sigma]
+        }
+        "normcdf" => {
+            let (mu, sigma) = (param(0, 0.0), param(1, 1.0));
+            let z = (x - mu) / sigma;
+            let f = normal_pdf(z) / sigma;
+            vec![f, -f, -f * z]
+        }
+        "norminv" => {
+            let sigma = param(1, 1.0);
+            let z = special::normal_quantile(x)?;
+            vec![sigma / normal_pdf(z), 1.0, z]
+        }
+        // The derivative of the probability of k successes is n times the difference between the
+        // probabilities of k - 1 and k successes in n - 1 trials.
+        "binompdf" => {
+            let (n, p) = (params[0], params[1]);
+            let dp = n * (binomial_pdf(x - 1.0, n - 1.0, p) - binomial_pdf(x, n - 1.0, p));
+            vec![0.0, 0.0, dp]
+        }
+        "binomcdf" => {
+            let (n, p) = (params[0], params[1]);
+            vec![0.0, 0.0, -n * binomial_pdf(x.floor(), n - 1.0, p)]
+        }
+        "poissonpdf" => {
+            let mean = params[0];
+            vec![0.0, poisson_pdf(x - 1.0, mean) - poisson_pdf(x, mean)]
+        }
+        "tcdf" => {
+            let df = params[0];
+            let ln_density = special::ln_gamma((df + 1.0) / 2.0)
+                - special::ln_gamma(df / 2.0)
+                - 0.5 * (df * PI).ln()
+                - (df + 1.0) / 2.0 * (x * x / df).ln_1p();
+            vec![ln_density.exp(), difference(|df| student_cdf(x, df), df)?]
+        }
+        "chi2cdf" => {
+            let k = params[0];
+            let density = match x > 0.0 {
+                true => ((k / 2.0 - 1.0) * x.ln() - x / 2.0 - k / 2.0 * 2f64.ln() - special::ln_gamma(k / 2.0)).exp(),
+                false => 0.0,
+            };
+            vec![density, difference(|k| distribution(x, &[k]), k)?]
+        }
+        "fcdf" => {
+            let (d1, d2) = (params[0], params[1]);
+            let density = match x > 0.0 {
+                true => (d1 / 2.0 * (d1 * x).ln() + d2 / 2.0 * d2.ln()
+                    - (d1 + d2) / 2.0 * (d1 * x + d2).ln()
+                    - x.ln()
+                    - special::ln_gamma(d1 / 2.0)
+                    - special::ln_gamma(d2 / 2.0)
+                    + special::ln_gamma((d1 + d2) / 2.0))
+                    .exp(),
+                false => 0.0,
+            };
+            vec![
+                density,
+                difference(|d1| fisher_cdf(x, d1, d2), d1)?,
+                difference(|d2| fisher_cdf(x, d1, d2), d2)?,
+            ]
+        }
+        "expcdf" => {
+            let rate = params[0];
+            match x > 0.0 {
+                true => vec![rate * (-rate * x).exp(), x * (-rate * x).exp()],
+                false => vec![0.0, 0.0],
+            }
+        }
+        "unifcdf" => {
+            let (a, b) = (params[0], params[1]);
+            match a < x && x < b {
+                true => vec![1.0 / (b - a), (x - b) / (b - a).powi(2), -(x - a) / (b - a).powi(2)],
+                false => vec![0.0, 0.0, 0.0],
+            }
+        }
+        name => unreachable!("{name} is not a distribution"),
+    };
+
+    Ok(partials[..=params.len()].to_vec())
+}
+
+/// The density of the standard normal distribution.
+fn normal_pdf(z: f64) -> f64 {
+    (-0.5 * z * z).exp() / (2.0 * PI).sqrt()
+}
+
+/// The derivative of a function at a positive point, from a central difference with a step small
+/// enough next to the point that it stays positive.
+fn difference(f: impl Fn(f64) -> Result<f64, EvalError>, x: f64) -> Result<f64, EvalError> {
+    let h = 1e-5 * x;
+    Ok((f(x + h)? - f(x - h)?) / (2.0 * h))
+}
+
+fn positive(value: f64, name: &str) -> Result<f64, EvalError> {
+    match value > 0.0 {
+        true => Ok(value),
+        false => Err(EvalError::InvalidArgument(format!(
+            "{name} has to be positive, not {value}"
+        ))),
+    }
+}
+
+fn probability(p: f64) -> Result<f64, EvalError> {
+    match (0.0..=1.0).contains(&p) {
+        true => Ok(p),
+        false => Err(EvalError::InvalidArgument(format!(
+            "A probability has to be between 0 and 1, not {p}"
+        ))),
+    }
+}
+
+fn count(n: f64, name: &str) -> Result<f64, EvalError> {
+    match n >= 0.0 && n.fract() == 0.0 {
+        true => Ok(n),
+        false => Err(EvalError::InvalidArgument(format!(
+            "{name} has to be a non-negative integer, not {n}"
+        ))),
+    }
+}
+
+/// The probability of `k` successes in `n` trials. Loader's saddle point expansion keeps the
+/// result accurate far into the tails, where subtracting large log-factorials would lose it.
+fn binomial_pdf(k: f64, n: f64, p: f64) -> f64 {
+    if k < 0.0 || k > n || k.fract() != 0.0 {
+        return 0.0;
+    }
+
+    let q = 1.0 - p;
+    match (k, p, q) {
+        (_, 0.0, _) => (k == 0.0) as u8 as f64,
+        (_, _, 0.0) => (k == n) as u8 as f64,
+        (0.0, _, _) => (n * (-p).ln_1p()).exp(),
+        (k, _, _) if k == n => (n * p.ln()).exp(),
+        _ => {
+            let lc = stirling_error(n)
+                - stirling_error(k)
+                - stirling_error(n - k)
+                - deviance(k, n * p)
+                - deviance(n - k, n * q);
+            let lf = (2.0 * PI).ln() + k.ln() + (-k / n).ln_1p();
+            (lc - 0.5 * lf).exp()
+        }
+    }
+}
+
+/// The probability of at most `k` successes in `n` trials, from the incomplete beta function.
+fn binomial_cdf(k: f64, n: f64, p: f64) -> Result<f64, EvalError> {
+    let k = k.floor();
+    if k < 0.0 {
+        return Ok(0.0);
+    }
+    if k >= n {
+        return Ok(1.0);
+    }
+
+    special::beta_inc(k + 1.0, n - k, p).map(|(_, complement)| complement)
+}
+
+/// The probability of `k` events when `mean` are expected, using the same expansion as
+/// [`binomial_pdf`].
+fn poisson_pdf(k: f64, mean: f64) -> f64 {
+    match k {
+        k if k < 0.0 || k.fract() != 0.0 => 0.0,
+        0.0 => (-mean).exp(),
+        k => (-stirling_error(k) - deviance(k, mean)).exp() / (2.0 * PI * k).sqrt(),
+    }
+}
+
+/// The cumulative distribution function of Student's t distribution. The tail is the incomplete
+/// beta function of whichever of `x^2 / (df + x^2)` and `df / (df + x^2)` is smaller, so it
+/// doesn't lose precision to cancellation.
+fn student_cdf(x: f64, df: f64) -> Result<f64, EvalError> {
+    let tail = if x * x < df {
+        special::beta_inc(0.5, df / 2.0, x * x / (df + x * x))?.1 / 2.0
+    } else {
+        special::beta_inc(df / 2.0, 0.5, df / (df + x * x))?.0 / 2.0
+    };
+
+    Ok(if x < 0.0 { tail } else { 1.0 - tail })
+}
+
+/// The cumulative distribution function of the F distribution, chosen like [`student_cdf`].
+fn fisher_cdf(x: f64, d1: f64, d2: f64) -> Result<f64, EvalError> {
+    if x <= 0.0 {
+        return Ok(0.0);
+    }
+
+    if d1 * x < d2 {
+        special::beta_inc(d1 / 2.0, d2 / 2.0, d1 * x / (d1 * x + d2)).map(|(i, _)| i)
+    } else {
+        special::beta_inc(d2 / 2.0, d1 / 2.0, d2 / (d1 * x + d2)).map(|(_, complement)| complement)
+    }
+}
+
+/// The error of Stirling's approximation, `ln(n!) - ln(sqrt(2 pi n) (n / e)^n)`, for a whole
+/// number `n`. Small factorials are exact, and the asymptotic series is used for the rest.
+fn stirling_error(n: f64) -> f64 {
+    if n <= 15.0 {
+        let factorial = (1..=n as u64).product::<u64>() as f64;
+        return factorial.ln() - (n + 0.5) * n.ln() + n - 0.5 * (2.0 * PI).ln();
+    }
+
+    let n2 = n * n;
+    (1.0 / 12.0 - (1.0 / 360.0 - (1.0 / 1260.0 - (1.0 / 1680.0 - 1.0 / (1188.0 * n2)) / n2) / n2) / n2)
+        / n
+}
+
+/// The deviance term `x ln(x / m) + m - x`, computed with a series when `x` is close to `m` so
+/// that it doesn't cancel.
+fn deviance(x: f64, m: f64) -> f64 {
+    if (x - m).abs() >= 0.1 * (x + m) {
+        return x * (x / m).ln() + m - x;
+    }
+
+    let v = (x - m) / (x + m);
+    let mut sum = (x - m) * v;
+    let mut term = 2.0 * x * v;
+    for j in 1.. {
+        term *= v * v;
+        let next = sum + term / (2 * j + 1) as f64;
+        if next == sum {
+            break;
+        }
+        sum = next;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_relative;
+
+    #[test]
+    fn test_normal() {
+        assert_relative(evaluate("normpdf", 0.0, &[]).unwrap(), 1.0 / (2.0 * PI).sqrt(), 1e-12);
+        assert_relative(
+            evaluate("normcdf", 130.0, &[100.0, 15.0]).unwrap(),
+            0.977_249_868_051_820_8,
+            1e-12,
+        );
+        assert_relative(evaluate("norminv", 0.05, &[]).unwrap(), -1.644_853_626_951_472_2, 1e-12);
+    }
+
+    #[test]
+    fn test_discrete() {
+        assert_relative(evaluate("binompdf", 3.0, &[10.0, 0.5]).unwrap(), 120.0 / 1024.0, 1e-12);
+        assert_relative(evaluate("binomcdf", 5.0, &[10.0, 0.5]).unwrap(), 638.0 / 1024.0, 1e-12);
+        assert_relative(evaluate("poissonpdf", 2.0, &[3.0]).unwrap(), 4.5 * (-3f64).exp(), 1e-12);
+        assert_eq!(evaluate("binompdf", 2.5, &[10.0, 0.5]), Ok(0.0));
+
+        // The far tail, where 1000 choose 10 times 0.5^1000 is around 1e-278.
+        let tail = evaluate("binompdf", 10.0, &[1000.0, 0.5]).unwrap();
+        assert_relative(tail, 2.458_305_595_450_807_4e-278, 1e-12);
+    }
+
+    #[test]
+    fn test_continuous() {
+        assert_relative(evaluate("tcdf", 2.228_138_851_986_274, &[10.0]).unwrap(), 0.975, 1e-12);
+        assert_relative(evaluate("tcdf", -50.0, &[3.0]).unwrap(), 8.808_576_020_635_987e-6, 1e-12);
+        assert_relative(evaluate("chi2cdf", 3.841_458_820_694_124, &[1.0]).unwrap(), 0.95, 1e-12);
+        assert_relative(evaluate("fcdf", 1.0, &[5.0, 5.0]).unwrap(), 0.5, 1e-12);
+        assert_relative(evaluate("expcdf", 1.0, &[2.0]).unwrap(), 1.0 - (-2f64).exp(), 1e-12);
+        assert_relative(evaluate("unifcdf", 0.25, &[0.0, 2.0]).unwrap(), 0.125, 1e-12);
+    }
+
+    #[test]
+    fn test_partials() {
+        // Each partial derivative is compared with a central difference of the function.
+        let cases: [(&str, f64, &[f64]); 11] = [
+            ("normpdf", 0.7, &[0.2, 1.5]),
+            ("normcdf", 130.0, &[100.0, 15.0]),
+            ("norminv", 0.3, &[1.0, 2.0]),
+            ("binompdf", 3.0, &[10.0, 0.3]),
+            ("binomcdf", 3.0, &[10.0, 0.3]),
+            ("poissonpdf", 2.0, &[3.0]),
+            ("tcdf", 1.3, &[4.0]),
+            ("chi2cdf", 2.5, &[3.0]),
+            ("fcdf", 1.2, &[4.0, 7.0]),
+            ("expcdf", 0.8, &[1.5]),
+            ("unifcdf", 0.5, &[0.0, 2.0]),
+        ];
+
+        for (name, x, params) in cases {
+            let partials = partials(name, x, params).unwrap();
+            assert_eq!(partials.len(), params.len() + 1);
+
+            let mut point = vec![x];
+            point.extend(params);
+            let f = |point: &[f64]| evaluate(name, point[0], &point[1..]).unwrap();
+            for (i, partial) in partials.iter().enumerate() {
+                // Counts are integers, so they don't change smoothly.
+                if name.starts_with("binom") && i < 2 || name == "poissonpdf" && i == 0 {
+                    assert_eq!(*partial, 0.0);
+                    continue;
+                }
+
+                let h = 1e-6 * point[i].abs().max(1.0);
+                let (mut above, mut below) = (point.clone(), point.clone());
+                above[i] += h;
+                below[i] -= h;
+                let expected = (f(&above) - f(&below)) / (2.0 * h);
+                assert!((partial - expected).abs() <= 1e-6 * expected.abs().max(1e-3), "{name}: {partial} is not close to {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(evaluate("normcdf", 0.0, &[0.0, -1.0]).is_err());
+        assert!(evaluate("norminv", 1.5, &[]).is_err());
+        assert!(evaluate("binompdf", 1.0, &[2.5, 0.5]).is_err());
+        assert!(evaluate("unifcdf", 1.0, &[2.0, 1.0]).is_err());
+    }
+}
@@ -5,6 +5,33 @@ pub enum EvalError {
     NoFunctionArguments(String),
     InvalidArgumentCount { expected: usize, got: usize },
     ProgramIsStupid,
+    ProgrammerModeOnly(String),
+    NotInProgrammerMode(String),
+    NotInDecimalMode(String),
+    NotInModularMode(String),
+    NotAnInteger(f64),
+    Overflow,
+    DivisionByZero,
+    NoInverse { value: u64, modulus: u64 },
+    NegativeShift,
+    NegativeExponent,
+    UnknownCommand(String),
+    ExpectedVariable(String),
+    NotDifferentiable(String),
+    NoConvergence { iterations: usize },
+    NoSignChange,
+    TooManyTerms { limit: usize },
+    NoLimit,
+    Unbounded,
+    NotAPolynomial(String),
+    DegreeTooHigh { limit: usize },
+    NotANumber(String),
+    ShapeMismatch { expected: (usize, usize), got: (usize, usize) },
+    LengthMismatch { expected: usize, got: usize },
+    NotSquare { rows: usize, columns: usize },
+    SingularMatrix,
+    InvalidOperation(String),
+    InvalidArgument(String),
     // InvalidToken,
     // InvalidOperator,
     // InvalidNumber,
@@ -1,62 +1,760 @@
-use crate::{ast::{self, *}, errors::EvalError, tokenize::{self, *}};
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Rc};
+
+use crate::{ast::{self, *}, autodiff, complex::Complex, datetime, distributions, errors::EvalError, finance, fit, fraction, identify, integrate, interpolate::{Interpolant, Interpolation}, limit, matrix::Matrix, modular, montecarlo, ode, optimize, polynomial, random::{self, Random}, series, signal, solve, statistics, symbolic, tokenize::{self, *}, uncertainty::Uncertain, value::Value};
+
+/// The most numbers a range like `1..10` can have.
+const MAX_RANGE: usize = 1_000_000;
+
+/// The values of the variables an expression is evaluated with, like the `x` that `integrate`
+/// binds while evaluating its expression, or the ones defined in the REPL with `x = expr`.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    variables: HashMap<String, Value>,
+    /// The expressions of the defined variables in the order they were defined, so they can be
+    /// drawn again when they're random.
+    definitions: Vec<(String, ASTNode)>,
+    /// The generator used by the random functions. It's shared by every copy of the scope, so
+    /// bound variables don't repeat the numbers drawn outside them.
+    random: Rc<RefCell<Random>>,
+    /// The terms added up so far by the sum or product being evaluated and the ones nested in
+    /// it, or `None` outside of them. It's shared like the generator, so nested sums count
+    /// against the same limit.
+    terms: Rc<Cell<Option<usize>>>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty scope whose random functions draw from the given seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Scope {
+            random: Rc::new(RefCell::new(Random::new(seed))),
+            ..Self::default()
+        }
+    }
+
+    /// Restarts the random numbers from the given seed.
+    pub fn reseed(&self, seed: u64) {
+        *self.random.borrow_mut() = Random::new(seed);
+    }
+
+    /// Defines a variable as the value of the expression, replacing an earlier definition.
+    /// Returns the value.
+    pub fn define(&mut self, name: &str, ast: &ASTNode) -> Result<Value, EvalError> {
+        let value = evaluate(ast, self)?;
+
+        // A definition in terms of the variable itself, like `x = x + 1`, is only evaluated once.
+        self.definitions.retain(|(defined, _)| defined != name);
+        if !ast.contains_variable(name) {
+            self.definitions.push((name.to_string(), ast.clone()));
+        }
+
+        self.set_value(name, value.clone());
+        Ok(value)
+    }
+
+    /// Gives the variable a value worked out outside of the scope, like a decimal, replacing an
+    /// earlier definition.
+    pub fn assign(&mut self, name: &str, value: Value) {
+        self.definitions.retain(|(defined, _)| defined != name);
+        self.set_value(name, value);
+    }
+
+    /// Returns a copy of the scope where the defined variables are evaluated again, so the ones
+    /// that depend on random functions get new values.
+    pub fn resample(&self) -> Result<Scope, EvalError> {
+        let mut scope = self.clone();
+        for (name, ast) in &self.definitions {
+            let value = evaluate(ast, &scope)?;
+            scope.set_value(name, value);
+        }
+        Ok(scope)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    /// Gives the variable a number as its value, replacing the one it had.
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.set_value(name, Value::Number(value));
+    }
+
+    /// Evaluates a sum or product. The terms of the outermost one and of every one nested in it
+    /// count against the same `series::MAX_TERMS`, so nesting them can't multiply the work.
+    pub fn count_terms<T>(&self, f: impl FnOnce() -> T) -> T {
+        let outermost = self.terms.get().is_none();
+        if outermost {
+            self.terms.set(Some(0));
+        }
+
+        let result = f();
+        if outermost {
+            self.terms.set(None);
+        }
+        result
+    }
+
+    /// Counts a term of the sum or product being evaluated, failing once there are too many.
+    pub fn count_term(&self) -> Result<(), EvalError> {
+        let count = self.terms.get().unwrap_or(0) + 1;
+        if count > series::MAX_TERMS {
+            return Err(EvalError::TooManyTerms { limit: series::MAX_TERMS });
+        }
+        self.terms.set(Some(count));
+        Ok(())
+    }
+
+    /// Gives the variable a value, replacing the one it had.
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        match self.variables.get_mut(name) {
+            Some(v) => *v = value,
+            None => {
+                self.variables.insert(name.to_string(), value);
+            }
+        }
+    }
+}
 
 /// Evaluates the given expression
 pub fn evaluate_expression(expr: &str) -> Result<f64, EvalError> {
     let tokens = tokenize::parse_expression(expr)?;
     let ast = ast::get_ast(&tokens)?;
-    Ok(evaluate_ast(ast))
+    evaluate_ast(&ast, &Scope::new())
 }
 
-/// Recursively collapses the ast and evaluated each node, then returns the resulting number.
-pub fn evaluate_ast(mut ast: ASTNode) -> f64 {
-    if let Token::Number(n) | Token::Constant(n) = ast.token {
-        return n;
+/// Evaluates the expression to a number. Expressions that give something else, like a matrix or
+/// the roots of a polynomial, are an error.
+pub fn evaluate_ast(ast: &ASTNode, scope: &Scope) -> Result<f64, EvalError> {
+    match evaluate(ast, scope)? {
+        Value::Number(n) => Ok(n),
+        _ => Err(EvalError::NotANumber(ast.to_string())),
     }
+}
 
-    if let Token::Function(ref func) = ast.token {
-        let mut args = vec![];
-        for child in ast.children {
-            args.push(evaluate_ast(child));
+/// Recursively collapses the ast and evaluated each node, then returns the resulting value.
+/// Variables get their values from the scope.
+pub fn evaluate(ast: &ASTNode, scope: &Scope) -> Result<Value, EvalError> {
+    match ast.token {
+        Token::Number(n) | Token::Constant(n) => return Ok(Value::Number(n)),
+        Token::Integer(n) => return Ok(Value::Number(n as f64)),
+        Token::Timestamp(t) => return Ok(Value::Time(t)),
+        Token::Duration(d) => return Ok(Value::Duration(d)),
+        // `now` and `today` are read from the clock unless they're defined as variables.
+        // Decimals defined in decimal mode are used as the closest float.
+        Token::Variable(ref name) => {
+            return scope
+                .get(name)
+                .map(|value| match value {
+                    Value::Decimal(d) => Value::Number(d.to_f64()),
+                    value => value.clone(),
+                })
+                .or_else(|| datetime::clock(name))
+                .ok_or_else(|| EvalError::UnknownKeyword(name.clone()))
         }
+        Token::Matrix { rows, columns } => {
+            let mut data = vec![];
+            for child in &ast.children {
+                data.push(evaluate_ast(child, scope)?);
+            }
+            return Ok(Value::Matrix(Matrix::new(rows, columns, data)));
+        }
+        _ => {}
+    }
 
-        match func.0.name {
-            "sin" => return args[0].sin(),
-            "cos" => return args[0].cos(),
-            "tan" => return args[0].tan(),
-            "arcsin" => return args[0].asin(),
-            "arccos" => return args[0].acos(),
-            "arctan" => return args[0].atan(),
-            "log" => return args[1].log(args[0]),
-            "ln" => return args[0].ln(),
-            "sqrt" => return args[0].sqrt(),
-            _ => unreachable!("Unknown function"),
+    // Derivatives and other symbolic functions are worked out before being evaluated.
+    if ast.function_name().is_some_and(|name| symbolic::SYMBOLIC_FUNCTIONS.contains(&name)) {
+        return evaluate(&symbolic::expand(ast, scope)?, scope);
+    }
+
+    // Functions that bind a variable evaluate their expression themselves.
+    if ast.function_name() == Some("integrate") {
+        return integrate::evaluate_integral(ast, scope).map(|integral| Value::Number(integral.value));
+    }
+
+    if ast.function_name() == Some("solve") && ast.bound_arguments().is_some() {
+        return solve::evaluate_solve(ast, scope).map(Value::Number);
+    }
+
+    if ast.function_name() == Some("limit") {
+        return limit::evaluate_limit(ast, scope).map(Value::Number);
+    }
+
+    if ast.function_name() == Some("roots") {
+        let roots = polynomial::evaluate_roots(ast, scope)?;
+        return Ok(Value::List(roots.into_iter().map(Value::from).collect()));
+    }
+
+    if ast.function_name() == Some("polydiv") {
+        let (quotient, remainder) = polynomial::evaluate_polydiv(ast, scope)?;
+        return Ok(Value::List(vec![
+            Value::Expression(quotient),
+            Value::Expression(remainder),
+        ]));
+    }
+
+    // sum and prod of a single list add up or multiply its elements instead.
+    if matches!(ast.function_name(), Some("sum" | "prod")) {
+        match ast.children.len() {
+            1 => {}
+            4 => return series::evaluate_series(ast, scope).map(Value::Number),
+            got => return Err(EvalError::InvalidArgumentCount { expected: 4, got }),
         }
     }
 
-    if let Token::Operator(ref op) = ast.token {
+    if ast.function_name() == Some("montecarlo") {
+        return montecarlo::evaluate_montecarlo(ast, scope).map(|simulation| Value::Number(simulation.mean));
+    }
 
-        let left = evaluate_ast(ast.children.remove(0));
-        let right= evaluate_ast(ast.children.remove(0));
+    if ast.function_name() == Some("fit") {
+        return fit::evaluate_fit(ast, scope).map(Value::Fit);
+    }
 
+    if matches!(ast.function_name(), Some("minimize" | "maximize")) {
+        return optimize::evaluate_minimize(ast, scope);
+    }
+
+    if ast.function_name() == Some("odesolve") {
+        return ode::evaluate_odesolve(ast, scope);
+    }
+
+    if ast.function_name() == Some("window") {
+        return signal::evaluate_window(ast, scope);
+    }
+
+    if ast.function_name() == Some("tz") {
+        return datetime::evaluate_tz(ast, scope);
+    }
+
+    // Modular inverses, discrete logarithms and the Chinese remainder theorem work on exact
+    // integers, and so does `x mod n` when it can.
+    if modular::is_modular_function(ast) {
+        return modular::evaluate_function(ast, scope, None).map(|n| Value::Number(n as f64));
+    }
+
+    if let Some(remainder) = modular::evaluate_modulo(ast, scope)? {
+        return Ok(Value::Number(remainder));
+    }
+
+    if ast.function_name() == Some("map") {
+        return evaluate_map(ast, scope);
+    }
+
+    if let Token::List = ast.token {
+        let mut elements = vec![];
+        for child in &ast.children {
+            elements.push(evaluate(child, scope)?);
+        }
+        return Ok(Value::List(elements));
+    }
+
+    // Everything else works on numbers, matrices and lists. Fractions and identified numbers are
+    // used as the number they stand for.
+    let mut args = vec![];
+    for child in &ast.children {
+        match evaluate(child, scope)? {
+            Value::Fraction(fraction) => args.push(Value::Number(fraction.to_f64())),
+            Value::Identification(identification) => args.push(Value::Number(identification.value)),
+            value @ (Value::Number(_)
+            | Value::Matrix(_)
+            | Value::List(_)
+            | Value::Uncertain(_)
+            | Value::Complex(_)
+            | Value::Time(_)
+            | Value::Duration(_)) => args.push(value),
+            _ => return Err(EvalError::NotANumber(child.to_string())),
+        }
+    }
+
+    let measurement = matches!(ast.token, Token::Operator(Operator::PlusMinus));
+    if !measurement && args.iter().any(|arg| matches!(arg, Value::Uncertain(_))) {
+        return propagate(ast, &args, scope);
+    }
+
+    if let Token::Call(_) = ast.token {
+        let function = called_function(ast, scope)?;
+        return try_map(&args[0], &|x| function.evaluate(x).map(|(value, _)| value));
+    }
+
+    if let Token::Function(ref func) = ast.token {
+        if random::RANDOM_FUNCTIONS.contains(&func.0.name) {
+            let params = args
+                .iter()
+                .map(|param| number(param, "A parameter of a random function"))
+                .collect::<Result<Vec<_>, _>>()?;
+            return random::evaluate(func.0.name, &params, &mut scope.random.borrow_mut())
+                .map(Value::Number);
+        }
+
+        return evaluate_function(func.0.name, &args);
+    }
+
+    if let Token::Operator(Operator::Unary(op)) = ast.token {
         return match op {
-            Operator::Additive(op) => match op {
-                Additive::Add => left + right,
-                Additive::Subtract => left - right,
+            Unary::Negate => match &args[0] {
+                Value::Duration(d) => Ok(Value::Duration(datetime::Duration { seconds: -d.seconds })),
+                arg => map(arg, |a| -a),
             },
-            Operator::Multiplicative(op) => match op {
-                Multiplicative::Multiply => left * right,
-                Multiplicative::Divide => left / right,
-            },
-            Operator::Exponential => left.powf(right),
-        }
+            Unary::Complement => Err(EvalError::ProgrammerModeOnly("~".to_string())),
+        };
+    }
+
+    if let Token::Operator(op) = ast.token {
+        return evaluate_operator(op, &args[0], &args[1]);
     }
 
     unreachable!("Invalid AST")
 }
 
+/// Returns the function a call like `f(2.5)` calls, which is the value of the variable. It has
+/// to be called with a single argument.
+pub fn called_function<'a>(ast: &ASTNode, scope: &'a Scope) -> Result<&'a Interpolant, EvalError> {
+    let Token::Call((ref name, _)) = ast.token else {
+        unreachable!("{ast} is not a call");
+    };
+
+    match (scope.get(name), ast.children.len()) {
+        (Some(Value::Function(function)), 1) => Ok(function),
+        (Some(Value::Function(_)), got) => Err(EvalError::InvalidArgumentCount { expected: 1, got }),
+        (Some(_), _) => Err(EvalError::InvalidOperation(format!(
+            "`{name}` isn't a function, so it can't be called"
+        ))),
+        (None, _) => Err(EvalError::UnknownKeyword(name.clone())),
+    }
+}
+
+/// Evaluates a call to `map(expr, x, list)`, which evaluates the expression with `x` set to each
+/// element of the list.
+fn evaluate_map(ast: &ASTNode, scope: &Scope) -> Result<Value, EvalError> {
+    let var = ast.children[1].variable_name("map")?;
+    let values = numbers(&evaluate(&ast.children[2], scope)?)?;
+
+    let mut inner = scope.clone();
+    let mut results = vec![];
+    for value in values {
+        inner.set(&var, value);
+        results.push(evaluate(&ast.children[0], &inner)?);
+    }
+    Ok(Value::List(results))
+}
+
+/// Applies a function to numbers, matrices or lists. Functions of a single number are applied to
+/// every element of a matrix or list, numbers are treated as 1x1 matrices by the matrix
+/// functions, and the elements of a matrix are treated as a list by the list functions.
+fn evaluate_function(name: &str, args: &[Value]) -> Result<Value, EvalError> {
+    let list = |values: Vec<f64>| Value::List(values.into_iter().map(Value::Number).collect());
+
+    match name {
+        "sin" => map(&args[0], f64::sin),
+        "cos" => map(&args[0], f64::cos),
+        "tan" => map(&args[0], f64::tan),
+        "arcsin" => map(&args[0], f64::asin),
+        "arccos" => map(&args[0], f64::acos),
+        "arctan" => map(&args[0], f64::atan),
+        "log" => broadcast(&args[0], &args[1], |b, y| y.log(b)),
+        "ln" => map(&args[0], f64::ln),
+        "sqrt" => map(&args[0], f64::sqrt),
+        "round" => {
+            let places = match args.get(1) {
+                Some(places) => number(places, "The number of decimal places")?,
+                None => 0.0,
+            };
+            if places.fract() != 0.0 {
+                return Err(EvalError::NotAnInteger(places));
+            }
+            let scale = 10f64.powf(places);
+            try_map(&args[0], &|x| Ok((x * scale).round() / scale))
+        }
+        "transpose" => Ok(Value::from(to_matrix(&args[0])?.transpose())),
+        "det" => to_matrix(&args[0])?.det().map(Value::Number),
+        "inv" => to_matrix(&args[0])?.inverse().map(Value::from),
+        "rank" => Ok(Value::Number(to_matrix(&args[0])?.rank() as f64)),
+        "eig" => {
+            let eigenvalues = to_matrix(&args[0])?.eigenvalues()?;
+            Ok(Value::List(eigenvalues.into_iter().map(Value::from).collect()))
+        }
+        "norm" => {
+            let p = match args.get(1) {
+                Some(p) => number(p, "p")?,
+                None => 2.0,
+            };
+            to_matrix(&args[0])?.norm(p).map(Value::Number)
+        }
+        // solve(A, b) solves the linear system A x = b.
+        "solve" => to_matrix(&args[0])?.solve(&to_matrix(&args[1])?).map(Value::from),
+        "mean" => statistics::mean(&numbers(&args[0])?).map(Value::Number),
+        "median" => statistics::median(&numbers(&args[0])?).map(Value::Number),
+        "mode" => statistics::mode(&numbers(&args[0])?).map(Value::Number),
+        "stdev" => statistics::stdev(&numbers(&args[0])?).map(Value::Number),
+        "var" => statistics::variance(&numbers(&args[0])?).map(Value::Number),
+        "percentile" => {
+            let p = number(&args[1], "The percentile")?;
+            statistics::percentile(&numbers(&args[0])?, p).map(Value::Number)
+        }
+        "sort" => Ok(list(statistics::sorted(&numbers(&args[0])?))),
+        "len" => match &args[0] {
+            Value::List(elements) => Ok(Value::Number(elements.len() as f64)),
+            value => Ok(Value::Number(numbers(value)?.len() as f64)),
+        },
+        "sum" => Ok(Value::Number(numbers(&args[0])?.iter().sum())),
+        "prod" => Ok(Value::Number(numbers(&args[0])?.iter().product())),
+        "min" => extreme(&args[0], "min", f64::min),
+        "max" => extreme(&args[0], "max", f64::max),
+        "cumsum" => Ok(list(statistics::cumsum(&numbers(&args[0])?))),
+        "linfit" => fit::linear(&numbers(&args[0])?, &numbers(&args[1])?).map(Value::Fit),
+        "polyfit" => {
+            let degree = number(&args[2], "The degree")?;
+            fit::polynomial(&numbers(&args[0])?, &numbers(&args[1])?, degree).map(Value::Fit)
+        }
+        "expfit" => fit::exponential(&numbers(&args[0])?, &numbers(&args[1])?).map(Value::Fit),
+        "fft" => Ok(signal::values(signal::fft(&complexes(&args[0])?))),
+        "ifft" => Ok(signal::values(signal::ifft(&complexes(&args[0])?))),
+        "powerspectrum" => Ok(list(signal::power_spectrum(&complexes(&args[0])?))),
+        "convolve" => Ok(signal::values(signal::convolve(&complexes(&args[0])?, &complexes(&args[1])?))),
+        "freqs" => {
+            let n = signal::length(number(&args[0], "The number of samples")?)?;
+            let rate = number(&args[1], "The sampling rate")?;
+            Ok(list(signal::frequencies(n, rate)))
+        }
+        name if finance::TIME_VALUE.contains(&name) => {
+            let params = args
+                .iter()
+                .map(|param| number(param, "A parameter of a time value function"))
+                .collect::<Result<Vec<_>, _>>()?;
+            finance::time_value(name, &params).map(Value::Number)
+        }
+        "weekday" => Ok(Value::Number(time(&args[0])?.weekday() as f64)),
+        "workdays" => Ok(Value::Number(datetime::workdays(&time(&args[0])?, &time(&args[1])?))),
+        "npv" => finance::npv(number(&args[0], "The rate")?, &numbers(&args[1])?).map(Value::Number),
+        "irr" => {
+            let guess = match args.get(1) {
+                Some(guess) => Some(number(guess, "The guess")?),
+                None => None,
+            };
+            finance::irr(&numbers(&args[0])?, guess).map(Value::Number)
+        }
+        "rat" => {
+            let x = number(&args[0], "The number to approximate")?;
+            fraction::best_approximation(x, number(&args[1], "The largest denominator")?).map(Value::Fraction)
+        }
+        "cf" => {
+            let x = number(&args[0], "The number to expand")?;
+            fraction::continued_fraction(x, number(&args[1], "The number of terms")?).map(list)
+        }
+        "identify" => identify::identify(number(&args[0], "The number to identify")?).map(Value::Identification),
+        "amort" => {
+            let principal = number(&args[0], "The principal")?;
+            let rate = number(&args[1], "The rate")?;
+            let periods = number(&args[2], "The number of periods")?;
+            finance::amortization(principal, rate, periods).map(Value::Matrix)
+        }
+        "interp" => {
+            let table = Interpolant::new(Interpolation::Linear, &numbers(&args[1])?, &numbers(&args[2])?)?;
+            try_map(&args[0], &|x| table.evaluate(x).map(|(value, _)| value))
+        }
+        "spline" | "pchip" => {
+            let kind = match name {
+                "spline" => Interpolation::Spline,
+                _ => Interpolation::Pchip,
+            };
+            Interpolant::new(kind, &numbers(&args[0])?, &numbers(&args[1])?).map(Value::Function)
+        }
+        name if distributions::DISTRIBUTIONS.contains(&name) => {
+            let params = args[1..]
+                .iter()
+                .map(|param| number(param, "A parameter of a distribution"))
+                .collect::<Result<Vec<_>, _>>()?;
+            try_map(&args[0], &|x| distributions::evaluate(name, x, &params))
+        }
+        "rotl" | "rotr" | "popcount" | "clz" | "ctz" => Err(EvalError::ProgrammerModeOnly(name.to_string())),
+        name => Err(EvalError::UnknownKeyword(name.to_string())),
+    }
+}
+
+/// Applies a binary operator to numbers, matrices or lists. `*`, `/` and `^` follow the rules of
+/// matrix algebra when both sides are matrices, while the element-wise operators and `+` and `-`
+/// work element by element. Lists are always combined element by element, and a number is
+/// combined with every element of a matrix or list. Dates and durations follow the rules of
+/// [`datetime::operate`].
+fn evaluate_operator(op: Operator, left: &Value, right: &Value) -> Result<Value, EvalError> {
+    if let Some(result) = datetime::operate(op, left, right) {
+        return result;
+    }
+    if let (Operator::Multiplicative(Multiplicative::Modulo), Value::Number(b)) = (op, right) {
+        if *b == 0.0 {
+            return Err(EvalError::DivisionByZero);
+        }
+    }
+
+    let scalar: fn(f64, f64) -> f64 = match op {
+        Operator::Additive(Additive::Add) => |a, b| a + b,
+        Operator::Additive(Additive::Subtract) => |a, b| a - b,
+        Operator::Multiplicative(Multiplicative::Multiply)
+        | Operator::ElementWise(ElementWise::Multiply) => |a, b| a * b,
+        Operator::Multiplicative(Multiplicative::Divide)
+        | Operator::ElementWise(ElementWise::Divide) => |a, b| a / b,
+        // The remainder has the sign of the divisor, so `-1 mod 3` is 2.
+        Operator::Multiplicative(Multiplicative::Modulo) => |a, b| (a % b + b) % b,
+        Operator::Exponential | Operator::ElementWise(ElementWise::Power) => f64::powf,
+        Operator::Range => return range(left, right),
+        Operator::PlusMinus => return measure(left, right),
+        Operator::Shift(Shift::Left) => return Err(EvalError::ProgrammerModeOnly("<<".to_string())),
+        Operator::Shift(Shift::Right) => return Err(EvalError::ProgrammerModeOnly(">>".to_string())),
+        Operator::BitAnd => return Err(EvalError::ProgrammerModeOnly("&".to_string())),
+        Operator::BitOr => return Err(EvalError::ProgrammerModeOnly("|".to_string())),
+        Operator::BitXor => return Err(EvalError::ProgrammerModeOnly("xor".to_string())),
+        Operator::Unary(_) => unreachable!("Unary operators are handled above"),
+    };
+
+    match (op, left, right) {
+        (Operator::Multiplicative(Multiplicative::Multiply), Value::Matrix(a), Value::Matrix(b)) => {
+            a.matmul(b).map(Value::from)
+        }
+        // Dividing by a matrix multiplies by its inverse.
+        (
+            Operator::Multiplicative(Multiplicative::Divide),
+            Value::Number(_) | Value::Matrix(_),
+            Value::Matrix(b),
+        ) => to_matrix(left)?.matmul(&b.inverse()?).map(Value::from),
+        (Operator::Exponential, Value::Matrix(a), Value::Number(n)) => match n.fract() {
+            0.0 => a.pow(*n as i64).map(Value::from),
+            _ => Err(EvalError::NotAnInteger(*n)),
+        },
+        (Operator::Exponential, Value::Number(_) | Value::Matrix(_), Value::Matrix(_)) => {
+            Err(EvalError::InvalidOperation(
+                "Can't raise to the power of a matrix, use `.^` to raise element by element"
+                    .to_string(),
+            ))
+        }
+        _ => broadcast(left, right, scalar),
+    }
+}
+
+/// The list of numbers from `a` up to `b` in steps of one, which is empty when `b` is less than
+/// `a`.
+fn range(left: &Value, right: &Value) -> Result<Value, EvalError> {
+    let (Value::Number(a), Value::Number(b)) = (left, right) else {
+        return Err(EvalError::InvalidOperation(
+            "The ends of a range have to be numbers".to_string(),
+        ));
+    };
+
+    let length = ((b - a).floor() + 1.0).max(0.0);
+    if length > MAX_RANGE as f64 {
+        return Err(EvalError::InvalidArgument(format!(
+            "A range can't have more than {MAX_RANGE} numbers"
+        )));
+    }
+
+    Ok(Value::List(
+        (0..length as usize).map(|i| Value::Number(a + i as f64)).collect(),
+    ))
+}
+
+/// A measured value with a standard uncertainty, like `9.81 ± 0.02`.
+fn measure(left: &Value, right: &Value) -> Result<Value, EvalError> {
+    let (Value::Number(value), Value::Number(uncertainty)) = (left, right) else {
+        return Err(EvalError::InvalidOperation(
+            "Both sides of `±` have to be numbers".to_string(),
+        ));
+    };
+
+    if *uncertainty < 0.0 {
+        return Err(EvalError::InvalidArgument(format!(
+            "An uncertainty can't be negative, but it's {uncertainty}"
+        )));
+    }
+
+    Ok(Value::Uncertain(Uncertain::new(*value, *uncertainty)))
+}
+
+/// Carries the uncertainties of the arguments through a function or operator to first order,
+/// using the partial derivatives that automatic differentiation uses.
+fn propagate(ast: &ASTNode, args: &[Value], scope: &Scope) -> Result<Value, EvalError> {
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            Value::Number(n) => Ok(Uncertain::from(*n)),
+            Value::Uncertain(u) => Ok(u.clone()),
+            _ => Err(EvalError::InvalidOperation(
+                "Uncertain values can only be combined with numbers".to_string(),
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A small uncertainty can move a rounded value by a whole step, which no derivative describes.
+    if ast.function_name() == Some("round") {
+        return Err(EvalError::InvalidOperation(
+            "Uncertainties can't be carried through `round`, since its result jumps instead of changing smoothly".to_string(),
+        ));
+    }
+
+    let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+    let (value, partials) = autodiff::apply(ast, &values, scope).map_err(|err| match err {
+        EvalError::NotDifferentiable(name) => EvalError::InvalidOperation(format!(
+            "Uncertainties can't be carried through `{name}`"
+        )),
+        err => err,
+    })?;
+
+    Ok(Value::Uncertain(Uncertain::propagate(value, args.iter().zip(partials))))
+}
+
+/// Applies the function to a number, or to every element of a matrix or list.
+fn map(value: &Value, f: fn(f64) -> f64) -> Result<Value, EvalError> {
+    match value {
+        Value::Number(a) => Ok(Value::Number(f(*a))),
+        Value::Matrix(a) => Ok(Value::Matrix(a.map(f))),
+        Value::List(elements) => elements
+            .iter()
+            .map(|element| map(element, f))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        value => Err(EvalError::NotANumber(value.to_string())),
+    }
+}
+
+/// Like [`map`], for functions that can fail.
+fn try_map(value: &Value, f: &dyn Fn(f64) -> Result<f64, EvalError>) -> Result<Value, EvalError> {
+    match value {
+        Value::Number(a) => f(*a).map(Value::Number),
+        Value::Matrix(a) => {
+            let data = a.data().iter().map(|&a| f(a)).collect::<Result<_, _>>()?;
+            Ok(Value::Matrix(Matrix::new(a.rows(), a.columns(), data)))
+        }
+        Value::List(elements) => elements
+            .iter()
+            .map(|element| try_map(element, f))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        value => Err(EvalError::NotANumber(value.to_string())),
+    }
+}
+
+/// Combines two numbers, a number with every element of a matrix or list, or two matrices or
+/// lists of the same shape element by element.
+fn broadcast(left: &Value, right: &Value, f: fn(f64, f64) -> f64) -> Result<Value, EvalError> {
+    let elements = |pairs: Vec<(&Value, &Value)>| {
+        pairs
+            .into_iter()
+            .map(|(a, b)| broadcast(a, b, f))
+            .collect::<Result<_, _>>()
+            .map(Value::List)
+    };
+
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(f(*a, *b))),
+        (Value::Matrix(a), Value::Number(b)) => Ok(Value::Matrix(a.map(|a| f(a, *b)))),
+        (Value::Number(a), Value::Matrix(b)) => Ok(Value::Matrix(b.map(|b| f(*a, b)))),
+        (Value::Matrix(a), Value::Matrix(b)) => a.zip_with(b, f).map(Value::Matrix),
+        (Value::List(a), Value::List(b)) if a.len() != b.len() => Err(EvalError::LengthMismatch {
+            expected: a.len(),
+            got: b.len(),
+        }),
+        (Value::List(a), Value::List(b)) => elements(a.iter().zip(b).collect()),
+        (Value::List(a), b @ Value::Number(_)) => elements(a.iter().map(|a| (a, b)).collect()),
+        (a @ Value::Number(_), Value::List(b)) => elements(b.iter().map(|b| (a, b)).collect()),
+        (Value::List(_), Value::Matrix(_)) | (Value::Matrix(_), Value::List(_)) => {
+            Err(EvalError::InvalidOperation(
+                "Lists and matrices can't be combined".to_string(),
+            ))
+        }
+        (Value::Number(_) | Value::Matrix(_) | Value::List(_), value) | (value, _) => {
+            Err(EvalError::NotANumber(value.to_string()))
+        }
+    }
+}
+
+/// Treats a number as a 1x1 matrix.
+fn to_matrix(value: &Value) -> Result<Matrix, EvalError> {
+    match value {
+        Value::Number(a) => Ok(Matrix::new(1, 1, vec![*a])),
+        Value::Matrix(a) => Ok(a.clone()),
+        value => Err(EvalError::InvalidArgument(format!("{value} is not a matrix"))),
+    }
+}
+
+/// The numbers in a list, or the elements of a matrix row by row. A single number is a list of
+/// one.
+pub fn numbers(value: &Value) -> Result<Vec<f64>, EvalError> {
+    match value {
+        Value::Number(a) => Ok(vec![*a]),
+        Value::Matrix(a) => Ok(a.data().to_vec()),
+        Value::List(elements) => elements
+            .iter()
+            .map(|element| match element {
+                Value::Number(a) => Ok(*a),
+                element => Err(EvalError::NotANumber(element.to_string())),
+            })
+            .collect(),
+        value => Err(EvalError::NotANumber(value.to_string())),
+    }
+}
+
+/// The numbers in a list or matrix like [`numbers`], where complex numbers are allowed too.
+fn complexes(value: &Value) -> Result<Vec<Complex>, EvalError> {
+    match value {
+        Value::Complex(z) => Ok(vec![*z]),
+        Value::List(elements) => elements
+            .iter()
+            .map(|element| match element {
+                Value::Number(a) => Ok(Complex::real(*a)),
+                Value::Complex(z) => Ok(*z),
+                element => Err(EvalError::NotANumber(element.to_string())),
+            })
+            .collect(),
+        value => Ok(numbers(value)?.into_iter().map(Complex::real).collect()),
+    }
+}
+
+/// An argument that has to be a date or moment, like the one given to `weekday`.
+fn time(value: &Value) -> Result<datetime::Timestamp, EvalError> {
+    match value {
+        Value::Time(t) => Ok(*t),
+        value => Err(EvalError::InvalidArgument(format!("{value} isn't a date or time"))),
+    }
+}
+
+/// An argument that has to be a single number, like the `p` of `norm`.
+fn number(value: &Value, name: &str) -> Result<f64, EvalError> {
+    match value {
+        Value::Number(a) => Ok(*a),
+        _ => Err(EvalError::InvalidArgument(format!("{name} has to be a number"))),
+    }
+}
+
+/// The smallest or largest of the values, depending on `f`. Of a list of uncertain values, it's
+/// the element that is the extreme, with its uncertainty, which is the first-order result
+/// everywhere except where two of them are equal.
+fn extreme(value: &Value, name: &str, f: fn(f64, f64) -> f64) -> Result<Value, EvalError> {
+    let empty = || EvalError::InvalidArgument(format!("`{name}` needs at least one value"));
+
+    let Value::List(elements) = value else {
+        return numbers(value)?.into_iter().reduce(f).map(Value::Number).ok_or_else(empty);
+    };
+    let value = |element: &Value| match element {
+        Value::Number(a) => Ok(*a),
+        Value::Uncertain(u) => Ok(u.value),
+        element => Err(EvalError::NotANumber(element.to_string())),
+    };
+
+    let mut extreme = elements.first().ok_or_else(empty)?;
+    for element in &elements[1..] {
+        let (a, b) = (value(extreme)?, value(element)?);
+        if f(a, b) != a {
+            extreme = element;
+        }
+    }
+    value(extreme)?;
+    Ok(extreme.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::assert_close;
 
     #[test]
     fn test_sin_pi() {
@@ -111,4 +809,170 @@ mod tests {
 
         assert_eq!(res, 2.0);
     }
+
+    #[test]
+    fn test_left_associativity() {
+        let expression = "1 - 2 + 3 - 8 / 2 / 2";
+
+        let res = evaluate_expression(expression).unwrap();
+
+        assert_eq!(res, 0.0);
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        let expression = "-2^2 * -3 + 2^-1";
+
+        let res = evaluate_expression(expression).unwrap();
+
+        assert_eq!(res, 12.5);
+    }
+
+    #[test]
+    fn test_bitwise_requires_programmer_mode() {
+        let expression = "6 & 3";
+
+        let res = evaluate_expression(expression);
+
+        assert_eq!(res, Err(EvalError::ProgrammerModeOnly("&".to_string())));
+        assert_eq!(evaluate_expression("popcount(6)"), Err(EvalError::ProgrammerModeOnly("popcount".to_string())));
+        assert_eq!(evaluate_function("sinn", &[]), Err(EvalError::UnknownKeyword("sinn".to_string())));
+    }
+
+    fn evaluate_value(expression: &str) -> Result<Value, EvalError> {
+        let ast = ast::get_ast(&tokenize::parse_expression(expression)?)?;
+        evaluate(&ast, &Scope::new())
+    }
+
+    #[test]
+    fn test_matrix_arithmetic() {
+        let product = evaluate_value("[1, 2; 3, 4] * [5; 6]").unwrap();
+        let element_wise = evaluate_value("[1, 2; 3, 4] .* [1, 2; 3, 4] - 1").unwrap();
+        let dot = evaluate_value("[1, 2] [3; 4]").unwrap();
+
+        assert_eq!(product.to_string(), "[17; 39]");
+        assert_eq!(element_wise.to_string(), "[0, 3; 8, 15]");
+        assert_eq!(dot, Value::Number(11.0));
+    }
+
+    #[test]
+    fn test_matrix_functions() {
+        let solution = evaluate_value("solve([2, 1; 1, 3], [3; 5])").unwrap();
+        let eigenvalues = evaluate_value("eig([2, 0; 0, 3])").unwrap();
+
+        assert_eq!(evaluate_expression("det([1, 2; 3, 4])"), Ok(-2.0));
+        assert_eq!(evaluate_expression("rank([1, 2; 2, 4])"), Ok(1.0));
+        assert_eq!(evaluate_expression("norm([3, 4])"), Ok(5.0));
+        assert_eq!(solution.to_string(), "[0.8; 1.4]");
+        assert_eq!(eigenvalues.to_string(), "{2, 3}");
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let sum = evaluate_value("[1, 2] + [1; 2]");
+        let square = evaluate_value("[1, 2]^2");
+
+        assert_eq!(
+            sum,
+            Err(EvalError::ShapeMismatch {
+                expected: (1, 2),
+                got: (2, 1)
+            })
+        );
+        assert_eq!(
+            square,
+            Err(EvalError::NotSquare {
+                rows: 1,
+                columns: 2
+            })
+        );
+        assert_eq!(
+            evaluate_expression("[1, 2] + 1"),
+            Err(EvalError::NotANumber("[1, 2] + 1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let squares = evaluate_value("map(x^2, x, 1..4)").unwrap();
+        let broadcast = evaluate_value("sqrt({1, 4, 9}) + {1, 2, 3} * 2").unwrap();
+
+        assert_eq!(squares.to_string(), "{1, 4, 9, 16}");
+        assert_eq!(broadcast.to_string(), "{3, 6, 9}");
+        assert_eq!(evaluate_value("3..1").unwrap(), Value::List(vec![]));
+        assert_eq!(
+            evaluate_value("{1, 2} + {1, 2, 3}"),
+            Err(EvalError::LengthMismatch {
+                expected: 2,
+                got: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_list_functions() {
+        let sorted = evaluate_value("sort({3, 1, 4, 1, 5})").unwrap();
+        let totals = evaluate_value("cumsum(1..4)").unwrap();
+
+        assert_eq!(sorted.to_string(), "{1, 1, 3, 4, 5}");
+        assert_eq!(totals.to_string(), "{1, 3, 6, 10}");
+        assert_eq!(evaluate_expression("mean({3, 1, 4, 1, 5})"), Ok(2.8));
+        assert_eq!(evaluate_expression("len(1..10) + sum(1..10) + prod(1..5)"), Ok(185.0));
+        assert_eq!(evaluate_expression("max({3, 1, 4}) - min({3, 1, 4})"), Ok(3.0));
+        assert_eq!(evaluate_expression("sum(i, 1, 10, i)"), Ok(55.0));
+    }
+
+    #[test]
+    fn test_distributions() {
+        let total = evaluate_expression("sum(binompdf(0..10, 10, 0.3))").unwrap();
+
+        assert_close(total, 1.0, 1e-14);
+        assert_eq!(evaluate_expression("normcdf(0)"), Ok(0.5));
+        assert_eq!(evaluate_expression("unifcdf(3, 2, 6)"), Ok(0.25));
+        assert!(matches!(
+            evaluate_expression("normpdf(1, 0, -2)"),
+            Err(EvalError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_uncertainty() {
+        let period = evaluate_value("2pi sqrt((1.5 ± 0.01) / (9.81 ± 0.02))").unwrap();
+        let Value::Uncertain(period) = period else {
+            panic!("Expected an uncertain value, got {period}");
+        };
+
+        // The relative uncertainty is half of the combined relative uncertainties.
+        let relative = 0.5 * ((0.01f64 / 1.5).powi(2) + (0.02f64 / 9.81).powi(2)).sqrt();
+        assert_close(period.uncertainty() / period.value, relative, 1e-12);
+        assert!(matches!(evaluate_value("(1 ± 0.1) ± 2"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(evaluate_value("3 ± -1"), Err(EvalError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_uncertain_functions() {
+        let display = |expression: &str| evaluate_value(expression).unwrap().to_string();
+
+        // The density of the standard normal distribution at 1 is about 0.242.
+        assert_eq!(display("normcdf(1 ± 0.1)"), "0.841 ± 0.024");
+        assert_eq!(display("chi2cdf(2, 2 ± 0.1)"), "0.632 ± 0.022");
+        assert_eq!(display("max({2 ± 0.1, 1, 1.5 ± 0.2})"), "2.00 ± 0.10");
+        assert_eq!(display("min({2 ± 0.1, 1, 1.5 ± 0.2})"), "1");
+        assert!(matches!(evaluate_value("round(1.5 ± 0.1)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(evaluate_value("max({})"), Err(EvalError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_fits() {
+        let line = evaluate_value("linfit({0, 1, 2}, {1, 3, 5})").unwrap();
+        let Value::Fit(line) = line else {
+            panic!("Expected a fit, got {line}");
+        };
+
+        assert_eq!(line.parameters[0].0, "a");
+        assert_close(line.parameters[0].1, 2.0, 1e-12);
+        assert_close(line.parameters[1].1, 1.0, 1e-12);
+        assert_close(line.r_squared, 1.0, 1e-12);
+        assert!(matches!(evaluate_value("fit(a x, {a}, {1, 2}, {1})"), Err(EvalError::LengthMismatch { .. })));
+    }
 }
@@ -0,0 +1,236 @@
+use crate::{errors::EvalError, matrix::Matrix, solve};
+
+/// The time value of money functions. Like in a spreadsheet, money paid out is negative and money
+/// received is positive, and the optional `type` argument is 1 when payments are made at the start
+/// of each period rather than at the end.
+pub const TIME_VALUE: [&str; 5] = ["pv", "fv", "pmt", "nper", "rate"];
+
+/// The most periods an amortization schedule can have.
+const MAX_PERIODS: usize = 100_000;
+
+/// The rate `rate` and `irr` start from when no guess is given, the same as in spreadsheets.
+const DEFAULT_GUESS: f64 = 0.1;
+
+/// Evaluates one of the [`TIME_VALUE`] functions, which all solve the same equation
+/// `pv (1 + r)^n + pmt (1 + r type) ((1 + r)^n - 1) / r + fv = 0` for one of its variables.
+pub fn time_value(name: &str, params: &[f64]) -> Result<f64, EvalError> {
+    let param = |i: usize| params.get(i).copied().unwrap_or(0.0);
+    let due = due(param(4))?;
+
+    match name {
+        "pv" => {
+            let (r, n, pmt, fv) = (rate(params[0])?, params[1], params[2], param(3));
+            Ok(-(fv + pmt * annuity(r, n, due)) / growth(r, n))
+        }
+        "fv" => {
+            let (r, n, pmt, pv) = (rate(params[0])?, params[1], params[2], param(3));
+            Ok(-(pv * growth(r, n) + pmt * annuity(r, n, due)))
+        }
+        "pmt" => {
+            let (r, n, pv, fv) = (rate(params[0])?, params[1], params[2], param(3));
+            Ok(-(fv + pv * growth(r, n)) / annuity(r, n, due))
+        }
+        "nper" => {
+            let (r, pmt, pv, fv) = (rate(params[0])?, params[1], params[2], param(3));
+            if r == 0.0 {
+                return Ok(-(pv + fv) / pmt);
+            }
+            let payment = pmt * (1.0 + r * due) / r;
+            let n = ((payment - fv) / (payment + pv)).ln() / r.ln_1p();
+            match n.is_finite() {
+                true => Ok(n),
+                false => Err(EvalError::InvalidArgument(format!(
+                    "Payments of {pmt} never take {pv} to {fv} at a rate of {r}"
+                ))),
+            }
+        }
+        "rate" => {
+            let (n, pmt, pv, fv) = (params[0], params[1], params[2], param(3));
+            let f = |r: f64| Ok(pv * growth(r, n) + pmt * annuity(r, n, due) + fv);
+            let df = |r: f64| {
+                // The derivative of the annuity factor, which tends to n (n - 1) / 2 at zero.
+                let h = f64::EPSILON.cbrt() * r.abs().max(1e-3);
+                let slope = (annuity(r + h, n, due) - annuity(r - h, n, due)) / (2.0 * h);
+                Ok(pv * n * growth(r, n - 1.0) + pmt * slope)
+            };
+            find_rate(f, df, params.get(5).copied().unwrap_or(DEFAULT_GUESS))
+        }
+        name => unreachable!("{name} is not a time value function"),
+    }
+}
+
+/// The net present value of cash flows at the end of each period, where the first one is
+/// discounted by one period like in spreadsheets.
+pub fn npv(r: f64, cashflows: &[f64]) -> Result<f64, EvalError> {
+    let r = rate(r)?;
+    Ok(cashflows
+        .iter()
+        .enumerate()
+        .map(|(i, cashflow)| cashflow / growth(r, i as f64 + 1.0))
+        .sum())
+}
+
+/// The internal rate of return, the rate at which cash flows starting now have a net present
+/// value of zero. There has to be at least one positive and one negative cash flow.
+pub fn irr(cashflows: &[f64], guess: Option<f64>) -> Result<f64, EvalError> {
+    if !(cashflows.iter().any(|&c| c > 0.0) && cashflows.iter().any(|&c| c < 0.0)) {
+        return Err(EvalError::InvalidArgument(
+            "The cash flows need to have both a positive and a negative one for a rate of return".to_string(),
+        ));
+    }
+
+    let f = |r: f64| {
+        Ok(cashflows.iter().enumerate().map(|(i, c)| c / growth(r, i as f64)).sum())
+    };
+    let df = |r: f64| {
+        Ok(cashflows
+            .iter()
+            .enumerate()
+            .map(|(i, c)| -(i as f64) * c / growth(r, i as f64 + 1.0))
+            .sum())
+    };
+    find_rate(f, df, guess.unwrap_or(DEFAULT_GUESS))
+}
+
+/// The amortization schedule of a loan paid back with equal payments at the end of each period.
+/// Each row holds the period, the payment, how much of it is interest and how much pays back the
+/// principal, and the balance left afterwards. The last payment clears the balance exactly.
+pub fn amortization(principal: f64, r: f64, n: f64) -> Result<Matrix, EvalError> {
+    let r = rate(r)?;
+    if n.fract() != 0.0 || !(1.0..=MAX_PERIODS as f64).contains(&n) {
+        return Err(EvalError::InvalidArgument(format!(
+            "The number of periods has to be an integer between 1 and {MAX_PERIODS}, not {n}"
+        )));
+    }
+
+    let payment = time_value("pmt", &[r, n, -principal])?;
+    let mut balance = principal;
+    let mut table = vec![];
+    for period in 1..=n as usize {
+        let interest = balance * r;
+        let repaid = match period == n as usize {
+            true => balance,
+            false => payment - interest,
+        };
+        balance -= repaid;
+        table.extend([period as f64, interest + repaid, interest, repaid, balance]);
+    }
+
+    Ok(Matrix::new(n as usize, 5, table))
+}
+
+/// How much money grows over `n` periods, `(1 + r)^n`.
+fn growth(r: f64, n: f64) -> f64 {
+    (n * r.ln_1p()).exp()
+}
+
+/// What payments of one each period add up to after `n` periods, `(1 + r type) ((1 + r)^n - 1) / r`,
+/// which is `n` when there's no interest.
+fn annuity(r: f64, n: f64, due: f64) -> f64 {
+    match r {
+        0.0 => n,
+        _ => (1.0 + r * due) * (n * r.ln_1p()).exp_m1() / r,
+    }
+}
+
+/// Finds a rate with Newton's method, falling back to Brent's method between rates where the
+/// function changes sign when Newton's method doesn't settle down or leaves the rates above -1.
+fn find_rate<F, D>(mut f: F, df: D, guess: f64) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+    D: FnMut(f64) -> Result<f64, EvalError>,
+{
+    match solve::newton(&mut f, df, guess) {
+        Ok(r) if r > -1.0 && r.is_finite() => return Ok(r),
+        Ok(_) | Err(EvalError::NoConvergence { .. }) => {}
+        Err(err) => return Err(err),
+    }
+
+    // Rates from just above -100% up to 1000000%, closer together around zero.
+    let rates = (-99..=60)
+        .map(|i| match i {
+            i if i < 0 => i as f64 / 100.0,
+            i => (i as f64 / 4.0).exp2() - 1.0,
+        })
+        .collect::<Vec<_>>();
+    let mut previous: Option<(f64, f64)> = None;
+    for r in rates {
+        let y = f(r)?;
+        if let Some((a, ya)) = previous {
+            if y == 0.0 || (ya.is_finite() && y.is_finite() && ya.signum() != y.signum()) {
+                return solve::brent(&mut f, a, r);
+            }
+        }
+        previous = Some((r, y));
+    }
+
+    Err(EvalError::NoSignChange)
+}
+
+fn rate(r: f64) -> Result<f64, EvalError> {
+    match r > -1.0 {
+        true => Ok(r),
+        false => Err(EvalError::InvalidArgument(format!(
+            "The rate has to be above -1, not {r}"
+        ))),
+    }
+}
+
+fn due(due: f64) -> Result<f64, EvalError> {
+    match due {
+        0.0 | 1.0 => Ok(due),
+        _ => Err(EvalError::InvalidArgument(format!(
+            "The payment type has to be 0 for the end of each period or 1 for the start, not {due}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_relative;
+
+    #[test]
+    fn test_time_value() {
+        // A 30 year mortgage of 200000 at 6% a year, paid monthly.
+        let pmt = time_value("pmt", &[0.005, 360.0, 200_000.0]).unwrap();
+        assert_relative(pmt, -1_199.101_050_305_505, 1e-10);
+        assert_relative(time_value("pv", &[0.005, 360.0, pmt]).unwrap(), 200_000.0, 1e-10);
+        assert_relative(time_value("nper", &[0.005, pmt, 200_000.0]).unwrap(), 360.0, 1e-10);
+        assert_relative(time_value("rate", &[360.0, pmt, 200_000.0]).unwrap(), 0.005, 1e-10);
+        assert_relative(
+            time_value("fv", &[0.05, 10.0, -100.0, 0.0, 1.0]).unwrap(),
+            1_320.678_716_232_627,
+            1e-10,
+        );
+        assert_eq!(time_value("pmt", &[0.0, 10.0, 1000.0]), Ok(-100.0));
+        assert!(time_value("fv", &[0.05, 10.0, -100.0, 0.0, 2.0]).is_err());
+    }
+
+    #[test]
+    fn test_npv_and_irr() {
+        let cashflows = [-1000.0, 300.0, 400.0, 500.0];
+
+        assert_relative(
+            npv(0.1, &cashflows[1..]).unwrap() + cashflows[0],
+            -21.036_814_425_244_5,
+            1e-10,
+        );
+        let r = irr(&cashflows, None).unwrap();
+        assert!(npv(r, &cashflows[1..]).unwrap() + cashflows[0] < 1e-9);
+        assert_relative(r, 0.088_963_394_693_35, 1e-10);
+        assert!(irr(&[100.0, 200.0], None).is_err());
+    }
+
+    #[test]
+    fn test_amortization() {
+        let schedule = amortization(1000.0, 0.01, 12.0).unwrap();
+
+        assert_eq!(schedule.shape(), (12, 5));
+        assert_eq!(schedule[(11, 4)], 0.0);
+        assert_relative(schedule[(0, 1)], 88.848_788_678_341_7, 1e-10);
+        assert_relative(schedule[(0, 2)], 10.0, 1e-10);
+        let repaid = (0..12).map(|i| schedule[(i, 3)]).sum::<f64>();
+        assert_relative(repaid, 1000.0, 1e-10);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{ast::{self, ASTNode}, autodiff, errors::EvalError, eval::*, matrix::Matrix, tokenize};
+
+/// The most steps Levenberg-Marquardt takes before giving up.
+const MAX_ITERATIONS: usize = 1000;
+
+/// The damping at which no step makes the fit better anymore, so the parameters are at a
+/// minimum.
+const MAX_DAMPING: f64 = 1e16;
+
+/// The names polynomial coefficients are given, from the highest power down.
+const COEFFICIENT_NAMES: &str = "abcdefghijklmnopqrstuvwxyz";
+
+/// The parameters of a model fitted to data, along with how well it fits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    pub parameters: Vec<(String, f64)>,
+    /// The coefficient of determination, the fraction of the variance of the data that the model
+    /// explains.
+    pub r_squared: f64,
+    /// The data minus the model, at each point.
+    pub residuals: Vec<f64>,
+}
+
+impl Fit {
+    fn new(names: &[String], values: Vec<f64>, ys: &[f64], predictions: &[f64]) -> Fit {
+        let residuals = ys.iter().zip(predictions).map(|(y, p)| y - p).collect::<Vec<_>>();
+
+        let mean = ys.iter().sum::<f64>() / ys.len() as f64;
+        let total = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>();
+        let unexplained = residuals.iter().map(|r| r * r).sum::<f64>();
+
+        Fit {
+            parameters: names.iter().cloned().zip(values).collect(),
+            r_squared: 1.0 - unexplained / total,
+            residuals,
+        }
+    }
+}
+
+/// Checks that there's a y for every x, and at least as many points as parameters.
+fn check_data(xs: &[f64], ys: &[f64], parameters: usize) -> Result<(), EvalError> {
+    if xs.len() != ys.len() {
+        return Err(EvalError::LengthMismatch {
+            expected: xs.len(),
+            got: ys.len(),
+        });
+    }
+    if xs.len() < parameters {
+        return Err(EvalError::InvalidArgument(format!(
+            "Fitting {parameters} parameters needs at least {parameters} points, not {}",
+            xs.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Fits the line `a x + b` to the points, like `linfit(xs, ys)`.
+pub fn linear(xs: &[f64], ys: &[f64]) -> Result<Fit, EvalError> {
+    polynomial(xs, ys, 1.0)
+}
+
+/// Fits a polynomial of the given degree to the points by least squares, like
+/// `polyfit(xs, ys, n)`. The coefficients are named `a`, `b`, `c` and so on from the highest
+/// power down.
+pub fn polynomial(xs: &[f64], ys: &[f64], degree: f64) -> Result<Fit, EvalError> {
+    if degree.fract() != 0.0 {
+        return Err(EvalError::NotAnInteger(degree));
+    }
+    if !(0.0..COEFFICIENT_NAMES.len() as f64).contains(&degree) {
+        return Err(EvalError::InvalidArgument(format!(
+            "The degree of a fitted polynomial has to be between 0 and {}, not {degree}",
+            COEFFICIENT_NAMES.len() - 1
+        )));
+    }
+
+    let terms = degree as usize + 1;
+    check_data(xs, ys, terms)?;
+
+    let powers = |x: f64| (0..terms).rev().map(move |power| x.powi(power as i32));
+    let vandermonde = Matrix::new(xs.len(), terms, xs.iter().flat_map(|&x| powers(x)).collect());
+    let coefficients = vandermonde.least_squares(ys)?;
+
+    let predictions = xs
+        .iter()
+        .map(|&x| powers(x).zip(&coefficients).map(|(p, c)| p * c).sum())
+        .collect::<Vec<f64>>();
+    let names = COEFFICIENT_NAMES.chars().take(terms).map(String::from).collect::<Vec<_>>();
+
+    Ok(Fit::new(&names, coefficients, ys, &predictions))
+}
+
+/// Fits the exponential `a e^(b x)` to the points, like `expfit(xs, ys)`. The line through the
+/// logarithms of the points is the starting guess, which is then refined so that the fit is best
+/// for the points themselves rather than their logarithms.
+pub fn exponential(xs: &[f64], ys: &[f64]) -> Result<Fit, EvalError> {
+    check_data(xs, ys, 2)?;
+    if ys.iter().any(|&y| y <= 0.0) {
+        return Err(EvalError::InvalidArgument(
+            "`expfit` needs every y to be positive".to_string(),
+        ));
+    }
+
+    let logarithms = ys.iter().map(|y| y.ln()).collect::<Vec<_>>();
+    let line = linear(xs, &logarithms)?;
+    let guess = vec![line.parameters[1].1.exp(), line.parameters[0].1];
+
+    let model = ast::get_ast(&tokenize::parse_expression("a e^(b x)")?)?;
+    let names = ["a".to_string(), "b".to_string()];
+    fit_model(&model, "x", &names, guess, xs, ys, &Scope::new())
+}
+
+/// Evaluates a call to `fit(model, params, xs, ys)`, which fits the parameters of a model to the
+/// points with Levenberg-Marquardt. The parameters are a list of variables, like `{a, b}` or
+/// `[a, b]`, and the model has to depend on one other variable. A list of starting guesses for the parameters
+/// can be given as a fifth argument, which otherwise start at 1.
+pub fn evaluate_fit(ast: &ASTNode, scope: &Scope) -> Result<Fit, EvalError> {
+    let model = &ast.children[0];
+    if !model.is_pure() {
+        return Err(EvalError::InvalidArgument(
+            "A model with random numbers can't be fitted, since it changes every time it's evaluated".to_string(),
+        ));
+    }
+    let names = match ast.children[1].token {
+        tokenize::Token::Matrix { .. } | tokenize::Token::List => ast.children[1]
+            .children
+            .iter()
+            .map(|c| c.variable_name("fit"))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => vec![ast.children[1].variable_name("fit")?],
+    };
+
+    // The variable is the one name besides the parameters that isn't defined, or the only name
+    // besides them at all, in which case it shadows its definition like the variable of an
+    // integral does.
+    let others = model
+        .free_variables()
+        .into_iter()
+        .filter(|v| !names.contains(v))
+        .collect::<Vec<_>>();
+    let undefined = others.iter().filter(|v| scope.get(v).is_none()).cloned().collect::<Vec<_>>();
+    let variables = match undefined.is_empty() {
+        true => others,
+        false => undefined,
+    };
+    let [variable] = &variables[..] else {
+        return Err(EvalError::InvalidArgument(format!(
+            "The model has to depend on one variable besides its parameters, but it depends on {}",
+            match variables.is_empty() {
+                true => "none".to_string(),
+                false => variables.join(", "),
+            }
+        )));
+    };
+
+    let xs = numbers(&evaluate(&ast.children[2], scope)?)?;
+    let ys = numbers(&evaluate(&ast.children[3], scope)?)?;
+    let guess = match ast.children.get(4) {
+        Some(guess) => numbers(&evaluate(guess, scope)?)?,
+        None => vec![1.0; names.len()],
+    };
+    if guess.len() != names.len() {
+        return Err(EvalError::LengthMismatch {
+            expected: names.len(),
+            got: guess.len(),
+        });
+    }
+
+    check_data(&xs, &ys, names.len())?;
+    fit_model(model, variable, &names, guess, &xs, &ys, scope)
+}
+
+/// Finds the parameters that minimize the sum of the squared residuals with the
+/// Levenberg-Marquardt method, which moves between Gauss-Newton steps when they work and
+/// gradient descent when they don't. The derivatives of the model come from automatic
+/// differentiation.
+fn fit_model(
+    model: &ASTNode,
+    variable: &str,
+    names: &[String],
+    mut parameters: Vec<f64>,
+    xs: &[f64],
+    ys: &[f64],
+    scope: &Scope,
+) -> Result<Fit, EvalError> {
+    let mut inner = scope.clone();
+    let names_ref = names.iter().map(String::as_str).collect::<Vec<_>>();
+
+    // The values of the model at every x, along with their gradients when asked for.
+    let mut evaluate_model = |parameters: &[f64], gradients: bool| {
+        for (name, value) in names.iter().zip(parameters) {
+            inner.set(name, *value);
+        }
+
+        let mut values = vec![];
+        let mut jacobian = vec![];
+        for &x in xs {
+            inner.set(variable, x);
+            if gradients {
+                let dual = autodiff::forward(model, &inner, &names_ref)?;
+                values.push(dual.value);
+                jacobian.extend(dual.gradient);
+            } else {
+                values.push(evaluate_ast(model, &inner)?);
+            }
+        }
+        Ok::<_, EvalError>((values, jacobian))
+    };
+    let cost = |values: &[f64]| ys.iter().zip(values).map(|(y, v)| (y - v).powi(2)).sum::<f64>();
+
+    let n = parameters.len();
+    let mut damping = 1e-3;
+    for _ in 0..MAX_ITERATIONS {
+        let (values, jacobian) = evaluate_model(&parameters, true)?;
+        let current = cost(&values);
+        if current == 0.0 {
+            return Ok(Fit::new(names, parameters, ys, &values));
+        }
+
+        let jacobian = Matrix::new(xs.len(), n, jacobian);
+        let residuals = ys.iter().zip(&values).map(|(y, v)| y - v).collect::<Vec<_>>();
+        let normal = jacobian.transpose().matmul(&jacobian)?;
+        let gradient = jacobian
+            .transpose()
+            .matmul(&Matrix::new(xs.len(), 1, residuals))?;
+
+        // The damping grows until a step makes the fit better.
+        loop {
+            let mut damped = normal.clone();
+            for i in 0..n {
+                damped[(i, i)] += damping * normal[(i, i)].max(f64::EPSILON);
+            }
+
+            let step = damped.solve(&gradient).ok();
+            let trial = step.as_ref().map(|step| {
+                parameters.iter().zip(step.data()).map(|(p, s)| p + s).collect::<Vec<_>>()
+            });
+            let trial_cost = trial
+                .as_ref()
+                .and_then(|trial| evaluate_model(trial, false).ok())
+                .map_or(f64::INFINITY, |(values, _)| cost(&values));
+
+            if let (Some(trial), true) = (trial, trial_cost < current) {
+                let converged = current - trial_cost <= 1e-14 * current;
+                parameters = trial;
+                damping = (damping / 10.0).max(1e-12);
+                if converged {
+                    let (values, _) = evaluate_model(&parameters, false)?;
+                    return Ok(Fit::new(names, parameters, ys, &values));
+                }
+                break;
+            }
+
+            damping *= 10.0;
+            if damping > MAX_DAMPING {
+                return Ok(Fit::new(names, parameters, ys, &values));
+            }
+        }
+    }
+
+    Err(EvalError::NoConvergence {
+        iterations: MAX_ITERATIONS,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    fn evaluate_str(expression: &str) -> Result<Fit, EvalError> {
+        let ast = ast::get_ast(&tokenize::parse_expression(expression)?)?;
+        evaluate_fit(&ast, &Scope::new())
+    }
+
+    #[test]
+    fn test_linear() {
+        let fit = linear(&[0.0, 1.0, 2.0, 3.0], &[1.0, 3.1, 4.9, 7.0]).unwrap();
+
+        assert_close(fit.parameters[0].1, 1.98, 1e-8);
+        assert_close(fit.parameters[1].1, 1.03, 1e-8);
+        assert!(fit.r_squared > 0.99 && fit.r_squared < 1.0);
+        assert_close(fit.residuals.iter().sum(), 0.0, 1e-8);
+    }
+
+    #[test]
+    fn test_polynomial() {
+        let xs: [f64; 6] = [-2.0, -1.0, 0.0, 1.0, 2.0, 3.0];
+        let ys = xs.map(|x| 2.0 * x * x - 3.0 * x + 1.0);
+        let fit = polynomial(&xs, &ys, 2.0).unwrap();
+
+        for ((name, value), expected) in fit.parameters.iter().zip([("a", 2.0), ("b", -3.0), ("c", 1.0)]) {
+            assert_eq!(name, expected.0);
+            assert_close(*value, expected.1, 1e-8);
+        }
+        assert_close(fit.r_squared, 1.0, 1e-8);
+        assert!(polynomial(&xs, &ys, 6.0).is_err());
+    }
+
+    #[test]
+    fn test_exponential() {
+        let xs: [f64; 5] = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let ys = xs.map(|x| 3.0 * (-0.5 * x).exp());
+        let fit = exponential(&xs, &ys).unwrap();
+
+        assert_close(fit.parameters[0].1, 3.0, 1e-8);
+        assert_close(fit.parameters[1].1, -0.5, 1e-8);
+    }
+
+    #[test]
+    fn test_general_model() {
+        // A logistic curve with a midpoint of 2 and a steepness of 1.5.
+        let fit = evaluate_str(
+            "fit(k / (1 + e^(-r(t - m))), {k, r, m}, {0, 1, 2, 3, 4, 5}, \
+             {0.474258731776, 1.824255238064, 5, 8.175744761936, 9.525741268224, 9.890130573694}, {8, 1, 1})",
+        )
+        .unwrap();
+
+        for ((_, value), expected) in fit.parameters.iter().zip([10.0, 1.5, 2.0]) {
+            assert_close(*value, expected, 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_defined_variable() {
+        let mut scope = Scope::new();
+        scope.set("x", 3.0);
+        scope.set("c", 1.0);
+        let fit = |expression: &str| {
+            let ast = ast::get_ast(&tokenize::parse_expression(expression).unwrap()).unwrap();
+            evaluate_fit(&ast, &scope).unwrap()
+        };
+
+        // `x` is the only name besides the parameter, so it's the variable even though it's
+        // defined, while `c` keeps its value next to the undefined `t`.
+        assert_close(fit("fit(a x, {a}, {0, 1, 2}, {0, 2, 4})").parameters[0].1, 2.0, 1e-8);
+        assert_close(fit("fit(a t + c, [a], {0, 1, 2}, {1, 3, 5})").parameters[0].1, 2.0, 1e-8);
+        let fit = fit("fit(a x + b, [a, b], {0, 1, 2}, {1, 3, 5})");
+        assert_close(fit.parameters[0].1, 2.0, 1e-8);
+        assert_close(fit.parameters[1].1, 1.0, 1e-8);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert_eq!(
+            linear(&[1.0, 2.0], &[1.0]),
+            Err(EvalError::LengthMismatch {
+                expected: 2,
+                got: 1
+            })
+        );
+        assert!(evaluate_str("fit(a x + b y, {a, b}, {1, 2}, {1, 2})").is_err());
+        assert!(exponential(&[1.0, 2.0], &[1.0, -1.0]).is_err());
+    }
+}
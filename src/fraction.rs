@@ -0,0 +1,194 @@
+use std::fmt;
+
+use crate::errors::EvalError;
+
+/// The most terms `cf` gives. Floats run out of precision long before this.
+const MAX_TERMS: f64 = 100.0;
+
+/// The largest denominator of a fraction shown next to a result.
+const SIMPLE_DENOMINATOR: f64 = 1000.0;
+
+/// How close a result has to be to a fraction, relative to its size, for the fraction to be shown
+/// next to it.
+const SIMPLE_TOLERANCE: f64 = 1e-9;
+
+/// A fraction in lowest terms with a positive denominator. The numerator and denominator are
+/// integers held in floats, so they can be as large as the numbers they approximate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fraction {
+    pub numerator: f64,
+    pub denominator: f64,
+}
+
+impl Fraction {
+    pub fn to_f64(&self) -> f64 {
+        self.numerator / self.denominator
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.denominator {
+            1.0 => write!(f, "{}", self.numerator),
+            _ => write!(f, "{}/{}", self.numerator, self.denominator),
+        }
+    }
+}
+
+/// The terms `[a0; a1, a2, ...]` of the continued fraction `a0 + 1/(a1 + 1/(a2 + ...))` of `x`,
+/// at most `n` of them. The terms stop early once the fraction they make is `x`.
+pub fn continued_fraction(x: f64, n: f64) -> Result<Vec<f64>, EvalError> {
+    finite(x)?;
+    if n.fract() != 0.0 || !(1.0..=MAX_TERMS).contains(&n) {
+        return Err(EvalError::InvalidArgument(format!(
+            "The number of terms has to be an integer between 1 and {MAX_TERMS}, not {n}"
+        )));
+    }
+
+    let mut terms = vec![];
+    let mut convergents = Convergents::new();
+    let mut y = x;
+    while terms.len() < n as usize {
+        let a = y.floor();
+        terms.push(a);
+        let (p, q) = convergents.next(a);
+
+        let rest = y - a;
+        if rest == 0.0 || p / q == x {
+            break;
+        }
+        y = 1.0 / rest;
+    }
+
+    Ok(terms)
+}
+
+/// The fraction closest to `x` whose denominator is at most `max_denominator`. It's either a
+/// convergent of the continued fraction of `x` or a semiconvergent between two of them.
+pub fn best_approximation(x: f64, max_denominator: f64) -> Result<Fraction, EvalError> {
+    finite(x)?;
+    if max_denominator.fract() != 0.0 || max_denominator < 1.0 {
+        return Err(EvalError::InvalidArgument(format!(
+            "The largest denominator has to be a positive integer, not {max_denominator}"
+        )));
+    }
+    if x < 0.0 {
+        let fraction = best_approximation(-x, max_denominator)?;
+        return Ok(Fraction {
+            numerator: -fraction.numerator + 0.0,
+            ..fraction
+        });
+    }
+
+    let mut convergents = Convergents::new();
+    let mut y = x;
+    loop {
+        let a = y.floor();
+        let (previous, last) = (convergents.previous, convergents.last);
+        let (p, q) = convergents.next(a);
+
+        // The next convergent's denominator is too big, so the best fraction is either the last
+        // convergent or the largest semiconvergent that fits.
+        if q > max_denominator {
+            let t = ((max_denominator - previous.1) / last.1).floor();
+            let semiconvergent = (t * last.0 + previous.0, t * last.1 + previous.1);
+            let error = |(p, q): (f64, f64)| (x - p / q).abs();
+            let (p, q) = match error(semiconvergent) < error(last) {
+                true => semiconvergent,
+                false => last,
+            };
+            return Ok(Fraction {
+                numerator: p,
+                denominator: q,
+            });
+        }
+
+        let rest = y - a;
+        if rest == 0.0 || p / q == x {
+            return Ok(Fraction {
+                numerator: p,
+                denominator: q,
+            });
+        }
+        y = 1.0 / rest;
+    }
+}
+
+/// A fraction with a small denominator that `x` is very close to, like `1/7` for
+/// `0.142857142857`, which is shown next to results. Integers don't get one.
+pub fn simple(x: f64) -> Option<Fraction> {
+    if !x.is_finite() || x.fract() == 0.0 {
+        return None;
+    }
+
+    let fraction = best_approximation(x, SIMPLE_DENOMINATOR).ok()?;
+    ((fraction.to_f64() - x).abs() <= SIMPLE_TOLERANCE * x.abs()).then_some(fraction)
+}
+
+/// The convergents `p/q` of a continued fraction, which are worked out one term at a time from
+/// the two before them.
+struct Convergents {
+    previous: (f64, f64),
+    last: (f64, f64),
+}
+
+impl Convergents {
+    fn new() -> Self {
+        Convergents {
+            previous: (0.0, 1.0),
+            last: (1.0, 0.0),
+        }
+    }
+
+    fn next(&mut self, a: f64) -> (f64, f64) {
+        let next = (a * self.last.0 + self.previous.0, a * self.last.1 + self.previous.1);
+        (self.previous, self.last) = (self.last, next);
+        next
+    }
+}
+
+fn finite(x: f64) -> Result<(), EvalError> {
+    match x.is_finite() {
+        true => Ok(()),
+        false => Err(EvalError::InvalidArgument(format!("{x} has no fractions close to it"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{E, PI, SQRT_2};
+
+    use super::*;
+
+    #[test]
+    fn test_continued_fraction() {
+        assert_eq!(continued_fraction(PI, 5.0), Ok(vec![3.0, 7.0, 15.0, 1.0, 292.0]));
+        assert_eq!(continued_fraction(E, 7.0), Ok(vec![2.0, 1.0, 2.0, 1.0, 1.0, 4.0, 1.0]));
+        assert_eq!(continued_fraction(SQRT_2, 4.0), Ok(vec![1.0, 2.0, 2.0, 2.0]));
+        assert_eq!(continued_fraction(0.1, 10.0), Ok(vec![0.0, 10.0]));
+        assert_eq!(continued_fraction(-0.5, 10.0), Ok(vec![-1.0, 2.0]));
+        assert!(continued_fraction(PI, 0.5).is_err());
+    }
+
+    #[test]
+    fn test_best_approximation() {
+        let rat = |x: f64, max: f64| best_approximation(x, max).unwrap().to_string();
+
+        assert_eq!(rat(PI, 10.0), "22/7");
+        assert_eq!(rat(PI, 1000.0), "355/113");
+        // 311/99 is a semiconvergent between 22/7 and 333/106.
+        assert_eq!(rat(PI, 100.0), "311/99");
+        assert_eq!(rat(-0.75, 100.0), "-3/4");
+        assert_eq!(rat(2.5, 1.0), "2");
+        assert_eq!(rat(0.142857142857, 100.0), "1/7");
+        assert!(best_approximation(f64::NAN, 10.0).is_err());
+    }
+
+    #[test]
+    fn test_simple() {
+        assert_eq!(simple(0.142857142857).map(|f| f.to_string()), Some("1/7".to_string()));
+        assert_eq!(simple(2.0 / 3.0).map(|f| f.to_string()), Some("2/3".to_string()));
+        assert_eq!(simple(PI), None);
+        assert_eq!(simple(3.0), None);
+    }
+}
@@ -0,0 +1,385 @@
+use std::fmt;
+
+use crate::{errors::EvalError, keywords::CONSTANTS};
+
+/// The largest coefficient of a relation that's turned into a formula.
+const MAX_COEFFICIENT: f64 = 10_000.0;
+
+/// The most iterations of PSLQ for each form a number is tried against.
+const MAX_ITERATIONS: usize = 200;
+
+/// The largest chance of a coincidence at which a formula is still reported.
+const MAX_CHANCE: f64 = 1e-3;
+
+/// A closed form found for a number, like `pi/4` for `0.7853981633974483`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identification {
+    pub value: f64,
+    pub formula: String,
+    /// Whether the formula gives the number to within rounding, rather than just being close.
+    pub exact: bool,
+    /// Roughly how likely it is that a number picked at random would be matched as closely by a
+    /// formula at least this simple. The smaller it is, the more convincing the formula.
+    pub chance: f64,
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let relation = if self.exact { "=" } else { "≈" };
+        write!(
+            f,
+            "{} {relation} {} (chance of a coincidence below {:.0e})",
+            self.value, self.formula, self.chance
+        )
+    }
+}
+
+/// The shapes of closed forms a number is tried against. Each one looks for integers that make a
+/// combination of the number and known constants zero.
+#[derive(Debug, Clone, PartialEq)]
+enum Form {
+    /// `p/q`, from `q x - p = 0`.
+    Rational,
+    /// `(p + r c)/q` for a constant `c`, from `q x - p - r c = 0`.
+    Linear(String, f64),
+    /// `p/(q c)` for a constant `c`, from `q c x - p = 0`.
+    Reciprocal(String, f64),
+    /// A root of `a x^2 + b x + c = 0`, which is `(p + r sqrt(d))/q`.
+    Quadratic,
+}
+
+/// Looks for a simple closed form of `x` among rationals, rational combinations of a rational and
+/// one of the constants, their squares, the square roots of 2, 3 and 5, or the logarithms of 2, 3
+/// and 10, rationals divided by one of those, and roots of quadratics with integer coefficients.
+/// The relations are found with the PSLQ integer relation algorithm, and the simplest one that
+/// matches `x` well enough to be unlikely to be a coincidence is returned.
+pub fn identify(x: f64) -> Result<Identification, EvalError> {
+    if !x.is_finite() {
+        return Err(EvalError::InvalidArgument(format!("{x} can't be identified")));
+    }
+
+    let forms = forms();
+    let mut best: Option<Identification> = None;
+    for form in &forms {
+        for relation in pslq(&form.vector(x)) {
+            if relation.iter().any(|c| c.abs() > MAX_COEFFICIENT) {
+                continue;
+            }
+            let Some((value, formula)) = form.solve(x, &relation) else {
+                continue;
+            };
+
+            // There are about this many relations with coefficients no larger than these, and a
+            // number at random is within the error of one of them with about this probability.
+            let count = relation.iter().map(|c| 2.0 * c.abs() + 1.0).product::<f64>();
+            let error = match x {
+                0.0 => value.abs(),
+                x => ((x - value) / x).abs(),
+            };
+            let chance = (forms.len() as f64 * count * error.max(f64::EPSILON)).min(1.0);
+
+            if best.as_ref().is_none_or(|best| chance < best.chance) {
+                best = Some(Identification {
+                    value: x,
+                    formula,
+                    exact: error <= 4.0 * f64::EPSILON,
+                    chance,
+                });
+            }
+        }
+    }
+
+    best.filter(|best| best.chance <= MAX_CHANCE)
+        .ok_or_else(|| EvalError::InvalidArgument(format!("No simple closed form was found for {x}")))
+}
+
+/// The forms that are tried, with the constants sorted so the results don't depend on the order
+/// of [`CONSTANTS`].
+fn forms() -> Vec<Form> {
+    let mut constants = CONSTANTS
+        .iter()
+        .filter(|(_, value)| value.is_finite())
+        .flat_map(|(name, &value)| [(name.clone(), value), (format!("{name}^2"), value * value)])
+        .collect::<Vec<_>>();
+    constants.sort_by(|a, b| a.0.cmp(&b.0));
+    for n in [2.0, 3.0, 5.0] {
+        constants.push((format!("sqrt({n})"), f64::sqrt(n)));
+    }
+    for n in [2.0, 3.0, 10.0] {
+        constants.push((format!("ln({n})"), f64::ln(n)));
+    }
+
+    let mut forms = vec![Form::Rational, Form::Quadratic];
+    for (name, value) in constants {
+        forms.push(Form::Linear(name.clone(), value));
+        forms.push(Form::Reciprocal(name, value));
+    }
+    forms
+}
+
+impl Form {
+    /// The numbers an integer relation is looked for between.
+    fn vector(&self, x: f64) -> Vec<f64> {
+        match self {
+            Form::Rational => vec![x, 1.0],
+            Form::Linear(_, c) => vec![x, 1.0, *c],
+            Form::Reciprocal(_, c) => vec![x * c, 1.0],
+            Form::Quadratic => vec![x * x, x, 1.0],
+        }
+    }
+
+    /// The number a relation between the numbers of [`Form::vector`] gives for `x`, along with
+    /// the formula for it. `None` means the relation doesn't pin down `x`, or a simpler form
+    /// covers it.
+    fn solve(&self, x: f64, relation: &[f64]) -> Option<(f64, String)> {
+        // The relations are scaled so the first coefficient is positive.
+        let sign = relation[0].signum();
+        let c = relation.iter().map(|c| c * sign + 0.0).collect::<Vec<_>>();
+        if c[0] == 0.0 {
+            return None;
+        }
+
+        match self {
+            Form::Rational => Some((-c[1] / c[0], linear(-c[1], 0.0, "", c[0]))),
+            Form::Linear(_, _) if c[2] == 0.0 => None,
+            Form::Linear(name, constant) => {
+                Some(((-c[1] - c[2] * constant) / c[0], linear(-c[1], -c[2], name, c[0])))
+            }
+            Form::Reciprocal(_, _) if c[1] == 0.0 => None,
+            Form::Reciprocal(name, constant) => {
+                let g = gcd(c[0], c[1].abs());
+                let (p, q) = (-c[1] / g, c[0] / g);
+                let denominator = match q {
+                    1.0 => name.clone(),
+                    q => format!("({q}*{name})"),
+                };
+                Some((p / (q * constant), format!("{p}/{denominator}")))
+            }
+            Form::Quadratic => {
+                let (a, b, c) = (c[0], c[1], c[2]);
+                let (root, radicand) = square_part(b * b - 4.0 * a * c)?;
+                if radicand == 1.0 {
+                    return None;
+                }
+
+                // The root closest to x.
+                let r = match (x - (-b + root * radicand.sqrt()) / (2.0 * a)).abs()
+                    < (x - (-b - root * radicand.sqrt()) / (2.0 * a)).abs()
+                {
+                    true => root,
+                    false => -root,
+                };
+                let value = (-b + r * radicand.sqrt()) / (2.0 * a);
+                Some((value, linear(-b, r, &format!("sqrt({radicand})"), 2.0 * a)))
+            }
+        }
+    }
+}
+
+/// Writes `(p + r c)/q` in lowest terms, where `c` is the name of a constant and `q` is positive,
+/// leaving out the parts that are zero or one.
+fn linear(p: f64, r: f64, name: &str, q: f64) -> String {
+    let g = gcd(gcd(p.abs(), r.abs()), q);
+    let (p, r, q) = (p / g + 0.0, r / g + 0.0, q / g);
+
+    let multiple = |r: f64| match r {
+        1.0 => name.to_string(),
+        r => format!("{r}*{name}"),
+    };
+    let sign = |n: f64| if n < 0.0 { "-" } else { "+" };
+
+    let (numerator, terms) = match (p, r) {
+        (p, 0.0) => (format!("{p}"), 1),
+        (0.0, r) if r < 0.0 => (format!("-{}", multiple(-r)), 1),
+        (0.0, r) => (multiple(r), 1),
+        (p, r) if p > 0.0 => (format!("{p} {} {}", sign(r), multiple(r.abs())), 2),
+        (p, r) if r < 0.0 => (format!("-{} - {}", multiple(-r), -p), 2),
+        (p, r) => (format!("{} - {}", multiple(r), -p), 2),
+    };
+
+    match (q, terms) {
+        (1.0, _) => numerator,
+        (q, 1) => format!("{numerator}/{q}"),
+        (q, _) => format!("({numerator})/{q}"),
+    }
+}
+
+/// Splits a positive integer into `f^2 d` where `d` has no square factors, returning `f` and `d`.
+fn square_part(n: f64) -> Option<(f64, f64)> {
+    if n <= 0.0 || n > 2f64.powi(53) {
+        return None;
+    }
+
+    let (mut root, mut rest) = (1.0, n);
+    let mut i = 2.0;
+    while i * i <= rest {
+        while rest % (i * i) == 0.0 {
+            rest /= i * i;
+            root *= i;
+        }
+        i += 1.0;
+    }
+    Some((root, rest))
+}
+
+fn gcd(a: f64, b: f64) -> f64 {
+    match b {
+        0.0 => a.max(1.0),
+        b => gcd(b, a % b),
+    }
+}
+
+/// Looks for integers `c`, not all zero, where `c·x` is zero, with the PSLQ algorithm of Ferguson
+/// and Bailey. The columns of the matrix `B` it keeps are always integer vectors, and each
+/// iteration brings one closer to being a relation. Every column seen is returned, since with
+/// rounded numbers the simple relation that's wanted usually shows up before the algorithm stops
+/// at one that fits the rounding errors instead.
+fn pslq(x: &[f64]) -> Vec<Vec<f64>> {
+    let n = x.len();
+    let gamma = (4.0f64 / 3.0).sqrt();
+
+    // The partial norms of x, and x scaled to a unit vector.
+    let mut s = (0..n).map(|k| x[k..].iter().map(|v| v * v).sum::<f64>().sqrt()).collect::<Vec<_>>();
+    let norm = s[0];
+    let mut y = x.iter().map(|v| v / norm).collect::<Vec<_>>();
+    s.iter_mut().for_each(|v| *v /= norm);
+
+    let mut h = vec![vec![0.0; n - 1]; n];
+    for i in 0..n {
+        for j in 0..(n - 1).min(i + 1) {
+            h[i][j] = match i == j {
+                true => s[j + 1] / s[j],
+                false => -y[i] * y[j] / (s[j] * s[j + 1]),
+            };
+        }
+    }
+    let mut b = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect::<Vec<_>>()).collect::<Vec<_>>();
+
+    let columns = |b: &[Vec<f64>]| (0..n).map(|j| b.iter().map(|row| row[j]).collect::<Vec<_>>()).collect::<Vec<_>>();
+    let mut relations = columns(&b);
+
+    // Subtracts multiples of earlier rows of H to keep its entries small, updating y and B to
+    // match.
+    let reduce = |h: &mut [Vec<f64>], y: &mut [f64], b: &mut [Vec<f64>], i: usize, j: usize| {
+        if h[j][j] == 0.0 {
+            return;
+        }
+        let t = (h[i][j] / h[j][j]).round();
+        if t == 0.0 {
+            return;
+        }
+        y[j] += t * y[i];
+        let (above, below) = h.split_at_mut(i);
+        for (a, b) in below[0].iter_mut().zip(&above[j]).take(j + 1) {
+            *a -= t * b;
+        }
+        for row in b.iter_mut() {
+            row[j] += t * row[i];
+        }
+    };
+
+    for i in 1..n {
+        for j in (0..i).rev() {
+            reduce(&mut h, &mut y, &mut b, i, j);
+        }
+    }
+    relations.extend(columns(&b));
+
+    for _ in 0..MAX_ITERATIONS {
+        // Swaps the rows where it shrinks the diagonal of H the most.
+        let m = (0..n - 1)
+            .max_by(|&i, &j| {
+                let weight = |i: usize| gamma.powi(i as i32 + 1) * h[i][i].abs();
+                weight(i).total_cmp(&weight(j))
+            })
+            .unwrap_or(0);
+        y.swap(m, m + 1);
+        h.swap(m, m + 1);
+        for row in b.iter_mut() {
+            row.swap(m, m + 1);
+        }
+
+        // The swap leaves H with an entry above the diagonal, which is rotated away.
+        if m + 2 < n {
+            let t0 = h[m][m].hypot(h[m][m + 1]);
+            let (t1, t2) = (h[m][m] / t0, h[m][m + 1] / t0);
+            for row in h.iter_mut().skip(m) {
+                let (t3, t4) = (row[m], row[m + 1]);
+                row[m] = t1 * t3 + t2 * t4;
+                row[m + 1] = -t2 * t3 + t1 * t4;
+            }
+        }
+
+        for i in m + 1..n {
+            for j in (0..i.min(m + 2)).rev() {
+                reduce(&mut h, &mut y, &mut b, i, j);
+            }
+        }
+        relations.extend(columns(&b));
+
+        let found = y.iter().any(|v| v.abs() < 16.0 * f64::EPSILON);
+        let large = b.iter().flatten().any(|v| v.abs() > MAX_COEFFICIENT);
+        let degenerate = (0..n - 1).any(|i| h[i][i] == 0.0);
+        if found || large || degenerate {
+            break;
+        }
+    }
+
+    relations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{E, LN_2, PI, SQRT_2};
+
+    use super::*;
+
+    fn formula(x: f64) -> Option<String> {
+        identify(x).ok().map(|identification| identification.formula)
+    }
+
+    #[test]
+    fn test_pslq() {
+        let relations = pslq(&[PI / 4.0, 1.0, PI]);
+
+        assert!(relations.iter().any(|c| c == &[4.0, 0.0, -1.0] || c == &[-4.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(formula(PI / 4.0), Some("pi/4".to_string()));
+        assert_eq!(formula(3.0 * PI / 2.0), Some("3*pi/2".to_string()));
+        assert_eq!(formula(E - 1.0), Some("e - 1".to_string()));
+        assert_eq!(formula(PI * PI / 6.0), Some("pi^2/6".to_string()));
+        assert_eq!(formula(2.0 / PI), Some("2/pi".to_string()));
+        assert_eq!(formula(SQRT_2 / 2.0), Some("1/sqrt(2)".to_string()));
+        assert_eq!(formula(SQRT_2 / 3.0), Some("sqrt(2)/3".to_string()));
+        assert_eq!(formula(LN_2 / 3.0), Some("ln(2)/3".to_string()));
+        assert_eq!(formula(0.75), Some("3/4".to_string()));
+        assert_eq!(formula(-1.25), Some("-5/4".to_string()));
+    }
+
+    #[test]
+    fn test_quadratics() {
+        assert_eq!(formula((1.0 + 5f64.sqrt()) / 2.0), Some("(1 + sqrt(5))/2".to_string()));
+        assert_eq!(formula((3.0 - 7f64.sqrt()) / 2.0), Some("(3 - sqrt(7))/2".to_string()));
+        assert_eq!(formula(2.0 * 11f64.sqrt()), Some("2*sqrt(11)".to_string()));
+    }
+
+    #[test]
+    fn test_confidence() {
+        let exact = identify(PI / 4.0).unwrap();
+        assert!(exact.exact && exact.chance < 1e-10);
+        assert!(exact.to_string().starts_with("0.7853981633974483 = pi/4 (chance of a coincidence below"));
+
+        // Six digits are still enough for a simple formula, but not for an exact one.
+        let rounded = identify((PI / 4.0 * 1e6).round() / 1e6).unwrap();
+        assert_eq!(rounded.formula, "pi/4");
+        assert!(!rounded.exact && rounded.chance > exact.chance);
+
+        // Euler's constant isn't known to have a closed form.
+        assert_eq!(formula(0.577_215_664_901_532_9), None);
+        assert_eq!(formula(PI.sqrt().ln()), None);
+        assert!(identify(f64::NAN).is_err());
+    }
+}
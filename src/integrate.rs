@@ -0,0 +1,249 @@
+use crate::{ast::ASTNode, errors::EvalError, eval::*};
+
+/// The tolerance used when `integrate` isn't given one.
+pub const DEFAULT_TOLERANCE: f64 = 1e-10;
+
+/// The most times the interval is split before giving up on reaching the tolerance.
+const MAX_SUBDIVISIONS: usize = 1000;
+
+/// Nodes of the 15 point Kronrod rule on [-1, 1], from the outside in. Every other node, starting
+/// at the second, is also a node of the 7 point Gauss rule.
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0.0,
+];
+
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+/// The value of a definite integral and an estimate of its absolute error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Integral {
+    pub value: f64,
+    pub error: f64,
+}
+
+/// Evaluates a call to `integrate(expr, x, a, b)`, with an optional tolerance as a fifth argument.
+pub fn evaluate_integral(ast: &ASTNode, scope: &Scope) -> Result<Integral, EvalError> {
+    let var = ast.children[1].variable_name("integrate")?;
+    let a = evaluate_ast(&ast.children[2], scope)?;
+    let b = evaluate_ast(&ast.children[3], scope)?;
+    let tolerance = match ast.children.get(4) {
+        Some(tolerance) => evaluate_ast(tolerance, scope)?,
+        None => DEFAULT_TOLERANCE,
+    };
+    if tolerance.is_nan() || tolerance <= 0.0 {
+        return Err(EvalError::InvalidArgument(format!("The tolerance has to be positive, not {tolerance}")));
+    }
+
+    let mut inner = scope.clone();
+    integrate(
+        |x| {
+            inner.set(&var, x);
+            evaluate_ast(&ast.children[0], &inner)
+        },
+        a,
+        b,
+        tolerance,
+    )
+}
+
+/// Integrates the function from `a` to `b` using adaptive Gauss–Kronrod quadrature. Either limit
+/// can be infinite, in which case the variable is transformed so the interval becomes finite.
+pub fn integrate<F>(mut f: F, a: f64, b: f64, tolerance: f64) -> Result<Integral, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    if a == b {
+        return Ok(Integral {
+            value: 0.0,
+            error: 0.0,
+        });
+    }
+
+    if a > b {
+        let integral = integrate(f, b, a, tolerance)?;
+        return Ok(Integral {
+            value: -integral.value,
+            ..integral
+        });
+    }
+
+    match (a.is_infinite(), b.is_infinite()) {
+        (false, false) => adaptive(f, a, b, tolerance),
+        // x = a + t / (1 - t), where t goes from 0 to 1.
+        (false, true) => adaptive(
+            |t| Ok(f(a + t / (1.0 - t))? / (1.0 - t).powi(2)),
+            0.0,
+            1.0,
+            tolerance,
+        ),
+        // x = b - (1 - t) / t, where t goes from 0 to 1.
+        (true, false) => adaptive(
+            |t| Ok(f(b - (1.0 - t) / t)? / t.powi(2)),
+            0.0,
+            1.0,
+            tolerance,
+        ),
+        // x = t / (1 - t^2), where t goes from -1 to 1.
+        (true, true) => adaptive(
+            |t| {
+                let s = 1.0 - t * t;
+                Ok(f(t / s)? * (1.0 + t * t) / s.powi(2))
+            },
+            -1.0,
+            1.0,
+            tolerance,
+        ),
+    }
+}
+
+/// Splits the interval with the largest error in half until the total error is within the
+/// tolerance, relative to the value when it's larger than one.
+fn adaptive<F>(mut f: F, a: f64, b: f64, tolerance: f64) -> Result<Integral, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let mut intervals = vec![(a, b, gauss_kronrod(&mut f, a, b)?)];
+
+    for iterations in 0..MAX_SUBDIVISIONS {
+        let value = intervals.iter().map(|(_, _, i)| i.value).sum::<f64>();
+        let error = intervals.iter().map(|(_, _, i)| i.error).sum::<f64>();
+
+        if !value.is_finite() {
+            return Err(EvalError::NoConvergence { iterations });
+        }
+
+        if error <= tolerance * value.abs().max(1.0) {
+            return Ok(Integral { value, error });
+        }
+
+        let worst = (0..intervals.len())
+            .max_by(|&i, &j| intervals[i].2.error.total_cmp(&intervals[j].2.error))
+            .unwrap();
+        let (a, b, _) = intervals.swap_remove(worst);
+        let mid = a + (b - a) / 2.0;
+
+        // The interval can't be split any further once it's as small as a float allows.
+        if mid <= a || mid >= b {
+            return Err(EvalError::NoConvergence { iterations });
+        }
+
+        intervals.push((a, mid, gauss_kronrod(&mut f, a, mid)?));
+        intervals.push((mid, b, gauss_kronrod(&mut f, mid, b)?));
+    }
+
+    Err(EvalError::NoConvergence {
+        iterations: MAX_SUBDIVISIONS,
+    })
+}
+
+/// Integrates the function over the interval with the 15 point Kronrod rule. The difference from
+/// the 7 point Gauss rule, which uses a subset of the same points, is the error estimate.
+fn gauss_kronrod<F>(f: &mut F, a: f64, b: f64) -> Result<Integral, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let center = (a + b) / 2.0;
+    let half = (b - a) / 2.0;
+
+    let f_center = f(center)?;
+    let mut kronrod = f_center * KRONROD_WEIGHTS[7];
+    let mut gauss = f_center * GAUSS_WEIGHTS[3];
+
+    for j in 0..7 {
+        let x = half * KRONROD_NODES[j];
+        let pair = f(center - x)? + f(center + x)?;
+
+        kronrod += KRONROD_WEIGHTS[j] * pair;
+        if j % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[j / 2] * pair;
+        }
+    }
+
+    Ok(Integral {
+        value: kronrod * half,
+        error: ((kronrod - gauss) * half).abs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_polynomial() {
+        let res = evaluate_expression("integrate(3x^2 + 1, x, 0, 2)").unwrap();
+
+        assert_close(res, 10.0, 1e-9);
+    }
+
+    #[test]
+    fn test_reversed_limits() {
+        let res = evaluate_expression("integrate(sin(t), t, pi, 0)").unwrap();
+
+        assert_close(res, -2.0, 1e-9);
+    }
+
+    #[test]
+    fn test_infinite_limits() {
+        let gaussian = evaluate_expression("integrate(e^(-x^2), x, -inf, inf)").unwrap();
+        let decay = evaluate_expression("integrate(1 / x^2, x, 1, inf)").unwrap();
+
+        assert_close(gaussian, std::f64::consts::PI.sqrt(), 1e-9);
+        assert_close(decay, 1.0, 1e-9);
+    }
+
+    #[test]
+    fn test_nested_integral() {
+        let res = evaluate_expression("integrate(integrate(x y, x, 0, y), y, 0, 1)").unwrap();
+
+        assert_close(res, 0.125, 1e-9);
+    }
+
+    #[test]
+    fn test_error_estimate() {
+        let integral = integrate(|x| Ok(x.sqrt()), 0.0, 1.0, 1e-12).unwrap();
+
+        assert_close(integral.value, 2.0 / 3.0, 1e-9);
+        assert!(integral.error <= 1e-12);
+    }
+
+    #[test]
+    fn test_divergent_integral() {
+        let res = evaluate_expression("integrate(1 / x, x, 0, 1)");
+
+        assert!(matches!(res, Err(EvalError::NoConvergence { .. })));
+    }
+
+    #[test]
+    fn test_invalid_tolerance() {
+        for tolerance in ["0", "-1", "0/0"] {
+            let res = evaluate_expression(&format!("integrate(x, x, 0, 1, {tolerance})"));
+
+            assert!(matches!(res, Err(EvalError::InvalidArgument(_))), "{tolerance}");
+        }
+    }
+}
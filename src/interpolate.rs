@@ -0,0 +1,245 @@
+use std::fmt;
+
+use crate::errors::EvalError;
+
+/// How a tabulated function goes from one point to the next.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Interpolation {
+    /// Straight lines between the points.
+    Linear,
+    /// A natural cubic spline, which has a continuous second derivative and no curvature at the
+    /// ends.
+    Spline,
+    /// A piecewise cubic Hermite polynomial with the slopes chosen so that it doesn't overshoot the
+    /// data, which keeps monotonic data monotonic.
+    Pchip,
+}
+
+/// A function given by a table of points, like the result of `spline(xs, ys)`. It can be called
+/// like any other function within the range of the table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interpolant {
+    pub kind: Interpolation,
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    /// The slope of the function at each point, which is all a cubic Hermite polynomial needs
+    /// besides the values. Empty for linear interpolation.
+    slopes: Vec<f64>,
+}
+
+impl Interpolant {
+    /// Creates the function through the points, which don't have to be sorted.
+    pub fn new(kind: Interpolation, xs: &[f64], ys: &[f64]) -> Result<Interpolant, EvalError> {
+        if xs.len() != ys.len() {
+            return Err(EvalError::LengthMismatch {
+                expected: xs.len(),
+                got: ys.len(),
+            });
+        }
+        if xs.len() < 2 {
+            return Err(EvalError::InvalidArgument(format!(
+                "Interpolating needs at least 2 points, not {}",
+                xs.len()
+            )));
+        }
+        if let Some(x) = xs.iter().chain(ys).find(|n| !n.is_finite()) {
+            return Err(EvalError::InvalidArgument(format!(
+                "Every point of the table has to be finite, not {x}"
+            )));
+        }
+
+        let mut points = xs.iter().copied().zip(ys.iter().copied()).collect::<Vec<_>>();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(EvalError::InvalidArgument(format!(
+                "The table has two values at x = {}",
+                pair[0].0
+            )));
+        }
+
+        let (xs, ys): (Vec<f64>, Vec<f64>) = points.into_iter().unzip();
+        let slopes = match kind {
+            Interpolation::Linear => vec![],
+            Interpolation::Spline => spline_slopes(&xs, &ys),
+            Interpolation::Pchip => pchip_slopes(&xs, &ys),
+        };
+
+        Ok(Interpolant {
+            kind,
+            xs,
+            ys,
+            slopes,
+        })
+    }
+
+    /// The value of the function and its derivative at `x`. At a point of a linear table the
+    /// derivative is the slope of the line to the right of it, except at the last point.
+    pub fn evaluate(&self, x: f64) -> Result<(f64, f64), EvalError> {
+        let (first, last) = (self.xs[0], self.xs[self.xs.len() - 1]);
+        if !(first..=last).contains(&x) {
+            return Err(EvalError::InvalidArgument(format!(
+                "{x} is outside the table, which goes from {first} to {last}"
+            )));
+        }
+
+        let i = self.xs.partition_point(|&v| v <= x).clamp(1, self.xs.len() - 1) - 1;
+        let (x0, x1, y0, y1) = (self.xs[i], self.xs[i + 1], self.ys[i], self.ys[i + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+
+        if self.kind == Interpolation::Linear {
+            return Ok((y0 + (y1 - y0) * t, (y1 - y0) / h));
+        }
+
+        // The cubic Hermite basis functions and their derivatives with respect to t.
+        let (m0, m1) = (self.slopes[i] * h, self.slopes[i + 1] * h);
+        let (t2, t3) = (t * t, t * t * t);
+        let value = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * m0
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * m1;
+        let derivative = (6.0 * t2 - 6.0 * t) * y0
+            + (3.0 * t2 - 4.0 * t + 1.0) * m0
+            + (-6.0 * t2 + 6.0 * t) * y1
+            + (3.0 * t2 - 2.0 * t) * m1;
+
+        Ok((value, derivative / h))
+    }
+}
+
+impl fmt::Display for Interpolant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            Interpolation::Linear => "linear interpolation",
+            Interpolation::Spline => "spline",
+            Interpolation::Pchip => "pchip",
+        };
+        let (first, last) = (self.xs[0], self.xs[self.xs.len() - 1]);
+        write!(f, "{kind} through {} points from {first} to {last}", self.xs.len())
+    }
+}
+
+/// The slopes of the lines between consecutive points.
+fn secants(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    xs.windows(2).zip(ys.windows(2)).map(|(x, y)| (y[1] - y[0]) / (x[1] - x[0])).collect()
+}
+
+/// The slopes of the natural cubic spline at the points. The second derivatives are found from
+/// the tridiagonal system that makes the first derivative continuous, with the Thomas algorithm,
+/// and then turned into slopes.
+fn spline_slopes(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    let n = xs.len();
+    let h = xs.windows(2).map(|x| x[1] - x[0]).collect::<Vec<_>>();
+    let d = secants(xs, ys);
+
+    // The second derivatives are zero at both ends, so only the inner ones are unknown.
+    let mut second = vec![0.0; n];
+    let mut diagonal = vec![0.0; n];
+    let mut rhs = vec![0.0; n];
+    for i in 1..n - 1 {
+        diagonal[i] = 2.0 * (h[i - 1] + h[i]);
+        rhs[i] = 6.0 * (d[i] - d[i - 1]);
+        if i > 1 {
+            let factor = h[i - 1] / diagonal[i - 1];
+            diagonal[i] -= factor * h[i - 1];
+            rhs[i] -= factor * rhs[i - 1];
+        }
+    }
+    for i in (1..n - 1).rev() {
+        second[i] = (rhs[i] - h[i] * second[i + 1]) / diagonal[i];
+    }
+
+    let mut slopes = (0..n - 1)
+        .map(|i| d[i] - h[i] * (2.0 * second[i] + second[i + 1]) / 6.0)
+        .collect::<Vec<_>>();
+    slopes.push(d[n - 2] + h[n - 2] * (second[n - 2] + 2.0 * second[n - 1]) / 6.0);
+    slopes
+}
+
+/// The slopes of the shape-preserving interpolant, from Fritsch and Carlson's method as it's used
+/// by MATLAB's `pchip`. Inside the table the slope is a weighted harmonic mean of the secants on
+/// either side, or zero at a peak or valley. The ends use a three-point formula that's limited so
+/// it can't overshoot.
+fn pchip_slopes(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    let n = xs.len();
+    let h = xs.windows(2).map(|x| x[1] - x[0]).collect::<Vec<_>>();
+    let d = secants(xs, ys);
+
+    if n == 2 {
+        return vec![d[0], d[0]];
+    }
+
+    let mut slopes = vec![0.0; n];
+    for k in 1..n - 1 {
+        if d[k - 1] * d[k] > 0.0 {
+            let w1 = 2.0 * h[k] + h[k - 1];
+            let w2 = h[k] + 2.0 * h[k - 1];
+            slopes[k] = (w1 + w2) / (w1 / d[k - 1] + w2 / d[k]);
+        }
+    }
+
+    let end = |h0: f64, h1: f64, d0: f64, d1: f64| {
+        let slope = ((2.0 * h0 + h1) * d0 - h0 * d1) / (h0 + h1);
+        if slope.signum() != d0.signum() || d0 == 0.0 {
+            0.0
+        } else if d0.signum() != d1.signum() && slope.abs() > (3.0 * d0).abs() {
+            3.0 * d0
+        } else {
+            slope
+        }
+    };
+    slopes[0] = end(h[0], h[1], d[0], d[1]);
+    slopes[n - 1] = end(h[n - 2], h[n - 3], d[n - 2], d[n - 3]);
+    slopes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_linear() {
+        let table = Interpolant::new(Interpolation::Linear, &[2.0, 0.0, 1.0], &[0.0, 0.0, 4.0]).unwrap();
+
+        assert_eq!(table.evaluate(0.25), Ok((1.0, 4.0)));
+        assert_eq!(table.evaluate(1.5), Ok((2.0, -4.0)));
+        assert_eq!(table.evaluate(2.0), Ok((0.0, -4.0)));
+        assert!(table.evaluate(2.5).is_err());
+    }
+
+    #[test]
+    fn test_spline() {
+        // A natural spline through points of a line is the line, and through three points it's
+        // known in closed form: the middle slope of 0, 1, 0 at 0, 1, 2 is 0 and the ends are ±1.5.
+        let line = Interpolant::new(Interpolation::Spline, &[0.0, 1.0, 3.0, 4.0], &[1.0, 3.0, 7.0, 9.0]).unwrap();
+        let peak = Interpolant::new(Interpolation::Spline, &[0.0, 1.0, 2.0], &[0.0, 1.0, 0.0]).unwrap();
+
+        let (value, slope) = line.evaluate(2.5).unwrap();
+        assert_close(value, 6.0, 1e-12);
+        assert_close(slope, 2.0, 1e-12);
+        assert_eq!(peak.slopes, vec![1.5, 0.0, -1.5]);
+        assert_eq!(peak.evaluate(0.5).unwrap().0, 0.6875);
+    }
+
+    #[test]
+    fn test_pchip_is_monotonic() {
+        let xs = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let ys = [0.0, 0.1, 0.2, 5.0, 5.1];
+        let table = Interpolant::new(Interpolation::Pchip, &xs, &ys).unwrap();
+
+        let values = (0..=400).map(|i| table.evaluate(i as f64 / 100.0).unwrap().0).collect::<Vec<_>>();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(table.evaluate(3.0), Ok((5.0, table.slopes[3])));
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        assert_eq!(
+            Interpolant::new(Interpolation::Spline, &[0.0, 1.0], &[1.0]),
+            Err(EvalError::LengthMismatch { expected: 2, got: 1 })
+        );
+        assert!(Interpolant::new(Interpolation::Linear, &[1.0], &[1.0]).is_err());
+        assert!(Interpolant::new(Interpolation::Pchip, &[1.0, 2.0, 1.0], &[1.0, 2.0, 3.0]).is_err());
+    }
+}
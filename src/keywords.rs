@@ -2,14 +2,30 @@ use std::collections::HashMap;
 
 use once_cell::sync::Lazy;
 
+use crate::tokenize::{Multiplicative, Operator};
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Function {
     pub name: &'static str,
     pub argument_count: usize,
+    /// How many arguments can be given after the required ones.
+    pub optional_arguments: usize,
 }
 
 type FuncMap = HashMap<String, Function>;
 type ConstMap = HashMap<String, f64>;
+type OperatorMap = HashMap<String, Operator>;
+type BindingMap = HashMap<String, Binding>;
+
+/// Where a function that binds a variable, like `integrate`, takes the variable and the
+/// expression the variable is bound in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Binding {
+    pub expression: usize,
+    pub variable: usize,
+    /// The fewest arguments the function has to be called with to bind a variable.
+    pub arguments: usize,
+}
 
 pub static FUNCTIONS: Lazy<FuncMap, fn() -> FuncMap> = Lazy::new(|| {
     HashMap::from(
@@ -17,38 +33,462 @@ pub static FUNCTIONS: Lazy<FuncMap, fn() -> FuncMap> = Lazy::new(|| {
             Function {
                 name: "sin",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "cos",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "tan",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "arcsin",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "arccos",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "arctan",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "log",
                 argument_count: 2,
+                optional_arguments: 0,
             },
             Function {
                 name: "ln",
                 argument_count: 1,
+                optional_arguments: 0,
             },
             Function {
                 name: "sqrt",
                 argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "round",
+                argument_count: 1,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "diff",
+                argument_count: 2,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "simplify",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "integrate",
+                argument_count: 4,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "solve",
+                argument_count: 2,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "limit",
+                argument_count: 3,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "taylor",
+                argument_count: 4,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "sum",
+                argument_count: 1,
+                optional_arguments: 3,
+            },
+            Function {
+                name: "prod",
+                argument_count: 1,
+                optional_arguments: 3,
+            },
+            Function {
+                name: "roots",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "expand",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "polydiv",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "polygcd",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "transpose",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "det",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "inv",
+                argument_count: 1,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "rank",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "eig",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "norm",
+                argument_count: 1,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "mean",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "median",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "mode",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "stdev",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "var",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "percentile",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "sort",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "len",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "min",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "max",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "cumsum",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "map",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "linfit",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "polyfit",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "expfit",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "fit",
+                argument_count: 4,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "minimize",
+                argument_count: 3,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "maximize",
+                argument_count: 3,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "odesolve",
+                argument_count: 6,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "interp",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "spline",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "pchip",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "fft",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "ifft",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "powerspectrum",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "convolve",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "window",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "freqs",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "pv",
+                argument_count: 3,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "fv",
+                argument_count: 3,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "pmt",
+                argument_count: 3,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "nper",
+                argument_count: 3,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "rate",
+                argument_count: 3,
+                optional_arguments: 3,
+            },
+            Function {
+                name: "npv",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "irr",
+                argument_count: 1,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "amort",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "weekday",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "workdays",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "tz",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "rat",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "cf",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "identify",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "crt",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "discretelog",
+                argument_count: 2,
+                optional_arguments: 1,
+            },
+            Function {
+                name: "normpdf",
+                argument_count: 1,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "normcdf",
+                argument_count: 1,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "norminv",
+                argument_count: 1,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "binompdf",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "binomcdf",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "poissonpdf",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "tcdf",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "chi2cdf",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "fcdf",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "expcdf",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "unifcdf",
+                argument_count: 3,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "rand",
+                argument_count: 0,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "randint",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "randn",
+                argument_count: 0,
+                optional_arguments: 2,
+            },
+            Function {
+                name: "montecarlo",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "rotl",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "rotr",
+                argument_count: 2,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "popcount",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "clz",
+                argument_count: 1,
+                optional_arguments: 0,
+            },
+            Function {
+                name: "ctz",
+                argument_count: 1,
+                optional_arguments: 0,
             },
         ]
         .map(|f| (f.name.to_string(), f)),
@@ -59,5 +499,104 @@ pub static CONSTANTS: Lazy<ConstMap, fn() -> ConstMap> = Lazy::new(|| {
     HashMap::from([
         ("e".to_string(), std::f64::consts::E),
         ("pi".to_string(), std::f64::consts::PI),
+        ("inf".to_string(), f64::INFINITY),
+    ])
+});
+
+/// Operators that are written as words instead of symbols.
+pub static OPERATOR_KEYWORDS: Lazy<OperatorMap, fn() -> OperatorMap> =
+    Lazy::new(|| {
+        HashMap::from([
+            ("xor".to_string(), Operator::BitXor),
+            // `±` is hard to type, so it can be spelled out.
+            ("pm".to_string(), Operator::PlusMinus),
+            ("mod".to_string(), Operator::Multiplicative(Multiplicative::Modulo)),
+        ])
+    });
+
+/// Functions that bind a variable while evaluating one of their arguments. The variable isn't free
+/// inside that argument, so it doesn't need a value from outside.
+pub static BINDING_FUNCTIONS: Lazy<BindingMap, fn() -> BindingMap> = Lazy::new(|| {
+    HashMap::from([
+        (
+            "integrate".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 4,
+            },
+        ),
+        (
+            "solve".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 3,
+            },
+        ),
+        (
+            "limit".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 3,
+            },
+        ),
+        (
+            "roots".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 2,
+            },
+        ),
+        (
+            "sum".to_string(),
+            Binding {
+                expression: 3,
+                variable: 0,
+                arguments: 4,
+            },
+        ),
+        (
+            "prod".to_string(),
+            Binding {
+                expression: 3,
+                variable: 0,
+                arguments: 4,
+            },
+        ),
+        (
+            "minimize".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 3,
+            },
+        ),
+        (
+            "maximize".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 3,
+            },
+        ),
+        (
+            "odesolve".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 6,
+            },
+        ),
+        (
+            "map".to_string(),
+            Binding {
+                expression: 0,
+                variable: 1,
+                arguments: 3,
+            },
+        ),
     ])
 });
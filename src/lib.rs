@@ -0,0 +1,40 @@
+pub mod ast;
+pub mod autodiff;
+pub mod calculator;
+pub mod compile;
+pub mod complex;
+pub mod datetime;
+pub mod decimal;
+pub mod distributions;
+pub mod errors;
+pub mod finance;
+pub mod fit;
+pub mod fraction;
+pub mod identify;
+pub mod eval;
+pub mod integrate;
+pub mod interpolate;
+pub mod keywords;
+pub mod limit;
+pub mod matrix;
+pub mod modular;
+pub mod montecarlo;
+pub mod ode;
+pub mod optimize;
+pub mod polynomial;
+pub mod programmer;
+pub mod random;
+pub mod series;
+pub mod signal;
+pub mod simplify;
+pub mod solve;
+pub mod special;
+pub mod statistics;
+pub mod symbolic;
+#[cfg(test)]
+mod testing;
+pub mod tokenize;
+pub mod uncertainty;
+pub mod value;
+
+pub use calculator::Calculator;
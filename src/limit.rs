@@ -0,0 +1,233 @@
+use crate::{ast::ASTNode, errors::EvalError, eval::*};
+
+/// How far from the point the first value is taken, relative to the size of the point when it's
+/// larger than one. The distance is halved every step after that.
+const INITIAL_STEP: f64 = 0.125;
+
+/// The most times the distance to the point is halved.
+const MAX_STEPS: usize = 24;
+
+/// The most times the distance to the point is halved while squeezing values that close in on the
+/// limit without a pattern that can be extrapolated.
+const MAX_SQUEEZE_STEPS: usize = 60;
+
+/// How many of the last changes in the values are compared to the ones before them while
+/// squeezing.
+const SQUEEZE_BLOCK: usize = 8;
+
+/// How close successive extrapolations have to be, relative to the limit when it's larger than
+/// one.
+const TOLERANCE: f64 = 1e-8;
+
+/// The side a limit is approached from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Below,
+    Above,
+}
+
+/// Evaluates a call to `limit(expr, x, a)`, with an optional fourth argument that approaches the
+/// point only from above when it's positive or only from below when it's negative.
+pub fn evaluate_limit(ast: &ASTNode, scope: &Scope) -> Result<f64, EvalError> {
+    let var = ast.children[1].variable_name("limit")?;
+    let point = evaluate_ast(&ast.children[2], scope)?;
+    let direction = match ast.children.get(3) {
+        Some(direction) => evaluate_ast(direction, scope)?,
+        None => 0.0,
+    };
+
+    let mut inner = scope.clone();
+    let f = |x: f64| {
+        inner.set(&var, x);
+        evaluate_ast(&ast.children[0], &inner)
+    };
+
+    approach(f, point, direction)
+}
+
+/// Finds the limit of the function at the point from above when the direction is positive, from
+/// below when it's negative, and from both sides when it's zero. Both sides have to agree.
+pub fn approach<F>(mut f: F, point: f64, direction: f64) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    match (point, direction) {
+        (f64::INFINITY, _) => limit(f, point, Side::Below),
+        (f64::NEG_INFINITY, _) => limit(f, point, Side::Above),
+        (_, d) if d > 0.0 => limit(f, point, Side::Above),
+        (_, d) if d < 0.0 => limit(f, point, Side::Below),
+        _ => {
+            let below = limit(&mut f, point, Side::Below)?;
+            let above = limit(&mut f, point, Side::Above)?;
+
+            // Limits of opposite infinities are infinitely far apart, not equal.
+            let close = below.is_finite() && (below - above).abs() <= TOLERANCE * below.abs().max(1.0);
+            if below == above || close {
+                Ok((below + above) / 2.0)
+            } else {
+                Err(EvalError::NoLimit)
+            }
+        }
+    }
+}
+
+/// Finds the limit of the function as it approaches the point from one side. Values closer and
+/// closer to the point are extrapolated to the point with Richardson extrapolation. Limits that
+/// go to infinity are found by extrapolating the reciprocal of the function to zero instead.
+pub fn limit<F>(mut f: F, point: f64, side: Side) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    // Infinite points are approached through x = 1 / t as t goes to zero.
+    let mut g = |h: f64| match (point.is_infinite(), side) {
+        (true, _) => f(point.signum() / h),
+        (false, Side::Above) => f(point + h),
+        (false, Side::Below) => f(point - h),
+    };
+
+    let first = INITIAL_STEP * if point.is_infinite() { 1.0 } else { point.abs().max(1.0) };
+    let mut values = vec![];
+    for i in 0..MAX_STEPS {
+        values.push(g(first / (1 << i) as f64)?);
+    }
+
+    if let Some(limit) = extrapolate(&values) {
+        return Ok(limit);
+    }
+
+    // A function that goes to infinity has a reciprocal that goes to zero, as long as it keeps
+    // the same sign.
+    let last = values[values.len() - 1];
+    if values.iter().rev().take(4).all(|v| v.signum() == last.signum()) {
+        let reciprocals = values.iter().map(|v| 1.0 / v).collect::<Vec<_>>();
+        if extrapolate(&reciprocals).is_some_and(|r| r.abs() <= TOLERANCE) {
+            return Ok(f64::INFINITY.copysign(last));
+        }
+    }
+
+    // Values that close in on the limit without settling into a pattern, like those of
+    // `x sin(1/x)` at zero, are squeezed instead. As long as the values change by at most about
+    // the same multiple of the distance every step, the limit is within twice that multiple of the
+    // last distance from the last value, and the distance keeps being halved until that's within
+    // the tolerance.
+    let distance = |i: usize| first / 2f64.powi(i as i32);
+    for i in MAX_STEPS..=MAX_SQUEEZE_STEPS {
+        let changes = values.windows(2).enumerate().map(|(k, v)| (v[1] - v[0]).abs() / distance(k));
+        let changes = changes.collect::<Vec<_>>();
+        let n = changes.len();
+        let rate = largest(&changes[n - SQUEEZE_BLOCK..]);
+        let bounded = rate <= 2.0 * largest(&changes[n - 2 * SQUEEZE_BLOCK..n - SQUEEZE_BLOCK]);
+        if !bounded {
+            break;
+        }
+
+        let closest = values[values.len() - 1];
+        let error = 2.0 * rate * distance(values.len() - 1);
+        if error <= TOLERANCE * closest.abs().max(1.0) {
+            // A limit that could be on either side of zero is zero.
+            return Ok(if closest.abs() <= error { 0.0 } else { closest });
+        }
+        if i < MAX_SQUEEZE_STEPS {
+            values.push(g(distance(i))?);
+        }
+    }
+
+    Err(EvalError::NoLimit)
+}
+
+/// The largest of the values, which is NaN if any of them are.
+fn largest(values: &[f64]) -> f64 {
+    match values.iter().any(|v| v.is_nan()) {
+        true => f64::NAN,
+        false => values.iter().fold(0.0, |largest, &v| largest.max(v)),
+    }
+}
+
+/// Extrapolates values taken at distances that halve each time to a distance of zero. The
+/// estimate with the smallest change from the one before it is used, since rounding errors
+/// eventually make later estimates worse.
+fn extrapolate(values: &[f64]) -> Option<f64> {
+    let mut previous: Vec<f64> = vec![];
+    let mut estimate = f64::NAN;
+    let mut best: Option<(f64, f64)> = None;
+
+    for &value in values {
+        let mut row = vec![value];
+        for (m, &above) in previous.iter().enumerate() {
+            let current = row[m];
+            row.push(current + (current - above) / ((1 << (m + 1)) as f64 - 1.0));
+        }
+
+        let next = *row.last().unwrap();
+        let change = (next - estimate).abs();
+        if change.is_finite() && best.is_none_or(|(_, best)| change < best) {
+            best = Some((next, change));
+        }
+
+        estimate = next;
+        previous = row;
+    }
+
+    best.filter(|&(limit, change)| change <= TOLERANCE * limit.abs().max(1.0))
+        .map(|(limit, _)| limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_removable_singularity() {
+        let sinc = evaluate_expression("limit(sin(x) / x, x, 0)").unwrap();
+        let cosine = evaluate_expression("limit((1 - cos(x)) / x^2, x, 0)").unwrap();
+
+        assert_close(sinc, 1.0, 1e-7);
+        assert_close(cosine, 0.5, 1e-7);
+    }
+
+    #[test]
+    fn test_limit_at_infinity() {
+        let res = evaluate_expression("limit((1 + 1/n)^n, n, inf)").unwrap();
+
+        assert_close(res, std::f64::consts::E, 1e-7);
+    }
+
+    #[test]
+    fn test_one_sided() {
+        let above = evaluate_expression("limit(sqrt(x^2) / x, x, 0, 1)").unwrap();
+        let below = evaluate_expression("limit(sqrt(x^2) / x, x, 0, -1)").unwrap();
+        let both = evaluate_expression("limit(sqrt(x^2) / x, x, 0)");
+
+        assert_close(above, 1.0, 1e-7);
+        assert_close(below, -1.0, 1e-7);
+        assert_eq!(both, Err(EvalError::NoLimit));
+    }
+
+    #[test]
+    fn test_infinite_limit() {
+        let res = evaluate_expression("limit(1 / x^2, x, 0)").unwrap();
+        let above = evaluate_expression("limit(1 / x, x, 0, 1)").unwrap();
+
+        assert_eq!(res, f64::INFINITY);
+        assert_eq!(above, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_no_limit() {
+        let res = evaluate_expression("limit(sin(1 / x), x, 0)");
+        let poles = evaluate_expression("limit(1 / x, x, 0)");
+
+        assert_eq!(res, Err(EvalError::NoLimit));
+        assert_eq!(poles, Err(EvalError::NoLimit));
+    }
+
+    #[test]
+    fn test_squeezed_limit() {
+        let zero = evaluate_expression("limit(x sin(1 / x), x, 0)").unwrap();
+        let one = evaluate_expression("limit(1 + x^2 cos(1 / x), x, 0)").unwrap();
+
+        assert_eq!(zero, 0.0);
+        assert_close(one, 1.0, 1e-7);
+    }
+}
@@ -5,36 +5,72 @@
 
 use std::{io, io::Write};
 
-use errors::EvalError;
-
-use crate::eval::*;
-
-mod ast;
-mod errors;
-mod eval;
-mod tokenize;
-mod keywords;
+use calculator::{errors::EvalError, Calculator};
 
 fn main() -> io::Result<()> {
+    let mut calculator = Calculator::new();
+
     while let Ok(input) = get_input("Enter an expression: ") {
         if input.is_empty() {
             break;
         }
 
-        let result = evaluate_expression(&input);
+        // Lines starting with a colon are commands that change the calculator's settings.
+        if let Some(command) = input.trim().strip_prefix(':') {
+            match calculator.run_command(command) {
+                Ok(message) => println!("{message}"),
+                Err(err) => print_error(err),
+            }
+            continue;
+        }
+
+        let result = calculator.evaluate(&input);
         match result {
-            Ok(result) => println!("Your expression evaluated to: {}", result),
-            Err(EvalError::InvalidExpression(_)) => println!("Invalid expression, please try again."),
-            Err(EvalError::UnknownKeyword(k)) => println!("Unknown keyword: {:#?}", k),
-            Err(EvalError::NoFunctionArguments(k)) => println!("No function variablesfor function: {:#?}", k),
-            Err(EvalError::InvalidArgumentCount { expected, got }) => println!("Invalid argument count, expected: {:#?}, got: {:#?}", expected, got),
-            Err(EvalError::ProgramIsStupid) => println!("there is a collision between a function and a constant, program is stupid"),
+            Ok(result) => println!("Your expression evaluated to: {}", calculator.display(&result)),
+            Err(err) => print_error(err),
         }
     }
 
     Ok(())
 }
 
+fn print_error(err: EvalError) {
+    match err {
+        EvalError::InvalidExpression(_) => println!("Invalid expression, please try again."),
+        EvalError::UnknownKeyword(k) => println!("Unknown keyword: {:#?}", k),
+        EvalError::NoFunctionArguments(k) => println!("No function variablesfor function: {:#?}", k),
+        EvalError::InvalidArgumentCount { expected, got } => println!("Invalid argument count, expected: {:#?}, got: {:#?}", expected, got),
+        EvalError::ProgramIsStupid => println!("there is a collision between a function and a constant, program is stupid"),
+        EvalError::ProgrammerModeOnly(k) => println!("{:#?} is only available in programmer mode, enter `:prog <type>` to use it", k),
+        EvalError::NotInProgrammerMode(k) => println!("{:#?} is not available in programmer mode", k),
+        EvalError::NotInDecimalMode(k) => println!("{:#?} is not available in decimal mode", k),
+        EvalError::NotInModularMode(k) => println!("{:#?} is not available in modular arithmetic, which only works on integers", k),
+        EvalError::NotAnInteger(n) => println!("{} is not an integer", n),
+        EvalError::Overflow => println!("The result does not fit in the integer type"),
+        EvalError::DivisionByZero => println!("Division by zero"),
+        EvalError::NoInverse { value, modulus } => println!("{} has no inverse mod {}, because they have a common factor", value, modulus),
+        EvalError::NegativeShift => println!("Cannot shift or rotate by a negative amount"),
+        EvalError::NegativeExponent => println!("Cannot raise an integer to a negative power"),
+        EvalError::UnknownCommand(c) => println!("Unknown command: {:#?}", c),
+        EvalError::ExpectedVariable(f) => println!("{:#?} expects a variable to work with respect to", f),
+        EvalError::NotDifferentiable(k) => println!("{:#?} cannot be differentiated", k),
+        EvalError::NoConvergence { iterations } => println!("No convergence after {} iterations", iterations),
+        EvalError::NoSignChange => println!("The expression has the same sign at both ends of the interval, or everywhere searched around the guess"),
+        EvalError::TooManyTerms { limit } => println!("Sums and products can't have more than {} terms", limit),
+        EvalError::NoLimit => println!("The limit doesn't exist"),
+        EvalError::Unbounded => println!("The expression is unbounded, so it has no minimum or maximum"),
+        EvalError::NotAPolynomial(e) => println!("{:#?} is not a polynomial", e),
+        EvalError::DegreeTooHigh { limit } => println!("Polynomials can't have a degree higher than {}", limit),
+        EvalError::NotANumber(f) => println!("{:#?} doesn't evaluate to a number, so it can't be used in an expression", f),
+        EvalError::ShapeMismatch { expected, got } => println!("Expected a {}x{} matrix, got a {}x{} matrix", expected.0, expected.1, got.0, got.1),
+        EvalError::LengthMismatch { expected, got } => println!("Expected a list of {} elements, got {}", expected, got),
+        EvalError::NotSquare { rows, columns } => println!("Expected a square matrix, got a {}x{} matrix", rows, columns),
+        EvalError::SingularMatrix => println!("The matrix is singular"),
+        EvalError::InvalidOperation(o) => println!("{}", o),
+        EvalError::InvalidArgument(a) => println!("{}", a),
+    }
+}
+
 /// Helper function to get input from stdin with a query.
 fn get_input(query: &str) -> Result<String, io::Error> {
     let mut buffer = String::new();
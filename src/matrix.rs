@@ -0,0 +1,817 @@
+use std::{
+    fmt,
+    ops::{Index, IndexMut},
+};
+
+use crate::{
+    complex::{self, Complex},
+    errors::EvalError,
+};
+
+/// The most sweeps of the Jacobi method, and the most iterations per eigenvalue of the QR
+/// algorithm, before giving up.
+const MAX_ITERATIONS: usize = 60;
+
+/// A matrix of floats, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    data: Vec<f64>,
+}
+
+/// An LU decomposition with partial pivoting, where `lu` holds both triangles and row `i` of it
+/// came from row `permutation[i]` of the original matrix.
+struct Lu {
+    lu: Matrix,
+    permutation: Vec<usize>,
+    sign: f64,
+}
+
+impl Matrix {
+    /// Creates a matrix from its elements, given row by row.
+    pub fn new(rows: usize, columns: usize, data: Vec<f64>) -> Matrix {
+        assert_eq!(rows * columns, data.len(), "The data has to fill the matrix");
+        Matrix {
+            rows,
+            columns,
+            data,
+        }
+    }
+
+    pub fn zeros(rows: usize, columns: usize) -> Matrix {
+        Matrix::new(rows, columns, vec![0.0; rows * columns])
+    }
+
+    pub fn identity(n: usize) -> Matrix {
+        let mut identity = Matrix::zeros(n, n);
+        for i in 0..n {
+            identity[(i, i)] = 1.0;
+        }
+        identity
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    /// The elements, row by row.
+    pub fn data(&self) -> &[f64] {
+        &self.data
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.rows == 1 || self.columns == 1
+    }
+
+    fn square(&self) -> Result<usize, EvalError> {
+        match self.rows == self.columns {
+            true => Ok(self.rows),
+            false => Err(EvalError::NotSquare {
+                rows: self.rows,
+                columns: self.columns,
+            }),
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut transpose = Matrix::zeros(self.columns, self.rows);
+        for i in 0..self.rows {
+            for j in 0..self.columns {
+                transpose[(j, i)] = self[(i, j)];
+            }
+        }
+        transpose
+    }
+
+    pub fn map<F: FnMut(f64) -> f64>(&self, f: F) -> Matrix {
+        Matrix::new(self.rows, self.columns, self.data.iter().copied().map(f).collect())
+    }
+
+    /// Combines the matrices element by element. They have to be the same shape.
+    pub fn zip_with<F>(&self, other: &Matrix, mut f: F) -> Result<Matrix, EvalError>
+    where
+        F: FnMut(f64, f64) -> f64,
+    {
+        if self.shape() != other.shape() {
+            return Err(EvalError::ShapeMismatch {
+                expected: self.shape(),
+                got: other.shape(),
+            });
+        }
+
+        let data = self.data.iter().zip(&other.data).map(|(a, b)| f(*a, *b)).collect();
+        Ok(Matrix::new(self.rows, self.columns, data))
+    }
+
+    /// The matrix product, which needs as many columns on the left as rows on the right.
+    pub fn matmul(&self, other: &Matrix) -> Result<Matrix, EvalError> {
+        if self.columns != other.rows {
+            return Err(EvalError::ShapeMismatch {
+                expected: (self.columns, other.columns),
+                got: other.shape(),
+            });
+        }
+
+        let mut product = Matrix::zeros(self.rows, other.columns);
+        for i in 0..self.rows {
+            for k in 0..self.columns {
+                let a = self[(i, k)];
+                for j in 0..other.columns {
+                    product[(i, j)] += a * other[(k, j)];
+                }
+            }
+        }
+        Ok(product)
+    }
+
+    /// Raises a square matrix to an integer power by repeated squaring. Negative powers are
+    /// powers of the inverse.
+    pub fn pow(&self, exponent: i64) -> Result<Matrix, EvalError> {
+        let n = self.square()?;
+        let mut base = match exponent < 0 {
+            true => self.inverse()?,
+            false => self.clone(),
+        };
+
+        let mut result = Matrix::identity(n);
+        let mut exponent = exponent.unsigned_abs();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.matmul(&base)?;
+            }
+            base = base.matmul(&base)?;
+            exponent >>= 1;
+        }
+        Ok(result)
+    }
+
+    /// The largest absolute value of an element, used to judge what counts as zero.
+    fn scale(&self) -> f64 {
+        self.data.iter().fold(0.0, |max: f64, a| max.max(a.abs()))
+    }
+
+    /// Decomposes a square matrix into lower and upper triangles, swapping rows so the largest
+    /// remaining element of each column is the pivot.
+    fn lu(&self) -> Result<Lu, EvalError> {
+        let n = self.square()?;
+        let mut lu = self.clone();
+        let mut permutation = (0..n).collect::<Vec<_>>();
+        let mut sign = 1.0;
+
+        for k in 0..n {
+            let pivot = (k..n)
+                .max_by(|&a, &b| lu[(a, k)].abs().total_cmp(&lu[(b, k)].abs()))
+                .unwrap();
+            if pivot != k {
+                for j in 0..n {
+                    lu.data.swap(k * n + j, pivot * n + j);
+                }
+                permutation.swap(k, pivot);
+                sign = -sign;
+            }
+
+            if lu[(k, k)] == 0.0 {
+                continue;
+            }
+
+            for i in k + 1..n {
+                let factor = lu[(i, k)] / lu[(k, k)];
+                lu[(i, k)] = factor;
+                for j in k + 1..n {
+                    lu[(i, j)] -= factor * lu[(k, j)];
+                }
+            }
+        }
+
+        Ok(Lu {
+            lu,
+            permutation,
+            sign,
+        })
+    }
+
+    pub fn det(&self) -> Result<f64, EvalError> {
+        let Lu { lu, sign, .. } = self.lu()?;
+        Ok((0..lu.rows).fold(sign, |det, i| det * lu[(i, i)]))
+    }
+
+    pub fn inverse(&self) -> Result<Matrix, EvalError> {
+        self.solve(&Matrix::identity(self.square()?))
+    }
+
+    /// Solves `self * x = b` for `x`, where `b` can have several columns. The matrix has to be
+    /// square and not singular.
+    pub fn solve(&self, b: &Matrix) -> Result<Matrix, EvalError> {
+        let n = self.square()?;
+        if b.rows != n {
+            return Err(EvalError::ShapeMismatch {
+                expected: (n, b.columns),
+                got: b.shape(),
+            });
+        }
+
+        let Lu {
+            lu, permutation, ..
+        } = self.lu()?;
+
+        // Pivots this small next to the matrix are rounding errors of a singular matrix.
+        let tolerance = n as f64 * f64::EPSILON * self.scale();
+        if (0..n).any(|i| lu[(i, i)].abs() <= tolerance) {
+            return Err(EvalError::SingularMatrix);
+        }
+
+        let mut x = Matrix::zeros(n, b.columns);
+        for column in 0..b.columns {
+            // Forward substitution with the lower triangle, then back substitution with the upper.
+            let mut y = permutation.iter().map(|&p| b[(p, column)]).collect::<Vec<_>>();
+            for i in 0..n {
+                for j in 0..i {
+                    y[i] -= lu[(i, j)] * y[j];
+                }
+            }
+            for i in (0..n).rev() {
+                for j in i + 1..n {
+                    y[i] -= lu[(i, j)] * y[j];
+                }
+                y[i] /= lu[(i, i)];
+            }
+
+            for (i, value) in y.into_iter().enumerate() {
+                x[(i, column)] = value;
+            }
+        }
+        Ok(x)
+    }
+
+    /// Finds the `x` that minimizes the squared length of `self * x - b`, for a matrix with at least
+    /// as many rows as columns. Householder reflections turn the matrix into a triangle without
+    /// squaring its condition number, which the normal equations would.
+    pub fn least_squares(&self, b: &[f64]) -> Result<Vec<f64>, EvalError> {
+        let (m, n) = self.shape();
+        if b.len() != m {
+            return Err(EvalError::LengthMismatch {
+                expected: m,
+                got: b.len(),
+            });
+        }
+        if m < n {
+            return Err(EvalError::InvalidArgument(format!(
+                "A least squares problem needs at least as many equations as unknowns, but there are {m} equations and {n} unknowns"
+            )));
+        }
+
+        // b is reflected along with the matrix as an extra column.
+        let mut a = Matrix::zeros(m, n + 1);
+        for i in 0..m {
+            for j in 0..n {
+                a[(i, j)] = self[(i, j)];
+            }
+            a[(i, n)] = b[i];
+        }
+
+        for k in 0..n {
+            // The reflection maps the column below the diagonal onto the diagonal, choosing the
+            // sign that avoids cancellation.
+            let norm = (k..m).map(|i| a[(i, k)].powi(2)).sum::<f64>().sqrt();
+            let alpha = if a[(k, k)] > 0.0 { -norm } else { norm };
+            let mut v = (k..m).map(|i| a[(i, k)]).collect::<Vec<_>>();
+            v[0] -= alpha;
+
+            let length = v.iter().map(|x| x * x).sum::<f64>();
+            if length == 0.0 {
+                continue;
+            }
+
+            for j in k..=n {
+                let dot = (k..m).map(|i| v[i - k] * a[(i, j)]).sum::<f64>();
+                for i in k..m {
+                    a[(i, j)] -= 2.0 * dot / length * v[i - k];
+                }
+            }
+        }
+
+        let tolerance = m as f64 * f64::EPSILON * self.scale();
+        let mut x = vec![0.0; n];
+        for i in (0..n).rev() {
+            if a[(i, i)].abs() <= tolerance {
+                return Err(EvalError::SingularMatrix);
+            }
+            let sum = (i + 1..n).map(|j| a[(i, j)] * x[j]).sum::<f64>();
+            x[i] = (a[(i, n)] - sum) / a[(i, i)];
+        }
+        Ok(x)
+    }
+
+    /// The number of linearly independent rows, found by Gaussian elimination with complete
+    /// pivoting.
+    pub fn rank(&self) -> usize {
+        let mut a = self.clone();
+        let tolerance = self.rows.max(self.columns) as f64 * f64::EPSILON * self.scale();
+        let mut rank = 0;
+
+        while rank < a.rows.min(a.columns) {
+            let (mut pi, mut pj) = (rank, rank);
+            for i in rank..a.rows {
+                for j in rank..a.columns {
+                    if a[(i, j)].abs() > a[(pi, pj)].abs() {
+                        (pi, pj) = (i, j);
+                    }
+                }
+            }
+            if a[(pi, pj)].abs() <= tolerance {
+                break;
+            }
+
+            for j in 0..a.columns {
+                a.data.swap(rank * a.columns + j, pi * a.columns + j);
+            }
+            for i in 0..a.rows {
+                a.data.swap(i * a.columns + rank, i * a.columns + pj);
+            }
+
+            for i in rank + 1..a.rows {
+                let factor = a[(i, rank)] / a[(rank, rank)];
+                for j in rank..a.columns {
+                    a[(i, j)] -= factor * a[(rank, j)];
+                }
+            }
+            rank += 1;
+        }
+
+        rank
+    }
+
+    fn is_symmetric(&self) -> bool {
+        let tolerance = f64::EPSILON * self.scale();
+        self.rows == self.columns
+            && (0..self.rows).all(|i| (0..i).all(|j| (self[(i, j)] - self[(j, i)]).abs() <= tolerance))
+    }
+
+    /// The eigenvalues of a square matrix, real ones first. Symmetric matrices use the Jacobi
+    /// method, which only gives real eigenvalues, and everything else the QR algorithm.
+    pub fn eigenvalues(&self) -> Result<Vec<Complex>, EvalError> {
+        self.square()?;
+
+        let mut eigenvalues = match self.is_symmetric() {
+            true => self.jacobi()?.into_iter().map(Complex::real).collect(),
+            false => self.hessenberg().hqr()?,
+        };
+        complex::sort(&mut eigenvalues);
+        Ok(eigenvalues)
+    }
+
+    /// Finds the eigenvalues of a symmetric matrix by rotating away the off-diagonal elements one
+    /// at a time until the matrix is diagonal.
+    fn jacobi(&self) -> Result<Vec<f64>, EvalError> {
+        let n = self.rows;
+        let mut a = self.clone();
+        let tolerance = f64::EPSILON * self.scale();
+
+        for _ in 0..MAX_ITERATIONS {
+            let off_diagonal = (0..n)
+                .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+                .fold(0.0, |max: f64, (i, j)| max.max(a[(i, j)].abs()));
+            if off_diagonal <= tolerance {
+                return Ok((0..n).map(|i| a[(i, i)]).collect());
+            }
+
+            for p in 0..n {
+                for q in p + 1..n {
+                    if a[(p, q)] == 0.0 {
+                        continue;
+                    }
+
+                    // The rotation that zeroes a[p][q].
+                    let theta = (a[(q, q)] - a[(p, p)]) / (2.0 * a[(p, q)]);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+
+                    for k in 0..n {
+                        let (akp, akq) = (a[(k, p)], a[(k, q)]);
+                        a[(k, p)] = c * akp - s * akq;
+                        a[(k, q)] = s * akp + c * akq;
+                    }
+                    for k in 0..n {
+                        let (apk, aqk) = (a[(p, k)], a[(q, k)]);
+                        a[(p, k)] = c * apk - s * aqk;
+                        a[(q, k)] = s * apk + c * aqk;
+                    }
+                }
+            }
+        }
+
+        Err(EvalError::NoConvergence {
+            iterations: MAX_ITERATIONS,
+        })
+    }
+
+    /// Reduces the matrix to upper Hessenberg form, which has the same eigenvalues and only zeros
+    /// below the first subdiagonal, by elimination with pivoting.
+    fn hessenberg(&self) -> Matrix {
+        let n = self.rows;
+        let mut a = self.clone();
+
+        for m in 1..n.saturating_sub(1) {
+            let pivot = (m..n)
+                .max_by(|&i, &j| a[(i, m - 1)].abs().total_cmp(&a[(j, m - 1)].abs()))
+                .unwrap();
+            let x = a[(pivot, m - 1)];
+
+            if pivot != m {
+                for j in m - 1..n {
+                    a.data.swap(pivot * n + j, m * n + j);
+                }
+                for j in 0..n {
+                    a.data.swap(j * n + pivot, j * n + m);
+                }
+            }
+
+            if x == 0.0 {
+                continue;
+            }
+
+            for i in m + 1..n {
+                let y = a[(i, m - 1)] / x;
+                if y == 0.0 {
+                    continue;
+                }
+                a[(i, m - 1)] = 0.0;
+                for j in m..n {
+                    a[(i, j)] -= y * a[(m, j)];
+                }
+                for j in 0..n {
+                    a[(j, m)] += y * a[(j, i)];
+                }
+            }
+        }
+
+        a
+    }
+
+    /// Finds the eigenvalues of an upper Hessenberg matrix with the shifted QR algorithm, splitting
+    /// off one real eigenvalue or a pair of complex ones whenever a subdiagonal element becomes
+    /// negligible.
+    fn hqr(mut self) -> Result<Vec<Complex>, EvalError> {
+        let n = self.rows as isize;
+        let mut eigenvalues = vec![Complex::default(); self.rows];
+        let at = |i: isize, j: isize| (i * n + j) as usize;
+        let a = &mut self.data;
+
+        let mut norm = 0.0;
+        for i in 0..n {
+            for j in (i - 1).max(0)..n {
+                norm += a[at(i, j)].abs();
+            }
+        }
+
+        let mut nn = n - 1;
+        let mut t = 0.0;
+        while nn >= 0 {
+            let mut iterations = 0;
+            loop {
+                // Look for a negligible subdiagonal element to split the matrix at.
+                let mut l = nn;
+                while l >= 1 {
+                    let mut s = a[at(l - 1, l - 1)].abs() + a[at(l, l)].abs();
+                    if s == 0.0 {
+                        s = norm;
+                    }
+                    if a[at(l, l - 1)].abs() + s == s {
+                        a[at(l, l - 1)] = 0.0;
+                        break;
+                    }
+                    l -= 1;
+                }
+
+                let mut x = a[at(nn, nn)];
+                if l == nn {
+                    // One real eigenvalue split off.
+                    eigenvalues[nn as usize] = Complex::real(x + t);
+                    nn -= 1;
+                    break;
+                }
+
+                let mut y = a[at(nn - 1, nn - 1)];
+                let mut w = a[at(nn, nn - 1)] * a[at(nn - 1, nn)];
+                if l == nn - 1 {
+                    // Two eigenvalues split off, from the 2x2 block at the bottom.
+                    let p = 0.5 * (y - x);
+                    let q = p * p + w;
+                    let z = q.abs().sqrt();
+                    x += t;
+                    if q >= 0.0 {
+                        let z = p + z.copysign(p);
+                        eigenvalues[nn as usize - 1] = Complex::real(x + z);
+                        eigenvalues[nn as usize] =
+                            Complex::real(if z != 0.0 { x - w / z } else { x + z });
+                    } else {
+                        eigenvalues[nn as usize - 1] = Complex::new(x + p, -z);
+                        eigenvalues[nn as usize] = Complex::new(x + p, z);
+                    }
+                    nn -= 2;
+                    break;
+                }
+
+                if iterations == MAX_ITERATIONS {
+                    return Err(EvalError::NoConvergence { iterations });
+                }
+
+                // Exceptional shifts break cycles that the usual shift can get stuck in.
+                if iterations == 10 || iterations == 20 {
+                    t += x;
+                    for i in 0..=nn {
+                        a[at(i, i)] -= x;
+                    }
+                    let s = a[at(nn, nn - 1)].abs() + a[at(nn - 1, nn - 2)].abs();
+                    x = 0.75 * s;
+                    y = x;
+                    w = -0.4375 * s * s;
+                }
+                iterations += 1;
+
+                // Look for two consecutive small subdiagonal elements to start the double shift
+                // from.
+                let (mut p, mut q, mut r);
+                let mut m = nn - 2;
+                loop {
+                    let z = a[at(m, m)];
+                    let rr = x - z;
+                    let s = y - z;
+                    p = (rr * s - w) / a[at(m + 1, m)] + a[at(m, m + 1)];
+                    q = a[at(m + 1, m + 1)] - z - rr - s;
+                    r = a[at(m + 2, m + 1)];
+                    let s = p.abs() + q.abs() + r.abs();
+                    p /= s;
+                    q /= s;
+                    r /= s;
+                    if m == l {
+                        break;
+                    }
+                    let u = a[at(m, m - 1)].abs() * (q.abs() + r.abs());
+                    let v = p.abs() * (a[at(m - 1, m - 1)].abs() + z.abs() + a[at(m + 1, m + 1)].abs());
+                    if u + v == v {
+                        break;
+                    }
+                    m -= 1;
+                }
+
+                for i in m + 2..=nn {
+                    a[at(i, i - 2)] = 0.0;
+                    if i != m + 2 {
+                        a[at(i, i - 3)] = 0.0;
+                    }
+                }
+
+                // The double QR step, chasing the bulge down the matrix.
+                let mut k = m;
+                while k < nn {
+                    if k != m {
+                        p = a[at(k, k - 1)];
+                        q = a[at(k + 1, k - 1)];
+                        r = if k != nn - 1 { a[at(k + 2, k - 1)] } else { 0.0 };
+                        x = p.abs() + q.abs() + r.abs();
+                        if x != 0.0 {
+                            p /= x;
+                            q /= x;
+                            r /= x;
+                        }
+                    }
+
+                    let s = (p * p + q * q + r * r).sqrt().copysign(p);
+                    if s != 0.0 {
+                        if k == m {
+                            if l != m {
+                                a[at(k, k - 1)] = -a[at(k, k - 1)];
+                            }
+                        } else {
+                            a[at(k, k - 1)] = -s * x;
+                        }
+                        p += s;
+                        x = p / s;
+                        y = q / s;
+                        let z = r / s;
+                        q /= p;
+                        r /= p;
+
+                        for j in k..=nn {
+                            let mut p = a[at(k, j)] + q * a[at(k + 1, j)];
+                            if k != nn - 1 {
+                                p += r * a[at(k + 2, j)];
+                                a[at(k + 2, j)] -= p * z;
+                            }
+                            a[at(k + 1, j)] -= p * y;
+                            a[at(k, j)] -= p * x;
+                        }
+
+                        for i in l..=nn.min(k + 3) {
+                            let mut p = x * a[at(i, k)] + y * a[at(i, k + 1)];
+                            if k != nn - 1 {
+                                p += z * a[at(i, k + 2)];
+                                a[at(i, k + 2)] -= p * r;
+                            }
+                            a[at(i, k + 1)] -= p * q;
+                            a[at(i, k)] -= p;
+                        }
+                    }
+                    k += 1;
+                }
+            }
+        }
+
+        Ok(eigenvalues)
+    }
+
+    /// The p-norm of a vector, or the matrix norm induced by it for p = 1, 2 or infinity. The
+    /// 2-norm of a matrix is its largest singular value.
+    pub fn norm(&self, p: f64) -> Result<f64, EvalError> {
+        let abs = self.data.iter().map(|a| a.abs());
+
+        if self.is_vector() {
+            return match p {
+                f64::INFINITY => Ok(abs.fold(0.0, f64::max)),
+                p if p >= 1.0 => Ok(abs.map(|a| a.powf(p)).sum::<f64>().powf(1.0 / p)),
+                p => Err(EvalError::InvalidArgument(format!(
+                    "The norm of a vector needs p to be at least 1, not {p}"
+                ))),
+            };
+        }
+
+        let sums = |lines: usize, line: &dyn Fn(usize) -> f64| {
+            (0..lines).map(line).fold(0.0, f64::max)
+        };
+
+        match p {
+            1.0 => Ok(sums(self.columns, &|j| (0..self.rows).map(|i| self[(i, j)].abs()).sum())),
+            2.0 => {
+                let gram = self.transpose().matmul(self)?;
+                let largest = gram.jacobi()?.into_iter().fold(0.0, f64::max);
+                Ok(largest.sqrt())
+            }
+            f64::INFINITY => {
+                Ok(sums(self.rows, &|i| (0..self.columns).map(|j| self[(i, j)].abs()).sum()))
+            }
+            p => Err(EvalError::InvalidArgument(format!(
+                "The norm of a matrix needs p to be 1, 2 or inf, not {p}"
+            ))),
+        }
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = f64;
+
+    fn index(&self, (i, j): (usize, usize)) -> &f64 {
+        &self.data[i * self.columns + j]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f64 {
+        &mut self.data[i * self.columns + j]
+    }
+}
+
+/// Matrices are written like they're entered, with commas between elements and semicolons
+/// between rows.
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for i in 0..self.rows {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            for j in 0..self.columns {
+                if j > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", self[(i, j)])?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    fn matrix(rows: &[&[f64]]) -> Matrix {
+        Matrix::new(rows.len(), rows[0].len(), rows.concat())
+    }
+
+    #[test]
+    fn test_matmul() {
+        let a = matrix(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        let b = matrix(&[&[5.0], &[6.0]]);
+
+        assert_eq!(a.matmul(&b).unwrap(), matrix(&[&[17.0], &[39.0]]));
+        assert_eq!(
+            b.matmul(&a),
+            Err(EvalError::ShapeMismatch {
+                expected: (1, 2),
+                got: (2, 2)
+            })
+        );
+    }
+
+    #[test]
+    fn test_det() {
+        let a = matrix(&[&[2.0, -3.0, 1.0], &[2.0, 0.0, -1.0], &[1.0, 4.0, 5.0]]);
+
+        assert_close(a.det().unwrap(), 49.0, 1e-9);
+        assert_eq!(
+            matrix(&[&[1.0, 2.0]]).det(),
+            Err(EvalError::NotSquare {
+                rows: 1,
+                columns: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_least_squares() {
+        // The line through (0, 1), (1, 3) and (2, 4) that fits best.
+        let a = matrix(&[&[0.0, 1.0], &[1.0, 1.0], &[2.0, 1.0]]);
+        let x = a.least_squares(&[1.0, 3.0, 4.0]).unwrap();
+
+        assert_close(x[0], 1.5, 1e-9);
+        assert_close(x[1], 7.0 / 6.0, 1e-9);
+        assert_eq!(
+            matrix(&[&[1.0, 2.0], &[2.0, 4.0], &[3.0, 6.0]]).least_squares(&[1.0, 2.0, 3.0]),
+            Err(EvalError::SingularMatrix)
+        );
+    }
+
+    #[test]
+    fn test_inverse_and_solve() {
+        let a = matrix(&[&[4.0, 7.0], &[2.0, 6.0]]);
+        let inverse = a.inverse().unwrap();
+        let x = a.solve(&matrix(&[&[1.0], &[2.0]])).unwrap();
+
+        for (value, expected) in inverse.data().iter().zip([0.6, -0.7, -0.2, 0.4]) {
+            assert_close(*value, expected, 1e-9);
+        }
+        assert_close(x[(0, 0)], -0.8, 1e-9);
+        assert_close(x[(1, 0)], 0.6, 1e-9);
+        assert_eq!(
+            matrix(&[&[1.0, 2.0], &[2.0, 4.0]]).inverse(),
+            Err(EvalError::SingularMatrix)
+        );
+    }
+
+    #[test]
+    fn test_rank() {
+        let a = matrix(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0]]);
+
+        assert_eq!(a.rank(), 2);
+        assert_eq!(Matrix::identity(4).rank(), 4);
+    }
+
+    #[test]
+    fn test_eigenvalues() {
+        let symmetric = matrix(&[&[2.0, 1.0], &[1.0, 2.0]]).eigenvalues().unwrap();
+        let rotation = matrix(&[&[0.0, -1.0], &[1.0, 0.0]]).eigenvalues().unwrap();
+        let general = matrix(&[&[1.0, 2.0, 3.0], &[0.0, 4.0, 5.0], &[1.0, 0.0, 6.0]])
+            .eigenvalues()
+            .unwrap();
+
+        assert_close(symmetric[0].re, 1.0, 1e-9);
+        assert_close(symmetric[1].re, 3.0, 1e-9);
+        assert_eq!(rotation, vec![Complex::new(0.0, -1.0), Complex::new(0.0, 1.0)]);
+
+        // The eigenvalues add up to the trace and multiply to the determinant.
+        let sum = general.iter().fold(Complex::default(), |acc, z| acc + *z);
+        let product = general.iter().fold(Complex::real(1.0), |acc, z| acc * *z);
+        assert_close(sum.re, 11.0, 1e-9);
+        assert_close(product.re, 22.0, 1e-9);
+        assert_close(product.im, 0.0, 1e-9);
+    }
+
+    #[test]
+    fn test_norm() {
+        let v = matrix(&[&[3.0, -4.0]]);
+        let a = matrix(&[&[1.0, -2.0], &[3.0, 4.0]]);
+
+        assert_close(v.norm(2.0).unwrap(), 5.0, 1e-9);
+        assert_close(v.norm(f64::INFINITY).unwrap(), 4.0, 1e-9);
+        assert_close(a.norm(1.0).unwrap(), 6.0, 1e-9);
+        assert_close(a.norm(f64::INFINITY).unwrap(), 7.0, 1e-9);
+        assert_close(a.norm(2.0).unwrap(), 5.116672736016927, 1e-9);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(matrix(&[&[1.0, 2.5], &[3.0, 4.0]]).to_string(), "[1, 2.5; 3, 4]");
+    }
+}
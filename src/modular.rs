@@ -0,0 +1,460 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{ast::*, decimal::Decimal, errors::EvalError, eval::{self, Scope}, tokenize::*, value::Value};
+
+/// The most baby steps `discretelog` takes, which limits it to moduli below 2^40.
+const MAX_STEPS: u64 = 1 << 20;
+
+/// A number modulo `modulus`, the result of evaluating in modular mode. The value is always
+/// reduced to be below the modulus.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Residue {
+    pub value: u64,
+    pub modulus: u64,
+}
+
+impl fmt::Display for Residue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
+    }
+}
+
+/// Recursively collapses the ast into an integer modulo `modulus`. `+`, `-` and `*` are reduced
+/// after every step, `/` multiplies by the modular inverse, and `^` is worked out by repeated
+/// squaring with an exponent that isn't reduced, so `3^200` never gets any bigger than the
+/// modulus. Variables have to hold integers.
+pub fn evaluate_mod(ast: &ASTNode, modulus: u64, scope: &Scope) -> Result<u64, EvalError> {
+    match &ast.token {
+        Token::Number(n) => reduce_float(*n, modulus),
+        Token::Integer(n) => Ok(n % modulus),
+        Token::Decimal(d) => reduce_decimal(d, modulus),
+        Token::Variable(_) => Ok(reduce(integer(ast, scope)?, modulus)),
+        Token::Constant(n) => Err(EvalError::NotAnInteger(*n)),
+        Token::Function(_) => Ok(evaluate_function(ast, scope, Some(modulus))? % modulus),
+        Token::Operator(Operator::Unary(op)) => {
+            let operand = evaluate_mod(&ast.children[0], modulus, scope)?;
+
+            match op {
+                Unary::Negate => Ok((modulus - operand) % modulus),
+                Unary::Complement => Err(EvalError::ProgrammerModeOnly("~".to_string())),
+            }
+        }
+        // The exponent is an integer of its own and the modulus of `a mod m` starts a new context.
+        Token::Operator(Operator::Exponential) => {
+            let base = evaluate_mod(&ast.children[0], modulus, scope)?;
+            power(base, integer(&ast.children[1], scope)?, modulus)
+        }
+        Token::Operator(Operator::Multiplicative(Multiplicative::Modulo)) => {
+            let inner = to_modulus(integer(&ast.children[1], scope)?)?;
+            Ok(evaluate_mod(&ast.children[0], inner, scope)? % modulus)
+        }
+        Token::Operator(op) => {
+            let left = evaluate_mod(&ast.children[0], modulus, scope)?;
+            let right = evaluate_mod(&ast.children[1], modulus, scope)?;
+
+            match op {
+                Operator::Additive(Additive::Add) => Ok(((left as u128 + right as u128) % modulus as u128) as u64),
+                Operator::Additive(Additive::Subtract) => {
+                    Ok(((left as u128 + (modulus - right) as u128) % modulus as u128) as u64)
+                }
+                Operator::Multiplicative(Multiplicative::Multiply) => Ok(multiply(left, right, modulus)),
+                Operator::Multiplicative(Multiplicative::Divide) => {
+                    Ok(multiply(left, inverse(right, modulus)?, modulus))
+                }
+                Operator::Shift(Shift::Left) => Err(EvalError::ProgrammerModeOnly("<<".to_string())),
+                Operator::Shift(Shift::Right) => Err(EvalError::ProgrammerModeOnly(">>".to_string())),
+                Operator::BitAnd => Err(EvalError::ProgrammerModeOnly("&".to_string())),
+                Operator::BitOr => Err(EvalError::ProgrammerModeOnly("|".to_string())),
+                Operator::BitXor => Err(EvalError::ProgrammerModeOnly("xor".to_string())),
+                Operator::ElementWise(_) | Operator::Range | Operator::PlusMinus => {
+                    Err(EvalError::NotInModularMode(ast.to_string()))
+                }
+                Operator::Exponential
+                | Operator::Multiplicative(Multiplicative::Modulo)
+                | Operator::Unary(_) => unreachable!("Handled above"),
+            }
+        }
+        Token::Matrix { .. } | Token::List | Token::Call(_) | Token::Timestamp(_) | Token::Duration(_) => {
+            Err(EvalError::NotInModularMode(ast.to_string()))
+        }
+        _ => unreachable!("Invalid AST"),
+    }
+}
+
+/// Evaluates `x mod n` outside of modular mode. When `n` is a nonzero integer and `x` only
+/// involves integers, `x` is evaluated modulo `n`, so `3^200 mod 97` is exact. Otherwise `None` is
+/// returned and the remainder is taken of floats instead. The result has the sign of `n`, like
+/// the remainder of floats does.
+pub fn evaluate_modulo(ast: &ASTNode, scope: &Scope) -> Result<Option<f64>, EvalError> {
+    let Token::Operator(Operator::Multiplicative(Multiplicative::Modulo)) = ast.token else {
+        return Ok(None);
+    };
+
+    let n = match integer(&ast.children[1], scope) {
+        Ok(0) => return Err(EvalError::DivisionByZero),
+        Ok(n) if n.unsigned_abs() <= u64::MAX as u128 => n,
+        Ok(_) | Err(EvalError::NotAnInteger(_) | EvalError::NotInModularMode(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let modulus = n.unsigned_abs() as u64;
+
+    match evaluate_mod(&ast.children[0], modulus, scope) {
+        // x mod -m = -((-x) mod m), which is r - m when x mod m is a nonzero r.
+        Ok(value) if n < 0 && value != 0 => Ok(Some(-((modulus - value) as f64))),
+        Ok(value) => Ok(Some(value as f64)),
+        Err(EvalError::NotAnInteger(_) | EvalError::NotInModularMode(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns whether the ast is a call to one of the functions of modular arithmetic, which work on
+/// exact integers in every mode. `inv` of a single argument is the inverse of a matrix instead,
+/// except in modular mode.
+pub fn is_modular_function(ast: &ASTNode) -> bool {
+    match ast.function_name() {
+        Some("crt" | "discretelog") => true,
+        Some("inv") => ast.children.len() == 2,
+        _ => false,
+    }
+}
+
+/// Evaluates `inv(a, n)`, `discretelog(g, h, n)` or `crt(remainders, moduli)`. The modulus `n` can
+/// be left out in modular mode, where it's the modulus of the mode.
+pub fn evaluate_function(ast: &ASTNode, scope: &Scope, context: Option<u64>) -> Result<u64, EvalError> {
+    let name = ast.function_name().unwrap_or_default();
+    let modulus = |i: usize| match ast.children.get(i) {
+        Some(child) => to_modulus(integer(child, scope)?),
+        None => context.ok_or(EvalError::InvalidArgumentCount {
+            expected: i + 1,
+            got: i,
+        }),
+    };
+
+    match name {
+        "inv" => {
+            let n = modulus(1)?;
+            inverse(evaluate_mod(&ast.children[0], n, scope)?, n)
+        }
+        "discretelog" => {
+            let n = modulus(2)?;
+            let g = evaluate_mod(&ast.children[0], n, scope)?;
+            let h = evaluate_mod(&ast.children[1], n, scope)?;
+            discrete_log(g, h, n)
+        }
+        "crt" => {
+            let remainders = integers(&ast.children[0], scope)?;
+            let moduli = integers(&ast.children[1], scope)?;
+            crt(&remainders, &moduli).map(|(value, _)| value)
+        }
+        name => Err(EvalError::NotInModularMode(name.to_string())),
+    }
+}
+
+/// The inverse of `a` modulo `n`, the number that gives 1 when multiplied by `a`. It only exists
+/// when `a` and `n` have no common factor.
+pub fn inverse(a: u64, n: u64) -> Result<u64, EvalError> {
+    // The extended Euclidean algorithm, keeping track of the multiple of `a` in each remainder.
+    let (mut old_r, mut r) = (a as i128, n as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+
+    match old_r {
+        1 => Ok(old_s.rem_euclid(n as i128) as u64),
+        _ => Err(EvalError::NoInverse { value: a, modulus: n }),
+    }
+}
+
+/// Raises `base` to a power modulo `n` by repeated squaring. A negative exponent raises the
+/// inverse instead.
+pub fn power(base: u64, exponent: i128, n: u64) -> Result<u64, EvalError> {
+    let mut base = match exponent < 0 {
+        true => inverse(base, n)?,
+        false => base % n,
+    };
+    let mut exponent = exponent.unsigned_abs();
+    let mut result = 1 % n;
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(result, base, n);
+        }
+        exponent >>= 1;
+        base = multiply(base, base, n);
+    }
+
+    Ok(result)
+}
+
+/// Finds the number that has each of the remainders when divided by the corresponding modulus,
+/// using the Chinese remainder theorem. Returns the smallest one along with the least common
+/// multiple of the moduli, which every other solution differs by. The moduli don't have to be
+/// coprime, as long as the remainders agree wherever they overlap.
+pub fn crt(remainders: &[i128], moduli: &[i128]) -> Result<(u64, u64), EvalError> {
+    if remainders.len() != moduli.len() {
+        return Err(EvalError::LengthMismatch {
+            expected: remainders.len(),
+            got: moduli.len(),
+        });
+    }
+
+    let (mut value, mut modulus) = (0u64, 1u64);
+    for (&remainder, &m) in remainders.iter().zip(moduli) {
+        let m = to_modulus(m)?;
+        let remainder = reduce(remainder, m);
+
+        // Solves `value + modulus t = remainder (mod m)` for t.
+        let g = gcd(modulus, m);
+        let difference = remainder as i128 - value as i128;
+        if difference % g as i128 != 0 {
+            return Err(EvalError::InvalidArgument(format!(
+                "No number is {remainder} mod {m} and also {value} mod {modulus}"
+            )));
+        }
+
+        let step = m / g;
+        let t = multiply(reduce(difference / g as i128, step), inverse(modulus / g % step, step)?, step);
+        let lcm = (modulus / g).checked_mul(m).ok_or(EvalError::Overflow)?;
+        value = ((value as u128 + modulus as u128 * t as u128) % lcm as u128) as u64;
+        modulus = lcm;
+    }
+
+    Ok((value, modulus))
+}
+
+/// Finds the smallest `x` where `g^x = h` modulo `n` with the baby-step giant-step algorithm,
+/// which takes about `sqrt(n)` steps. `g` has to be coprime to `n`.
+pub fn discrete_log(g: u64, h: u64, n: u64) -> Result<u64, EvalError> {
+    let steps = (n as f64).sqrt().ceil() as u64;
+    if steps > MAX_STEPS {
+        return Err(EvalError::InvalidArgument(format!(
+            "Discrete logarithms can only be found modulo numbers below 2^40, not {n}"
+        )));
+    }
+
+    // The baby steps g^j, keeping the smallest j for each power.
+    let mut table = HashMap::new();
+    let mut step = 1 % n;
+    for j in 0..steps {
+        table.entry(step).or_insert(j);
+        step = multiply(step, g, n);
+    }
+
+    // The giant steps h g^(-steps i), which meet a baby step when x = steps i + j.
+    let giant = power(inverse(g, n)?, steps as i128, n)?;
+    let mut y = h % n;
+    for i in 0..steps {
+        if let Some(j) = table.get(&y) {
+            return Ok(i * steps + j);
+        }
+        y = multiply(y, giant, n);
+    }
+
+    Err(EvalError::InvalidArgument(format!("No power of {g} is {h} mod {n}")))
+}
+
+/// Evaluates the ast as an exact integer, like the exponent of a power or a modulus. Division has
+/// to come out even.
+fn integer(ast: &ASTNode, scope: &Scope) -> Result<i128, EvalError> {
+    let operands = || -> Result<(i128, i128), EvalError> {
+        Ok((integer(&ast.children[0], scope)?, integer(&ast.children[1], scope)?))
+    };
+
+    match &ast.token {
+        Token::Number(n) => float_integer(*n),
+        Token::Integer(n) => Ok(*n as i128),
+        Token::Decimal(d) => d.to_integer().map(i128::from).ok_or(EvalError::NotAnInteger(d.to_f64())),
+        Token::Constant(n) => Err(EvalError::NotAnInteger(*n)),
+        Token::Variable(name) => match scope.get(name) {
+            Some(Value::Number(n)) => float_integer(*n),
+            Some(Value::Decimal(d)) => d.to_integer().map(i128::from).ok_or(EvalError::NotAnInteger(d.to_f64())),
+            Some(_) => Err(EvalError::NotInModularMode(name.clone())),
+            None => Err(EvalError::UnknownKeyword(name.clone())),
+        },
+        Token::Function(_) if is_modular_function(ast) => Ok(evaluate_function(ast, scope, None)? as i128),
+        Token::Operator(Operator::Unary(Unary::Negate)) => {
+            integer(&ast.children[0], scope)?.checked_neg().ok_or(EvalError::Overflow)
+        }
+        Token::Operator(Operator::Additive(Additive::Add)) => {
+            let (a, b) = operands()?;
+            a.checked_add(b).ok_or(EvalError::Overflow)
+        }
+        Token::Operator(Operator::Additive(Additive::Subtract)) => {
+            let (a, b) = operands()?;
+            a.checked_sub(b).ok_or(EvalError::Overflow)
+        }
+        Token::Operator(Operator::Multiplicative(Multiplicative::Multiply)) => {
+            let (a, b) = operands()?;
+            a.checked_mul(b).ok_or(EvalError::Overflow)
+        }
+        Token::Operator(Operator::Multiplicative(Multiplicative::Divide)) => match operands()? {
+            (_, 0) => Err(EvalError::DivisionByZero),
+            (a, b) if a % b == 0 => Ok(a / b),
+            (a, b) => Err(EvalError::NotAnInteger(a as f64 / b as f64)),
+        },
+        Token::Operator(Operator::Multiplicative(Multiplicative::Modulo)) => {
+            let modulus = to_modulus(integer(&ast.children[1], scope)?)?;
+            Ok(evaluate_mod(&ast.children[0], modulus, scope)? as i128)
+        }
+        Token::Operator(Operator::Exponential) => {
+            let (base, exponent) = operands()?;
+            let exponent = u32::try_from(exponent).map_err(|_| match exponent < 0 {
+                true => EvalError::NegativeExponent,
+                false => EvalError::Overflow,
+            })?;
+            base.checked_pow(exponent).ok_or(EvalError::Overflow)
+        }
+        _ => Err(EvalError::NotInModularMode(ast.to_string())),
+    }
+}
+
+/// Evaluates the ast as a list of integers, like the remainders given to `crt`.
+fn integers(ast: &ASTNode, scope: &Scope) -> Result<Vec<i128>, EvalError> {
+    if let Token::List = ast.token {
+        return ast.children.iter().map(|child| integer(child, scope)).collect();
+    }
+
+    match eval::evaluate(ast, scope)? {
+        Value::List(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Number(n) => float_integer(*n),
+                value => Err(EvalError::NotANumber(value.to_string())),
+            })
+            .collect(),
+        _ => Err(EvalError::InvalidArgument(format!("{ast} has to be a list of integers"))),
+    }
+}
+
+fn float_integer(n: f64) -> Result<i128, EvalError> {
+    match n.fract() == 0.0 && n.abs() < i128::MAX as f64 {
+        true => Ok(n as i128),
+        false => Err(EvalError::NotAnInteger(n)),
+    }
+}
+
+fn to_modulus(n: i128) -> Result<u64, EvalError> {
+    match u64::try_from(n) {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(EvalError::InvalidArgument(format!(
+            "The modulus has to be a positive integer below 2^64, not {n}"
+        ))),
+    }
+}
+
+fn reduce(n: i128, modulus: u64) -> u64 {
+    n.rem_euclid(modulus as i128) as u64
+}
+
+/// Reduces an integer float exactly, however large it is, by splitting it into its 53 bits of
+/// mantissa and a power of two.
+fn reduce_float(n: f64, modulus: u64) -> Result<u64, EvalError> {
+    if n.fract() != 0.0 || !n.is_finite() {
+        return Err(EvalError::NotAnInteger(n));
+    }
+    if n == 0.0 {
+        return Ok(0);
+    }
+
+    let bits = n.abs().to_bits();
+    let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+    let value = match ((bits >> 52) & 0x7FF) as i128 - 1075 {
+        shift if shift >= 0 => multiply(mantissa % modulus, power(2, shift, modulus)?, modulus),
+        shift => (mantissa >> -shift) % modulus,
+    };
+
+    Ok(match n < 0.0 {
+        true => (modulus - value) % modulus,
+        false => value,
+    })
+}
+
+/// Reduces an integer decimal exactly, which is its coefficient times a power of ten.
+fn reduce_decimal(d: &Decimal, modulus: u64) -> Result<u64, EvalError> {
+    let value = match d.exponent {
+        e if e >= 0 => multiply((d.coefficient % modulus as u128) as u64, power(10, e as i128, modulus)?, modulus),
+        e => match u32::try_from(-e).ok().and_then(|e| 10u128.checked_pow(e)) {
+            Some(scale) if d.coefficient.is_multiple_of(scale) => (d.coefficient / scale % modulus as u128) as u64,
+            None if d.coefficient == 0 => 0,
+            _ => return Err(EvalError::NotAnInteger(d.to_f64())),
+        },
+    };
+
+    Ok(match d.negative {
+        true => (modulus - value) % modulus,
+        false => value,
+    })
+}
+
+fn multiply(a: u64, b: u64, modulus: u64) -> u64 {
+    (a as u128 * b as u128 % modulus as u128) as u64
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str, modulus: u64) -> Result<u64, EvalError> {
+        let tokens = parse_decimal_expression(expression)?;
+        let ast = get_ast(&tokens)?;
+        evaluate_mod(&ast, modulus, &Scope::new())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(evaluate("3^200", 97), Ok(62));
+        assert_eq!(evaluate("5 - 7", 11), Ok(9));
+        assert_eq!(evaluate("-3 * 4 + 20", 7), Ok(1));
+        assert_eq!(evaluate("1 / 3", 7), Ok(5));
+        assert_eq!(evaluate("2^-1", 97), Ok(49));
+        assert_eq!(evaluate("123456789012345678901234567890", 1_000_000_007), Ok(197_434_842));
+        assert_eq!(evaluate("(2^64 + 5) mod 2^32", 1000), Ok(5));
+        assert_eq!(evaluate("2 / 4", 6), Err(EvalError::NoInverse { value: 4, modulus: 6 }));
+        assert_eq!(evaluate("1.5", 7), Err(EvalError::NotAnInteger(1.5)));
+    }
+
+    #[test]
+    fn test_large_moduli() {
+        let n = u64::MAX - 58; // The largest prime below 2^64.
+
+        assert_eq!(evaluate(&format!("({n} - 1)^2"), n), Ok(1));
+        assert_eq!(power(3, n as i128 - 1, n), Ok(1));
+        assert_eq!(reduce_float(2f64.powi(200), 1_000_000_007), power(2, 200, 1_000_000_007));
+        assert_eq!(reduce_float(-1e15, 7), Ok(7 - 1_000_000_000_000_000 % 7));
+    }
+
+    #[test]
+    fn test_inverse() {
+        assert_eq!(inverse(3, 7), Ok(5));
+        assert_eq!(inverse(17, 3120), Ok(2753));
+        assert_eq!(inverse(6, 9), Err(EvalError::NoInverse { value: 6, modulus: 9 }));
+        assert_eq!(inverse(0, 1), Ok(0));
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(&[2, 3, 2], &[3, 5, 7]), Ok((23, 105)));
+        assert_eq!(crt(&[-1, 3], &[4, 6]), Ok((3, 12)));
+        assert!(crt(&[1, 2], &[4, 6]).is_err());
+        assert!(crt(&[1], &[4, 6]).is_err());
+    }
+
+    #[test]
+    fn test_discrete_log() {
+        assert_eq!(discrete_log(2, 3, 29), Ok(5));
+        let h = power(5, 123_456, 1_000_003).unwrap();
+        assert_eq!(power(5, discrete_log(5, h, 1_000_003).unwrap() as i128, 1_000_003), Ok(h));
+        assert_eq!(discrete_log(3, 1, 7), Ok(0));
+        assert!(discrete_log(2, 3, 7).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{ast::ASTNode, errors::EvalError, eval::*, statistics};
+
+/// The most samples a simulation can take.
+const MAX_SAMPLES: usize = 10_000_000;
+
+/// The percentiles of the samples that are reported, which cover the middle 95% and the median.
+pub const PERCENTILES: [f64; 3] = [2.5, 50.0, 97.5];
+
+/// A summary of the values an expression took in a Monte Carlo simulation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub mean: f64,
+    pub stdev: f64,
+    /// The values at each of the [`PERCENTILES`].
+    pub percentiles: Vec<f64>,
+}
+
+/// Evaluates a call to `montecarlo(expr, N)`, which evaluates the expression `N` times. Random
+/// functions like `randn` draw new numbers every time, and so do the variables that were defined
+/// with them.
+pub fn evaluate_montecarlo(ast: &ASTNode, scope: &Scope) -> Result<Simulation, EvalError> {
+    let n = evaluate_ast(&ast.children[1], scope)?;
+    if n.fract() != 0.0 {
+        return Err(EvalError::NotAnInteger(n));
+    }
+    if !(2.0..=MAX_SAMPLES as f64).contains(&n) {
+        return Err(EvalError::InvalidArgument(format!(
+            "A simulation needs between 2 and {MAX_SAMPLES} samples, not {n}"
+        )));
+    }
+
+    let mut samples = Vec::with_capacity(n as usize);
+    for _ in 0..n as usize {
+        let inner = scope.resample()?;
+        samples.push(evaluate_ast(&ast.children[0], &inner)?);
+    }
+
+    Ok(Simulation {
+        mean: statistics::mean(&samples)?,
+        stdev: statistics::stdev(&samples)?,
+        percentiles: PERCENTILES
+            .iter()
+            .map(|&p| statistics::percentile(&samples, p))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, testing::assert_close, tokenize};
+
+    fn simulate(expr: &str, scope: &Scope) -> Result<Simulation, EvalError> {
+        let tokens = tokenize::parse_expression(expr)?;
+        evaluate_montecarlo(&ast::get_ast(&tokens)?, scope)
+    }
+
+    #[test]
+    fn test_tolerance_stack() {
+        // Three parts of 10 ± 0.1 stack up to 30 ± 0.1 sqrt(3).
+        let simulation = simulate("montecarlo(randn(10, 0.1) + randn(10, 0.1) + randn(10, 0.1), 20000)", &Scope::new()).unwrap();
+
+        assert_close(simulation.mean, 30.0, 0.01);
+        assert_close(simulation.stdev, 0.1 * 3f64.sqrt(), 0.01);
+        assert!(simulation.percentiles[0] < simulation.percentiles[1]);
+        assert!(simulation.percentiles[1] < simulation.percentiles[2]);
+    }
+
+    #[test]
+    fn test_defined_variables() {
+        let mut scope = Scope::new();
+        let tokens = tokenize::parse_expression("randn(5, 1)").unwrap();
+        scope.define("d", &ast::get_ast(&tokens).unwrap()).unwrap();
+
+        // The variable is drawn once per sample, so it cancels itself out.
+        let difference = simulate("montecarlo(d - d, 100)", &scope).unwrap();
+        let sum = simulate("montecarlo(d + d, 10000)", &scope).unwrap();
+
+        assert_eq!(difference.stdev, 0.0);
+        assert_close(sum.stdev, 2.0, 0.1);
+    }
+
+    #[test]
+    fn test_sample_count() {
+        assert_eq!(simulate("montecarlo(rand(), 2.5)", &Scope::new()), Err(EvalError::NotAnInteger(2.5)));
+        assert!(simulate("montecarlo(rand(), 1)", &Scope::new()).is_err());
+    }
+}
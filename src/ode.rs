@@ -0,0 +1,477 @@
+use crate::{
+    ast::ASTNode, autodiff, compile::Program, errors::EvalError, eval::*, matrix::Matrix,
+    tokenize::Token, value::Value,
+};
+
+/// The most steps taken before giving up.
+const MAX_STEPS: usize = 1_000_000;
+
+/// The most samples a table can have.
+const MAX_SAMPLES: usize = 100_000;
+
+/// The error allowed in each step, relative to the size of the solution.
+const RELATIVE_TOLERANCE: f64 = 1e-9;
+
+/// The error allowed in each step for components of the solution that are close to zero.
+const ABSOLUTE_TOLERANCE: f64 = 1e-12;
+
+/// How many steps in a row have to look stiff before the stiff method takes over, and the step
+/// size times the largest eigenvalue above which a step looks stiff. Both are from Hairer's
+/// DOPRI5.
+const STIFF_STEPS: usize = 15;
+const STIFF_BOUND: f64 = 3.25;
+
+/// The coefficients of the Dormand-Prince method. `A[i]` are the weights of the earlier stages in
+/// stage `i + 2`, which is taken at the time `C[i]` into the step. The last row of `A` gives the
+/// fifth order solution, and `E` is the difference between the fifth and fourth order solutions.
+const C: [f64; 5] = [1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0];
+const A: [&[f64]; 6] = [
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0],
+    &[9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0],
+    &[35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+const E: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+/// The right-hand side of a system of differential equations `y' = f(t, y)`.
+struct System<'a> {
+    expressions: Vec<&'a ASTNode>,
+    /// The variables of the solution followed by the time.
+    variables: Vec<&'a str>,
+    scope: Scope,
+    /// The expressions compiled for speed, if they all could be.
+    programs: Option<Vec<Program>>,
+    values: Vec<f64>,
+}
+
+impl<'a> System<'a> {
+    fn new(expressions: Vec<&'a ASTNode>, variables: Vec<&'a str>, scope: &Scope) -> System<'a> {
+        let programs = expressions
+            .iter()
+            .map(|expr| Program::compile(expr, &variables, scope))
+            .collect::<Option<Vec<_>>>();
+
+        System {
+            expressions,
+            variables,
+            scope: scope.clone(),
+            programs,
+            values: vec![],
+        }
+    }
+
+    fn set(&mut self, t: f64, y: &[f64]) {
+        self.values.clear();
+        self.values.extend(y);
+        self.values.push(t);
+        for (name, value) in self.variables.iter().zip(&self.values) {
+            self.scope.set(name, *value);
+        }
+    }
+
+    /// The derivative of the solution at the time.
+    fn derivative(&mut self, t: f64, y: &[f64]) -> Result<Vec<f64>, EvalError> {
+        if let Some(programs) = &mut self.programs {
+            self.values.clear();
+            self.values.extend(y);
+            self.values.push(t);
+            return Ok(programs.iter_mut().map(|program| program.evaluate(&self.values)).collect());
+        }
+
+        self.set(t, y);
+        self.expressions.iter().map(|expr| evaluate_ast(expr, &self.scope)).collect()
+    }
+
+    /// The Jacobian of the right-hand side with respect to the solution, and its derivative with
+    /// respect to the time.
+    fn jacobian(&mut self, t: f64, y: &[f64]) -> Result<(Matrix, Vec<f64>), EvalError> {
+        self.set(t, y);
+        let n = y.len();
+        let mut jacobian = vec![];
+        let mut time = vec![];
+        for expr in &self.expressions {
+            let dual = autodiff::forward(expr, &self.scope, &self.variables)?;
+            jacobian.extend_from_slice(&dual.gradient[..n]);
+            time.push(dual.gradient[n]);
+        }
+        Ok((Matrix::new(n, n, jacobian), time))
+    }
+}
+
+/// Evaluates a call to `odesolve(expr, y, t, y0, t0, t1)`, which solves the differential equation
+/// `y' = expr` from `y(t0) = y0` up to `t1`, and returns `y(t1)`. A system is solved when `y` is a
+/// vector of variables, like `odesolve([v, -x], [x, v], t, [1, 0], 0, pi)`. A seventh argument
+/// asks for a table of that many evenly spaced times instead, with the time in the first column
+/// and the solution in the others.
+///
+/// The solution is found with the adaptive Dormand-Prince method, which switches to a Rosenbrock
+/// method when the equation turns out to be stiff.
+pub fn evaluate_odesolve(ast: &ASTNode, scope: &Scope) -> Result<Value, EvalError> {
+    let single = matches!(ast.children[1].token, Token::Variable(_));
+    let names = elements(&ast.children[1])
+        .into_iter()
+        .map(|c| c.variable_name("odesolve"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let time = ast.children[2].variable_name("odesolve")?;
+    let expressions = elements(&ast.children[0]);
+
+    if let Some(name) = names.iter().enumerate().find(|(i, v)| names[..*i].contains(v) || **v == time) {
+        return Err(EvalError::InvalidArgument(format!(
+            "`{}` can only be used once among the variables of `odesolve`",
+            name.1
+        )));
+    }
+    if expressions.len() != names.len() {
+        return Err(EvalError::LengthMismatch {
+            expected: names.len(),
+            got: expressions.len(),
+        });
+    }
+
+    let y0 = numbers(&evaluate(&ast.children[3], scope)?)?;
+    if y0.len() != names.len() {
+        return Err(EvalError::LengthMismatch {
+            expected: names.len(),
+            got: y0.len(),
+        });
+    }
+    let t0 = evaluate_ast(&ast.children[4], scope)?;
+    let t1 = evaluate_ast(&ast.children[5], scope)?;
+    if let Some(t) = [t0, t1].into_iter().find(|t| !t.is_finite()) {
+        return Err(EvalError::InvalidArgument(format!(
+            "The times of `odesolve` have to be finite, not {t}"
+        )));
+    }
+
+    let mut variables = names.iter().map(String::as_str).collect::<Vec<_>>();
+    variables.push(&time);
+    let mut system = System::new(expressions, variables, scope);
+
+    let Some(samples) = ast.children.get(6) else {
+        let y = solve(&mut system, y0, t0, t1)?;
+        return Ok(match single {
+            true => Value::Number(y[0]),
+            false => Value::List(y.into_iter().map(Value::Number).collect()),
+        });
+    };
+
+    let samples = evaluate_ast(samples, scope)?;
+    if samples.fract() != 0.0 {
+        return Err(EvalError::NotAnInteger(samples));
+    }
+    if !(2.0..=MAX_SAMPLES as f64).contains(&samples) {
+        return Err(EvalError::InvalidArgument(format!(
+            "A table needs between 2 and {MAX_SAMPLES} samples, not {samples}"
+        )));
+    }
+
+    let samples = samples as usize;
+    let mut table = vec![t0];
+    table.extend(&y0);
+    let (mut t, mut y) = (t0, y0);
+    for i in 1..samples {
+        let next = t0 + (t1 - t0) * i as f64 / (samples - 1) as f64;
+        y = solve(&mut system, y, t, next)?;
+        t = next;
+        table.push(t);
+        table.extend(&y);
+    }
+
+    Ok(Value::Matrix(Matrix::new(samples, names.len() + 1, table)))
+}
+
+/// The elements of a vector or list in the tree, or the node itself when it's neither.
+fn elements(node: &ASTNode) -> Vec<&ASTNode> {
+    match node.token {
+        Token::Matrix { .. } | Token::List => node.children.iter().collect(),
+        _ => vec![node],
+    }
+}
+
+/// The size of the error relative to what's allowed, where 1 is just allowed.
+fn error_norm(error: &[f64], y: &[f64], next: &[f64]) -> f64 {
+    let sum = error
+        .iter()
+        .zip(y.iter().zip(next))
+        .map(|(e, (a, b))| {
+            let scale = ABSOLUTE_TOLERANCE + RELATIVE_TOLERANCE * a.abs().max(b.abs());
+            (e / scale).powi(2)
+        })
+        .fold(0.0, |sum, e| sum + e);
+    (sum / error.len() as f64).sqrt()
+}
+
+/// A first step that's small compared to how fast the solution changes, following Hairer.
+fn initial_step(y: &[f64], dy: &[f64], span: f64) -> f64 {
+    let norm = |v: &[f64]| {
+        let sum = v
+            .iter()
+            .zip(y)
+            .map(|(v, y)| (v / (ABSOLUTE_TOLERANCE + RELATIVE_TOLERANCE * y.abs())).powi(2))
+            .fold(0.0, |sum, v| sum + v);
+        (sum / v.len() as f64).sqrt()
+    };
+
+    let (d0, d1) = (norm(y), norm(dy));
+    let step = match d0 < 1e-5 || d1 < 1e-5 {
+        true => 1e-6,
+        false => 0.01 * d0 / d1,
+    };
+    step.min(span.abs()).copysign(span)
+}
+
+/// Solves the system from `y(t0) = y` up to `t1`.
+fn solve(system: &mut System, y: Vec<f64>, t0: f64, t1: f64) -> Result<Vec<f64>, EvalError> {
+    if t0 == t1 {
+        return Ok(y);
+    }
+
+    match dormand_prince(system, y, t0, t1)? {
+        Integration::Done(y) => Ok(y),
+        Integration::Stiff(t, y, step) => rosenbrock(system, y, t, t1, step),
+    }
+}
+
+/// Where an integration ended up.
+enum Integration {
+    Done(Vec<f64>),
+    /// The equation became stiff at the time, where the solution had the value, with the step
+    /// size that was last taken.
+    Stiff(f64, Vec<f64>, f64),
+}
+
+/// Integrates with the Dormand-Prince 5(4) method, whose fourth order solution estimates the error
+/// of the fifth order one to adapt the step size. The last stage is the derivative at the end of
+/// the step, so it's reused as the first stage of the next one.
+fn dormand_prince(
+    system: &mut System,
+    mut y: Vec<f64>,
+    mut t: f64,
+    t1: f64,
+) -> Result<Integration, EvalError> {
+    let n = y.len();
+    let mut k = vec![system.derivative(t, &y)?];
+    let mut step = initial_step(&y, &k[0], t1 - t);
+    let (mut stiff, mut not_stiff) = (0, 0);
+
+    for _ in 0..MAX_STEPS {
+        let last = (t1 - t).abs() <= step.abs();
+        let h = if last { t1 - t } else { step };
+
+        // The point of the last stage is kept for the stiffness test.
+        let mut stage6 = vec![];
+        k.truncate(1);
+        for (c, a) in C.iter().zip(A) {
+            stage6 = (0..n)
+                .map(|i| y[i] + h * a.iter().zip(&k).map(|(a, k)| a * k[i]).sum::<f64>())
+                .collect::<Vec<_>>();
+            k.push(system.derivative(t + c * h, &stage6)?);
+        }
+
+        let next = (0..n)
+            .map(|i| y[i] + h * A[5].iter().zip(&k).map(|(a, k)| a * k[i]).sum::<f64>())
+            .collect::<Vec<_>>();
+        k.push(system.derivative(t + h, &next)?);
+
+        let error = (0..n)
+            .map(|i| h * E.iter().zip(&k).map(|(e, k)| e * k[i]).sum::<f64>())
+            .collect::<Vec<_>>();
+        let norm = error_norm(&error, &y, &next);
+        if !norm.is_finite() {
+            if h.abs() <= f64::EPSILON * t.abs() {
+                return Err(EvalError::InvalidArgument(format!(
+                    "The solution of `odesolve` isn't finite at t = {t}"
+                )));
+            }
+            step = 0.1 * h;
+            continue;
+        }
+
+        let factor = (0.9 * norm.powf(-0.2)).clamp(0.2, 5.0);
+        if norm > 1.0 {
+            step = h * factor.min(1.0);
+            if step.abs() <= f64::EPSILON * t.abs() {
+                return Err(EvalError::NoConvergence { iterations: MAX_STEPS });
+            }
+            continue;
+        }
+
+        // An estimate of the step size times the largest eigenvalue of the Jacobian, which stays
+        // around the edge of the stability region when the steps are held back by stability
+        // rather than accuracy.
+        let difference = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum::<f64>();
+        let denominator = difference(&next, &stage6);
+        if denominator > 0.0 {
+            let eigenvalue = h.abs() * (difference(&k[6], &k[5]) / denominator).sqrt();
+            if eigenvalue > STIFF_BOUND {
+                not_stiff = 0;
+                stiff += 1;
+                if stiff == STIFF_STEPS && !last {
+                    return Ok(Integration::Stiff(t + h, next, h));
+                }
+            } else {
+                not_stiff += 1;
+                if not_stiff == 6 {
+                    stiff = 0;
+                }
+            }
+        }
+
+        t += h;
+        y = next;
+        if last {
+            return Ok(Integration::Done(y));
+        }
+
+        step = h * factor;
+        let derivative = k.pop().unwrap_or_default();
+        k = vec![derivative];
+    }
+
+    Err(EvalError::NoConvergence { iterations: MAX_STEPS })
+}
+
+/// Integrates with the second order Rosenbrock method of MATLAB's `ode23s`, which stays stable
+/// however large the step is by solving a linear system with the Jacobian in every step. A third
+/// order stage estimates the error.
+fn rosenbrock(
+    system: &mut System,
+    mut y: Vec<f64>,
+    mut t: f64,
+    t1: f64,
+    mut step: f64,
+) -> Result<Vec<f64>, EvalError> {
+    let n = y.len();
+    let d = 1.0 / (2.0 + 2f64.sqrt());
+    let e32 = 6.0 + 2f64.sqrt();
+    let column = |v: Vec<f64>| Matrix::new(n, 1, v);
+    let combine = |parts: &[(f64, &[f64])]| {
+        (0..n).map(|i| parts.iter().map(|(c, v)| c * v[i]).sum::<f64>()).collect::<Vec<_>>()
+    };
+
+    let mut f0 = system.derivative(t, &y)?;
+    for _ in 0..MAX_STEPS {
+        let last = (t1 - t).abs() <= step.abs();
+        let h = if last { t1 - t } else { step };
+
+        let (jacobian, time) = system.jacobian(t, &y)?;
+        let w = Matrix::identity(n)
+            .zip_with(&jacobian, |i, j| i - h * d * j)?
+            .inverse()?;
+        let solve = |v: Vec<f64>| w.matmul(&column(v)).map(|x| x.data().to_vec());
+
+        let k1 = solve(combine(&[(1.0, &f0), (h * d, &time)]))?;
+        let f1 = system.derivative(t + 0.5 * h, &combine(&[(1.0, &y), (0.5 * h, &k1)]))?;
+        let k2 = combine(&[(1.0, &solve(combine(&[(1.0, &f1), (-1.0, &k1)]))?), (1.0, &k1)]);
+        let next = combine(&[(1.0, &y), (h, &k2)]);
+        let f2 = system.derivative(t + h, &next)?;
+        let k3 = solve(combine(&[
+            (1.0, &f2),
+            (-e32, &k2),
+            (e32, &f1),
+            (-2.0, &k1),
+            (2.0, &f0),
+            (h * d, &time),
+        ]))?;
+
+        let error = combine(&[(h / 6.0, &k1), (-h / 3.0, &k2), (h / 6.0, &k3)]);
+        let norm = error_norm(&error, &y, &next);
+        let factor = match norm.is_finite() {
+            true => (0.8 * norm.powf(-1.0 / 3.0)).clamp(0.2, 5.0),
+            false => 0.1,
+        };
+
+        if norm > 1.0 || !norm.is_finite() {
+            step = h * factor.min(1.0);
+            if step.abs() <= f64::EPSILON * t.abs() {
+                return Err(EvalError::NoConvergence { iterations: MAX_STEPS });
+            }
+            continue;
+        }
+
+        t += h;
+        y = next;
+        f0 = f2;
+        if last {
+            return Ok(y);
+        }
+        step = h * factor;
+    }
+
+    Err(EvalError::NoConvergence { iterations: MAX_STEPS })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, testing::assert_close, tokenize};
+
+    fn odesolve(expr: &str) -> Result<Value, EvalError> {
+        let tokens = tokenize::parse_expression(expr)?;
+        evaluate_odesolve(&ast::get_ast(&tokens)?, &Scope::new())
+    }
+
+    #[test]
+    fn test_exponential_decay() {
+        let Ok(Value::Number(y)) = odesolve("odesolve(-2y, y, t, 1, 0, 3)") else {
+            panic!("Expected a number");
+        };
+
+        assert_close(y, (-6f64).exp(), 1e-10);
+    }
+
+    #[test]
+    fn test_oscillator() {
+        let Ok(Value::List(y)) = odesolve("odesolve([v, -x], [x, v], t, [1, 0], 0, 2pi)") else {
+            panic!("Expected a list");
+        };
+
+        assert_close(numbers(&y[0]).unwrap()[0], 1.0, 1e-8);
+        assert_close(numbers(&y[1]).unwrap()[0], 0.0, 1e-8);
+    }
+
+    #[test]
+    fn test_table() {
+        let Ok(Value::Matrix(table)) = odesolve("odesolve(t, y, t, 0, 0, 2, 5)") else {
+            panic!("Expected a matrix");
+        };
+
+        assert_eq!(table.shape(), (5, 2));
+        for i in 0..5 {
+            let t = table[(i, 0)];
+            assert_eq!(t, i as f64 / 2.0);
+            assert_close(table[(i, 1)], t * t / 2.0, 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_stiff() {
+        // The fast component decays a million times faster than the solution changes, which would
+        // take Dormand-Prince millions of steps.
+        let Ok(Value::Number(y)) = odesolve("odesolve(-1000000 (y - cos(t)), y, t, 0, 0, 2)") else {
+            panic!("Expected a number");
+        };
+
+        assert_close(y, 2f64.cos() + 2f64.sin() / 1e6, 1e-7);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert_eq!(
+            odesolve("odesolve([v, -x], [x, v], t, [1, 0, 2], 0, 1)"),
+            Err(EvalError::LengthMismatch { expected: 2, got: 3 })
+        );
+        assert!(odesolve("odesolve(y, y, y, 1, 0, 1)").is_err());
+        assert_eq!(odesolve("odesolve(y, y, t, 1, 0, 1, 2.5)"), Err(EvalError::NotAnInteger(2.5)));
+    }
+}
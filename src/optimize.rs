@@ -0,0 +1,474 @@
+use crate::{ast::ASTNode, autodiff, errors::EvalError, eval::*, tokenize::Token, value::Value};
+
+/// The most steps any of the methods takes before giving up.
+const MAX_ITERATIONS: usize = 10_000;
+
+/// The fraction of the interval that golden-section steps move into the larger part, (3 - √5) / 2.
+const GOLDEN: f64 = 0.381_966_011_250_105_1;
+
+/// How close the minimum has to be located, relative to its size when it's larger than one. A
+/// minimum is flat, so its position can't be found more precisely than the square root of the
+/// precision of the values.
+const TOLERANCE: f64 = 1.5e-8;
+
+/// How small the gradient has to be, relative to the value, for BFGS to have found a minimum.
+const GRADIENT_TOLERANCE: f64 = 1e-10;
+
+/// How large the values and positions can get before the expression is considered unbounded.
+const UNBOUNDED: f64 = 1e150;
+
+/// Evaluates a call to `minimize(expr, x, a, b)`, which finds the minimum between `a` and `b` with
+/// Brent's method, or `minimize(expr, [x, y], [x0, y0])`, which finds a minimum near the starting
+/// point with BFGS. `maximize` finds the maximum instead. Returns a list of where the minimum is
+/// and its value.
+pub fn evaluate_minimize(ast: &ASTNode, scope: &Scope) -> Result<Value, EvalError> {
+    let name = ast.function_name().unwrap_or("minimize");
+    let sign = match name {
+        "maximize" => -1.0,
+        _ => 1.0,
+    };
+    let expr = &ast.children[0];
+    // An expression that draws random numbers has a different minimum every time it's evaluated.
+    if !expr.is_pure() {
+        return Err(EvalError::InvalidArgument(format!(
+            "`{name}` can't be used on an expression with random numbers, since it changes every time it's evaluated"
+        )));
+    }
+
+    if let Some(b) = ast.children.get(3) {
+        let var = ast.children[1].variable_name(name)?;
+        let a = evaluate_ast(&ast.children[2], scope)?;
+        let b = evaluate_ast(b, scope)?;
+
+        let mut inner = scope.clone();
+        let f = |x: f64| {
+            inner.set(&var, x);
+            Ok(sign * evaluate_ast(expr, &inner)?)
+        };
+
+        let (x, value) = minimize_interval(f, a, b)?;
+        return Ok(Value::List(vec![Value::Number(x), Value::Number(sign * value)]));
+    }
+
+    let single = matches!(ast.children[1].token, Token::Variable(_));
+    let names = match ast.children[1].token {
+        Token::Matrix { .. } | Token::List => ast.children[1]
+            .children
+            .iter()
+            .map(|c| c.variable_name(name))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => vec![ast.children[1].variable_name(name)?],
+    };
+    if let Some(duplicate) = names.iter().enumerate().find(|(i, v)| names[..*i].contains(v)) {
+        return Err(EvalError::InvalidArgument(format!(
+            "`{}` can only be minimized over once",
+            duplicate.1
+        )));
+    }
+
+    let start = numbers(&evaluate(&ast.children[2], scope)?)?;
+    if start.len() != names.len() {
+        return Err(EvalError::LengthMismatch {
+            expected: names.len(),
+            got: start.len(),
+        });
+    }
+
+    let (point, value) = minimize_expression(expr, &names, start, sign, scope)?;
+    let point = match single {
+        true => Value::Number(point[0]),
+        false => Value::List(point.into_iter().map(Value::Number).collect()),
+    };
+    Ok(Value::List(vec![point, Value::Number(sign * value)]))
+}
+
+/// Finds a minimum of the expression times `sign` over the variables, starting from the point.
+/// BFGS is used with gradients from automatic differentiation, and Nelder-Mead takes over when
+/// the expression can't be differentiated.
+fn minimize_expression(
+    expr: &ASTNode,
+    names: &[String],
+    start: Vec<f64>,
+    sign: f64,
+    scope: &Scope,
+) -> Result<(Vec<f64>, f64), EvalError> {
+    let variables = names.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut inner = scope.clone();
+    let mut f = |point: &[f64], gradient: bool| {
+        for (name, value) in names.iter().zip(point) {
+            inner.set(name, *value);
+        }
+        match gradient {
+            true => {
+                let dual = autodiff::reverse(expr, &inner, &variables)?;
+                Ok((sign * dual.value, dual.gradient.into_iter().map(|g| sign * g).collect()))
+            }
+            false => Ok((sign * evaluate_ast(expr, &inner)?, vec![])),
+        }
+    };
+
+    match bfgs(&mut f, start)? {
+        Search::Minimum(point, value) => Ok((point, value)),
+        Search::Stuck(point) => nelder_mead(|point| Ok(f(point, false)?.0), point),
+    }
+}
+
+/// Finds the minimum between `a` and `b` with Brent's method, which takes parabolic steps through
+/// the best three points when they're trustworthy and golden-section steps when they aren't. The
+/// ends of the interval are checked too, since the function might keep decreasing towards one.
+pub fn minimize_interval<F>(mut f: F, a: f64, b: f64) -> Result<(f64, f64), EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    if !a.is_finite() || !b.is_finite() || a == b {
+        return Err(EvalError::InvalidArgument(format!(
+            "The interval to minimize over has to have two different finite ends, not {a} and {b}"
+        )));
+    }
+
+    let (mut a, mut b) = (a.min(b), a.max(b));
+    let ends = [(a, f(a)?), (b, f(b)?)];
+
+    // x is the best point so far, w the second best and v the one before w.
+    let mut x = a + GOLDEN * (b - a);
+    let mut fx = f(x)?;
+    let (mut w, mut fw, mut v, mut fv) = (x, fx, x, fx);
+    let (mut d, mut e) = (0.0f64, 0.0f64);
+
+    let mut iterations = 0;
+    loop {
+        let middle = 0.5 * (a + b);
+        let tolerance = TOLERANCE * x.abs().max(1.0);
+        if (x - middle).abs() <= 2.0 * tolerance - 0.5 * (b - a) {
+            break;
+        }
+        if iterations == MAX_ITERATIONS {
+            return Err(EvalError::NoConvergence { iterations });
+        }
+        iterations += 1;
+
+        let mut golden = true;
+        if e.abs() > tolerance {
+            // The minimum of the parabola through x, w and v is at x + p / q.
+            let r = (x - w) * (fx - fv);
+            let mut q = (x - v) * (fx - fw);
+            let mut p = (x - v) * q - (x - w) * r;
+            q = 2.0 * (q - r);
+            if q > 0.0 {
+                p = -p;
+            }
+            q = q.abs();
+
+            // The step is only taken when it's less than half the one before the last, and it
+            // stays inside the interval.
+            if p.abs() < (0.5 * q * e).abs() && p > q * (a - x) && p < q * (b - x) {
+                e = d;
+                d = p / q;
+                let u = x + d;
+                if u - a < 2.0 * tolerance || b - u < 2.0 * tolerance {
+                    d = tolerance.copysign(middle - x);
+                }
+                golden = false;
+            }
+        }
+        if golden {
+            e = if x < middle { b - x } else { a - x };
+            d = GOLDEN * e;
+        }
+
+        let u = match d.abs() >= tolerance {
+            true => x + d,
+            false => x + tolerance.copysign(d),
+        };
+        let fu = f(u)?;
+
+        if fu <= fx {
+            if u < x {
+                b = x;
+            } else {
+                a = x;
+            }
+            (v, fv, w, fw, x, fx) = (w, fw, x, fx, u, fu);
+        } else {
+            if u < x {
+                a = u;
+            } else {
+                b = u;
+            }
+            if fu <= fw || w == x {
+                (v, fv, w, fw) = (w, fw, u, fu);
+            } else if fu <= fv || v == x || v == w {
+                (v, fv) = (u, fu);
+            }
+        }
+    }
+
+    let best = ends.into_iter().fold((x, fx), |best, end| match end.1 < best.1 {
+        true => end,
+        false => best,
+    });
+    Ok(best)
+}
+
+/// Where a search for a minimum ended up.
+enum Search {
+    Minimum(Vec<f64>, f64),
+    /// The search couldn't continue from the point, because the expression can't be
+    /// differentiated there.
+    Stuck(Vec<f64>),
+}
+
+/// Finds a minimum with the BFGS quasi-Newton method, which builds up an approximation of the
+/// inverse Hessian from how the gradient changes between steps. Each step searches back along
+/// the direction until the value decreases enough.
+fn bfgs<F>(f: &mut F, start: Vec<f64>) -> Result<Search, EvalError>
+where
+    F: FnMut(&[f64], bool) -> Result<(f64, Vec<f64>), EvalError>,
+{
+    let n = start.len();
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+    let identity = || (0..n * n).map(|i| if i % (n + 1) == 0 { 1.0 } else { 0.0 }).collect::<Vec<_>>();
+
+    // Errors that come from the expression itself, rather than from differentiating it, are
+    // returned as they are.
+    let mut x = start;
+    let mut evaluate = |x: &[f64]| match f(x, true) {
+        Ok((value, g)) if value.is_finite() && g.iter().all(|g| g.is_finite()) => Ok(Some((value, g))),
+        Ok(_) | Err(EvalError::NotDifferentiable(_)) => Ok(None),
+        Err(err) => Err(err),
+    };
+
+    let Some((mut value, mut g)) = evaluate(&x)? else {
+        return Ok(Search::Stuck(x));
+    };
+    let mut inverse = identity();
+
+    for iteration in 0..MAX_ITERATIONS {
+        let norm = g.iter().fold(0.0f64, |max, g| max.max(g.abs()));
+        if norm <= GRADIENT_TOLERANCE * value.abs().max(1.0) {
+            return Ok(Search::Minimum(x, value));
+        }
+
+        let mut direction = (0..n).map(|i| -dot(&inverse[i * n..(i + 1) * n], &g)).collect::<Vec<_>>();
+        let mut slope = dot(&direction, &g);
+        if slope >= 0.0 {
+            inverse = identity();
+            direction = g.iter().map(|g| -g).collect();
+            slope = dot(&direction, &g);
+        }
+
+        // Backtracking until the Armijo condition holds.
+        let mut step = 1.0;
+        let next = loop {
+            let candidate = x.iter().zip(&direction).map(|(x, d)| x + step * d).collect::<Vec<_>>();
+            match evaluate(&candidate)? {
+                Some((v, _)) if v < -UNBOUNDED => return Err(EvalError::Unbounded),
+                Some((v, next_g)) if v <= value + 1e-4 * step * slope => break Some((candidate, v, next_g)),
+                _ if step < 1e-20 => break None,
+                _ => step *= 0.5,
+            }
+        };
+
+        // When no step decreases the value, the point is as good as the precision allows.
+        let Some((next, next_value, next_g)) = next else {
+            return match norm <= TOLERANCE * value.abs().max(1.0) {
+                true => Ok(Search::Minimum(x, value)),
+                false => Ok(Search::Stuck(x)),
+            };
+        };
+        if next.iter().any(|x| x.abs() > UNBOUNDED) {
+            return Err(EvalError::Unbounded);
+        }
+
+        let s = next.iter().zip(&x).map(|(a, b)| a - b).collect::<Vec<_>>();
+        let y = next_g.iter().zip(&g).map(|(a, b)| a - b).collect::<Vec<_>>();
+        let sy = dot(&s, &y);
+        let converged = s.iter().zip(&next).all(|(s, x)| s.abs() <= f64::EPSILON * x.abs().max(1.0));
+        (x, value, g) = (next, next_value, next_g);
+        if converged {
+            return Ok(Search::Minimum(x, value));
+        }
+
+        // The update keeps the approximation positive definite only when the curvature is.
+        if sy > 0.0 {
+            if iteration == 0 {
+                let scale = sy / dot(&y, &y);
+                inverse = identity().into_iter().map(|h| h * scale).collect();
+            }
+
+            let hy = (0..n).map(|i| dot(&inverse[i * n..(i + 1) * n], &y)).collect::<Vec<_>>();
+            let yhy = dot(&y, &hy);
+            for i in 0..n {
+                for j in 0..n {
+                    inverse[i * n + j] += ((sy + yhy) * s[i] * s[j]) / (sy * sy)
+                        - (hy[i] * s[j] + s[i] * hy[j]) / sy;
+                }
+            }
+        }
+    }
+
+    Ok(Search::Stuck(x))
+}
+
+/// Finds a minimum with the Nelder-Mead simplex method, which only needs values. The simplex
+/// reflects, expands and contracts its worst point until it's shrunk around the minimum.
+fn nelder_mead<F>(mut f: F, start: Vec<f64>) -> Result<(Vec<f64>, f64), EvalError>
+where
+    F: FnMut(&[f64]) -> Result<f64, EvalError>,
+{
+    let n = start.len();
+    let mut evaluate = |point: &[f64]| match f(point)? {
+        value if value < -UNBOUNDED || point.iter().any(|x| x.abs() > UNBOUNDED) => {
+            Err(EvalError::Unbounded)
+        }
+        value if value.is_nan() => Ok(f64::INFINITY),
+        value => Ok(value),
+    };
+
+    // The starting simplex moves each coordinate by 5%, like MATLAB's fminsearch.
+    let mut simplex = vec![(start.clone(), evaluate(&start)?)];
+    for i in 0..n {
+        let mut point = start.clone();
+        point[i] = match point[i] {
+            0.0 => 0.00025,
+            x => 1.05 * x,
+        };
+        let value = evaluate(&point)?;
+        simplex.push((point, value));
+    }
+
+    let along = |from: &[f64], to: &[f64], t: f64| {
+        from.iter().zip(to).map(|(a, b)| a + t * (b - a)).collect::<Vec<_>>()
+    };
+
+    for _ in 0..MAX_ITERATIONS {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (&simplex[0], &simplex[n]);
+
+        let size = simplex[1..].iter().fold(0.0f64, |max, (point, _)| {
+            point.iter().zip(&best.0).fold(max, |max, (a, b)| max.max((a - b).abs()))
+        });
+        let spread = worst.1 - best.1;
+        let scale = best.0.iter().fold(1.0f64, |max, x| max.max(x.abs()));
+        if size <= TOLERANCE * scale && spread <= TOLERANCE * TOLERANCE * best.1.abs().max(1.0) {
+            return Ok(simplex.swap_remove(0));
+        }
+
+        let centroid = (0..n)
+            .map(|j| simplex[..n].iter().map(|(point, _)| point[j]).sum::<f64>() / n as f64)
+            .collect::<Vec<_>>();
+        let worst = simplex[n].clone();
+
+        let reflected = along(&centroid, &worst.0, -1.0);
+        let fr = evaluate(&reflected)?;
+        if fr < simplex[0].1 {
+            let expanded = along(&centroid, &worst.0, -2.0);
+            let fe = evaluate(&expanded)?;
+            simplex[n] = if fe < fr { (expanded, fe) } else { (reflected, fr) };
+            continue;
+        }
+        if fr < simplex[n - 1].1 {
+            simplex[n] = (reflected, fr);
+            continue;
+        }
+
+        // Contract towards the better of the worst and reflected points.
+        let (t, limit) = match fr < worst.1 {
+            true => (-0.5, fr),
+            false => (0.5, worst.1),
+        };
+        let contracted = along(&centroid, &worst.0, t);
+        let fc = evaluate(&contracted)?;
+        if fc < limit {
+            simplex[n] = (contracted, fc);
+            continue;
+        }
+
+        // Nothing worked, so the simplex shrinks around the best point.
+        let best = simplex[0].0.clone();
+        for vertex in simplex.iter_mut().skip(1) {
+            let point = along(&best, &vertex.0, 0.5);
+            *vertex = (point.clone(), evaluate(&point)?);
+        }
+    }
+
+    Err(EvalError::NoConvergence {
+        iterations: MAX_ITERATIONS,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, testing::assert_close, tokenize};
+
+    fn minimize(expr: &str) -> Result<Value, EvalError> {
+        let tokens = tokenize::parse_expression(expr)?;
+        evaluate_minimize(&ast::get_ast(&tokens)?, &Scope::new())
+    }
+
+    /// Returns where the minimum is and its value.
+    fn minimum(expr: &str) -> (Vec<f64>, f64) {
+        let Ok(Value::List(result)) = minimize(expr) else {
+            panic!("Expected a list");
+        };
+        let point = match &result[0] {
+            Value::Number(x) => vec![*x],
+            point => numbers(point).unwrap(),
+        };
+        let Value::Number(value) = result[1] else {
+            panic!("Expected a number");
+        };
+        (point, value)
+    }
+
+    #[test]
+    fn test_interval() {
+        let (x, value) = minimum("minimize(x^2 - 2x, x, -5, 5)");
+        let (peak, top) = minimum("maximize(sin(x), x, 0, 3)");
+        let (end, _) = minimum("minimize(x^3, x, 1, 2)");
+
+        assert_close(x[0], 1.0, 1e-7);
+        assert_eq!(value, -1.0);
+        assert_close(peak[0], std::f64::consts::FRAC_PI_2, 1e-7);
+        assert_close(top, 1.0, 1e-14);
+        assert_eq!(end, vec![1.0]);
+    }
+
+    #[test]
+    fn test_bfgs() {
+        let (point, value) = minimum("minimize((1 - x)^2 + 100(y - x^2)^2, [x, y], [-1.2, 1])");
+        let (x, _) = minimum("minimize(e^x - 2x, x, 0)");
+
+        assert_close(point[0], 1.0, 1e-6);
+        assert_close(point[1], 1.0, 1e-6);
+        assert_close(value, 0.0, 1e-12);
+        assert_close(x[0], 2f64.ln(), 1e-8);
+    }
+
+    #[test]
+    fn test_nelder_mead() {
+        // The distributions can't be differentiated, so Nelder-Mead is used.
+        let (point, value) = minimum("minimize(-normpdf(x, 1, 2) normpdf(y, -3), {x, y}, {0, 0})");
+
+        assert_close(point[0], 1.0, 1e-6);
+        assert_close(point[1], -3.0, 1e-6);
+        assert_close(value, -normal(0.0, 2.0) * normal(0.0, 1.0), 1e-14);
+    }
+
+    fn normal(x: f64, sigma: f64) -> f64 {
+        (-x * x / (2.0 * sigma * sigma)).exp() / (sigma * (2.0 * std::f64::consts::PI).sqrt())
+    }
+
+    #[test]
+    fn test_failures() {
+        assert_eq!(minimize("minimize(x + y, [x, y], [0, 0])"), Err(EvalError::Unbounded));
+        assert!(minimize("minimize(x^2, x, 1, 1)").is_err());
+        assert_eq!(
+            minimize("minimize(x^2 + y^2, [x, y], [1, 2, 3])"),
+            Err(EvalError::LengthMismatch { expected: 2, got: 3 })
+        );
+        assert_eq!(minimize("minimize(x^2, 2, 1, 1)"), Err(EvalError::ExpectedVariable("minimize".to_string())));
+        assert!(matches!(minimize("minimize(x^2 + rand(), [x], [1])"), Err(EvalError::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,664 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::{
+    ast::ASTNode, complex::{self, Complex}, errors::EvalError, eval::*, simplify::simplify, symbolic,
+    tokenize::*,
+};
+
+/// Coefficients smaller than this, relative to the largest one, are treated as zero when dividing,
+/// and the real or imaginary part of a root this small next to the root is dropped.
+const TOLERANCE: f64 = 1e-10;
+
+/// The most iterations of the Aberth method used for polynomials of degree five or more.
+const MAX_ITERATIONS: usize = 500;
+
+/// The highest degree a power of a polynomial can have. Multiplying polynomials takes time that
+/// grows with the square of their degree.
+pub const MAX_DEGREE: usize = 10_000;
+
+/// A polynomial in one variable with real coefficients, stored from the constant term up. There
+/// are never trailing zeros, so the zero polynomial has no coefficients.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Polynomial {
+    coefficients: Vec<f64>,
+}
+
+impl Polynomial {
+    /// Creates the polynomial with the given coefficients, from the constant term up.
+    pub fn new(coefficients: Vec<f64>) -> Polynomial {
+        let mut polynomial = Polynomial { coefficients };
+        polynomial.trim();
+        polynomial
+    }
+
+    pub fn constant(c: f64) -> Polynomial {
+        Polynomial::new(vec![c])
+    }
+
+    /// The polynomial `x`.
+    pub fn variable() -> Polynomial {
+        Polynomial::new(vec![0.0, 1.0])
+    }
+
+    pub fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+
+    /// The highest power with a nonzero coefficient, or `None` for the zero polynomial.
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.len().checked_sub(1)
+    }
+
+    fn leading(&self) -> f64 {
+        self.coefficients.last().copied().unwrap_or(0.0)
+    }
+
+    fn trim(&mut self) {
+        while self.coefficients.last() == Some(&0.0) {
+            self.coefficients.pop();
+        }
+    }
+
+    /// Reads the coefficients of the expression as a polynomial in the variable. Parts of the
+    /// expression that don't depend on the variable are evaluated with the scope.
+    pub fn from_ast(ast: &ASTNode, var: &str, scope: &Scope) -> Result<Polynomial, EvalError> {
+        match Polynomial::collect(ast, var, scope) {
+            // Expressions like `x^3 / x` only become polynomials once they're simplified.
+            Err(EvalError::NotAPolynomial(_)) => {
+                Polynomial::collect(&simplify(ast), var, scope).map_err(|err| match err {
+                    EvalError::NotAPolynomial(_) => EvalError::NotAPolynomial(ast.to_string()),
+                    err => err,
+                })
+            }
+            result => result,
+        }
+    }
+
+    fn collect(ast: &ASTNode, var: &str, scope: &Scope) -> Result<Polynomial, EvalError> {
+        if !ast.contains_variable(var) {
+            return evaluate_ast(ast, scope).map(Polynomial::constant);
+        }
+
+        let not_polynomial = || EvalError::NotAPolynomial(ast.to_string());
+        let child = |i: usize| Polynomial::collect(&ast.children[i], var, scope);
+
+        match &ast.token {
+            Token::Variable(_) => Ok(Polynomial::variable()),
+            Token::Operator(Operator::Unary(Unary::Negate)) => Ok(-child(0)?),
+            Token::Operator(Operator::Additive(Additive::Add)) => Ok(child(0)? + child(1)?),
+            Token::Operator(Operator::Additive(Additive::Subtract)) => Ok(child(0)? - child(1)?),
+            Token::Operator(Operator::Multiplicative(Multiplicative::Multiply)) => {
+                Ok(child(0)? * child(1)?)
+            }
+            Token::Operator(Operator::Multiplicative(Multiplicative::Divide))
+                if !ast.children[1].contains_variable(var) =>
+            {
+                let divisor = evaluate_ast(&ast.children[1], scope)?;
+                Ok(child(0)?.scale(1.0 / divisor))
+            }
+            Token::Operator(Operator::Exponential) if !ast.children[1].contains_variable(var) => {
+                let exponent = evaluate_ast(&ast.children[1], scope)?;
+                if exponent.fract() != 0.0 || exponent < 0.0 {
+                    return Err(not_polynomial());
+                }
+                child(0)?.pow(exponent as u32)
+            }
+            Token::Function((func, _)) if symbolic::SYMBOLIC_FUNCTIONS.contains(&func.name) => {
+                Polynomial::collect(&symbolic::expand(ast, scope)?, var, scope)
+            }
+            _ => Err(not_polynomial()),
+        }
+    }
+
+    /// Writes the polynomial as an expression in the variable, from the highest power down.
+    pub fn to_ast(&self, var: &str) -> ASTNode {
+        let mut ast: Option<ASTNode> = None;
+
+        for (k, &c) in self.coefficients.iter().enumerate().rev() {
+            if c == 0.0 {
+                continue;
+            }
+
+            let power = match k {
+                0 => None,
+                1 => Some(ASTNode::variable(var)),
+                _ => Some(ASTNode::operator(
+                    Operator::Exponential,
+                    vec![ASTNode::variable(var), ASTNode::number(k as f64)],
+                )),
+            };
+            let term = match power {
+                None => ASTNode::number(c.abs()),
+                Some(power) if c.abs() == 1.0 => power,
+                Some(power) => ASTNode::operator(
+                    Operator::Multiplicative(Multiplicative::Multiply),
+                    vec![ASTNode::number(c.abs()), power],
+                ),
+            };
+
+            ast = Some(match ast {
+                None if c < 0.0 => ASTNode::operator(Operator::Unary(Unary::Negate), vec![term]),
+                None => term,
+                Some(ast) => {
+                    let op = if c < 0.0 { Additive::Subtract } else { Additive::Add };
+                    ASTNode::operator(Operator::Additive(op), vec![ast, term])
+                }
+            });
+        }
+
+        ast.unwrap_or_else(|| ASTNode::number(0.0))
+    }
+
+    pub fn scale(&self, factor: f64) -> Polynomial {
+        Polynomial::new(self.coefficients.iter().map(|c| c * factor).collect())
+    }
+
+    /// Raises the polynomial to a power by repeated squaring. Powers with a degree above
+    /// `MAX_DEGREE` are refused.
+    pub fn pow(&self, exponent: u32) -> Result<Polynomial, EvalError> {
+        if self.degree().unwrap_or(0) as f64 * exponent as f64 > MAX_DEGREE as f64 {
+            return Err(EvalError::DegreeTooHigh { limit: MAX_DEGREE });
+        }
+
+        let mut result = Polynomial::constant(1.0);
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base.clone();
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.clone() * base;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn derivative(&self) -> Polynomial {
+        Polynomial::new(
+            self.coefficients
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(k, c)| c * k as f64)
+                .collect(),
+        )
+    }
+
+    /// Evaluates the polynomial with Horner's method.
+    pub fn evaluate(&self, x: Complex) -> Complex {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(Complex::default(), |acc, &c| acc * x + Complex::real(c))
+    }
+
+    /// Evaluates the polynomial at a real number with compensated Horner's method, which keeps
+    /// track of the rounding error of every step and is about as accurate as using twice the
+    /// precision.
+    pub fn evaluate_real(&self, x: f64) -> f64 {
+        let mut coefficients = self.coefficients.iter().rev();
+        let mut sum = coefficients.next().copied().unwrap_or(0.0);
+        let mut error = 0.0;
+
+        for &c in coefficients {
+            let product = sum * x;
+            let product_error = sum.mul_add(x, -product);
+
+            let next = product + c;
+            let z = next - product;
+            let sum_error = (product - (next - z)) + (c - z);
+
+            sum = next;
+            error = error * x + (product_error + sum_error);
+        }
+
+        sum + error
+    }
+
+    /// Divides the polynomial by another one, returning the quotient and the remainder.
+    /// Coefficients of the remainder that are only left over from rounding are dropped.
+    pub fn div_rem(&self, divisor: &Polynomial) -> Result<(Polynomial, Polynomial), EvalError> {
+        let d = divisor.degree().ok_or(EvalError::DivisionByZero)?;
+        let scale = self.coefficients.iter().fold(0.0, |max: f64, c| max.max(c.abs()));
+
+        let mut remainder = self.coefficients.clone();
+        let mut quotient = vec![0.0; remainder.len().saturating_sub(d)];
+
+        for k in (0..quotient.len()).rev() {
+            let factor = remainder[k + d] / divisor.leading();
+            quotient[k] = factor;
+            for (j, c) in divisor.coefficients.iter().enumerate() {
+                remainder[k + j] -= factor * c;
+            }
+            remainder[k + d] = 0.0;
+        }
+
+        for c in &mut remainder {
+            if c.abs() <= TOLERANCE * scale {
+                *c = 0.0;
+            }
+        }
+
+        Ok((Polynomial::new(quotient), Polynomial::new(remainder)))
+    }
+
+    /// Returns the monic greatest common divisor of the two polynomials, found with the Euclidean
+    /// algorithm.
+    pub fn gcd(&self, other: &Polynomial) -> Result<Polynomial, EvalError> {
+        let (mut a, mut b) = (self.clone(), other.clone());
+
+        while b.degree().is_some() {
+            let (_, remainder) = a.div_rem(&b)?;
+            (a, b) = (b, remainder);
+        }
+
+        match a.degree() {
+            Some(_) => Ok(a.scale(1.0 / a.leading())),
+            None => Ok(a),
+        }
+    }
+
+    /// Finds every root of the polynomial, repeated by multiplicity. Polynomials up to degree four
+    /// are solved with the closed-form formulas, and higher degrees with the Aberth method.
+    pub fn roots(&self) -> Result<Vec<Complex>, EvalError> {
+        if self.degree().is_none() {
+            return Err(EvalError::InvalidExpression(
+                "Every number is a root of 0".to_string(),
+            ));
+        }
+
+        // Repeated roots are hard to find precisely, so they're divided out first. The greatest
+        // common divisor with the derivative has every repeated root once less than the
+        // polynomial, and dividing by it leaves every root once. The divisor is only trusted for
+        // integer coefficients, since roots that are merely close, like those of
+        // `(x - 1)(x - 1.0000001)`, have an approximate common divisor too.
+        let exact = self.coefficients.iter().all(|c| c.fract() == 0.0 && c.abs() < 2f64.powi(53));
+        self.find_roots(exact)
+    }
+
+    /// Finds every root of the polynomial, dividing out repeated roots first if `deflate` is set.
+    /// The divisors of a polynomial that was deflated are deflated too, since their coefficients
+    /// are only approximate but their roots are still repeated roots of the original.
+    fn find_roots(&self, deflate: bool) -> Result<Vec<Complex>, EvalError> {
+        let repeated = match deflate {
+            true => self.gcd(&self.derivative())?,
+            false => Polynomial::constant(1.0),
+        };
+        if repeated.degree() > Some(0) {
+            let (distinct, _) = self.div_rem(&repeated)?;
+            let mut roots = distinct.find_roots(false)?;
+            roots.extend(repeated.find_roots(true)?);
+            complex::sort(&mut roots);
+            return Ok(roots);
+        }
+
+        // Factors of x give roots at zero exactly.
+        let zeros = self.coefficients.iter().take_while(|c| **c == 0.0).count();
+        let reduced = Polynomial::new(self.coefficients[zeros..].to_vec());
+        let monic = reduced.scale(1.0 / reduced.leading());
+        let c = |k: usize| monic.coefficients[k];
+
+        let mut roots = vec![Complex::default(); zeros];
+        roots.extend(match monic.degree() {
+            Some(1) => vec![Complex::real(-c(0))],
+            Some(2) => quadratic(Complex::real(c(1)), Complex::real(c(0))).to_vec(),
+            Some(3) => cubic(c(2), c(1), c(0)).to_vec(),
+            Some(4) => quartic(c(3), c(2), c(1), c(0)).to_vec(),
+            Some(_) => aberth(&monic)?,
+            None => unreachable!("The zero polynomial is handled above"),
+        });
+
+        // The formulas lose some precision to cancellation, which a few Newton steps win back.
+        let derivative = self.derivative();
+        for root in &mut roots {
+            for _ in 0..3 {
+                let next = *root - self.evaluate(*root) / derivative.evaluate(*root);
+                let improved = self.evaluate(next).abs() < self.evaluate(*root).abs();
+                if !improved {
+                    break;
+                }
+                *root = next;
+            }
+
+            // Parts that are only left over from rounding are dropped.
+            if root.is_real(TOLERANCE) {
+                root.im = 0.0;
+            }
+            if root.re.abs() <= TOLERANCE * root.abs() {
+                root.re = 0.0;
+            }
+
+            // Real roots get a few more steps with the polynomial evaluated more precisely, since
+            // an ordinary evaluation is often exactly zero a rounding error away from the root.
+            if root.im == 0.0 {
+                for _ in 0..3 {
+                    let x = root.re;
+                    let next = x - self.evaluate_real(x) / derivative.evaluate_real(x);
+                    let improved = self.evaluate_real(next).abs() < self.evaluate_real(x).abs();
+                    if !improved {
+                        break;
+                    }
+                    root.re = next;
+                }
+            }
+        }
+
+        complex::sort(&mut roots);
+        Ok(roots)
+    }
+}
+
+
+/// Solves `x^2 + bx + c = 0`, picking the form of the quadratic formula that avoids cancellation.
+fn quadratic(b: Complex, c: Complex) -> [Complex; 2] {
+    let d = (b * b - c * 4.0).sqrt();
+    let q = if (b.conj() * d).re >= 0.0 { -(b + d) / 2.0 } else { -(b - d) / 2.0 };
+
+    if q == Complex::default() {
+        return [q, q];
+    }
+    [q, c / q]
+}
+
+/// Solves `x^3 + ax^2 + bx + c = 0` with Cardano's formula.
+fn cubic(a: f64, b: f64, c: f64) -> [Complex; 3] {
+    // Substituting x = t - a/3 gives t^3 + pt + q = 0.
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a.powi(3) / 27.0 - a * b / 3.0 + c;
+    let shift = Complex::real(-a / 3.0);
+
+    if p == 0.0 && q == 0.0 {
+        return [shift; 3];
+    }
+
+    let d = Complex::real(q * q / 4.0 + p.powi(3) / 27.0).sqrt();
+    let half = Complex::real(-q / 2.0);
+    let u = [(half + d).cbrt(), (half - d).cbrt()]
+        .into_iter()
+        .max_by(|x, y| x.abs().total_cmp(&y.abs()))
+        .unwrap();
+
+    let omega = Complex::new(-0.5, 3f64.sqrt() / 2.0);
+    let mut rotation = Complex::real(1.0);
+    [0, 1, 2].map(|_| {
+        let uk = u * rotation;
+        rotation = rotation * omega;
+        uk - Complex::real(p / 3.0) / uk + shift
+    })
+}
+
+/// Solves `x^4 + ax^3 + bx^2 + cx + d = 0` with Ferrari's method.
+fn quartic(a: f64, b: f64, c: f64, d: f64) -> [Complex; 4] {
+    // Substituting x = y - a/4 gives y^4 + py^2 + qy + r = 0.
+    let p = b - 3.0 * a * a / 8.0;
+    let q = c - a * b / 2.0 + a.powi(3) / 8.0;
+    let r = d - a * c / 4.0 + a * a * b / 16.0 - 3.0 * a.powi(4) / 256.0;
+    let shift = Complex::real(-a / 4.0);
+
+    let [y1, y2, y3, y4] = if q.abs() <= TOLERANCE * (p.abs() + r.abs()).max(1.0) {
+        // With no odd powers, it's a quadratic in y^2.
+        let [z1, z2] = quadratic(Complex::real(p), Complex::real(r));
+        [z1.sqrt(), -z1.sqrt(), z2.sqrt(), -z2.sqrt()]
+    } else {
+        // Any nonzero root m of the resolvent cubic splits it into two quadratics.
+        let m = cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .max_by(|x, y| x.abs().total_cmp(&y.abs()))
+            .unwrap();
+        let s = (m * 2.0).sqrt();
+        let base = Complex::real(p / 2.0) + m;
+        let offset = Complex::real(q / 2.0) / s;
+
+        let [y1, y2] = quadratic(-s, base + offset);
+        let [y3, y4] = quadratic(s, base - offset);
+        [y1, y2, y3, y4]
+    };
+
+    [y1 + shift, y2 + shift, y3 + shift, y4 + shift]
+}
+
+/// Finds the roots of a monic polynomial with the Aberth method, which improves estimates of all
+/// of them at once, each one pushed away from the others.
+fn aberth(monic: &Polynomial) -> Result<Vec<Complex>, EvalError> {
+    let n = monic.degree().unwrap_or_default();
+    let derivative = monic.derivative();
+
+    // Every root lies within this radius (Fujiwara's bound).
+    let radius = (1..=n)
+        .map(|k| {
+            let c = monic.coefficients[n - k].abs();
+            if k == n { (c / 2.0).powf(1.0 / k as f64) } else { c.powf(1.0 / k as f64) }
+        })
+        .fold(0.0, f64::max)
+        * 2.0;
+
+    // Starting points spread around a circle, turned slightly so none start on the real axis.
+    let mut roots = (0..n)
+        .map(|k| Complex::polar(radius.max(1.0), std::f64::consts::TAU * k as f64 / n as f64 + 0.4))
+        .collect::<Vec<_>>();
+
+    // The size of the rounding error when evaluating at a point with magnitude r.
+    let magnitudes = Polynomial::new(monic.coefficients.iter().map(|c| c.abs()).collect());
+    let rounding = |r: f64| 4.0 * n as f64 * f64::EPSILON * magnitudes.evaluate_real(r);
+
+    // A root stops moving once its steps are tiny or the polynomial is as close to zero there as
+    // rounding errors allow.
+    let mut done = vec![false; n];
+
+    for _ in 0..MAX_ITERATIONS {
+        for k in 0..n {
+            if done[k] {
+                continue;
+            }
+
+            let z = roots[k];
+            let value = monic.evaluate(z);
+            if value.abs() <= rounding(z.abs()) {
+                done[k] = true;
+                continue;
+            }
+
+            let ratio = value / derivative.evaluate(z);
+            let repulsion = (0..n)
+                .filter(|&j| j != k)
+                .fold(Complex::default(), |acc, j| acc + Complex::real(1.0) / (z - roots[j]));
+            let step = ratio / (Complex::real(1.0) - ratio * repulsion);
+
+            let moving = step.abs() > 1e-14 * z.abs().max(1.0);
+            if step.abs().is_finite() {
+                roots[k] = z - step;
+            }
+            if !moving {
+                done[k] = true;
+            }
+        }
+
+        if done.iter().all(|&d| d) {
+            return Ok(roots);
+        }
+    }
+
+    Err(EvalError::NoConvergence {
+        iterations: MAX_ITERATIONS,
+    })
+}
+
+impl Add for Polynomial {
+    type Output = Polynomial;
+
+    fn add(self, other: Polynomial) -> Polynomial {
+        let len = self.coefficients.len().max(other.coefficients.len());
+        let c = |p: &Polynomial, k: usize| p.coefficients.get(k).copied().unwrap_or(0.0);
+        Polynomial::new((0..len).map(|k| c(&self, k) + c(&other, k)).collect())
+    }
+}
+
+impl Sub for Polynomial {
+    type Output = Polynomial;
+
+    fn sub(self, other: Polynomial) -> Polynomial {
+        self + -other
+    }
+}
+
+impl Mul for Polynomial {
+    type Output = Polynomial;
+
+    fn mul(self, other: Polynomial) -> Polynomial {
+        if self.degree().is_none() || other.degree().is_none() {
+            return Polynomial::default();
+        }
+
+        let mut product = vec![0.0; self.coefficients.len() + other.coefficients.len() - 1];
+        for (i, a) in self.coefficients.iter().enumerate() {
+            for (j, b) in other.coefficients.iter().enumerate() {
+                product[i + j] += a * b;
+            }
+        }
+        Polynomial::new(product)
+    }
+}
+
+impl Neg for Polynomial {
+    type Output = Polynomial;
+
+    fn neg(self) -> Polynomial {
+        self.scale(-1.0)
+    }
+}
+
+/// Evaluates a call to `roots(expr, x)`.
+pub fn evaluate_roots(ast: &ASTNode, scope: &Scope) -> Result<Vec<Complex>, EvalError> {
+    let var = ast.children[1].variable_name("roots")?;
+    Polynomial::from_ast(&ast.children[0], &var, scope)?.roots()
+}
+
+/// Evaluates a call to `polydiv(p, q, x)`, returning the quotient and remainder as expressions.
+pub fn evaluate_polydiv(ast: &ASTNode, scope: &Scope) -> Result<(ASTNode, ASTNode), EvalError> {
+    let var = ast.children[2].variable_name("polydiv")?;
+    let p = Polynomial::from_ast(&ast.children[0], &var, scope)?;
+    let q = Polynomial::from_ast(&ast.children[1], &var, scope)?;
+    let (quotient, remainder) = p.div_rem(&q)?;
+
+    Ok((quotient.to_ast(&var), remainder.to_ast(&var)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::get_ast, testing::assert_close, tokenize::parse_expression};
+
+    fn polynomial(expression: &str) -> Polynomial {
+        let ast = get_ast(&parse_expression(expression).unwrap()).unwrap();
+        Polynomial::from_ast(&ast, "x", &Scope::new()).unwrap()
+    }
+
+    fn assert_roots(expression: &str, expected: &[Complex]) {
+        let roots = polynomial(expression).roots().unwrap();
+
+        assert_eq!(roots.len(), expected.len());
+        for (root, expected) in roots.iter().zip(expected) {
+            assert_close(root.re, expected.re, 1e-9);
+            assert_close(root.im, expected.im, 1e-9);
+        }
+    }
+
+    fn real(values: &[f64]) -> Vec<Complex> {
+        values.iter().map(|&v| Complex::real(v)).collect()
+    }
+
+    #[test]
+    fn test_coefficients() {
+        assert_eq!(polynomial("(x + 1)^2 - 3x / 2").coefficients(), &[1.0, 0.5, 1.0]);
+        assert_eq!(polynomial("x^3 / x").coefficients(), &[0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(polynomial("(x + 1)^5").coefficients(), &[1.0, 5.0, 10.0, 10.0, 5.0, 1.0]);
+        assert_eq!(polynomial("(2x)^0").coefficients(), &[1.0]);
+
+        let ast = get_ast(&parse_expression("(x + 1)^100000000").unwrap()).unwrap();
+        let res = Polynomial::from_ast(&ast, "x", &Scope::new());
+        assert_eq!(res, Err(EvalError::DegreeTooHigh { limit: MAX_DEGREE }));
+    }
+
+    #[test]
+    fn test_not_a_polynomial() {
+        let ast = get_ast(&parse_expression("sin(x) + 1").unwrap()).unwrap();
+        let res = Polynomial::from_ast(&ast, "x", &Scope::new());
+
+        assert_eq!(res, Err(EvalError::NotAPolynomial("sin(x) + 1".to_string())));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(polynomial("(x - 1)^3").to_ast("x").to_string(), "x^3 - 3x^2 + 3x - 1");
+        assert_eq!(polynomial("-x^2 + 2").to_ast("x").to_string(), "-x^2 + 2");
+    }
+
+    #[test]
+    fn test_closed_form_roots() {
+        assert_roots("2x - 3", &real(&[1.5]));
+        assert_roots("x^2 + 1", &[Complex::new(0.0, -1.0), Complex::new(0.0, 1.0)]);
+        assert_roots("x^3 - 6x^2 + 11x - 6", &real(&[1.0, 2.0, 3.0]));
+        assert_roots("x^3 - 1", &[
+            Complex::real(1.0),
+            Complex::new(-0.5, -(3f64.sqrt()) / 2.0),
+            Complex::new(-0.5, 3f64.sqrt() / 2.0),
+        ]);
+        assert_roots("(x - 1)(x + 2)(x - 3)(x + 4)", &real(&[-4.0, -2.0, 1.0, 3.0]));
+        assert_roots("x^4 - 5x^2 + 4", &real(&[-2.0, -1.0, 1.0, 2.0]));
+    }
+
+    #[test]
+    fn test_repeated_roots() {
+        assert_roots("(x - 1)^5 (x + 2)^2", &real(&[-2.0, -2.0, 1.0, 1.0, 1.0, 1.0, 1.0]));
+        // Without integer coefficients the repeated roots aren't divided out, and the derivative
+        // is zero at them.
+        assert_roots("(x - 0.1)^2", &real(&[0.1, 0.1]));
+        assert_roots("(x - 0.5)^3", &real(&[0.5, 0.5, 0.5]));
+    }
+
+    #[test]
+    fn test_close_roots() {
+        // Rounding the coefficients moves roots this close by about 1e-9, but they stay apart.
+        let roots = polynomial("(x - 1)(x - 1.0000001)").roots().unwrap();
+        assert_close(roots[0].re, 1.0, 1e-8);
+        assert_close(roots[1].re, 1.0000001, 1e-8);
+        assert!(roots.iter().all(|root| root.im == 0.0), "{roots:?}");
+    }
+
+    #[test]
+    fn test_aberth_roots() {
+        assert_roots("x^5 - x", &[
+            Complex::real(-1.0),
+            Complex::real(0.0),
+            Complex::real(1.0),
+            Complex::new(0.0, -1.0),
+            Complex::new(0.0, 1.0),
+        ]);
+        assert_roots(
+            "(x - 1)(x - 2)(x - 3)(x - 4)(x - 5)(x - 6)",
+            &real(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        );
+    }
+
+    #[test]
+    fn test_division() {
+        let (quotient, remainder) = polynomial("x^3 - 2x + 1").div_rem(&polynomial("x - 2")).unwrap();
+
+        assert_eq!(quotient.to_ast("x").to_string(), "x^2 + 2x + 2");
+        assert_eq!(remainder.to_ast("x").to_string(), "5");
+    }
+
+    #[test]
+    fn test_gcd() {
+        let gcd = polynomial("x^3 - x").gcd(&polynomial("x^2 + 2x + 1")).unwrap();
+
+        assert_eq!(gcd.to_ast("x").to_string(), "x + 1");
+    }
+}
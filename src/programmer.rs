@@ -0,0 +1,385 @@
+use std::fmt;
+
+use crate::{ast::*, errors::EvalError, tokenize::*};
+
+/// The width and signedness of the integers used in programmer mode.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IntType {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+/// What happens when a result doesn't fit in the integer type.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Overflow {
+    /// Keep the lowest bits of the result, like two's complement hardware does.
+    Wrap,
+    /// Clamp the result to the smallest or largest value of the type.
+    Saturate,
+    /// Return an error.
+    Trap,
+}
+
+/// The settings used when evaluating in programmer mode.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IntFormat {
+    pub int_type: IntType,
+    pub overflow: Overflow,
+}
+
+/// A fixed-width integer. The value is always kept within the range of its type.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Int {
+    pub value: i128,
+    pub int_type: IntType,
+}
+
+impl IntType {
+    /// Parses a type name like `u8` or `i64`.
+    pub fn parse(name: &str) -> Option<IntType> {
+        let signed = match name.get(..1)? {
+            "u" => false,
+            "i" => true,
+            _ => return None,
+        };
+
+        match name[1..].parse::<u32>().ok()? {
+            bits @ (8 | 16 | 32 | 64) => Some(IntType { bits, signed }),
+            _ => None,
+        }
+    }
+
+    pub fn min(&self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max(&self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.bits)
+    }
+
+    /// Interprets the lowest bits of the pattern as a value of this type.
+    fn with_bits(&self, bits: u64) -> Int {
+        let bits = bits & self.mask();
+        let value = if self.signed && bits >> (self.bits - 1) == 1 {
+            bits as i128 - (1 << self.bits)
+        } else {
+            bits as i128
+        };
+
+        Int {
+            value,
+            int_type: *self,
+        }
+    }
+}
+
+impl Overflow {
+    pub fn parse(name: &str) -> Option<Overflow> {
+        match name {
+            "wrap" => Some(Overflow::Wrap),
+            "saturate" => Some(Overflow::Saturate),
+            "trap" => Some(Overflow::Trap),
+            _ => None,
+        }
+    }
+}
+
+impl IntFormat {
+    /// Fits a result into the integer type according to the overflow behaviour. `None` means the
+    /// result was too large to even be calculated, in which case the sign is used to saturate.
+    fn fit(&self, result: Option<i128>, sign: i128) -> Result<Int, EvalError> {
+        let ty = self.int_type;
+
+        match (result, self.overflow) {
+            (Some(value), _) if (ty.min()..=ty.max()).contains(&value) => Ok(Int {
+                value,
+                int_type: ty,
+            }),
+            (_, Overflow::Trap) => Err(EvalError::Overflow),
+            (Some(value), Overflow::Wrap) => Ok(ty.with_bits(value as u64)),
+            (result, _) => {
+                let value = match result.map_or(sign, i128::signum) {
+                    s if s < 0 => ty.min(),
+                    _ => ty.max(),
+                };
+
+                Ok(Int {
+                    value,
+                    int_type: ty,
+                })
+            }
+        }
+    }
+
+    /// Converts a number from the expression into the integer type.
+    fn int(&self, n: f64) -> Result<Int, EvalError> {
+        if n.fract() != 0.0 || !n.is_finite() {
+            return Err(EvalError::NotAnInteger(n));
+        }
+
+        self.fit(Some(n as i128), n.signum() as i128)
+    }
+}
+
+impl Int {
+    fn bits(&self) -> u64 {
+        self.value as u64 & self.int_type.mask()
+    }
+}
+
+impl fmt::Display for Int {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.int_type.bits as usize;
+        let prefix = if self.int_type.signed { 'i' } else { 'u' };
+
+        write!(
+            f,
+            "{} ({prefix}{width}) = 0x{:0hex$X} = 0b{:0width$b}",
+            self.value,
+            self.bits(),
+            self.bits(),
+            hex = width / 4,
+        )
+    }
+}
+
+/// Recursively collapses the ast using fixed-width integers instead of floats.
+pub fn evaluate_int(ast: &ASTNode, format: &IntFormat) -> Result<Int, EvalError> {
+    let ty = format.int_type;
+
+    match &ast.token {
+        Token::Number(n) => format.int(*n),
+        Token::Integer(n) => format.fit(Some(*n as i128), 1),
+        Token::Constant(n) => Err(EvalError::NotAnInteger(*n)),
+        Token::Variable(name) => Err(EvalError::UnknownKeyword(name.clone())),
+        Token::Matrix { .. } | Token::List | Token::Timestamp(_) | Token::Duration(_) => {
+            Err(EvalError::NotInProgrammerMode(ast.to_string()))
+        }
+        Token::Call((name, _)) => Err(EvalError::NotInProgrammerMode(name.clone())),
+        Token::Function((func, _)) => {
+            let mut args = vec![];
+            for child in &ast.children {
+                args.push(evaluate_int(child, format)?);
+            }
+
+            let bits = args[0].bits();
+            let amount = || match args[1].value {
+                n if n < 0 => Err(EvalError::NegativeShift),
+                n => Ok((n % ty.bits as i128) as u32),
+            };
+
+            // The rotations work on the lowest bits of a u64, so the width is adjusted for.
+            let rotated = |left: bool| -> Result<Int, EvalError> {
+                let n = amount()?;
+                let (a, b) = if left { (n, ty.bits - n) } else { (ty.bits - n, n) };
+                let high = bits.checked_shl(a).unwrap_or(0);
+                let low = bits.checked_shr(b).unwrap_or(0);
+                Ok(ty.with_bits(high | low))
+            };
+
+            let count = |n: u32| Ok(Int {
+                value: n as i128,
+                int_type: ty,
+            });
+
+            match func.name {
+                "rotl" => rotated(true),
+                "rotr" => rotated(false),
+                "popcount" => count(bits.count_ones()),
+                "clz" => count(bits.leading_zeros() - (64 - ty.bits)),
+                "ctz" => count(bits.trailing_zeros().min(ty.bits)),
+                name => Err(EvalError::NotInProgrammerMode(name.to_string())),
+            }
+        }
+        Token::Operator(Operator::Unary(op)) => {
+            let operand = evaluate_int(&ast.children[0], format)?;
+
+            match op {
+                Unary::Negate => format.fit(Some(-operand.value), -operand.value.signum()),
+                Unary::Complement => Ok(ty.with_bits(!operand.bits())),
+            }
+        }
+        Token::Operator(op) => {
+            let left = evaluate_int(&ast.children[0], format)?;
+            let right = evaluate_int(&ast.children[1], format)?;
+            let (a, b) = (left.value, right.value);
+            let sign = a.signum() * b.signum();
+
+            match op {
+                Operator::Additive(Additive::Add) => format.fit(Some(a + b), 0),
+                Operator::Additive(Additive::Subtract) => format.fit(Some(a - b), 0),
+                Operator::Multiplicative(Multiplicative::Multiply) => {
+                    // Two 64-bit values can overflow even an i128, in which case wrapping has to be
+                    // done by the i128 itself.
+                    match (a.checked_mul(b), format.overflow) {
+                        (None, Overflow::Wrap) => Ok(ty.with_bits(a.wrapping_mul(b) as u64)),
+                        (result, _) => format.fit(result, sign),
+                    }
+                }
+                Operator::Multiplicative(Multiplicative::Divide) => match b {
+                    0 => Err(EvalError::DivisionByZero),
+                    _ => format.fit(Some(a / b), sign),
+                },
+                // Unlike division, the remainder rounds down, so it has the sign of the divisor.
+                Operator::Multiplicative(Multiplicative::Modulo) => match b {
+                    0 => Err(EvalError::DivisionByZero),
+                    _ => format.fit(Some((a % b + b) % b), b.signum()),
+                },
+                Operator::Exponential => power(left, right, format),
+                Operator::Shift(shift) => {
+                    let n = match b {
+                        n if n < 0 => return Err(EvalError::NegativeShift),
+                        n => n.min(ty.bits as i128) as u32,
+                    };
+
+                    match shift {
+                        Shift::Left => Ok(ty.with_bits(left.bits().checked_shl(n).unwrap_or(0))),
+                        // Shifting a signed value keeps its sign, like an arithmetic shift.
+                        Shift::Right => Ok(Int {
+                            value: a >> n,
+                            int_type: ty,
+                        }),
+                    }
+                }
+                Operator::BitAnd => Ok(ty.with_bits(left.bits() & right.bits())),
+                Operator::BitOr => Ok(ty.with_bits(left.bits() | right.bits())),
+                Operator::BitXor => Ok(ty.with_bits(left.bits() ^ right.bits())),
+                Operator::ElementWise(op) => {
+                    let symbol = match op {
+                        ElementWise::Multiply => ".*",
+                        ElementWise::Divide => "./",
+                        ElementWise::Power => ".^",
+                    };
+                    Err(EvalError::NotInProgrammerMode(symbol.to_string()))
+                }
+                Operator::Range => Err(EvalError::NotInProgrammerMode("..".to_string())),
+                Operator::PlusMinus => Err(EvalError::NotInProgrammerMode("±".to_string())),
+                Operator::Unary(_) => unreachable!("Unary operators are handled above"),
+            }
+        }
+        _ => unreachable!("Invalid AST"),
+    }
+}
+
+/// Raises an integer to a non-negative power by repeated squaring, applying the overflow
+/// behaviour at every step.
+fn power(base: Int, exponent: Int, format: &IntFormat) -> Result<Int, EvalError> {
+    if exponent.value < 0 {
+        return Err(EvalError::NegativeExponent);
+    }
+
+    let multiply = |a: Int, b: Int| -> Result<Int, EvalError> {
+        match (a.value.checked_mul(b.value), format.overflow) {
+            (None, Overflow::Wrap) => {
+                Ok(format.int_type.with_bits(a.value.wrapping_mul(b.value) as u64))
+            }
+            (result, _) => format.fit(result, a.value.signum() * b.value.signum()),
+        }
+    };
+
+    let mut result = format.fit(Some(1), 1)?;
+    let mut base = base;
+    let mut exponent = exponent.value;
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(result, base)?;
+        }
+
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(base, base)?;
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str, int_type: &str, overflow: Overflow) -> Result<Int, EvalError> {
+        let format = IntFormat {
+            int_type: IntType::parse(int_type).unwrap(),
+            overflow,
+        };
+
+        let tokens = parse_integer_expression(expression)?;
+        let ast = get_ast(&tokens)?;
+        evaluate_int(&ast, &format)
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let res = evaluate("0xF0 | 0x0F & 0x3C xor 1", "u8", Overflow::Wrap).unwrap();
+
+        assert_eq!(res.value, 0xFD);
+    }
+
+    #[test]
+    fn test_shifts_and_complement() {
+        let res = evaluate("~0 << 4 >> 2", "u8", Overflow::Wrap).unwrap();
+
+        assert_eq!(res.value, 0x3C);
+    }
+
+    #[test]
+    fn test_overflow_behaviour() {
+        let wrapped = evaluate("200 + 100", "u8", Overflow::Wrap).unwrap();
+        let saturated = evaluate("-100 - 100", "i8", Overflow::Saturate).unwrap();
+        let trapped = evaluate("2^64", "u64", Overflow::Trap);
+
+        assert_eq!(wrapped.value, 44);
+        assert_eq!(saturated.value, -128);
+        assert_eq!(trapped, Err(EvalError::Overflow));
+    }
+
+    #[test]
+    fn test_64_bit_literals() {
+        let exact = evaluate("9007199254740993", "u64", Overflow::Trap).unwrap();
+        let largest = evaluate("18446744073709551615", "u64", Overflow::Trap).unwrap();
+        let signed = evaluate("9223372036854775807", "i64", Overflow::Trap).unwrap();
+
+        assert_eq!(exact.value, 9007199254740993);
+        assert_eq!(largest.value, u64::MAX as i128);
+        assert_eq!(signed.value, i64::MAX as i128);
+        assert_eq!(evaluate("18446744073709551616", "u64", Overflow::Trap), Err(EvalError::Overflow));
+        assert_eq!(evaluate("9223372036854775808", "i64", Overflow::Trap), Err(EvalError::Overflow));
+    }
+
+    #[test]
+    fn test_bit_counting() {
+        let res = evaluate("popcount(0xF0) + clz(1) + ctz(0b1000)", "u16", Overflow::Wrap).unwrap();
+
+        assert_eq!(res.value, 4 + 15 + 3);
+    }
+
+    #[test]
+    fn test_rotations() {
+        let left = evaluate("rotl(0x81, 1)", "u8", Overflow::Wrap).unwrap();
+        let right = evaluate("rotr(1, 1)", "u32", Overflow::Wrap).unwrap();
+
+        assert_eq!(left.value, 0x03);
+        assert_eq!(right.value, 0x8000_0000);
+    }
+
+    #[test]
+    fn test_display() {
+        let res = evaluate("-1", "i8", Overflow::Wrap).unwrap();
+
+        assert_eq!(res.to_string(), "-1 (i8) = 0xFF = 0b11111111");
+    }
+}
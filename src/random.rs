@@ -0,0 +1,165 @@
+use std::f64::consts::PI;
+
+use crate::errors::EvalError;
+
+/// The functions that draw a random number, so evaluating them twice gives different values.
+pub const RANDOM_FUNCTIONS: [&str; 3] = ["rand", "randint", "randn"];
+
+/// The seed of generators that weren't given one.
+const DEFAULT_SEED: u64 = 0x5eed;
+
+/// A xoshiro256** pseudorandom number generator. The same seed always gives the same numbers, so
+/// results can be reproduced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Random {
+    state: [u64; 4],
+}
+
+impl Random {
+    /// Creates a generator from a seed. The seed is spread over the state with SplitMix64, since
+    /// xoshiro doesn't work with a state that's mostly zeros.
+    pub fn new(seed: u64) -> Random {
+        let mut x = seed;
+        let mut split_mix = || {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        Random {
+            state: [split_mix(), split_mix(), split_mix(), split_mix()],
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// A number between 0 and 1, which can be 0 but not 1.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * 2f64.powi(-53)
+    }
+
+    /// An integer between `a` and `b`, both included, where every integer is equally likely.
+    pub fn integer(&mut self, a: i64, b: i64) -> i64 {
+        let range = b.abs_diff(a) + 1;
+
+        // Numbers from the incomplete block at the top would make the low integers more likely,
+        // so they're drawn again.
+        let limit = u64::MAX - u64::MAX % range;
+        loop {
+            let x = self.next_u64();
+            if x < limit {
+                return a + (x % range) as i64;
+            }
+        }
+    }
+
+    /// A normally distributed number, using the Box-Muller transform.
+    pub fn normal(&mut self, mu: f64, sigma: f64) -> f64 {
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+        mu + sigma * (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::new(DEFAULT_SEED)
+    }
+}
+
+/// Draws a number with one of the [`RANDOM_FUNCTIONS`], checking its parameters first.
+pub fn evaluate(name: &str, params: &[f64], random: &mut Random) -> Result<f64, EvalError> {
+    match name {
+        "rand" => Ok(random.uniform()),
+        "randint" => {
+            let (a, b) = (params[0], params[1]);
+            for n in [a, b] {
+                if n.fract() != 0.0 || n.abs() >= 2f64.powi(53) {
+                    return Err(EvalError::NotAnInteger(n));
+                }
+            }
+            if a > b {
+                return Err(EvalError::InvalidArgument(format!(
+                    "The lower end of `randint` has to be at most the upper end, but {a} is above {b}"
+                )));
+            }
+            Ok(random.integer(a as i64, b as i64) as f64)
+        }
+        "randn" => {
+            let mu = params.first().copied().unwrap_or(0.0);
+            let sigma = params.get(1).copied().unwrap_or(1.0);
+            if sigma <= 0.0 {
+                return Err(EvalError::InvalidArgument(format!(
+                    "The standard deviation has to be positive, not {sigma}"
+                )));
+            }
+            Ok(random.normal(mu, sigma))
+        }
+        name => unreachable!("{name} is not a random function"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_reproducible() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+        let mut c = Random::new(43);
+
+        let first = a.next_u64();
+        assert_eq!(first, b.next_u64());
+        assert_ne!(first, c.next_u64());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut random = Random::default();
+
+        for _ in 0..1000 {
+            let x = random.uniform();
+            let n = random.integer(-2, 3);
+            assert!((0.0..1.0).contains(&x));
+            assert!((-2..=3).contains(&n));
+        }
+        assert_eq!(random.integer(7, 7), 7);
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut random = Random::new(1);
+        let samples = (0..100_000).map(|_| random.normal(10.0, 2.0)).collect::<Vec<_>>();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+
+        assert_close(mean, 10.0, 0.05);
+        assert_close(variance.sqrt(), 2.0, 0.05);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let mut random = Random::default();
+
+        assert_eq!(evaluate("randint", &[1.5, 3.0], &mut random), Err(EvalError::NotAnInteger(1.5)));
+        assert!(evaluate("randint", &[3.0, 1.0], &mut random).is_err());
+        assert!(evaluate("randn", &[0.0, 0.0], &mut random).is_err());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{ast::ASTNode, errors::EvalError, eval::*, symbolic};
+
+/// The most terms a finite sum or product adds up one by one.
+pub const MAX_TERMS: usize = 10_000_000;
+
+/// Infinite series are evaluated from partial sums of 2, 4, 8, ... terms, up to this many
+/// doublings.
+const MAX_DOUBLINGS: usize = 20;
+
+/// How many of the latest partial sums the extrapolation of an infinite series uses.
+const MAX_ORDER: usize = 8;
+
+/// How many of the last terms added in each doubling are checked to be shrinking.
+const TAIL: usize = 4;
+
+/// How close two extrapolations of an infinite series have to be, relative to their size.
+const TOLERANCE: f64 = 1e-10;
+
+/// Sums with more terms than this check whether the summand is a polynomial in the index, which
+/// can be summed without visiting every term.
+const POLYNOMIAL_THRESHOLD: usize = 1000;
+
+/// The highest degree of polynomial that is summed in closed form.
+const MAX_DEGREE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Series {
+    Sum,
+    Product,
+}
+
+impl Series {
+    /// Returns the kind of series `sum` or `prod` evaluates.
+    pub fn from_name(name: &str) -> Option<Series> {
+        match name {
+            "sum" => Some(Series::Sum),
+            "prod" => Some(Series::Product),
+            _ => None,
+        }
+    }
+
+    pub fn empty(self) -> f64 {
+        match self {
+            Series::Sum => 0.0,
+            Series::Product => 1.0,
+        }
+    }
+
+    pub fn combine(self, acc: f64, term: f64) -> f64 {
+        match self {
+            Series::Sum => acc + term,
+            Series::Product => acc * term,
+        }
+    }
+}
+
+/// Evaluates a call to `sum(i, a, b, expr)` or `prod(i, a, b, expr)`, which add or multiply the
+/// expression for every integer `i` from `a` to `b`. The upper limit can be `inf`. Sums nested in
+/// the expression share the limit on the number of terms with it.
+pub fn evaluate_series(ast: &ASTNode, scope: &Scope) -> Result<f64, EvalError> {
+    let name = ast.function_name().unwrap_or_default();
+    let series = Series::from_name(name).unwrap_or(Series::Sum);
+    let var = ast.children[0].variable_name(name)?;
+    let expr = &ast.children[3];
+    let (a, b) = evaluate_limits(ast, scope)?;
+
+    let mut inner = scope.clone();
+    let f = |i: f64| {
+        inner.count_term()?;
+        inner.set(&var, i);
+        evaluate_ast(expr, &inner)
+    };
+
+    scope.count_terms(|| evaluate(series, f, a, b, || polynomial_degree(expr, &var, scope)))
+}
+
+/// Evaluates the limits of the index of a `sum` or `prod` call, which have to be integers. The
+/// upper limit can also be infinite.
+pub fn evaluate_limits(ast: &ASTNode, scope: &Scope) -> Result<(f64, f64), EvalError> {
+    let a = evaluate_ast(&ast.children[1], scope)?;
+    let b = evaluate_ast(&ast.children[2], scope)?;
+
+    for limit in [a, b] {
+        if limit.fract() != 0.0 && limit != f64::INFINITY {
+            return Err(EvalError::NotAnInteger(limit));
+        }
+    }
+
+    Ok((a, b))
+}
+
+/// Adds or multiplies the terms from `a` to `b`. Long sums ask for the degree of the terms as a
+/// polynomial in the index, which lets them be summed without visiting every term.
+pub fn evaluate<F, D>(series: Series, mut f: F, a: f64, b: f64, degree: D) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+    D: FnOnce() -> Option<usize>,
+{
+    if b == f64::INFINITY {
+        return infinite(series, f, a);
+    }
+
+    if b < a {
+        return Ok(series.empty());
+    }
+
+    let count = b - a + 1.0;
+    if series == Series::Sum && count > POLYNOMIAL_THRESHOLD as f64 {
+        if let Some(degree) = degree() {
+            return polynomial_sum(f, a, count, degree);
+        }
+    }
+
+    if count > MAX_TERMS as f64 {
+        return Err(EvalError::TooManyTerms { limit: MAX_TERMS });
+    }
+
+    finite(series, &mut f, a, count as usize)
+}
+
+/// Adds or multiplies the first `count` terms starting from the index `a`.
+fn finite<F>(series: Series, f: &mut F, a: f64, count: usize) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let mut acc = series.empty();
+    for i in 0..count {
+        acc = series.combine(acc, f(a + i as f64)?);
+    }
+    Ok(acc)
+}
+
+/// Evaluates an infinite series by extrapolating its partial sums with Richardson extrapolation,
+/// which assumes the error after `n` terms shrinks like a power series in `1 / n`. That holds for
+/// most convergent series, including slow ones like the sum of `1 / k^2`. The terms also have to
+/// go to zero, or to one in a product, since partial sums like those of `(-1)^k` can look
+/// settled when they're only taken after an even number of terms.
+fn infinite<F>(series: Series, mut f: F, a: f64) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let first = f(a)?;
+    let mut partial = series.combine(series.empty(), first);
+    let mut terms = 1;
+
+    // How far the last few terms are from leaving the partial sum as it is.
+    let mut tail = (first - series.empty()).abs();
+
+    // Each row extrapolates the partial sums further than the one before it.
+    let mut previous: Vec<f64> = vec![];
+    let mut estimate = f64::NAN;
+
+    for _ in 0..MAX_DOUBLINGS {
+        let mut rest = series.empty();
+        let mut last = 0.0_f64;
+        for i in 0..terms {
+            let term = f(a + (terms + i) as f64)?;
+            rest = series.combine(rest, term);
+            if i + TAIL >= terms {
+                last = last.max((term - series.empty()).abs());
+            }
+        }
+        partial = series.combine(partial, rest);
+        terms *= 2;
+
+        let shrinking = last < tail || last == 0.0;
+        tail = last;
+
+        if !partial.is_finite() {
+            break;
+        }
+
+        let mut row = vec![partial];
+        for (m, &above) in previous.iter().take(MAX_ORDER - 1).enumerate() {
+            let current = row[m];
+            row.push(current + (current - above) / ((1 << (m + 1)) as f64 - 1.0));
+        }
+
+        // Partial sums that have settled while the terms haven't only look settled.
+        let next = *row.last().unwrap();
+        if (next - estimate).abs() <= TOLERANCE * next.abs() {
+            return match shrinking {
+                true => Ok(next),
+                false => Err(EvalError::NoConvergence { iterations: terms }),
+            };
+        }
+
+        estimate = next;
+        previous = row;
+    }
+
+    Err(EvalError::NoConvergence { iterations: terms })
+}
+
+/// Returns an upper bound on the degree of the expression if it's a polynomial in the variable,
+/// which it is when differentiating enough times leaves something that doesn't depend on it.
+/// Random numbers change from term to term, so an expression with them is never a polynomial.
+pub fn polynomial_degree(expr: &ASTNode, var: &str, scope: &Scope) -> Option<usize> {
+    if !expr.is_pure() {
+        return None;
+    }
+
+    let mut derivative = expr.clone();
+
+    for degree in 0..=MAX_DEGREE {
+        if !derivative.contains_variable(var) {
+            return Some(degree);
+        }
+        derivative = symbolic::differentiate(&derivative, var, scope).ok()?;
+    }
+
+    None
+}
+
+/// Sums a polynomial of the given degree over `count` indices starting from `a`. The sum of the
+/// first `n` terms is itself a polynomial in `n` of one degree higher, so it's found by
+/// interpolating the first few partial sums.
+fn polynomial_sum<F>(mut f: F, a: f64, count: f64, degree: usize) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let points = degree + 2;
+    let mut sums = vec![0.0];
+    for i in 1..points {
+        sums.push(sums[i - 1] + f(a + (i - 1) as f64)?);
+    }
+
+    // Lagrange interpolation through (0, sums[0]), (1, sums[1]), ...
+    let mut total = 0.0;
+    for (i, sum) in sums.iter().enumerate() {
+        let mut weight = 1.0;
+        for j in (0..points).filter(|&j| j != i) {
+            weight *= (count - j as f64) / (i as f64 - j as f64);
+        }
+        total += weight * sum;
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::get_ast, testing::assert_relative, tokenize::parse_expression};
+
+    #[test]
+    fn test_sum() {
+        let res = evaluate_expression("sum(i, 1, 100, i^2)").unwrap();
+
+        assert_eq!(res, 338350.0);
+    }
+
+    #[test]
+    fn test_prod() {
+        let res = evaluate_expression("prod(k, 2, 10, 1 - 1/k^2)").unwrap();
+
+        assert_relative(res, 0.55, 1e-9);
+    }
+
+    #[test]
+    fn test_empty_range() {
+        let res = evaluate_expression("sum(i, 5, 1, i) + prod(i, 5, 1, i)").unwrap();
+
+        assert_eq!(res, 1.0);
+    }
+
+    #[test]
+    fn test_nested() {
+        let res = evaluate_expression("sum(i, 1, 3, sum(j, 1, i, i j))").unwrap();
+
+        assert_eq!(res, 25.0);
+    }
+
+    #[test]
+    fn test_large_polynomial_sum() {
+        let res = evaluate_expression("sum(i, 1, 10^9, 3i^2 - i)").unwrap();
+
+        assert_relative(res, 1e27 + 1e18, 1e-9);
+    }
+
+    #[test]
+    fn test_large_random_sum() {
+        // Every evaluation starts from the same seed, so a sum that repeats its first term would be
+        // exactly 2000 times the first number drawn.
+        let res = evaluate_expression("sum(i, 1, 2000, rand())").unwrap();
+        let repeated = evaluate_expression("2000 rand()").unwrap();
+
+        assert_ne!(res, repeated);
+        assert!(res > 800.0 && res < 1200.0);
+    }
+
+    #[test]
+    fn test_infinite_sum() {
+        let basel = evaluate_expression("sum(k, 1, inf, 1 / k^2)").unwrap();
+        let geometric = evaluate_expression("sum(k, 0, inf, 0.5^k)").unwrap();
+        let alternating = evaluate_expression("sum(k, 1, inf, (-1)^(k + 1) / k)").unwrap();
+
+        assert_relative(basel, std::f64::consts::PI.powi(2) / 6.0, 1e-9);
+        assert_relative(geometric, 2.0, 1e-9);
+        assert_relative(alternating, 2f64.ln(), 1e-9);
+    }
+
+    #[test]
+    fn test_infinite_product() {
+        let res = evaluate_expression("prod(k, 2, inf, 1 - 1/k^2)").unwrap();
+
+        assert_relative(res, 0.5, 1e-9);
+    }
+
+    #[test]
+    fn test_divergent_sum() {
+        let res = evaluate_expression("sum(k, 1, inf, 1 / k)");
+
+        assert!(matches!(res, Err(EvalError::NoConvergence { .. })));
+    }
+
+    #[test]
+    fn test_oscillating_divergent_sum() {
+        let signs = evaluate_expression("sum(k, 1, inf, (-1)^k)");
+        let cosines = evaluate_expression("sum(k, 0, inf, cos(pi k))");
+
+        assert!(matches!(signs, Err(EvalError::NoConvergence { .. })));
+        assert!(matches!(cosines, Err(EvalError::NoConvergence { .. })));
+    }
+
+    #[test]
+    fn test_too_many_terms() {
+        let res = evaluate_expression("sum(i, 1, 10^12, sin(i))");
+
+        assert_eq!(res, Err(EvalError::TooManyTerms { limit: MAX_TERMS }));
+    }
+
+    #[test]
+    fn test_too_many_nested_terms() {
+        let ast = |expression: &str| get_ast(&parse_expression(expression).unwrap()).unwrap();
+        let (small, large) = (ast("sum(i, 1, 2, sum(j, 1, 2, j))"), ast("sum(i, 1, 5, sum(j, 1, 5, j))"));
+        let scope = Scope::new();
+
+        // Only 10 terms are left, which is enough for the 6 of the first sum but not for the 30 of
+        // the second one. Sums evaluated on their own count from zero again.
+        let res = scope.count_terms(|| {
+            for _ in 0..MAX_TERMS - 10 {
+                scope.count_term().unwrap();
+            }
+            (evaluate_series(&small, &scope), evaluate_series(&large, &scope))
+        });
+
+        assert_eq!(res, (Ok(6.0), Err(EvalError::TooManyTerms { limit: MAX_TERMS })));
+        assert_eq!(evaluate_series(&large, &scope), Ok(75.0));
+    }
+
+    #[test]
+    fn test_non_integer_limit() {
+        let res = evaluate_expression("sum(i, 0.5, 3, i)");
+
+        assert_eq!(res, Err(EvalError::NotAnInteger(0.5)));
+    }
+}
@@ -0,0 +1,277 @@
+use std::f64::consts::PI;
+
+use crate::{ast::ASTNode, complex::Complex, errors::EvalError, eval::*, value::Value};
+
+/// The windows `window(name, N)` can make.
+pub const WINDOWS: [&str; 4] = ["hann", "hamming", "blackman", "rectangular"];
+
+/// The most elements a window or list of frequencies can have.
+const MAX_LENGTH: usize = 10_000_000;
+
+/// Below this many multiplications, convolutions are done directly, which is exact for integers.
+const DIRECT_CONVOLUTION: usize = 4096;
+
+/// The discrete Fourier transform `X[k] = sum x[n] e^(-2 pi i k n / N)` of any length. Powers of
+/// two use the radix-2 Cooley-Tukey algorithm, and other lengths are turned into a convolution of
+/// a power of two length with Bluestein's algorithm.
+pub fn fft(x: &[Complex]) -> Vec<Complex> {
+    match x.len() {
+        0 => vec![],
+        n if n.is_power_of_two() => radix2(x, false),
+        _ => bluestein(x),
+    }
+}
+
+/// The inverse transform, which undoes [`fft`].
+pub fn ifft(x: &[Complex]) -> Vec<Complex> {
+    let n = x.len() as f64;
+    let conjugated = x.iter().map(|z| z.conj()).collect::<Vec<_>>();
+    fft(&conjugated).into_iter().map(|z| z.conj() / n).collect()
+}
+
+/// The transform of a power of two length, or its unscaled inverse.
+fn radix2(x: &[Complex], inverse: bool) -> Vec<Complex> {
+    let n = x.len();
+    let bits = n.trailing_zeros();
+    let mut data = vec![Complex::default(); n];
+    for (i, z) in x.iter().enumerate() {
+        let j = match bits {
+            0 => 0,
+            _ => i.reverse_bits() >> (usize::BITS - bits),
+        };
+        data[j] = *z;
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= n {
+        // The twiddle factors are calculated directly rather than by repeated multiplication,
+        // which would pile up rounding errors.
+        let twiddles = (0..size / 2)
+            .map(|k| Complex::polar(1.0, sign * 2.0 * PI * k as f64 / size as f64))
+            .collect::<Vec<_>>();
+        for start in (0..n).step_by(size) {
+            for (k, w) in twiddles.iter().enumerate() {
+                let a = data[start + k];
+                let b = data[start + k + size / 2] * *w;
+                data[start + k] = a + b;
+                data[start + k + size / 2] = a - b;
+            }
+        }
+        size *= 2;
+    }
+
+    data
+}
+
+/// The transform of any length, written as a convolution with a chirp, which is done with power of
+/// two transforms.
+fn bluestein(x: &[Complex]) -> Vec<Complex> {
+    let n = x.len();
+    let m = (2 * n - 1).next_power_of_two();
+
+    // e^(-pi i k^2 / n), where k^2 is reduced first so large k keep their precision.
+    let chirp = (0..n)
+        .map(|k| {
+            let k2 = (k as u128 * k as u128 % (2 * n as u128)) as f64;
+            Complex::polar(1.0, -PI * k2 / n as f64)
+        })
+        .collect::<Vec<_>>();
+
+    let mut a = vec![Complex::default(); m];
+    let mut b = vec![Complex::default(); m];
+    for k in 0..n {
+        a[k] = x[k] * chirp[k];
+        b[k] = chirp[k].conj();
+        if k > 0 {
+            b[m - k] = chirp[k].conj();
+        }
+    }
+
+    let (a, b) = (radix2(&a, false), radix2(&b, false));
+    let product = a.iter().zip(&b).map(|(a, b)| *a * *b).collect::<Vec<_>>();
+    let convolution = radix2(&product, true);
+
+    (0..n).map(|k| convolution[k] / m as f64 * chirp[k]).collect()
+}
+
+/// The power at each frequency of the transform, `|X[k]|^2 / N`. By Parseval's theorem the powers
+/// add up to the sum of the squares of the signal.
+pub fn power_spectrum(x: &[Complex]) -> Vec<f64> {
+    let n = x.len() as f64;
+    fft(x).into_iter().map(|z| z.abs().powi(2) / n).collect()
+}
+
+/// The full convolution of two signals, which has one element less than their lengths added up.
+/// Long signals are convolved by multiplying their transforms.
+pub fn convolve(a: &[Complex], b: &[Complex]) -> Vec<Complex> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+
+    let length = a.len() + b.len() - 1;
+    if a.len() * b.len() <= DIRECT_CONVOLUTION {
+        let mut result = vec![Complex::default(); length];
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                result[i + j] = result[i + j] + *x * *y;
+            }
+        }
+        return result;
+    }
+
+    let m = length.next_power_of_two();
+    let padded = |x: &[Complex]| {
+        let mut padded = x.to_vec();
+        padded.resize(m, Complex::default());
+        radix2(&padded, false)
+    };
+    let product = padded(a).iter().zip(&padded(b)).map(|(a, b)| *a * *b).collect::<Vec<_>>();
+    radix2(&product, true).into_iter().take(length).map(|z| z / m as f64).collect()
+}
+
+/// A symmetric window of the given length, which tapers a signal towards its ends before it's
+/// transformed.
+pub fn window(name: &str, n: usize) -> Vec<f64> {
+    if n == 1 {
+        return vec![1.0];
+    }
+
+    let cosines = |a: &[f64]| {
+        (0..n)
+            .map(|k| {
+                let phase = 2.0 * PI * k as f64 / (n - 1) as f64;
+                a[0] - a[1] * phase.cos() + a[2] * (2.0 * phase).cos()
+            })
+            .collect()
+    };
+
+    match name {
+        "hann" => cosines(&[0.5, 0.5, 0.0]),
+        "hamming" => cosines(&[0.54, 0.46, 0.0]),
+        "blackman" => cosines(&[0.42, 0.5, 0.08]),
+        _ => vec![1.0; n],
+    }
+}
+
+/// The frequency of each element of a transform of `n` samples taken at the sampling rate.
+pub fn frequencies(n: usize, rate: f64) -> Vec<f64> {
+    (0..n).map(|k| k as f64 * rate / n as f64).collect()
+}
+
+/// Checks that a length is a positive integer that isn't too large.
+pub fn length(n: f64) -> Result<usize, EvalError> {
+    if n.fract() != 0.0 {
+        return Err(EvalError::NotAnInteger(n));
+    }
+    if !(1.0..=MAX_LENGTH as f64).contains(&n) {
+        return Err(EvalError::InvalidArgument(format!(
+            "The length has to be between 1 and {MAX_LENGTH}, not {n}"
+        )));
+    }
+    Ok(n as usize)
+}
+
+/// Evaluates a call to `window(name, N)`, where the name is one of the [`WINDOWS`].
+pub fn evaluate_window(ast: &ASTNode, scope: &Scope) -> Result<Value, EvalError> {
+    let name = ast.children[0].variable_name("window")?;
+    if !WINDOWS.contains(&name.as_str()) {
+        return Err(EvalError::InvalidArgument(format!(
+            "There's no {name} window, the windows are {}",
+            WINDOWS.join(", ")
+        )));
+    }
+
+    let n = length(evaluate_ast(&ast.children[1], scope)?)?;
+    Ok(Value::List(window(&name, n).into_iter().map(Value::Number).collect()))
+}
+
+/// Turns the result of a transform into values. Imaginary parts that are only rounding errors,
+/// like the ones left by transforming back a real signal, are dropped.
+pub fn values(z: Vec<Complex>) -> Value {
+    let largest = z.iter().fold(0.0f64, |max, z| max.max(z.abs()));
+    Value::List(
+        z.into_iter()
+            .map(|z| match z.im.abs() <= 1e-12 * largest {
+                true => Value::Number(z.re),
+                false => Value::Complex(z),
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    /// The transform straight from its definition.
+    fn dft(x: &[Complex]) -> Vec<Complex> {
+        let n = x.len();
+        (0..n)
+            .map(|k| {
+                x.iter().enumerate().fold(Complex::default(), |sum, (j, z)| {
+                    sum + *z * Complex::polar(1.0, -2.0 * PI * (k * j % n) as f64 / n as f64)
+                })
+            })
+            .collect()
+    }
+
+    fn signal(n: usize) -> Vec<Complex> {
+        (0..n).map(|k| Complex::new((k as f64 * 0.7).sin() + 1.0, (k * k % 5) as f64)).collect()
+    }
+
+    fn assert_all_close(a: &[Complex], b: &[Complex]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert_close(a.re, b.re, 1e-9);
+            assert_close(a.im, b.im, 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_fft() {
+        for n in [1, 2, 8, 5, 12, 97] {
+            let x = signal(n);
+            assert_all_close(&fft(&x), &dft(&x));
+            assert_all_close(&ifft(&fft(&x)), &x);
+        }
+    }
+
+    #[test]
+    fn test_power_spectrum() {
+        let x = signal(30);
+        let energy = x.iter().map(|z| z.abs().powi(2)).sum::<f64>();
+
+        assert_close(power_spectrum(&x).iter().sum(), energy, 1e-9);
+    }
+
+    #[test]
+    fn test_convolve() {
+        let real = |x: &[f64]| x.iter().copied().map(Complex::real).collect::<Vec<_>>();
+        let direct = convolve(&real(&[1.0, 2.0, 3.0]), &real(&[0.0, 1.0, 0.5]));
+        let a = signal(100);
+        let b = signal(70);
+
+        assert_eq!(direct, real(&[0.0, 1.0, 2.5, 4.0, 1.5]));
+        assert_all_close(&convolve(&a, &b), &ifft(&{
+            let mut a = a.clone();
+            let mut b = b.clone();
+            a.resize(169, Complex::default());
+            b.resize(169, Complex::default());
+            fft(&a).iter().zip(fft(&b)).map(|(a, b)| *a * b).collect::<Vec<_>>()
+        }));
+    }
+
+    #[test]
+    fn test_windows_and_frequencies() {
+        let hann = window("hann", 5);
+
+        assert_eq!(hann[0], 0.0);
+        assert_close(hann[1], 0.5, 1e-15);
+        assert_eq!(hann[2], 1.0);
+        assert_eq!(window("rectangular", 3), vec![1.0; 3]);
+        assert_eq!(frequencies(4, 8.0), vec![0.0, 2.0, 4.0, 6.0]);
+        assert_eq!(length(2.5), Err(EvalError::NotAnInteger(2.5)));
+    }
+}
@@ -0,0 +1,551 @@
+use std::f64::consts::E;
+
+use crate::{ast::ASTNode, eval::*, tokenize::*};
+
+/// The most passes that are made over the tree. A pass rewrites every node once, so this bounds
+/// the work done on expressions that keep changing.
+const MAX_PASSES: usize = 16;
+
+/// Rewrites the expression into a simpler one with the same value. Constant subtrees are folded,
+/// like terms and powers of the same base are combined, and a few trigonometric and logarithmic
+/// identities are applied.
+pub fn simplify(ast: &ASTNode) -> ASTNode {
+    let mut current = ast.clone();
+
+    for _ in 0..MAX_PASSES {
+        let next = simplify_node(&current);
+        if next == current {
+            break;
+        }
+        current = next;
+    }
+
+    current
+}
+
+/// Simplifies the children of the node, then the node itself.
+fn simplify_node(ast: &ASTNode) -> ASTNode {
+    let node = ASTNode {
+        token: ast.token.clone(),
+        children: ast.children.iter().map(simplify_node).collect(),
+    };
+
+    if let Some(folded) = fold_constant(&node) {
+        return folded;
+    }
+
+    // Random numbers are kept as they are, since every call draws a different one.
+    if !node.is_pure() {
+        return node;
+    }
+
+    match node.token {
+        Token::Operator(Operator::Additive(_) | Operator::Unary(Unary::Negate)) => {
+            simplify_sum(&node)
+        }
+        Token::Operator(Operator::Multiplicative(_) | Operator::Exponential) => {
+            simplify_product(&node)
+        }
+        Token::Function(_) => simplify_function(node),
+        _ => node,
+    }
+}
+
+/// Replaces a subtree without variables by its value. This only happens when the value is an
+/// integer or when the subtree already contains decimals, so that exact values like `sqrt(2)` and
+/// `2/3` are kept as they are.
+fn fold_constant(node: &ASTNode) -> Option<ASTNode> {
+    if node.children.is_empty() || !node.free_variables().is_empty() || !node.is_pure() {
+        return None;
+    }
+
+    let value = evaluate_ast(node, &Scope::new()).ok()?;
+    if !value.is_finite() {
+        return None;
+    }
+
+    // Values like sin(pi) are off by a rounding error, which shouldn't stop them from folding.
+    // The error is relative to the result, except when it comes from sums that cancel out or
+    // from the arguments of functions, where it's relative to the operands. Products of small
+    // numbers, like the coefficients of long Taylor polynomials, aren't rounded to zero.
+    let rounded = value.round();
+    let scale = match node.token {
+        _ if rounded != 0.0 => rounded.abs(),
+        Token::Operator(Operator::Additive(_)) | Token::Function(_) => node
+            .children
+            .iter()
+            .map(|child| evaluate_ast(child, &Scope::new()).map(f64::abs))
+            .try_fold(0.0, |max: f64, operand| operand.map(|operand| max.max(operand)))
+            .ok()?,
+        _ => 0.0,
+    };
+    if (value - rounded).abs() <= 1e-12 * scale {
+        return Some(ASTNode::number(rounded + 0.0));
+    }
+
+    has_decimals(node).then(|| ASTNode::number(value))
+}
+
+fn has_decimals(node: &ASTNode) -> bool {
+    match node.token {
+        Token::Number(n) => n.fract() != 0.0,
+        _ => node.children.iter().any(has_decimals),
+    }
+}
+
+/// A numeric coefficient, kept as a fraction while both parts are integers so that `x / 3` doesn't
+/// turn into `0.333x`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Ratio {
+    num: f64,
+    den: f64,
+}
+
+impl Ratio {
+    const ONE: Ratio = Ratio { num: 1.0, den: 1.0 };
+
+    fn new(num: f64, den: f64) -> Ratio {
+        let exact = |n: f64| n.fract() == 0.0 && n.abs() < 2f64.powi(53);
+
+        if !exact(num) || !exact(den) || den == 0.0 {
+            return Ratio {
+                num: num / den,
+                den: 1.0,
+            };
+        }
+
+        let (mut a, mut b) = (num.abs(), den.abs());
+        while b != 0.0 {
+            (a, b) = (b, a % b);
+        }
+
+        let divisor = a.max(1.0) * den.signum();
+        Ratio {
+            num: num / divisor + 0.0,
+            den: den / divisor,
+        }
+    }
+
+    fn add(self, other: Ratio) -> Ratio {
+        Ratio::new(self.num * other.den + other.num * self.den, self.den * other.den)
+    }
+
+    fn mul(self, other: Ratio) -> Ratio {
+        Ratio::new(self.num * other.num, self.den * other.den)
+    }
+
+    fn powi(self, n: i32) -> Ratio {
+        if n < 0 {
+            Ratio::new(self.den.powi(-n), self.num.powi(-n))
+        } else {
+            Ratio::new(self.num.powi(n), self.den.powi(n))
+        }
+    }
+
+    fn is_finite(&self) -> bool {
+        self.num.is_finite() && self.den.is_finite()
+    }
+}
+
+/// A product split into its numeric coefficient and its other factors, each with an exponent.
+/// Factors with the same base are combined, and they're sorted so that equal products compare
+/// equal.
+#[derive(Debug, Clone, PartialEq)]
+struct Product {
+    coefficient: Ratio,
+    factors: Vec<(ASTNode, ASTNode)>,
+}
+
+impl Product {
+    fn from_node(node: &ASTNode) -> Product {
+        let mut product = Product {
+            coefficient: Ratio::ONE,
+            factors: vec![],
+        };
+
+        product.collect(node, 1);
+        product.factors.retain(|(_, exponent)| !is_number(exponent, 0.0));
+        product
+            .factors
+            .sort_by_cached_key(|(base, exponent)| (rank(base, exponent), base.to_string()));
+        product
+    }
+
+    /// Adds the node raised to the integer power to the product. Integer powers can be distributed
+    /// over products and nested powers, which is what lets `(2x^2)^3` become `8x^6`.
+    fn collect(&mut self, node: &ASTNode, power: i32) {
+        let child = |i: usize| &node.children[i];
+
+        match node.token {
+            Token::Number(n) => self.coefficient = self.coefficient.mul(Ratio::new(n, 1.0).powi(power)),
+            Token::Operator(Operator::Unary(Unary::Negate)) => {
+                self.coefficient = self.coefficient.mul(Ratio::new(-1.0, 1.0).powi(power));
+                self.collect(child(0), power);
+            }
+            Token::Operator(Operator::Multiplicative(Multiplicative::Multiply)) => {
+                self.collect(child(0), power);
+                self.collect(child(1), power);
+            }
+            Token::Operator(Operator::Multiplicative(Multiplicative::Divide)) => match power.checked_neg() {
+                Some(negated) => {
+                    self.collect(child(0), power);
+                    self.collect(child(1), negated);
+                }
+                None => self.insert(node.clone(), ASTNode::number(power as f64)),
+            },
+            Token::Operator(Operator::Exponential) => match integer_value(child(1)).and_then(|n| power.checked_mul(n)) {
+                Some(n) => self.collect(child(0), n),
+                // e^ln(x) = x
+                None if is_constant(child(0), E) && child(1).function_name() == Some("ln") => {
+                    self.collect(&child(1).children[0], power)
+                }
+                None => {
+                    let exponent = multiply(child(1).clone(), power);
+                    self.insert(child(0).clone(), exponent);
+                }
+            },
+            // sqrt(x)^2 = x
+            Token::Function(_) if node.function_name() == Some("sqrt") && power % 2 == 0 => {
+                self.collect(child(0), power / 2)
+            }
+            _ => self.insert(node.clone(), ASTNode::number(power as f64)),
+        }
+    }
+
+    /// Multiplies the product by the base raised to the exponent, adding to the exponent of an
+    /// equal base if there is one.
+    fn insert(&mut self, base: ASTNode, exponent: ASTNode) {
+        match self.factors.iter_mut().find(|(b, _)| *b == base) {
+            Some((_, existing)) => {
+                let sum = ASTNode::operator(
+                    Operator::Additive(Additive::Add),
+                    vec![existing.clone(), exponent],
+                );
+                *existing = simplify_node(&sum);
+            }
+            None => self.factors.push((base, exponent)),
+        }
+    }
+
+    /// Builds the product back into a tree, with factors that have negative exponents in the
+    /// denominator.
+    fn to_node(&self) -> ASTNode {
+        let Ratio { num, den } = self.coefficient;
+        if num == 0.0 {
+            return ASTNode::number(0.0);
+        }
+
+        let mut numerator = vec![];
+        let mut denominator = vec![];
+
+        for (base, exponent) in &self.factors {
+            match exponent.token {
+                Token::Number(n) if n < 0.0 => denominator.push(power_node(base, -n)),
+                Token::Number(n) => numerator.push(power_node(base, n)),
+                _ => numerator.push(ASTNode::operator(
+                    Operator::Exponential,
+                    vec![base.clone(), exponent.clone()],
+                )),
+            }
+        }
+
+        if num.abs() != 1.0 || numerator.is_empty() {
+            numerator.insert(0, ASTNode::number(num.abs()));
+        }
+        if den != 1.0 {
+            denominator.insert(0, ASTNode::number(den));
+        }
+
+        let mut node = multiply_all(numerator);
+        if !denominator.is_empty() {
+            node = ASTNode::operator(
+                Operator::Multiplicative(Multiplicative::Divide),
+                vec![node, multiply_all(denominator)],
+            );
+        }
+
+        if num < 0.0 {
+            node = ASTNode::operator(Operator::Unary(Unary::Negate), vec![node]);
+        }
+
+        node
+    }
+}
+
+/// The order factors are sorted in: constants, then variables, then function calls, then
+/// everything else, like `e^x`.
+fn rank(base: &ASTNode, exponent: &ASTNode) -> u8 {
+    match (&base.token, &exponent.token) {
+        (Token::Constant(_), Token::Number(_)) => 0,
+        (Token::Variable(_), _) => 1,
+        (Token::Function(_), _) => 2,
+        _ => 3,
+    }
+}
+
+fn power_node(base: &ASTNode, exponent: f64) -> ASTNode {
+    match exponent {
+        1.0 => base.clone(),
+        _ => ASTNode::operator(
+            Operator::Exponential,
+            vec![base.clone(), ASTNode::number(exponent)],
+        ),
+    }
+}
+
+fn multiply_all(factors: Vec<ASTNode>) -> ASTNode {
+    factors
+        .into_iter()
+        .reduce(|acc, factor| {
+            ASTNode::operator(
+                Operator::Multiplicative(Multiplicative::Multiply),
+                vec![acc, factor],
+            )
+        })
+        .unwrap_or_else(|| ASTNode::number(1.0))
+}
+
+fn multiply(node: ASTNode, n: i32) -> ASTNode {
+    match n {
+        1 => node,
+        _ => simplify_node(&ASTNode::operator(
+            Operator::Multiplicative(Multiplicative::Multiply),
+            vec![ASTNode::number(n as f64), node],
+        )),
+    }
+}
+
+fn simplify_product(node: &ASTNode) -> ASTNode {
+    let product = Product::from_node(node);
+
+    // Dividing by zero isn't something to simplify away.
+    match product.coefficient.is_finite() {
+        true => product.to_node(),
+        false => node.clone(),
+    }
+}
+
+/// Collects the terms of a sum, combining terms that only differ in their coefficient.
+fn collect_terms(node: &ASTNode, sign: f64, terms: &mut Vec<Product>) {
+    let child = |i: usize| &node.children[i];
+
+    match node.token {
+        Token::Operator(Operator::Additive(Additive::Add)) => {
+            collect_terms(child(0), sign, terms);
+            collect_terms(child(1), sign, terms);
+        }
+        Token::Operator(Operator::Additive(Additive::Subtract)) => {
+            collect_terms(child(0), sign, terms);
+            collect_terms(child(1), -sign, terms);
+        }
+        Token::Operator(Operator::Unary(Unary::Negate)) => collect_terms(child(0), -sign, terms),
+        _ => {
+            let mut term = Product::from_node(node);
+            term.coefficient = term.coefficient.mul(Ratio::new(sign, 1.0));
+
+            match terms.iter_mut().find(|t| t.factors == term.factors) {
+                Some(existing) => existing.coefficient = existing.coefficient.add(term.coefficient),
+                None => terms.push(term),
+            }
+        }
+    }
+}
+
+fn simplify_sum(node: &ASTNode) -> ASTNode {
+    let mut terms = vec![];
+    collect_terms(node, 1.0, &mut terms);
+
+    if terms.iter().any(|t| !t.coefficient.is_finite()) {
+        return node.clone();
+    }
+
+    apply_pythagorean_identity(&mut terms);
+    terms.retain(|t| t.coefficient.num != 0.0);
+
+    // The constant term goes last, like in `x^2 + 1`, but a positive term is put first if there is
+    // one so that the sum doesn't start with a minus.
+    terms.sort_by_key(|t| t.factors.is_empty());
+    if let Some(i) = terms.iter().position(|t| t.coefficient.num > 0.0) {
+        let first = terms.remove(i);
+        terms.insert(0, first);
+    }
+
+    let mut sum: Option<ASTNode> = None;
+    for term in terms {
+        let negative = term.coefficient.num < 0.0;
+        let mut magnitude = term;
+        magnitude.coefficient.num = magnitude.coefficient.num.abs();
+        let magnitude = magnitude.to_node();
+
+        sum = Some(match (sum, negative) {
+            (None, false) => magnitude,
+            (None, true) => ASTNode::operator(Operator::Unary(Unary::Negate), vec![magnitude]),
+            (Some(acc), false) => {
+                ASTNode::operator(Operator::Additive(Additive::Add), vec![acc, magnitude])
+            }
+            (Some(acc), true) => {
+                ASTNode::operator(Operator::Additive(Additive::Subtract), vec![acc, magnitude])
+            }
+        });
+    }
+
+    sum.unwrap_or_else(|| ASTNode::number(0.0))
+}
+
+/// Replaces `c sin(x)^2 + c cos(x)^2` with `c`.
+fn apply_pythagorean_identity(terms: &mut Vec<Product>) {
+    let squared = |term: &Product, name: &str| match &term.factors[..] {
+        [(base, exponent)] if base.function_name() == Some(name) && is_number(exponent, 2.0) => {
+            Some(base.children[0].clone())
+        }
+        _ => None,
+    };
+
+    while let Some((i, j)) = terms.iter().enumerate().find_map(|(i, sin)| {
+        let u = squared(sin, "sin")?;
+        let j = terms.iter().position(|cos| {
+            cos.coefficient == sin.coefficient && squared(cos, "cos").as_ref() == Some(&u)
+        })?;
+        Some((i, j))
+    }) {
+        let coefficient = terms[i].coefficient;
+        terms.remove(i.max(j));
+        terms.remove(i.min(j));
+
+        match terms.iter_mut().find(|t| t.factors.is_empty()) {
+            Some(constant) => constant.coefficient = constant.coefficient.add(coefficient),
+            None => terms.push(Product {
+                coefficient,
+                factors: vec![],
+            }),
+        }
+    }
+}
+
+fn simplify_function(node: ASTNode) -> ASTNode {
+    let name = node.function_name().unwrap_or_default();
+    let arg = &node.children[0];
+    let inner = arg.function_name();
+
+    let negated = match arg.token {
+        Token::Operator(Operator::Unary(Unary::Negate)) => Some(&arg.children[0]),
+        _ => None,
+    };
+
+    match (name, inner, negated) {
+        // Odd functions: f(-x) = -f(x)
+        ("sin" | "tan" | "arcsin" | "arctan", _, Some(u)) => ASTNode::operator(
+            Operator::Unary(Unary::Negate),
+            vec![ASTNode::function(name, vec![u.clone()])],
+        ),
+        // Even functions: f(-x) = f(x)
+        ("cos", _, Some(u)) => ASTNode::function(name, vec![u.clone()]),
+        // Inverse functions cancel out: sin(arcsin(x)) = x
+        ("sin", Some("arcsin"), _) | ("cos", Some("arccos"), _) | ("tan", Some("arctan"), _) => {
+            arg.children[0].clone()
+        }
+        // ln(e^x) = x
+        ("ln", _, _) if power_base(arg).is_some_and(|b| is_constant(b, E)) => {
+            arg.children[1].clone()
+        }
+        ("log", _, _) => {
+            let (base, value) = (&node.children[0], &node.children[1]);
+
+            if base == value {
+                ASTNode::number(1.0)
+            } else if power_base(value) == Some(base) {
+                // log(b, b^x) = x
+                value.children[1].clone()
+            } else {
+                node
+            }
+        }
+        _ => node,
+    }
+}
+
+fn power_base(node: &ASTNode) -> Option<&ASTNode> {
+    match node.token {
+        Token::Operator(Operator::Exponential) => Some(&node.children[0]),
+        _ => None,
+    }
+}
+
+fn is_number(node: &ASTNode, value: f64) -> bool {
+    matches!(node.token, Token::Number(n) if n == value)
+}
+
+fn is_constant(node: &ASTNode, value: f64) -> bool {
+    matches!(node.token, Token::Constant(c) if c == value)
+}
+
+fn integer_value(node: &ASTNode) -> Option<i32> {
+    match node.token {
+        Token::Number(n) if n.fract() == 0.0 && n.abs() <= i32::MAX as f64 => Some(n as i32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::get_ast;
+
+    fn simplified(expression: &str) -> String {
+        let ast = get_ast(&parse_expression(expression).unwrap()).unwrap();
+        simplify(&ast).to_string()
+    }
+
+    #[test]
+    fn test_identity_elements() {
+        assert_eq!(simplified("x * 1 + 0"), "x");
+        assert_eq!(simplified("x^1 - 0 * y"), "x");
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(simplified("2 * 3 + x - 4 / 2"), "x + 4");
+        assert_eq!(simplified("sin(pi) + 6 / 4"), "3 / 2");
+        assert_eq!(simplified("0.1 + 0.2 - 0.3"), "0");
+    }
+
+    #[test]
+    fn test_tiny_constants() {
+        assert_eq!(simplified("x * (1 / 10000000000000)"), "x / 10000000000000");
+        assert_eq!(simplified("x * 0.0000000000001"), "0.0000000000001x");
+    }
+
+    #[test]
+    fn test_like_terms() {
+        assert_eq!(simplified("2x + 3x - y + 2y"), "5x + y");
+        assert_eq!(simplified("x / 2 + x / 3"), "5x / 6");
+        assert_eq!(simplified("x - x"), "0");
+    }
+
+    #[test]
+    fn test_random_numbers() {
+        assert_eq!(simplified("rand() - rand()"), "rand() - rand()");
+        assert_eq!(simplified("randint(1, 6) + 2 * 3"), "randint(1, 6) + 6");
+    }
+
+    #[test]
+    fn test_combine_powers() {
+        assert_eq!(simplified("x^2 * x^3 / x"), "x^4");
+        assert_eq!(simplified("(2x^2)^3"), "8x^6");
+        assert_eq!(simplified("x * y * x"), "x^2 * y");
+        assert_eq!(simplified("(x^2)^2000000000"), "x^4000000000");
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_eq!(simplified("3sin(x)^2 + 3cos(x)^2 + 1"), "4");
+        assert_eq!(simplified("ln(e^(2x)) + log(2, 2^y)"), "2x + y");
+        assert_eq!(simplified("sin(-x) + cos(-x)"), "cos(x) - sin(x)");
+        assert_eq!(simplified("sqrt(x)^2 + e^(ln(y))"), "x + y");
+    }
+
+    #[test]
+    fn test_stops_on_division_by_zero() {
+        assert_eq!(simplified("x / 0"), "x / 0");
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{ast::ASTNode, errors::EvalError, eval::*, symbolic};
+
+/// The most steps either method takes before giving up.
+const MAX_ITERATIONS: usize = 100;
+
+/// How close two estimates of the root have to be, relative to its size when it's larger than one.
+const TOLERANCE: f64 = 1e-12;
+
+/// How many times the interval around a guess is widened while looking for a sign change when
+/// Newton's method fails.
+const MAX_EXPANSIONS: usize = 60;
+
+/// Evaluates a call to `solve(expr, x, guess)`, which finds a root with Newton's method starting
+/// from the guess, or `solve(expr, x, a, b)`, which finds a root between `a` and `b` with Brent's
+/// method.
+pub fn evaluate_solve(ast: &ASTNode, scope: &Scope) -> Result<f64, EvalError> {
+    let var = ast.children[1].variable_name("solve")?;
+    let expr = &ast.children[0];
+    let a = evaluate_ast(&ast.children[2], scope)?;
+    let b = match ast.children.get(3) {
+        Some(b) => Some(evaluate_ast(b, scope)?),
+        None => None,
+    };
+
+    let mut inner = scope.clone();
+    let mut f = |x: f64| {
+        inner.set(&var, x);
+        evaluate_ast(expr, &inner)
+    };
+
+    if let Some(b) = b {
+        return brent(f, a, b);
+    }
+
+    // Functions without a derivative, like `integrate`, fall back to a central difference.
+    let derivative = symbolic::differentiate(expr, &var, scope).ok();
+    let mut inner = scope.clone();
+    let df = |x: f64| match derivative {
+        Some(ref derivative) => {
+            inner.set(&var, x);
+            evaluate_ast(derivative, &inner)
+        }
+        None => {
+            let h = f64::EPSILON.cbrt() * x.abs().max(1.0);
+            inner.set(&var, x + h);
+            let above = evaluate_ast(expr, &inner)?;
+            inner.set(&var, x - h);
+            let below = evaluate_ast(expr, &inner)?;
+            Ok((above - below) / (2.0 * h))
+        }
+    };
+
+    // When Newton's method fails, it's the search for a sign change that failed last.
+    match newton(&mut f, df, a) {
+        Ok(root) => Ok(root),
+        Err(EvalError::NoConvergence { .. }) => {
+            let (a, b) = bracket(&mut f, a).ok_or(EvalError::NoSignChange)?;
+            brent(f, a, b)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Finds a root with Newton's method, starting from the guess. Fails when the derivative vanishes
+/// or the steps don't settle down.
+pub fn newton<F, D>(mut f: F, mut df: D, guess: f64) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+    D: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let mut x = guess;
+
+    for iterations in 0..MAX_ITERATIONS {
+        let y = f(x)?;
+        if y == 0.0 {
+            return Ok(x);
+        }
+
+        let step = y / df(x)?;
+        if !step.is_finite() {
+            return Err(EvalError::NoConvergence { iterations });
+        }
+
+        x -= step;
+        if step.abs() <= TOLERANCE * x.abs().max(1.0) {
+            return Ok(x);
+        }
+    }
+
+    Err(EvalError::NoConvergence {
+        iterations: MAX_ITERATIONS,
+    })
+}
+
+/// Looks for an interval on either side of the guess where the function changes sign, doubling
+/// its width each time.
+fn bracket<F>(f: &mut F, guess: f64) -> Option<(f64, f64)>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let y = f(guess).ok()?;
+    let mut width = 0.1 * guess.abs().max(1.0);
+
+    for _ in 0..MAX_EXPANSIONS {
+        for end in [guess + width, guess - width] {
+            if f(end).is_ok_and(|fe| fe.signum() != y.signum()) {
+                return Some((guess.min(end), guess.max(end)));
+            }
+        }
+        width *= 2.0;
+    }
+
+    None
+}
+
+/// Finds a root between `a` and `b` with Brent's method, which combines bisection with secant and
+/// inverse quadratic interpolation steps. The function has to change sign over the interval.
+pub fn brent<F>(mut f: F, a: f64, b: f64) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let (mut a, mut b) = (a, b);
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if fb == 0.0 {
+        return Ok(b);
+    }
+    if fa.signum() == fb.signum() {
+        return Err(EvalError::NoSignChange);
+    }
+
+    // `b` is the best estimate so far, and the root is always between `b` and `c`.
+    let (mut c, mut fc) = (a, fa);
+    let mut d = b - a;
+    let mut e = d;
+
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            (c, fc) = (a, fa);
+            d = b - a;
+            e = d;
+        }
+
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+
+        let tolerance = 2.0 * f64::EPSILON * b.abs() + TOLERANCE / 2.0;
+        let mid = (c - b) / 2.0;
+
+        if mid.abs() <= tolerance || fb == 0.0 {
+            return Ok(b);
+        }
+
+        if e.abs() >= tolerance && fa.abs() > fb.abs() {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                // Secant step
+                (2.0 * mid * s, 1.0 - s)
+            } else {
+                // Inverse quadratic interpolation
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * mid * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+
+            // The interpolation is only used when it stays well inside the interval and shrinks
+            // it faster than bisection would.
+            if 2.0 * p < (3.0 * mid * q - (tolerance * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = mid;
+                e = d;
+            }
+        } else {
+            d = mid;
+            e = d;
+        }
+
+        (a, fa) = (b, fb);
+        b += if d.abs() > tolerance {
+            d
+        } else {
+            tolerance.copysign(mid)
+        };
+        fb = f(b)?;
+    }
+
+    Err(EvalError::NoConvergence {
+        iterations: MAX_ITERATIONS,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_newton() {
+        let res = evaluate_expression("solve(x^3 - 2x - 5, x, 2)").unwrap();
+
+        assert_close(res, 2.0945514815423265, 1e-9);
+    }
+
+    #[test]
+    fn test_brent() {
+        let res = evaluate_expression("solve(cos(x) - x, x, 0, 1)").unwrap();
+
+        assert_close(res, 0.7390851332151607, 1e-9);
+    }
+
+    #[test]
+    fn test_newton_falls_back_to_bracket() {
+        // The derivative vanishes at the guess.
+        let res = evaluate_expression("solve(x^2 - 4, x, 0)").unwrap();
+
+        assert_close(res, 2.0, 1e-9);
+    }
+
+    #[test]
+    fn test_goal_seek() {
+        // The rate at which a loan of 10000 over 24 months has a payment of 500
+        let expression = "solve(10000 r / (1 - (1 + r)^-24) - 500, r, 0.01)";
+
+        let res = evaluate_expression(expression).unwrap();
+
+        assert_close(res, 0.015130843902374, 1e-9);
+    }
+
+    #[test]
+    fn test_no_sign_change() {
+        let res = evaluate_expression("solve(x^2 + 1, x, -1, 1)");
+
+        assert_eq!(res, Err(EvalError::NoSignChange));
+    }
+
+    #[test]
+    fn test_no_root_near_guess() {
+        let res = evaluate_expression("solve(x^2 + 1, x, 1)");
+        let constant = evaluate_expression("solve(1, x, 0)");
+
+        assert_eq!(res, Err(EvalError::NoSignChange));
+        assert_eq!(constant, Err(EvalError::NoSignChange));
+    }
+}
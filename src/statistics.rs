@@ -0,0 +1,132 @@
+use crate::errors::EvalError;
+
+fn non_empty(values: &[f64], function: &str) -> Result<(), EvalError> {
+    match values.is_empty() {
+        true => Err(EvalError::InvalidArgument(format!(
+            "`{function}` needs at least one value"
+        ))),
+        false => Ok(()),
+    }
+}
+
+/// Returns the values in increasing order.
+pub fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+pub fn mean(values: &[f64]) -> Result<f64, EvalError> {
+    non_empty(values, "mean")?;
+    Ok(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+pub fn median(values: &[f64]) -> Result<f64, EvalError> {
+    percentile(values, 50.0)
+}
+
+/// The most common value. When several values are equally common, the smallest one is used.
+pub fn mode(values: &[f64]) -> Result<f64, EvalError> {
+    non_empty(values, "mode")?;
+
+    let sorted = sorted(values);
+    let mut best = (sorted[0], 0);
+    let mut run = (sorted[0], 0);
+    for &value in &sorted {
+        run = match value == run.0 {
+            true => (value, run.1 + 1),
+            false => (value, 1),
+        };
+        if run.1 > best.1 {
+            best = run;
+        }
+    }
+
+    Ok(best.0)
+}
+
+/// The sample variance, which divides by one less than the number of values like the `VAR`
+/// function of spreadsheets. The mean is subtracted first so that large values with a small
+/// spread don't lose their precision.
+pub fn variance(values: &[f64]) -> Result<f64, EvalError> {
+    if values.len() < 2 {
+        return Err(EvalError::InvalidArgument(
+            "The variance needs at least two values".to_string(),
+        ));
+    }
+
+    let mean = mean(values)?;
+    let squares = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>();
+    Ok(squares / (values.len() - 1) as f64)
+}
+
+/// The sample standard deviation.
+pub fn stdev(values: &[f64]) -> Result<f64, EvalError> {
+    variance(values).map(f64::sqrt)
+}
+
+/// The value below which the given percentage of the values fall, interpolating linearly between
+/// the closest two values like the `PERCENTILE` function of spreadsheets.
+pub fn percentile(values: &[f64], p: f64) -> Result<f64, EvalError> {
+    non_empty(values, "percentile")?;
+    if !(0.0..=100.0).contains(&p) {
+        return Err(EvalError::InvalidArgument(format!(
+            "The percentile has to be between 0 and 100, not {p}"
+        )));
+    }
+
+    let sorted = sorted(values);
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    Ok(sorted[below] + (rank - below as f64) * (sorted[above] - sorted[below]))
+}
+
+/// The running totals of the values.
+pub fn cumsum(values: &[f64]) -> Vec<f64> {
+    values
+        .iter()
+        .scan(0.0, |total, x| {
+            *total += x;
+            Some(*total)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    const VALUES: [f64; 8] = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0];
+
+    #[test]
+    fn test_averages() {
+        assert_eq!(mean(&VALUES), Ok(3.875));
+        assert_eq!(median(&VALUES), Ok(3.5));
+        assert_eq!(mode(&VALUES), Ok(1.0));
+        assert_eq!(mode(&[2.0, 3.0]), Ok(2.0));
+    }
+
+    #[test]
+    fn test_spread() {
+        let variance = variance(&VALUES).unwrap();
+
+        assert_close(variance, 7.553571428571429, 1e-12);
+        assert_eq!(stdev(&[1e9 + 1.0, 1e9 + 3.0]), Ok(2f64.sqrt()));
+        assert!(stdev(&[1.0]).is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&VALUES, 0.0), Ok(1.0));
+        assert_eq!(percentile(&VALUES, 100.0), Ok(9.0));
+        assert_eq!(percentile(&VALUES, 25.0), Ok(1.75));
+        assert!(percentile(&VALUES, 101.0).is_err());
+        assert!(percentile(&[], 50.0).is_err());
+    }
+
+    #[test]
+    fn test_cumsum() {
+        assert_eq!(cumsum(&[1.0, 2.0, 3.0]), vec![1.0, 3.0, 6.0]);
+    }
+}
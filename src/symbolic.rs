@@ -0,0 +1,385 @@
+use crate::{
+    ast::ASTNode,
+    errors::EvalError,
+    eval::{evaluate_ast, Scope},
+    polynomial::Polynomial,
+    simplify::simplify,
+    tokenize::*,
+};
+
+/// Functions that produce a new expression from their arguments instead of a number.
+pub const SYMBOLIC_FUNCTIONS: [&str; 5] = ["diff", "simplify", "taylor", "expand", "polygcd"];
+
+/// Returns whether a symbolic function is called anywhere in the tree.
+pub fn is_symbolic(ast: &ASTNode) -> bool {
+    SYMBOLIC_FUNCTIONS.iter().any(|name| ast.contains_function(name))
+}
+
+/// Replaces every symbolic function call in the tree with the expression it stands for. A `diff`
+/// call with a third argument becomes the derivative with that argument substituted for the
+/// variable, and `taylor`, `expand` and `polygcd` calls become the polynomial they stand for.
+pub fn expand(ast: &ASTNode, scope: &Scope) -> Result<ASTNode, EvalError> {
+    let children = ast
+        .children
+        .iter()
+        .map(|child| expand(child, scope))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match ast.function_name() {
+        Some("diff") => {
+            let var = children[1].variable_name("diff")?;
+            let derivative = differentiate(&children[0], &var, scope)?;
+
+            Ok(match children.get(2) {
+                Some(point) => simplify(&derivative.substitute(&var, point)),
+                None => derivative,
+            })
+        }
+        Some("simplify") => Ok(simplify(&children[0])),
+        Some("taylor") => {
+            let var = children[1].variable_name("taylor")?;
+            let order = evaluate_ast(&children[3], scope)?;
+            if order.fract() != 0.0 || order < 0.0 {
+                return Err(EvalError::NotAnInteger(order));
+            }
+
+            taylor(&children[0], &var, &children[2], order as usize, scope)
+        }
+        Some("expand") => {
+            let var = children[1].variable_name("expand")?;
+            Ok(Polynomial::from_ast(&children[0], &var, scope)?.to_ast(&var))
+        }
+        Some("polygcd") => {
+            let var = children[2].variable_name("polygcd")?;
+            let p = Polynomial::from_ast(&children[0], &var, scope)?;
+            let q = Polynomial::from_ast(&children[1], &var, scope)?;
+            Ok(p.gcd(&q)?.to_ast(&var))
+        }
+        _ => Ok(ASTNode {
+            token: ast.token.clone(),
+            children,
+        }),
+    }
+}
+
+/// Returns the simplified derivative of the expression with respect to the variable. The scope is
+/// used for the arguments of symbolic functions inside the expression, like the order of `taylor`.
+pub fn differentiate(ast: &ASTNode, var: &str, scope: &Scope) -> Result<ASTNode, EvalError> {
+    derive(ast, var, scope).map(|derivative| simplify(&derivative))
+}
+
+/// Returns the Taylor polynomial of the expression around the point, up to the given order:
+/// the sum of `f^(k)(a) / k! * (x - a)^k` for every `k` up to the order, in increasing powers.
+/// Expressions that are infinite or undefined at a numeric point, like `1/x` at zero, have none.
+pub fn taylor(ast: &ASTNode, var: &str, point: &ASTNode, order: usize, scope: &Scope) -> Result<ASTNode, EvalError> {
+    let offset = sub(ASTNode::variable(var), point.clone());
+    let mut derivative = ast.clone();
+    let mut factorial = 1.0;
+    let mut polynomial = ASTNode::number(0.0);
+
+    for k in 0..=order {
+        if k > 0 {
+            derivative = differentiate(&derivative, var, scope)?;
+            factorial *= k as f64;
+        }
+
+        let value = derivative.substitute(var, point);
+        if evaluate_ast(&value, scope).is_ok_and(|value| !value.is_finite()) {
+            return Err(EvalError::InvalidArgument(format!("{ast} has no Taylor series at {var} = {point}")));
+        }
+
+        let coefficient = div(value, ASTNode::number(factorial));
+        let term = simplify(&mul(coefficient, pow(offset.clone(), ASTNode::number(k as f64))));
+
+        // The terms are simplified one at a time so the sum keeps them in order.
+        polynomial = match term.token {
+            Token::Operator(Operator::Unary(Unary::Negate)) => sub(polynomial, term.children[0].clone()),
+            _ => add(polynomial, term),
+        };
+    }
+
+    Ok(polynomial)
+}
+
+/// Applies the differentiation rules to the tree. The result is only simplified as it's built.
+fn derive(ast: &ASTNode, var: &str, scope: &Scope) -> Result<ASTNode, EvalError> {
+    // Matrices and lists are differentiated element by element.
+    if let Token::Matrix { .. } | Token::List = ast.token {
+        return Ok(ASTNode {
+            token: ast.token.clone(),
+            children: ast.children.iter().map(|c| derive(c, var, scope)).collect::<Result<_, _>>()?,
+        });
+    }
+
+    // Anything that doesn't depend on the variable is a constant.
+    if !ast.contains_variable(var) {
+        return Ok(ASTNode::number(0.0));
+    }
+
+    let d = |i: usize| derive(&ast.children[i], var, scope);
+
+    match &ast.token {
+        Token::Variable(_) => Ok(ASTNode::number(1.0)),
+        Token::Operator(Operator::Unary(Unary::Negate)) => Ok(neg(d(0)?)),
+        Token::Operator(Operator::Additive(Additive::Add)) => Ok(add(d(0)?, d(1)?)),
+        Token::Operator(Operator::Additive(Additive::Subtract)) => Ok(sub(d(0)?, d(1)?)),
+        Token::Operator(Operator::Multiplicative(op)) => {
+            let (a, b) = (ast.children[0].clone(), ast.children[1].clone());
+
+            match op {
+                // Product rule: (ab)' = a'b + ab'
+                Multiplicative::Multiply => Ok(add(mul(d(0)?, b), mul(a, d(1)?))),
+                Multiplicative::Divide if !b.contains_variable(var) => Ok(div(d(0)?, b)),
+                // Quotient rule: (a/b)' = (a'b - ab') / b^2
+                Multiplicative::Divide => Ok(div(
+                    sub(mul(d(0)?, b.clone()), mul(a, d(1)?)),
+                    pow(b, ASTNode::number(2.0)),
+                )),
+                Multiplicative::Modulo => Err(EvalError::NotDifferentiable(ast.to_string())),
+            }
+        }
+        Token::Operator(Operator::Exponential) => {
+            let (a, b) = (ast.children[0].clone(), ast.children[1].clone());
+
+            if !b.contains_variable(var) {
+                // Power rule: (a^n)' = n a^(n - 1) a'
+                let power = pow(a, sub(b.clone(), ASTNode::number(1.0)));
+                Ok(mul(d(0)?, mul(b, power)))
+            } else if !a.contains_variable(var) {
+                // Exponential rule: (c^b)' = c^b ln(c) b'
+                Ok(mul(d(1)?, mul(ast.clone(), ln(a))))
+            } else {
+                // General rule: (a^b)' = a^b (b' ln(a) + b a' / a)
+                let inner = add(mul(d(1)?, ln(a.clone())), div(mul(b, d(0)?), a));
+                Ok(mul(ast.clone(), inner))
+            }
+        }
+        Token::Operator(_) => Err(EvalError::NotDifferentiable(ast.to_string())),
+        Token::Function((func, _)) => {
+            let u = || ast.children[0].clone();
+            let one = || ASTNode::number(1.0);
+            let square = |node: ASTNode| pow(node, ASTNode::number(2.0));
+
+            // The derivative of the function itself, which is multiplied by the derivative of its
+            // argument by the chain rule.
+            let outer = match func.name {
+                "sin" => ASTNode::function("cos", vec![u()]),
+                "cos" => neg(ASTNode::function("sin", vec![u()])),
+                "tan" => div(one(), square(ASTNode::function("cos", vec![u()]))),
+                "arcsin" => div(one(), sqrt(sub(one(), square(u())))),
+                "arccos" => neg(div(one(), sqrt(sub(one(), square(u()))))),
+                "arctan" => div(one(), add(one(), square(u()))),
+                "ln" => div(one(), u()),
+                "sqrt" => div(one(), mul(ASTNode::number(2.0), sqrt(u()))),
+                // log(b, x) = ln(x) / ln(b), which also handles a base that depends on the variable.
+                "log" => {
+                    let quotient = div(ln(ast.children[1].clone()), ln(ast.children[0].clone()));
+                    return derive(&quotient, var, scope);
+                }
+                name if SYMBOLIC_FUNCTIONS.contains(&name) => return derive(&expand(ast, scope)?, var, scope),
+                name => return Err(EvalError::NotDifferentiable(name.to_string())),
+            };
+
+            Ok(chain(outer, d(0)?))
+        }
+        // A function given to a variable, like a spline, has no expression to differentiate.
+        Token::Call((name, _)) => Err(EvalError::NotDifferentiable(name.clone())),
+        _ => unreachable!("Invalid AST"),
+    }
+}
+
+/// Multiplies the derivative of a function by the derivative of its argument, moving the argument
+/// derivative into the numerator when the function derivative is a fraction.
+fn chain(outer: ASTNode, inner: ASTNode) -> ASTNode {
+    match outer.token {
+        Token::Operator(Operator::Multiplicative(Multiplicative::Divide))
+            if number_value(&outer.children[0]) == Some(1.0) =>
+        {
+            div(inner, outer.children[1].clone())
+        }
+        _ => mul(inner, outer),
+    }
+}
+
+fn is_product(node: &ASTNode) -> bool {
+    matches!(
+        node.token,
+        Token::Operator(Operator::Multiplicative(Multiplicative::Multiply))
+    )
+}
+
+fn number_value(node: &ASTNode) -> Option<f64> {
+    match node.token {
+        Token::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+// The constructors below fold numbers and drop terms that don't change the result, which keeps
+// derivatives from filling up with `0 * x` and `x^1`.
+
+fn binary(op: Operator, a: ASTNode, b: ASTNode) -> ASTNode {
+    ASTNode::operator(op, vec![a, b])
+}
+
+fn neg(a: ASTNode) -> ASTNode {
+    match a.token {
+        Token::Number(n) => ASTNode::number(-n),
+        Token::Operator(Operator::Unary(Unary::Negate)) => a.children[0].clone(),
+        _ => ASTNode::operator(Operator::Unary(Unary::Negate), vec![a]),
+    }
+}
+
+fn add(a: ASTNode, b: ASTNode) -> ASTNode {
+    match (number_value(&a), number_value(&b)) {
+        (Some(x), Some(y)) => ASTNode::number(x + y),
+        (Some(0.0), _) => b,
+        (_, Some(0.0)) => a,
+        (_, Some(y)) if y < 0.0 => sub(a, ASTNode::number(-y)),
+        _ => binary(Operator::Additive(Additive::Add), a, b),
+    }
+}
+
+fn sub(a: ASTNode, b: ASTNode) -> ASTNode {
+    match (number_value(&a), number_value(&b)) {
+        (Some(x), Some(y)) => ASTNode::number(x - y),
+        (Some(0.0), _) => neg(b),
+        (_, Some(0.0)) => a,
+        _ if a == b => ASTNode::number(0.0),
+        _ => binary(Operator::Additive(Additive::Subtract), a, b),
+    }
+}
+
+fn mul(a: ASTNode, b: ASTNode) -> ASTNode {
+    match (number_value(&a), number_value(&b)) {
+        (Some(x), Some(y)) => ASTNode::number(x * y),
+        (Some(0.0), _) | (_, Some(0.0)) => ASTNode::number(0.0),
+        (Some(1.0), _) => b,
+        (_, Some(1.0)) => a,
+        (Some(-1.0), _) => neg(b),
+        // Numbers multiplying a product that starts with a number are combined, like `3 * 2x`.
+        (Some(x), _) if is_product(&b) && number_value(&b.children[0]).is_some() => {
+            let y = number_value(&b.children[0]).unwrap();
+            mul(ASTNode::number(x * y), b.children[1].clone())
+        }
+        // Numbers go in front so the product can be printed like `2x`.
+        (None, Some(_)) => mul(b, a),
+        _ => binary(Operator::Multiplicative(Multiplicative::Multiply), a, b),
+    }
+}
+
+fn div(a: ASTNode, b: ASTNode) -> ASTNode {
+    match (number_value(&a), number_value(&b)) {
+        (Some(x), Some(y)) if y != 0.0 => ASTNode::number(x / y),
+        (Some(0.0), _) => ASTNode::number(0.0),
+        (_, Some(1.0)) => a,
+        _ => binary(Operator::Multiplicative(Multiplicative::Divide), a, b),
+    }
+}
+
+fn pow(a: ASTNode, b: ASTNode) -> ASTNode {
+    match (number_value(&a), number_value(&b)) {
+        (Some(x), Some(y)) => ASTNode::number(x.powf(y)),
+        (_, Some(0.0)) => ASTNode::number(1.0),
+        (_, Some(1.0)) => a,
+        _ => binary(Operator::Exponential, a, b),
+    }
+}
+
+fn ln(a: ASTNode) -> ASTNode {
+    match a.token {
+        Token::Constant(c) if c == std::f64::consts::E => ASTNode::number(1.0),
+        _ => ASTNode::function("ln", vec![a]),
+    }
+}
+
+fn sqrt(a: ASTNode) -> ASTNode {
+    ASTNode::function("sqrt", vec![a])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::get_ast, eval::evaluate_expression};
+
+    fn derivative(expression: &str) -> String {
+        let ast = get_ast(&parse_expression(expression).unwrap()).unwrap();
+        differentiate(&ast, "x", &Scope::new()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_polynomial() {
+        assert_eq!(derivative("3x^2 + 2x - 5"), "6x + 2");
+    }
+
+    #[test]
+    fn test_chain_rule() {
+        assert_eq!(derivative("sin(x^2)"), "2x * cos(x^2)");
+        assert_eq!(derivative("ln(2x + 1)"), "2 / (2x + 1)");
+    }
+
+    #[test]
+    fn test_exponential() {
+        assert_eq!(derivative("e^(3x)"), "3e^(3x)");
+    }
+
+    #[test]
+    fn test_simplified_derivative() {
+        assert_eq!(derivative("x^x"), "x^x * (ln(x) + 1)");
+        assert_eq!(derivative("x^2 / x"), "1");
+    }
+
+    #[test]
+    fn test_evaluate_derivative() {
+        let res = evaluate_expression("diff(x^3 - 2x, x, 2)").unwrap();
+
+        assert_eq!(res, 10.0);
+    }
+
+    #[test]
+    fn test_second_derivative() {
+        let res = evaluate_expression("diff(diff(sin(x), x), x, pi / 2)").unwrap();
+
+        assert_eq!(res, -1.0);
+    }
+
+    #[test]
+    fn test_taylor() {
+        let taylor = |expression: &str| {
+            let ast = get_ast(&parse_expression(expression).unwrap()).unwrap();
+            expand(&ast, &Scope::new()).unwrap().to_string()
+        };
+
+        assert_eq!(taylor("taylor(e^x, x, 0, 3)"), "1 + x + x^2 / 2 + x^3 / 6");
+        assert_eq!(taylor("taylor(ln(x), x, 1, 2)"), "x - 1 - (x - 1)^2 / 2");
+        assert_eq!(taylor("taylor(x^2, x, 0, 5)"), "x^2");
+
+        // The coefficients of high orders are tiny, but they aren't zero.
+        let sine = taylor("taylor(sin(x), x, 0, 20)");
+        assert!(sine.contains("+ x^17 / 355687428096000"), "{sine}");
+        assert!(sine.ends_with("x^19"), "{sine}");
+    }
+
+    #[test]
+    fn test_scope() {
+        let mut scope = Scope::new();
+        scope.set("n", 3.0);
+        scope.set("a", 1.0);
+        let expanded = |expression: &str| {
+            let ast = get_ast(&parse_expression(expression).unwrap()).unwrap();
+            expand(&ast, &scope).map(|ast| ast.to_string())
+        };
+
+        assert_eq!(expanded("taylor(sin(x), x, 0, n)"), Ok("x - x^3 / 6".to_string()));
+        assert_eq!(expanded("expand((x + a)^2, x)"), Ok("x^2 + 2x + 1".to_string()));
+        assert!(matches!(expanded("taylor(1 / x, x, 0, 3)"), Err(EvalError::InvalidArgument(_))));
+        assert!(matches!(expanded("taylor(sqrt(x), x, 0, 1)"), Err(EvalError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_not_differentiable() {
+        let res = evaluate_expression("diff(popcount(x), x)");
+
+        assert_eq!(res, Err(EvalError::NotDifferentiable("popcount".to_string())));
+    }
+}
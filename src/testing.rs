@@ -0,0 +1,12 @@
+/// Asserts that a number is within the tolerance of the one it's expected to be.
+#[track_caller]
+pub fn assert_close(a: f64, b: f64, tolerance: f64) {
+    assert!((a - b).abs() <= tolerance, "{a} is not close to {b}");
+}
+
+/// Asserts that a number is within the tolerance of the one it's expected to be, relative to the
+/// size of the expected one.
+#[track_caller]
+pub fn assert_relative(a: f64, b: f64, tolerance: f64) {
+    assert!((a - b).abs() <= tolerance * b.abs(), "{a} is not close to {b}");
+}
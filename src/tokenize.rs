@@ -1,27 +1,70 @@
-use crate::{errors::EvalError, keywords::*};
+use crate::{
+    datetime::{self, Duration, Timestamp},
+    decimal::Decimal,
+    errors::EvalError,
+    keywords::*,
+};
 
 /// Enum representing a symbol/token in the expression
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Number(f64),
+    /// An integer literal written in hexadecimal, binary or octal, e.g. `0xFF`, or any integer
+    /// literal in programmer mode. These are kept as integers so that 64-bit values don't lose
+    /// precision before reaching programmer mode.
+    Integer(u64),
+    /// A literal parsed straight into a base 10 decimal in decimal mode, so that `0.1` is exactly
+    /// a tenth.
+    Decimal(Decimal),
+    /// A date or moment, written like `2026-10-17` or `2026-10-17T14:30`.
+    Timestamp(Timestamp),
+    /// A length of time, written like `3d 4h`, `90min` or as a time of day like `14:30`.
+    Duration(Duration),
     Constant(f64),
     Operator(Operator),
 
     Separator,
+    /// Separates the rows of a matrix, written as `;`.
+    RowSeparator,
 
     Function((Function, Vec<Vec<Token>>)),
     Keyword(String),
+    /// A keyword that isn't a function, constant or operator. Its value has to be given when the
+    /// expression is evaluated.
+    Variable(String),
+    /// A call to a function that was given to a variable, like `f(2.5)` after `f = spline(xs, ys)`.
+    /// It's only recognised when the variable holds a function, since `x(2 + 1)` is otherwise a
+    /// multiplication.
+    Call((String, Vec<Vec<Token>>)),
 
     /// Holds the tokens from an expression inside parentheses.
     InnerExpression(Vec<Token>),
+    /// Holds the tokens from a matrix literal inside square brackets.
+    InnerMatrix(Vec<Token>),
+    /// A matrix in the AST, with its elements stored row by row as the children of the node.
+    Matrix { rows: usize, columns: usize },
+    /// Holds the tokens from a list literal inside curly brackets.
+    InnerList(Vec<Token>),
+    /// A list in the AST, with its elements as the children of the node.
+    List,
 }
 
-/// Enum representing an arithmetic operator.
+/// Enum representing an arithmetic or bitwise operator.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Operator {
     Additive(Additive),
     Multiplicative(Multiplicative),
     Exponential,
+    Shift(Shift),
+    BitAnd,
+    BitOr,
+    BitXor,
+    Unary(Unary),
+    ElementWise(ElementWise),
+    /// The range `a..b`, which is the list of numbers from `a` to `b` in steps of one.
+    Range,
+    /// `a ± b`, a measured value `a` with the standard uncertainty `b`.
+    PlusMinus,
 }
 
 /// Enum representing the additive operators, add and subtract.
@@ -31,23 +74,74 @@ pub enum Additive {
     Subtract,
 }
 
-/// Enum representing the multiplicative operators, multiply and divide.
+/// Enum representing the multiplicative operators, multiply, divide and the remainder `mod`.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Multiplicative {
     Multiply,
     Divide,
+    Modulo,
+}
+
+/// Enum representing the element-wise operators `.*`, `./` and `.^`, which work on matrices one
+/// element at a time instead of following the rules of matrix algebra.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ElementWise {
+    Multiply,
+    Divide,
+    Power,
+}
+
+/// Enum representing the bit shift operators, `<<` and `>>`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Shift {
+    Left,
+    Right,
+}
+
+/// Enum representing the operators that take a single operand. `Complement` is produced by the
+/// tokenizer from `~`, while `Negate` is produced by the AST from a leading `-`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Unary {
+    Negate,
+    Complement,
+}
+
+/// What the number literals of an expression are parsed into.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Literals {
+    Float,
+    /// Base 10 decimals, for decimal and modular mode.
+    Decimal,
+    /// Integers wherever they fit in 64 bits, for programmer mode.
+    Integer,
 }
 
 pub fn parse_expression(expression: &str) -> Result<Vec<Token>, EvalError> {
-    let tokens_pass_1 = parse_tokens(expression)?;
+    let tokens_pass_1 = parse_tokens(expression, Literals::Float)?;
+    let tokens = parse_keywords(&tokens_pass_1)?;
+
+    Ok(tokens)
+}
+
+/// Like [`parse_expression`], but the literals become decimals instead of floats.
+pub fn parse_decimal_expression(expression: &str) -> Result<Vec<Token>, EvalError> {
+    let tokens_pass_1 = parse_tokens(expression, Literals::Decimal)?;
+    let tokens = parse_keywords(&tokens_pass_1)?;
+
+    Ok(tokens)
+}
+
+/// Like [`parse_expression`], but integer literals stay exact integers instead of becoming floats.
+pub fn parse_integer_expression(expression: &str) -> Result<Vec<Token>, EvalError> {
+    let tokens_pass_1 = parse_tokens(expression, Literals::Integer)?;
     let tokens = parse_keywords(&tokens_pass_1)?;
 
     Ok(tokens)
 }
 
 /// Parses the expression string into an an array of tokens, representing numbers, operators and
-/// expressions inside parentheses
-pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
+/// expressions inside parentheses. The number literals are parsed as `literals` says.
+pub fn parse_tokens(expression: &str, literals: Literals) -> Result<Vec<Token>, EvalError> {
     // A newline is appended at the end so that the end doesnt end abruptly, allowing numbers to be
     // properly parsed. There is usually a newline at the end of the input, but if there isn't, this
     // will make sure that the expression is properly parsed.
@@ -58,25 +152,77 @@ pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
     let mut expr_stack: Vec<Vec<Token>> = vec![Vec::new()];
     let mut top_of_stack = 0;
 
+    // The bracket that opened each scope above the bottom one, so that a matrix can't be closed
+    // by a parenthesis or the other way around.
+    let mut openers: Vec<char> = vec![];
+
     // Vec used to store digits for parsing numbers
     let mut num = String::new();
 
     // Vec used to store letters for parsing keywords like constants and functions
     let mut kword = String::new();
 
-    for c in expression.chars() {
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        // A zero followed by `x`, `b` or `o` starts a hexadecimal, binary or octal literal.
+        if num == "0" && matches!(c, 'x' | 'b' | 'o') {
+            num.push(c);
+            continue;
+        }
+
+        // Letters are digits in hexadecimal literals, so they're collected before keywords are.
+        if num.starts_with("0x") && c.is_ascii_hexdigit() {
+            num.push(c);
+            continue;
+        }
+
+        // Dashes, colons and `T`s that continue a date or time, like `2026-10-17T14:30`, are
+        // collected with the digits.
+        if !num.is_empty() && datetime::continues_literal(&num, c, &chars) {
+            num.push(c);
+            continue;
+        }
+
+        // Digits after the first letter of a keyword belong to it, like in `chi2cdf` or `x1`.
+        // Numbers followed by letters, like `2x`, are still split into a number and a keyword, and
+        // so are built-in names followed by digits, so that `e2` and `pi2` are still products.
+        if !kword.is_empty() && c.is_ascii_digit() && !is_builtin(&kword) {
+            kword.push(c);
+            continue;
+        }
+
         // If a number has been found, add the char to the num vec and continue to the next char.
         // Repeat until another symbol is found to get the entire number.
-        if let '0'..='9' | '.' = c {
+        // A dot followed by an operator or another dot starts an operator instead, like `.*` or
+        // the `..` of a range.
+        let operator = c == '.' && matches!(chars.peek(), Some('*' | '/' | '^' | '.'));
+        if let ('0'..='9' | '.', false) = (c, operator) {
             num.push(c);
             continue;
         }
 
-        // When something else is found, parse the digits into a float and clear the num vec.
+        // When something else is found, parse the digits into a number and clear the num vec.
+        // A number directly followed by a unit, like `90min`, is a duration, and the unit is
+        // skipped.
         if !num.is_empty() {
-            let n = num.parse::<f64>().unwrap();
-            expr_stack[top_of_stack].push(Token::Number(n));
+            let unit = match c.is_ascii_alphabetic() && !datetime::is_literal(&num) {
+                true => datetime::parse_unit(&num, c, &mut chars),
+                false => None,
+            };
+            let is_unit = unit.is_some();
+            let token = match unit {
+                Some(duration) => duration,
+                None => parse_number(&num, literals)?,
+            };
+            if let Some(token) = datetime::join_literals(expr_stack[top_of_stack].last_mut(), token) {
+                expr_stack[top_of_stack].push(token);
+            }
             num.clear();
+
+            if is_unit {
+                continue;
+            }
         }
 
         // If a letter is found, add it to the kword vec and continue to the next char.
@@ -108,16 +254,53 @@ pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
             #[rustfmt::skip]
             '^' => expr_stack[top_of_stack]
                 .push(Token::Operator(Operator::Exponential)),
+            #[rustfmt::skip]
+            '&' => expr_stack[top_of_stack]
+                .push(Token::Operator(Operator::BitAnd)),
+            #[rustfmt::skip]
+            '|' => expr_stack[top_of_stack]
+                .push(Token::Operator(Operator::BitOr)),
+            #[rustfmt::skip]
+            '±' => expr_stack[top_of_stack]
+                .push(Token::Operator(Operator::PlusMinus)),
+            #[rustfmt::skip]
+            '~' => expr_stack[top_of_stack]
+                .push(Token::Operator(Operator::Unary(Unary::Complement))),
+
+            // Shifts are written with two characters, so the next one has to match the first.
+            '<' | '>' => {
+                if chars.next_if_eq(&c).is_none() {
+                    return Err(EvalError::InvalidExpression(format!(
+                        "Expected `{c}{c}`"
+                    )));
+                }
+
+                let shift = if c == '<' { Shift::Left } else { Shift::Right };
+                expr_stack[top_of_stack].push(Token::Operator(Operator::Shift(shift)));
+            }
+
+            '.' => {
+                let op = match chars.next() {
+                    Some('*') => Operator::ElementWise(ElementWise::Multiply),
+                    Some('/') => Operator::ElementWise(ElementWise::Divide),
+                    Some('^') => Operator::ElementWise(ElementWise::Power),
+                    _ => Operator::Range,
+                };
+                expr_stack[top_of_stack].push(Token::Operator(op));
+            }
 
             #[rustfmt::skip]
-            ',' |
-            ';' => expr_stack[top_of_stack]
+            ',' => expr_stack[top_of_stack]
                 .push(Token::Separator),
+            #[rustfmt::skip]
+            ';' => expr_stack[top_of_stack]
+                .push(Token::RowSeparator),
 
-            // If an open parentheses is found, add a vec to the stack and make everything add to
-            // that stack vec instead.
-            '(' => {
+            // If an open parentheses or bracket is found, add a vec to the stack and make
+            // everything add to that stack vec instead.
+            '(' | '[' | '{' => {
                 expr_stack.push(Vec::new());
+                openers.push(c);
                 top_of_stack += 1;
             }
             // If a closing parentheses is found add a token containing the tokens inside the
@@ -126,15 +309,39 @@ pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
                 // If there was no opening parentheses, treat it as if there was one.
                 if top_of_stack == 0 {
                     expr_stack.insert(0, Vec::new());
+                    openers.push('(');
                     top_of_stack += 1;
                 }
 
+                if openers.pop() != Some('(') {
+                    return Err(EvalError::InvalidExpression(
+                        "Expected a closing bracket before `)`".to_string(),
+                    ));
+                }
+
                 // Add the scope at the top of the stack as a token in the lower scope.
                 if let Some(inner) = expr_stack.pop() {
                     top_of_stack -= 1;
                     expr_stack[top_of_stack].push(Token::InnerExpression(inner))
                 }
             }
+            // Matrices and lists have to be closed by a bracket, since a missing one can't be
+            // guessed.
+            ']' | '}' => {
+                let opener = if c == ']' { '[' } else { '{' };
+                if openers.pop() != Some(opener) {
+                    return Err(EvalError::InvalidExpression(format!(
+                        "`{c}` without a matching `{opener}`"
+                    )));
+                }
+
+                let inner = expr_stack.pop().unwrap();
+                top_of_stack -= 1;
+                expr_stack[top_of_stack].push(match c {
+                    ']' => Token::InnerMatrix(inner),
+                    _ => Token::InnerList(inner),
+                })
+            }
             _ => (),
         }
 
@@ -145,27 +352,47 @@ pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
                 &expr_stack[top_of_stack][top_len - 2],
                 &expr_stack[top_of_stack][top_len - 1],
             ) {
-                (Token::Number(_), Token::Number(_)) => {
+                (
+                    Token::Number(_)
+                    | Token::Integer(_)
+                    | Token::Decimal(_)
+                    | Token::Timestamp(_)
+                    | Token::Duration(_),
+                    Token::Number(_)
+                    | Token::Integer(_)
+                    | Token::Decimal(_)
+                    | Token::Timestamp(_)
+                    | Token::Duration(_),
+                ) => {
                     return Err(EvalError::InvalidExpression(
                         "Two numbers in a row".to_string(),
                     ))
                 }
+                // Minus and complement can also be unary, so they're allowed to follow operators
+                // and separators.
+                (
+                    Token::Operator(_) | Token::Separator | Token::RowSeparator,
+                    Token::Operator(op),
+                ) if is_unary(op) => (),
                 (Token::Operator(_), Token::Operator(_)) => {
                     return Err(EvalError::InvalidExpression(
                         "Two operators in a row".to_string(),
                     ))
                 }
-                (Token::Operator(_), Token::Separator) => {
+                (Token::Operator(_), Token::Separator | Token::RowSeparator) => {
                     return Err(EvalError::InvalidExpression(
                         "Operator followed by separator".to_string(),
                     ))
                 }
-                (Token::Separator, Token::Operator(_)) => {
+                (Token::Separator | Token::RowSeparator, Token::Operator(_)) => {
                     return Err(EvalError::InvalidExpression(
                         "Separator followed by operator".to_string(),
                     ))
                 }
-                (Token::Separator, Token::Separator) => {
+                (
+                    Token::Separator | Token::RowSeparator,
+                    Token::Separator | Token::RowSeparator,
+                ) => {
                     return Err(EvalError::InvalidExpression(
                         "Two separators in a row".to_string(),
                     ))
@@ -177,6 +404,12 @@ pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
 
     // If there was no closing parentheses, collapse all inner scopes until one scope is left.
     while top_of_stack > 0 {
+        match openers.pop() {
+            Some('[') => return Err(EvalError::InvalidExpression("Expected `]`".to_string())),
+            Some('{') => return Err(EvalError::InvalidExpression("Expected `}`".to_string())),
+            _ => (),
+        }
+
         let top_expr = expr_stack.pop().unwrap();
         top_of_stack -= 1;
 
@@ -187,17 +420,65 @@ pub fn parse_tokens(expression: &str) -> Result<Vec<Token>, EvalError> {
     Ok(expr_stack.pop().unwrap())
 }
 
+/// Parses the collected digits of a number. Literals prefixed with `0x`, `0b` or `0o` become
+/// integers, dates and times become timestamps and durations, and everything else becomes a float,
+/// or whatever else `literals` asks for.
+fn parse_number(num: &str, literals: Literals) -> Result<Token, EvalError> {
+    if datetime::is_literal(num) {
+        return datetime::parse_literal(num);
+    }
+
+    let radix = match num.get(..2) {
+        Some("0x") => 16,
+        Some("0b") => 2,
+        Some("0o") => 8,
+        _ if literals == Literals::Decimal => {
+            return Decimal::parse(num)
+                .map(Token::Decimal)
+                .ok_or_else(|| EvalError::InvalidExpression(format!("Invalid number: {num}")))
+        }
+        _ => {
+            // Integers too large for 64 bits are left as floats, which overflow like other results.
+            if let (Literals::Integer, Ok(n)) = (literals, num.parse::<u64>()) {
+                return Ok(Token::Integer(n));
+            }
+            return num
+                .parse::<f64>()
+                .map(Token::Number)
+                .map_err(|_| EvalError::InvalidExpression(format!("Invalid number: {num}")))
+        }
+    };
+
+    u64::from_str_radix(&num[2..], radix)
+        .map(Token::Integer)
+        .map_err(|_| EvalError::InvalidExpression(format!("Invalid number: {num}")))
+}
+
+/// Returns whether the name is a function, constant or operator without any definitions.
+fn is_builtin(name: &str) -> bool {
+    FUNCTIONS.contains_key(name) || CONSTANTS.contains_key(name) || OPERATOR_KEYWORDS.contains_key(name)
+}
+
+/// Returns whether the operator can be used with a single operand.
+pub fn is_unary(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Additive(Additive::Subtract) | Operator::Unary(_)
+    )
+}
+
 fn parse_keywords(expression: &[Token]) -> Result<Vec<Token>, EvalError> {
     let mut output = expression.to_vec();
 
-    let inner_expr = expression.iter().enumerate().filter_map(|(i, t)| match t {
-        Token::InnerExpression(expr) => Some((i, expr)),
-        _ => None,
-    });
-
-    for (i, inner) in inner_expr {
-        let expr = parse_keywords(inner)?;
-        output[i] = Token::InnerExpression(expr);
+    for (i, token) in expression.iter().enumerate() {
+        match token {
+            Token::InnerExpression(inner) => {
+                output[i] = Token::InnerExpression(parse_keywords(inner)?);
+            }
+            Token::InnerMatrix(inner) => output[i] = Token::InnerMatrix(parse_keywords(inner)?),
+            Token::InnerList(inner) => output[i] = Token::InnerList(parse_keywords(inner)?),
+            _ => (),
+        }
     }
 
 
@@ -209,6 +490,12 @@ fn parse_keywords(expression: &[Token]) -> Result<Vec<Token>, EvalError> {
     let mut offset = 0;
 
     for (i, kword) in keywords {
+        // Operators that are spelled out are handled before functions and constants.
+        if let Some(op) = OPERATOR_KEYWORDS.get(kword) {
+            output[i - offset] = Token::Operator(*op);
+            continue;
+        }
+
         let kword_type = (FUNCTIONS.get(kword), CONSTANTS.get(kword));
         match kword_type {
             (Some(f), None) => {
@@ -216,10 +503,15 @@ fn parse_keywords(expression: &[Token]) -> Result<Vec<Token>, EvalError> {
                     return Err(EvalError::NoFunctionArguments(kword.to_string()));
                 };
 
-                let args = expr
-                    .split(|t| matches!(t, Token::Separator))
-                    .map(|v| v.to_vec())
-                    .collect::<Vec<Vec<Token>>>();
+                // Arguments used to be separated by semicolons too, which still works. Empty
+                // parentheses, like in `rand()`, have no arguments.
+                let args = match expr.is_empty() {
+                    true => vec![],
+                    false => expr
+                        .split(|t| matches!(t, Token::Separator | Token::RowSeparator))
+                        .map(|v| v.to_vec())
+                        .collect::<Vec<Vec<Token>>>(),
+                };
 
                 output[i - offset] = Token::Function((*f, args));
                 output.remove(i - offset + 1);
@@ -230,7 +522,7 @@ fn parse_keywords(expression: &[Token]) -> Result<Vec<Token>, EvalError> {
             }
             (Some(_), Some(_)) => return Err(EvalError::ProgramIsStupid),
             (None, None) => {
-                return Err(EvalError::UnknownKeyword(kword.to_string()));
+                output[i - offset] = Token::Variable(kword.to_string());
             }
         }
     }
@@ -238,10 +530,58 @@ fn parse_keywords(expression: &[Token]) -> Result<Vec<Token>, EvalError> {
     Ok(output)
 }
 
+/// Turns the variables that are followed by parentheses into calls, when the variable holds a
+/// function. Whether it does is decided by `is_function`, since the tokens don't know the values
+/// of the variables.
+pub fn parse_calls(expression: &[Token], is_function: &dyn Fn(&str) -> bool) -> Vec<Token> {
+    let mut output: Vec<Token> = vec![];
+
+    for token in expression {
+        let token = match token {
+            Token::InnerExpression(inner) => Token::InnerExpression(parse_calls(inner, is_function)),
+            Token::InnerMatrix(inner) => Token::InnerMatrix(parse_calls(inner, is_function)),
+            Token::InnerList(inner) => Token::InnerList(parse_calls(inner, is_function)),
+            Token::Function((func, args)) => Token::Function((
+                *func,
+                args.iter().map(|arg| parse_calls(arg, is_function)).collect(),
+            )),
+            token => token.clone(),
+        };
+
+        if let (Token::InnerExpression(inner), Some(Token::Variable(name))) = (&token, output.last()) {
+            if is_function(name) {
+                let args = match inner.is_empty() {
+                    true => vec![],
+                    false => inner
+                        .split(|t| matches!(t, Token::Separator | Token::RowSeparator))
+                        .map(|v| v.to_vec())
+                        .collect(),
+                };
+                let call = Token::Call((name.clone(), args));
+                *output.last_mut().unwrap() = call;
+                continue;
+            }
+        }
+
+        output.push(token);
+    }
+
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_decimal_literals() {
+        let tokens = parse_decimal_expression("0.10 + 3").unwrap();
+
+        assert_eq!(tokens[0], Token::Decimal(Decimal::new(false, 10, -2)));
+        assert_eq!(tokens[2], Token::Decimal(Decimal::new(false, 3, 0)));
+        assert!(parse_decimal_expression("1.2.3").is_err());
+    }
+
     #[test]
     fn parse_numbers() {
         let expression_1 = "123 + 456";
@@ -398,6 +738,7 @@ mod tests {
                     Function {
                         name: "sin",
                         argument_count: 1,
+                        optional_arguments: 0,
                     },
                     vec![vec![Token::Number(123.0)]]
                 )),
@@ -406,6 +747,7 @@ mod tests {
                     Function {
                         name: "log",
                         argument_count: 2,
+                        optional_arguments: 0,
                     },
                     vec![vec![Token::Number(2.0)], vec![Token::Number(16.0)]]
                 )),
@@ -426,4 +768,78 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn parse_variables() {
+        let expression = "2x + y\n";
+        let tokens = parse_expression(expression).unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.0),
+                Token::Variable("x".to_string()),
+                Token::Operator(Operator::Additive(Additive::Add)),
+                Token::Variable("y".to_string()),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_matrices() {
+        let tokens = parse_expression("[1, 2; 3, 4] .* x").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::InnerMatrix(vec![
+                    Token::Number(1.0),
+                    Token::Separator,
+                    Token::Number(2.0),
+                    Token::RowSeparator,
+                    Token::Number(3.0),
+                    Token::Separator,
+                    Token::Number(4.0),
+                ]),
+                Token::Operator(Operator::ElementWise(ElementWise::Multiply)),
+                Token::Variable("x".to_string()),
+            ]
+        );
+        assert!(parse_expression("[1, 2").is_err());
+        assert!(parse_expression("(1, 2]").is_err());
+    }
+
+    #[test]
+    fn parse_lists_and_ranges() {
+        let tokens = parse_expression("{1.5, 2} + 1..10").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::InnerList(vec![Token::Number(1.5), Token::Separator, Token::Number(2.0)]),
+                Token::Operator(Operator::Additive(Additive::Add)),
+                Token::Number(1.0),
+                Token::Operator(Operator::Range),
+                Token::Number(10.0),
+            ]
+        );
+        assert!(parse_expression("{1, 2]").is_err());
+    }
+
+    #[test]
+    fn parse_keywords_with_digits() {
+        let tokens = parse_expression("2x1 + y").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.0),
+                Token::Variable("x1".to_string()),
+                Token::Operator(Operator::Additive(Additive::Add)),
+                Token::Variable("y".to_string()),
+            ]
+        );
+        assert!(matches!(parse_expression("e2").unwrap()[..], [Token::Number(_), Token::Constant(_)]));
+        assert!(matches!(parse_expression("pi2").unwrap()[..], [Token::Number(_), Token::Constant(_)]));
+    }
 }
@@ -0,0 +1,119 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The id given to the next measurement, so every measurement has its own.
+static NEXT_MEASUREMENT: AtomicUsize = AtomicUsize::new(0);
+
+/// A value with a standard uncertainty, like `9.81 ± 0.02`, which is carried through calculations
+/// to first order.
+///
+/// The uncertainty is kept as the contribution of each independent measurement the value was
+/// calculated from. Contributions from the same measurement add up before they're combined with
+/// the others, so a measurement used twice is fully correlated with itself, and `x - x` is exactly
+/// zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uncertain {
+    pub value: f64,
+    /// The change in the value caused by a change of one standard uncertainty in each measurement,
+    /// by the measurement's id.
+    contributions: BTreeMap<usize, f64>,
+}
+
+impl Uncertain {
+    /// A new measurement, which is independent of every other one.
+    pub fn new(value: f64, uncertainty: f64) -> Uncertain {
+        let id = NEXT_MEASUREMENT.fetch_add(1, Ordering::Relaxed);
+        Uncertain {
+            value,
+            contributions: BTreeMap::from([(id, uncertainty)]),
+        }
+    }
+
+    /// A value calculated from others, given the partial derivative of the calculation with
+    /// respect to each of them.
+    pub fn propagate<'a>(
+        value: f64,
+        args: impl IntoIterator<Item = (&'a Uncertain, f64)>,
+    ) -> Uncertain {
+        let mut contributions = BTreeMap::new();
+        for (arg, partial) in args {
+            for (&id, contribution) in &arg.contributions {
+                *contributions.entry(id).or_insert(0.0) += partial * contribution;
+            }
+        }
+
+        Uncertain {
+            value,
+            contributions,
+        }
+    }
+
+    /// The combined standard uncertainty.
+    pub fn uncertainty(&self) -> f64 {
+        self.contributions.values().fold(0.0, |sum, c| sum + c * c).sqrt()
+    }
+}
+
+impl From<f64> for Uncertain {
+    /// An exact value, without any uncertainty.
+    fn from(value: f64) -> Uncertain {
+        Uncertain {
+            value,
+            contributions: BTreeMap::new(),
+        }
+    }
+}
+
+impl fmt::Display for Uncertain {
+    /// Rounds the uncertainty to one or two significant figures and the value to the same decimal
+    /// place, following the convention of the Particle Data Group: two figures when the first three
+    /// digits of the uncertainty are below 355, and one otherwise, unless they round up to 1000.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (value, uncertainty) = (self.value, self.uncertainty());
+        if uncertainty == 0.0 || !uncertainty.is_finite() || !value.is_finite() {
+            return write!(f, "{value} ± {uncertainty}");
+        }
+
+        let exponent = uncertainty.log10().floor() as i32;
+        let leading = (uncertainty / 10f64.powi(exponent - 2)).round();
+        let place = match leading < 355.0 {
+            true => exponent - 1,
+            false => exponent,
+        };
+
+        let round = |x: f64| (x / 10f64.powi(place)).round() * 10f64.powi(place);
+        let decimals = (-place).max(0) as usize;
+        write!(f, "{:.decimals$} ± {:.decimals$}", round(value), round(uncertainty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn test_correlation() {
+        let x = Uncertain::new(3.0, 0.1);
+        let y = Uncertain::new(4.0, 0.1);
+
+        // x - x and x + y
+        let difference = Uncertain::propagate(0.0, [(&x, 1.0), (&x, -1.0)]);
+        let sum = Uncertain::propagate(7.0, [(&x, 1.0), (&y, 1.0)]);
+
+        assert_eq!(difference.uncertainty(), 0.0);
+        assert_close(sum.uncertainty(), 0.02f64.sqrt(), 1e-15);
+    }
+
+    #[test]
+    fn test_significant_figures() {
+        assert_eq!(Uncertain::new(9.81, 0.02).to_string(), "9.810 ± 0.020");
+        assert_eq!(Uncertain::new(9.8123, 0.05).to_string(), "9.81 ± 0.05");
+        assert_eq!(Uncertain::new(9.8123, 0.0987).to_string(), "9.81 ± 0.10");
+        assert_eq!(Uncertain::new(12345.0, 234.0).to_string(), "12350 ± 230");
+        assert_eq!(Uncertain::from(2.5).to_string(), "2.5 ± 0");
+    }
+}
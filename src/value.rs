@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::{
+    ast::ASTNode, complex::Complex, datetime::{Duration, Timestamp}, decimal::Decimal, fit::Fit, fraction::Fraction, identify::Identification, interpolate::Interpolant, matrix::Matrix, modular::Residue, montecarlo::{Simulation, PERCENTILES},
+    programmer::Int, uncertainty::Uncertain,
+};
+
+/// The result of evaluating an expression. Which kind of value is produced depends on the mode
+/// the calculator is in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Integer(Int),
+    /// A base 10 number from decimal mode, which keeps its trailing zeros like `2.50`.
+    Decimal(Decimal),
+    /// An integer modulo a number, from modular mode.
+    Residue(Residue),
+    /// An expression that still contains variables, like the result of `diff(x^2, x)` or
+    /// `simplify(x + x)`.
+    Expression(ASTNode),
+    /// A number that was approximated, like an integral, along with an estimate of its error.
+    Estimate { value: f64, error: f64 },
+    Complex(Complex),
+    /// A fraction that approximates a number, like the result of `rat(pi, 1000)`.
+    Fraction(Fraction),
+    /// A number along with a closed form it was recognized as, the result of `identify`.
+    Identification(Identification),
+    /// Several values, like the roots of a polynomial or a list literal like `{3, 1, 4}`.
+    List(Vec<Value>),
+    Matrix(Matrix),
+    /// A measured value with a standard uncertainty, like `9.81 ± 0.02`.
+    Uncertain(Uncertain),
+    /// The parameters of a model fitted to data, like the result of `linfit`.
+    Fit(Fit),
+    /// The summary of a Monte Carlo simulation.
+    Simulation(Simulation),
+    /// A function of one number, like a spline through a table of points. It can be given to a
+    /// variable and then called, like `f(2.5)`.
+    Function(Interpolant),
+    /// A date or moment, like `2026-10-17` or `now + 90min`.
+    Time(Timestamp),
+    /// A length of time, like `3d 4h` or the difference between two dates.
+    Duration(Duration),
+}
+
+impl From<Complex> for Value {
+    /// Complex numbers without an imaginary part become plain numbers.
+    fn from(z: Complex) -> Value {
+        match z.im {
+            0.0 => Value::Number(z.re),
+            _ => Value::Complex(z),
+        }
+    }
+}
+
+impl From<Matrix> for Value {
+    /// Matrices with a single element become plain numbers, like `[1, 2] * [3; 4]`.
+    fn from(matrix: Matrix) -> Value {
+        match matrix.shape() {
+            (1, 1) => Value::Number(matrix[(0, 0)]),
+            _ => Value::Matrix(matrix),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Integer(n) => write!(f, "{n}"),
+            Value::Decimal(d) => write!(f, "{d}"),
+            Value::Residue(r) => write!(f, "{r}"),
+            Value::Expression(ast) => write!(f, "{ast}"),
+            Value::Estimate { value, error } => write!(f, "{value} (estimated error: {error:.1e})"),
+            Value::Complex(z) => write!(f, "{z}"),
+            Value::Fraction(fraction) => write!(f, "{fraction}"),
+            Value::Identification(identification) => write!(f, "{identification}"),
+            Value::List(values) => {
+                write!(f, "{{")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "}}")
+            }
+            Value::Matrix(matrix) => write!(f, "{matrix}"),
+            Value::Uncertain(u) => write!(f, "{u}"),
+            Value::Fit(fit) => {
+                for (name, value) in &fit.parameters {
+                    write!(f, "{name} = {value}, ")?;
+                }
+                let residuals = Value::List(fit.residuals.iter().copied().map(Value::Number).collect());
+                write!(f, "R² = {}, residuals {residuals}", fit.r_squared)
+            }
+            Value::Simulation(simulation) => {
+                write!(f, "mean {}, standard deviation {}", simulation.mean, simulation.stdev)?;
+                for (p, value) in PERCENTILES.iter().zip(&simulation.percentiles) {
+                    write!(f, ", {p}th percentile {value}")?;
+                }
+                Ok(())
+            }
+            Value::Function(function) => write!(f, "{function}"),
+            Value::Time(t) => write!(f, "{t}"),
+            Value::Duration(d) => write!(f, "{d}"),
+        }
+    }
+}